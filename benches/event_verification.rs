@@ -0,0 +1,22 @@
+//! Id only vs full verification of reported events, see
+//! `reportinator.signature_verification`
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nostr_sdk::prelude::*;
+
+fn reported_event() -> Event {
+    EventBuilder::text_note("I'm a hateful text, report me!", [])
+        .to_event(&Keys::generate())
+        .unwrap()
+}
+
+fn event_verification(c: &mut Criterion) {
+    let event = reported_event();
+
+    c.bench_function("verify_id", |b| {
+        b.iter(|| black_box(&event).verify_id().unwrap())
+    });
+    c.bench_function("verify", |b| b.iter(|| black_box(&event).verify().unwrap()));
+}
+
+criterion_group!(benches, event_verification);
+criterion_main!(benches);
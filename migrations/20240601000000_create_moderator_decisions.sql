@@ -0,0 +1,15 @@
+-- Moderator decisions taken from Slack, used by the transparency dashboard.
+-- Skipped requests have no report id nor category.
+CREATE TABLE IF NOT EXISTS moderator_decisions (
+    id BIGSERIAL PRIMARY KEY,
+    report_id TEXT,
+    category TEXT,
+    moderator TEXT NOT NULL,
+    target_type TEXT NOT NULL,
+    target TEXT NOT NULL,
+    reporter_pubkey TEXT NOT NULL,
+    decided_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS moderator_decisions_decided_at_idx
+    ON moderator_decisions (decided_at);
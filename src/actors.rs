@@ -1,5 +1,5 @@
 pub mod relay_event_dispatcher;
-pub use relay_event_dispatcher::{NostrPort, RelayEventDispatcher};
+pub use relay_event_dispatcher::{NostrPort, PublishOutcome, RelayEventDispatcher};
 
 pub mod gift_unwrapper;
 pub use gift_unwrapper::GiftUnwrapper;
@@ -7,14 +7,27 @@ pub use gift_unwrapper::GiftUnwrapper;
 pub mod event_enqueuer;
 pub use event_enqueuer::{EventEnqueuer, PubsubPort};
 
-pub mod slack_writer;
-pub use slack_writer::{SlackClientPort, SlackClientPortBuilder, SlackWriter};
+pub mod notification_writer;
+pub use notification_writer::{
+    NotificationPort, NotificationSink, NotificationWriter, SlackNotifierBuilder,
+};
+
+pub mod retry_actor;
+pub use retry_actor::RetryActor;
+
+pub mod content_tracker;
+pub use content_tracker::ContentTracker;
+
+pub mod relay_health_actor;
+pub use relay_health_actor::RelayHealthActor;
 
 pub mod supervisor;
 pub use supervisor::Supervisor;
 
 pub mod utilities;
 #[cfg(test)]
-pub use utilities::TestActor;
+pub use utilities::{
+    RecordingPublisher, RecordingSlackClientBuilder, ScriptedNostrService, TestActor,
+};
 
 pub mod messages;
@@ -0,0 +1,418 @@
+/// This module contains the ContentTracker, which counts the reporters of the
+/// same content, even when it was posted in different events, and escalates
+/// it to Slack once enough of them reported it
+use crate::actors::messages::{ContentTrackerMessage, NotificationWriterMessage};
+use crate::actors::utilities::mailbox;
+use crate::config::Configurable;
+use crate::domain_objects::ReportRequest;
+use crate::service_manager::actor_name;
+use metrics::{counter, histogram};
+use nostr_sdk::hashes::{sha256::Hash as Sha256Hash, Hash};
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Reports older than this stop counting for their content
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    // Distinct reporters of the same content within the window that get it
+    // written to Slack. Content is only counted when unset
+    #[serde(default)]
+    pub escalation_threshold: Option<usize>,
+    // Contents tracked at most. Past it the one reported least recently is
+    // forgotten to make room
+    #[serde(default = "default_max_contents")]
+    pub max_contents: usize,
+}
+
+fn default_window_secs() -> u64 {
+    86400
+}
+
+fn default_max_contents() -> usize {
+    100_000
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "content_tracking"
+    }
+}
+
+/// Hash of the content of a reported event, surrounding whitespace removed.
+/// None for pubkey targets and events without content.
+pub fn content_hash(report_request: &ReportRequest) -> Option<Sha256Hash> {
+    let event = report_request.target().event()?;
+
+    let content = event.content.trim();
+    if content.is_empty() {
+        return None;
+    }
+
+    Some(Sha256Hash::hash(content.as_bytes()))
+}
+
+#[derive(Default)]
+struct TrackedContent {
+    // The latest report request of each reporter
+    reports: Vec<(ReportRequest, Instant)>,
+    // Escalated once until all its reports are out of the window
+    escalated: bool,
+}
+
+impl TrackedContent {
+    fn track(&mut self, report_request: ReportRequest, now: Instant) {
+        self.reports.retain(|(tracked_report_request, _)| {
+            tracked_report_request.reporter_pubkey() != report_request.reporter_pubkey()
+        });
+        self.reports.push((report_request, now));
+    }
+
+    fn prune(&mut self, now: Instant, window: Duration) {
+        self.reports
+            .retain(|(_, tracked_at)| now.duration_since(*tracked_at) < window);
+    }
+
+    fn last_reported_at(&self) -> Option<Instant> {
+        self.reports.last().map(|(_, tracked_at)| *tracked_at)
+    }
+}
+
+pub struct ContentTracker;
+
+pub struct State {
+    config: Config,
+    notification_writer: Option<ActorRef<NotificationWriterMessage>>,
+    contents: HashMap<Sha256Hash, TrackedContent>,
+    // The tracked contents ordered by their last report, least recent first
+    by_last_report: BTreeSet<(Instant, Sha256Hash)>,
+}
+
+impl State {
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.config.window_secs)
+    }
+
+    // The report requests to escalate when this one crosses the threshold
+    fn track(
+        &mut self,
+        content_hash: Sha256Hash,
+        report_request: ReportRequest,
+        now: Instant,
+    ) -> Option<Vec<ReportRequest>> {
+        let window = self.window();
+        match self.contents.get(&content_hash) {
+            Some(tracked_content) => {
+                if let Some(last_reported_at) = tracked_content.last_reported_at() {
+                    self.by_last_report
+                        .remove(&(last_reported_at, content_hash));
+                }
+            }
+            None => self.make_room(now),
+        }
+        let tracked_content = self.contents.entry(content_hash).or_default();
+        tracked_content.prune(now, window);
+        tracked_content.track(report_request, now);
+        self.by_last_report.insert((now, content_hash));
+
+        let report_count = tracked_content.reports.len();
+        histogram!("content_report_count").record(report_count as f64);
+
+        let escalation_threshold = self.config.escalation_threshold?;
+        if tracked_content.escalated || report_count < escalation_threshold {
+            return None;
+        }
+
+        tracked_content.escalated = true;
+        Some(
+            tracked_content
+                .reports
+                .iter()
+                .map(|(report_request, _)| report_request.clone())
+                .collect(),
+        )
+    }
+
+    // Forgets the least recently reported contents, only counting them as
+    // evicted when they are still in the window
+    fn make_room(&mut self, now: Instant) {
+        let window = self.window();
+
+        while self.contents.len() >= self.config.max_contents.max(1) {
+            let Some((last_reported_at, least_recent)) = self.by_last_report.pop_first() else {
+                return;
+            };
+
+            self.contents.remove(&least_recent);
+            if now.duration_since(last_reported_at) < window {
+                counter!("content_tracker_evicted").increment(1);
+            }
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        let window = self.window();
+        let by_last_report = &mut self.by_last_report;
+        self.contents.retain(|content_hash, tracked_content| {
+            let last_reported_at = tracked_content.last_reported_at();
+            tracked_content.prune(now, window);
+            if !tracked_content.reports.is_empty() {
+                return true;
+            }
+
+            if let Some(last_reported_at) = last_reported_at {
+                by_last_report.remove(&(last_reported_at, *content_hash));
+            }
+            false
+        });
+    }
+}
+
+#[ractor::async_trait]
+impl Actor for ContentTracker {
+    type Msg = ContentTrackerMessage;
+    type State = State;
+    type Arguments = (Config, Option<ActorRef<NotificationWriterMessage>>);
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        (config, notification_writer): (Config, Option<ActorRef<NotificationWriterMessage>>),
+    ) -> Result<Self::State, ActorProcessingErr> {
+        mailbox::register::<Self::Msg>(actor_name::<Self>());
+
+        let state = State {
+            config,
+            notification_writer,
+            contents: HashMap::new(),
+            by_last_report: BTreeSet::new(),
+        };
+
+        // Content nobody reported again within the window is forgotten
+        myself.send_interval(state.window().max(Duration::from_secs(1)), || {
+            ContentTrackerMessage::Prune
+        });
+
+        Ok(state)
+    }
+
+    async fn handle(
+        &self,
+        _: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        let _handling = mailbox::handling(&message);
+        match message {
+            ContentTrackerMessage::Track(report_request) => {
+                let Some(content_hash) = content_hash(&report_request) else {
+                    return Ok(());
+                };
+
+                let Some(report_requests) =
+                    state.track(content_hash, report_request, Instant::now())
+                else {
+                    return Ok(());
+                };
+
+                counter!("content_escalated").increment(1);
+                info!(
+                    "Content {} reported by {} accounts, escalating to slack",
+                    content_hash,
+                    report_requests.len()
+                );
+
+                let Some(notification_writer) = &state.notification_writer else {
+                    return Ok(());
+                };
+
+                if let Err(e) =
+                    notification_writer.cast(NotificationWriterMessage::Escalate(report_requests))
+                {
+                    error!("Failed to escalate content {}: {}", content_hash, e);
+                }
+            }
+            ContentTrackerMessage::Prune => {
+                state.prune(Instant::now());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::TestActor;
+    use nostr_sdk::prelude::*;
+    use ractor::cast;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    fn report_request(content: &str, reporter_keys: &Keys) -> ReportRequest {
+        let reported_event = EventBuilder::text_note(content, [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        ReportRequest::new(reported_event.into(), reporter_keys.public_key(), None)
+    }
+
+    fn state(escalation_threshold: Option<usize>) -> State {
+        State {
+            config: Config {
+                window_secs: 60,
+                escalation_threshold,
+                max_contents: 3,
+            },
+            notification_writer: None,
+            contents: HashMap::new(),
+            by_last_report: BTreeSet::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_escalates_identical_content_from_multiple_reporters() {
+        let escalated = Arc::new(Mutex::new(Vec::new()));
+        let (notification_writer_ref, notification_writer_handle) =
+            Actor::spawn(None, TestActor::default(), Some(escalated.clone()))
+                .await
+                .unwrap();
+
+        let (content_tracker_ref, content_tracker_handle) = Actor::spawn(
+            None,
+            ContentTracker,
+            (
+                Config {
+                    window_secs: 60,
+                    escalation_threshold: Some(3),
+                    max_contents: 10,
+                },
+                Some(notification_writer_ref.clone()),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let first_reporter = Keys::generate();
+        let copypasta = "Send 1 BTC to get 2 back!!";
+        let report_requests = vec![
+            report_request(copypasta, &first_reporter),
+            // Repeated reports of the same reporter count once
+            report_request(copypasta, &first_reporter),
+            report_request("Something else", &Keys::generate()),
+            report_request(copypasta, &Keys::generate()),
+            report_request(&format!("  {}\n", copypasta), &Keys::generate()),
+            // Already escalated
+            report_request(copypasta, &Keys::generate()),
+        ];
+        for report_request in report_requests.iter().cloned() {
+            cast!(
+                content_tracker_ref,
+                ContentTrackerMessage::from(report_request)
+            )
+            .unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        content_tracker_ref.stop(None);
+        notification_writer_ref.stop(None);
+        content_tracker_handle.await.unwrap();
+        notification_writer_handle.await.unwrap();
+
+        let escalated = escalated.lock().await;
+        let [NotificationWriterMessage::Escalate(escalated_report_requests)] = escalated.as_slice()
+        else {
+            panic!("Expected a single escalation, got {:?}", escalated);
+        };
+        assert_eq!(
+            escalated_report_requests,
+            &[
+                report_requests[1].clone(),
+                report_requests[3].clone(),
+                report_requests[4].clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reports_out_of_the_window_stop_counting() {
+        let mut state = state(Some(2));
+        let first = report_request("Same old spam", &Keys::generate());
+        let second = report_request("Same old spam", &Keys::generate());
+        let content_hash = content_hash(&first).unwrap();
+        let start = Instant::now();
+
+        assert!(state.track(content_hash, first, start).is_none());
+        assert!(state
+            .track(content_hash, second, start + Duration::from_secs(61))
+            .is_none());
+
+        state.prune(start + Duration::from_secs(200));
+        assert!(state.contents.is_empty());
+        assert!(state.by_last_report.is_empty());
+    }
+
+    #[test]
+    fn test_only_counts_without_threshold() {
+        let mut state = state(None);
+        let now = Instant::now();
+
+        for _ in 0..5 {
+            let report_request = report_request("Spam", &Keys::generate());
+            let content_hash = content_hash(&report_request).unwrap();
+            assert!(state.track(content_hash, report_request, now).is_none());
+        }
+        assert_eq!(state.contents.values().next().unwrap().reports.len(), 5);
+    }
+
+    #[test]
+    fn test_forgets_the_least_recently_reported_content_when_full() {
+        let mut state = state(None);
+        let start = Instant::now();
+
+        let mut content_hashes = Vec::new();
+        for (i, content) in ["First", "Second", "Third"].into_iter().enumerate() {
+            let report_request = report_request(content, &Keys::generate());
+            let content_hash = content_hash(&report_request).unwrap();
+            content_hashes.push(content_hash);
+            state.track(
+                content_hash,
+                report_request,
+                start + Duration::from_secs(i as u64),
+            );
+        }
+
+        // Reported again, so the second one is now the least recent
+        let report_request_again = report_request("First", &Keys::generate());
+        state.track(
+            content_hashes[0],
+            report_request_again,
+            start + Duration::from_secs(3),
+        );
+        let fourth = report_request("Fourth", &Keys::generate());
+        let fourth_hash = content_hash(&fourth).unwrap();
+        state.track(fourth_hash, fourth, start + Duration::from_secs(4));
+
+        assert_eq!(state.contents.len(), 3);
+        assert_eq!(state.by_last_report.len(), 3);
+        assert!(!state.contents.contains_key(&content_hashes[1]));
+        assert!(state.contents.contains_key(&content_hashes[0]));
+        assert!(state.contents.contains_key(&fourth_hash));
+    }
+
+    #[test]
+    fn test_pubkey_targets_have_no_content_hash() {
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+
+        assert!(content_hash(&report_request).is_none());
+    }
+}
@@ -1,5 +1,14 @@
+use crate::actors::messages::{EventEnqueuerMessage, RetryDestination, RetryMessage};
+use crate::actors::utilities::auto_skip::AutoSkipRules;
+use crate::actors::utilities::downstream_limit::limited;
+use crate::actors::utilities::mailbox;
+use crate::actors::utilities::report_funnel::{self, FunnelStep};
+use crate::actors::utilities::reporter_queue::ReporterQueue;
+use crate::actors::utilities::self_test::DependencyCheck;
+use crate::dead_letter_queue::DeadLetterQueue;
 use crate::domain_objects::ReportRequest;
-use crate::{actors::messages::EventEnqueuerMessage, domain_objects::ReportTarget};
+use crate::domain_objects::ReportTarget;
+use crate::service_manager::actor_name;
 use anyhow::Result;
 use metrics::counter;
 use ractor::{Actor, ActorProcessingErr, ActorRef};
@@ -18,11 +27,33 @@ impl<T: PubsubPort> Default for EventEnqueuer<T> {
 
 pub struct State<T: PubsubPort> {
     pubsub_publisher: T,
+    retry_actor: Option<ActorRef<RetryMessage>>,
+    auto_skip_rules: AutoSkipRules,
+    reporter_queue: ReporterQueue,
+    dead_letter_queue: Option<DeadLetterQueue>,
 }
 
 #[ractor::async_trait]
 pub trait PubsubPort: Send + Sync + 'static {
     async fn publish_event(&mut self, event: &ReportRequest) -> Result<()>;
+
+    // Lightweight call for the admin self test, answering with a short
+    // detail. Backends without a remote end have nothing to check
+    async fn check_connectivity(&self) -> Result<String> {
+        Ok("Nothing to check".to_string())
+    }
+}
+
+// Allows choosing the backend at runtime from the config
+#[ractor::async_trait]
+impl PubsubPort for Box<dyn PubsubPort> {
+    async fn publish_event(&mut self, event: &ReportRequest) -> Result<()> {
+        (**self).publish_event(event).await
+    }
+
+    async fn check_connectivity(&self) -> Result<String> {
+        (**self).check_connectivity().await
+    }
 }
 
 #[ractor::async_trait]
@@ -32,24 +63,63 @@ where
 {
     type Msg = EventEnqueuerMessage;
     type State = State<T>;
-    type Arguments = T;
+    type Arguments = (
+        T,
+        Option<ActorRef<RetryMessage>>,
+        AutoSkipRules,
+        ReporterQueue,
+        Option<DeadLetterQueue>,
+    );
 
     async fn pre_start(
         &self,
         _: ActorRef<Self::Msg>,
-        pubsub_publisher: T,
+        (pubsub_publisher, retry_actor, auto_skip_rules, reporter_queue, dead_letter_queue): Self::Arguments,
     ) -> Result<Self::State, ActorProcessingErr> {
-        let state = State { pubsub_publisher };
+        mailbox::register::<Self::Msg>(actor_name::<Self>());
+        let state = State {
+            pubsub_publisher,
+            retry_actor,
+            auto_skip_rules,
+            reporter_queue,
+            dead_letter_queue,
+        };
 
         Ok(state)
     }
 
-    async fn handle(
+    // The requests held behind a retry would be lost, they go to the dead
+    // letter queue to be replayed with --replay-dlq
+    async fn post_stop(
         &self,
         _: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        let held = state.reporter_queue.drain();
+        let Some(dead_letter_queue) = &state.dead_letter_queue else {
+            return Ok(());
+        };
+
+        for report_request in held {
+            match dead_letter_queue.append(&report_request) {
+                Ok(()) => counter!("reporter_ordering_dead_lettered").increment(1),
+                Err(e) => error!(
+                    "Failed to add held report request to the dead letter queue: {}",
+                    e
+                ),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        myself: ActorRef<Self::Msg>,
         message: Self::Msg,
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
+        let _handling = mailbox::handling(&message);
         match message {
             EventEnqueuerMessage::Enqueue(report_request) => {
                 if let ReportTarget::Pubkey(_) = report_request.target() {
@@ -57,14 +127,50 @@ where
                     return Ok(());
                 }
 
-                if let Err(e) = state.pubsub_publisher.publish_event(&report_request).await {
-                    counter!("events_enqueued_error").increment(1);
-                    error!("Failed to publish event: {}", e);
+                if state.auto_skip_rules.skips(&report_request) {
                     return Ok(());
                 }
 
-                counter!("events_enqueued").increment(1);
-                info!("Event {} enqueued for moderation", report_request.target());
+                // With id_only verification nothing checked the signature
+                // yet, forged events don't leave the server
+                if let Some(event) = report_request.target().event() {
+                    if event.verify().is_err() {
+                        counter!("events_enqueued_invalid_signature").increment(1);
+                        error!(
+                            "Not enqueuing event {}, its signature doesn't verify",
+                            event.id
+                        );
+                        return Ok(());
+                    }
+                }
+
+                let Some(report_request) = state.reporter_queue.hold(report_request) else {
+                    counter!("reporter_ordering_held").increment(1);
+                    info!("Holding report request behind an earlier one from the same reporter");
+                    return Ok(());
+                };
+
+                Self::enqueue(&myself, state, report_request, 1).await;
+            }
+            EventEnqueuerMessage::Retry(report_request, attempt) => {
+                Self::enqueue(&myself, state, report_request, attempt).await;
+            }
+            EventEnqueuerMessage::GaveUp(report_request) => {
+                if let Some(next) = state
+                    .reporter_queue
+                    .release(report_request.reporter_pubkey())
+                {
+                    Self::enqueue(&myself, state, next, 1).await;
+                }
+            }
+            EventEnqueuerMessage::SelfTest(reply_port) => {
+                let check =
+                    DependencyCheck::run("pubsub", state.pubsub_publisher.check_connectivity())
+                        .await;
+
+                if !reply_port.is_closed() {
+                    reply_port.send(check)?;
+                }
             }
         }
 
@@ -72,9 +178,63 @@ where
     }
 }
 
+impl<T> EventEnqueuer<T>
+where
+    T: PubsubPort + Send + Sync + Sized + 'static,
+{
+    // Once published, the report requests held behind it follow in order
+    // until one of them fails too
+    async fn enqueue(
+        myself: &ActorRef<EventEnqueuerMessage>,
+        state: &mut State<T>,
+        report_request: ReportRequest,
+        attempt: u32,
+    ) {
+        let mut next = Some((report_request, attempt));
+        while let Some((report_request, attempt)) = next.take() {
+            if !Self::publish(myself, state, &report_request, attempt).await {
+                return;
+            }
+
+            next = state
+                .reporter_queue
+                .release(report_request.reporter_pubkey())
+                .map(|held| (held, 1));
+        }
+    }
+
+    async fn publish(
+        myself: &ActorRef<EventEnqueuerMessage>,
+        state: &mut State<T>,
+        report_request: &ReportRequest,
+        attempt: u32,
+    ) -> bool {
+        if let Err(e) = limited(state.pubsub_publisher.publish_event(report_request)).await {
+            counter!("events_enqueued_error").increment(1);
+            error!("Failed to publish event on attempt {}: {}", attempt, e);
+
+            if let Some(retry_actor) = &state.retry_actor {
+                let destination = RetryDestination::EventEnqueuer(myself.clone());
+                let failed = RetryMessage::Failed(report_request.clone(), destination, attempt);
+                match mailbox::cast(retry_actor, failed) {
+                    Ok(()) => state.reporter_queue.block(report_request),
+                    Err(e) => error!("Failed to send report request to the retry actor: {}", e),
+                }
+            }
+            return false;
+        }
+
+        counter!("events_enqueued").increment(1);
+        report_funnel::record(FunnelStep::Enqueued, report_request.target().label());
+        info!("Event {} enqueued for moderation", report_request.target());
+        true
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use nostr_sdk::prelude::{EventBuilder, Keys};
+    use crate::domain_objects::report_request::SignatureVerification;
+    use nostr_sdk::prelude::{Event, EventBuilder, Keys};
     use ractor::cast;
     use serde_json::json;
     use std::sync::Arc;
@@ -109,7 +269,13 @@ mod tests {
         let (event_enqueuer_ref, event_enqueuer_handle) = Actor::spawn(
             None,
             EventEnqueuer::default(),
-            test_google_publisher.clone(),
+            (
+                test_google_publisher.clone(),
+                None,
+                AutoSkipRules::default(),
+                ReporterQueue::default(),
+                None,
+            ),
         )
         .await
         .unwrap();
@@ -145,4 +311,59 @@ mod tests {
             [report_request]
         );
     }
+
+    #[tokio::test]
+    async fn test_forged_signatures_are_not_enqueued() {
+        let test_google_publisher = TestGooglePublisher::new();
+
+        let (event_enqueuer_ref, event_enqueuer_handle) = Actor::spawn(
+            None,
+            EventEnqueuer::default(),
+            (
+                test_google_publisher.clone(),
+                None,
+                AutoSkipRules::default(),
+                ReporterQueue::default(),
+                None,
+            ),
+        )
+        .await
+        .unwrap();
+
+        let keys = Keys::generate();
+        let event_to_report = EventBuilder::text_note("Original", [])
+            .to_event(&keys)
+            .unwrap();
+        let other_event = EventBuilder::text_note("Other", [])
+            .to_event(&keys)
+            .unwrap();
+        let mut event_value = serde_json::to_value(&event_to_report).unwrap();
+        event_value["sig"] = json!(other_event.sig.to_string());
+
+        let report_request = ReportRequest::builder()
+            .target(serde_json::from_value::<Event>(event_value).unwrap())
+            .reporter_pubkey(Keys::generate().public_key())
+            .signature_verification(SignatureVerification::IdOnly)
+            .build()
+            .unwrap();
+
+        cast!(
+            event_enqueuer_ref,
+            EventEnqueuerMessage::Enqueue(report_request)
+        )
+        .unwrap();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            event_enqueuer_ref.stop(None);
+        });
+
+        event_enqueuer_handle.await.unwrap();
+
+        assert!(test_google_publisher
+            .published_events
+            .lock()
+            .await
+            .is_empty());
+    }
 }
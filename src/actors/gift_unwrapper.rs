@@ -1,14 +1,156 @@
-use crate::actors::messages::GiftUnwrapperMessage;
-use crate::domain_objects::ReportRequest;
+use crate::actors::messages::{GiftUnwrapperMessage, SupervisorMessage};
+use crate::actors::utilities::dedup::SeenIds;
+use crate::actors::utilities::language_detection::LanguageDetector;
+use crate::actors::utilities::mailbox;
+use crate::actors::utilities::report_funnel::{self, FunnelStep};
+use crate::adapters::lookup_nip05;
+use crate::config::{reportinator, Configurable};
+use crate::domain_objects::report_request::SignatureVerification;
+use crate::domain_objects::{GiftWrapError, ReportRequest, ReportTarget};
+use crate::service_manager::actor_name;
 use anyhow::Result;
+use metrics::counter;
 use nostr_sdk::prelude::*;
-use ractor::{Actor, ActorProcessingErr, ActorRef, OutputPort};
-use tracing::{error, info};
+use ractor::{call_t, Actor, ActorProcessingErr, ActorRef, OutputPort};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Minimum NIP-13 difficulty of incoming gift wraps. Zero accepts any
+    #[serde(default)]
+    pub min_gift_wrap_pow: u8,
+    // How long the previous keys keep decrypting gift wraps after a rotation
+    #[serde(default = "default_key_rotation_grace_secs")]
+    pub key_rotation_grace_secs: u64,
+    // Kinds of the events that can be reported. Empty allows any
+    #[serde(default)]
+    pub reportable_kinds: Vec<u16>,
+    // Reported events created longer ago than this are rejected. Unset
+    // accepts events of any age
+    #[serde(default)]
+    pub max_event_age_secs: Option<u64>,
+    // Gift wraps created further than this ahead of our clock are rejected,
+    // they would outlive since filters and sort first
+    #[serde(default = "default_max_future_skew_secs")]
+    pub max_future_skew_secs: u64,
+    // What to do with reporters reporting themselves or their own events
+    #[serde(default)]
+    pub self_reports: SelfReportPolicy,
+    // Only reporters with a verified NIP-05 are acted on, to raise the bar
+    // against throwaway keys
+    #[serde(default)]
+    pub require_reporter_nip05: bool,
+    // Reported events must be found on one of our relays within
+    // event_lookup_timeout_ms, so events never published are not acted on
+    #[serde(default)]
+    pub require_published_event: bool,
+    #[serde(default = "default_event_lookup_timeout_ms")]
+    pub event_lookup_timeout_ms: u64,
+    // id_only skips the signature of reported events until a moderator acts
+    // on them, requests we drop anyway don't pay for it
+    #[serde(default)]
+    pub signature_verification: SignatureVerification,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelfReportPolicy {
+    // Rejected like any other invalid request
+    Drop,
+    // Let through, Slack messages mark them
+    #[default]
+    Flag,
+}
+
+impl SelfReportPolicy {
+    fn name(&self) -> &'static str {
+        match self {
+            SelfReportPolicy::Drop => "drop",
+            SelfReportPolicy::Flag => "flag",
+        }
+    }
+}
+
+fn default_key_rotation_grace_secs() -> u64 {
+    600
+}
+
+fn default_max_future_skew_secs() -> u64 {
+    300
+}
+
+// Relays get 5 seconds to answer the lookup
+fn default_event_lookup_timeout_ms() -> u64 {
+    6000
+}
+
+impl Config {
+    pub fn key_rotation_grace(&self) -> Duration {
+        Duration::from_secs(self.key_rotation_grace_secs)
+    }
+
+    // Pubkey reports have no event to look up
+    fn needs_lookups(&self, target: &ReportTarget) -> bool {
+        self.require_reporter_nip05 || (self.require_published_event && target.event().is_some())
+    }
+
+    // Pubkey reports are always allowed
+    fn is_reportable(&self, target: &ReportTarget) -> bool {
+        match target.event() {
+            Some(event) => {
+                self.reportable_kinds.is_empty()
+                    || self.reportable_kinds.contains(&event.kind.as_u16())
+            }
+            None => true,
+        }
+    }
+
+    // Pubkey reports are never stale
+    fn is_stale(&self, target: &ReportTarget, now: Timestamp) -> bool {
+        let (Some(event), Some(max_event_age_secs)) = (target.event(), self.max_event_age_secs)
+        else {
+            return false;
+        };
+
+        now.as_u64().saturating_sub(event.created_at.as_u64()) > max_event_age_secs
+    }
+
+    // NIP-59 only tweaks gift wrap timestamps into the past
+    fn is_from_the_future(&self, created_at: Timestamp, now: Timestamp) -> bool {
+        created_at.as_u64().saturating_sub(now.as_u64()) > self.max_future_skew_secs
+    }
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "reportinator"
+    }
+}
+
+/// What the lookups of require_reporter_nip05 and require_published_event
+/// found for a report request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LookupOutcome {
+    Passed,
+    UnverifiedReporter,
+    EventNotFound,
+    // Timed out or failed, not an answer
+    Failed(String),
+}
 
 /// An actor responsible for opening gift wrapped private direct messages and grab the events to moderate
 pub struct GiftUnwrapper;
 pub struct State {
-    keys: Keys, // Keys used for decrypting messages.
+    keys: Keys,                             // Keys used for decrypting messages.
+    previous_keys: Option<(Keys, Instant)>, // Rotated keys and when they stop being accepted.
+    config: Config,
+    seen_ids: SeenIds, // Gift wraps accepted before, dropped if they come again.
+    looking_up: HashSet<EventId>, // Gift wraps whose lookups aren't over yet.
+    language_detector: LanguageDetector, // Tags the report requests with their language.
+    supervisor: Option<ActorRef<SupervisorMessage>>, // Resolves reporter NIP-05s and looks up reported events when required.
     message_parsed_output_port: OutputPort<ReportRequest>, // Port for publishing the events to report parsed from gift wrapped payload
 }
 
@@ -16,18 +158,33 @@ pub struct State {
 impl Actor for GiftUnwrapper {
     type Msg = GiftUnwrapperMessage; // Defines message types handled by this actor.
     type State = State; // State containing keys and output port.
-    type Arguments = Keys; // Actor initialization arguments, here the decryption keys.
+    /// Actor initialization arguments, the decryption keys, the config, the ids
+    /// seen before, the language detector and who resolves NIP-05s.
+    type Arguments = (
+        Keys,
+        Config,
+        SeenIds,
+        LanguageDetector,
+        Option<ActorRef<SupervisorMessage>>,
+    );
 
     /// Prepares actor before starting, initializing its state with provided keys and a new output port.
     async fn pre_start(
         &self,
         _myself: ActorRef<Self::Msg>,
-        keys: Keys,
+        (keys, config, seen_ids, language_detector, supervisor): Self::Arguments,
     ) -> Result<Self::State, ActorProcessingErr> {
+        mailbox::register::<Self::Msg>(actor_name::<Self>());
         let message_parsed_output_port = OutputPort::default();
 
         Ok(State {
             keys,
+            previous_keys: None,
+            config,
+            seen_ids,
+            looking_up: HashSet::new(),
+            language_detector,
+            supervisor,
             message_parsed_output_port,
         })
     }
@@ -35,10 +192,11 @@ impl Actor for GiftUnwrapper {
     /// Handles incoming messages for the actor.
     async fn handle(
         &self,
-        _myself: ActorRef<Self::Msg>,
+        myself: ActorRef<Self::Msg>,
         message: Self::Msg,
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
+        let _handling = mailbox::handling(&message);
         match message {
             // Decrypts and forwards private messages so they can be sent to
             // google pubsub or whatever is hooked to the output port.
@@ -55,47 +213,383 @@ impl Actor for GiftUnwrapper {
                     return Ok(());
                 };
 
+                // Cheap spam filter before doing any decryption
+                let difficulty = gift_wrap.pow_difficulty();
+                if difficulty < state.config.min_gift_wrap_pow {
+                    counter!("report_rejected_low_pow").increment(1);
+                    info!(
+                        "Rejected gift wrap with difficulty {}, {} required",
+                        difficulty, state.config.min_gift_wrap_pow
+                    );
+                    return Ok(());
+                }
+
+                if state
+                    .config
+                    .is_from_the_future(gift_wrap.created_at(), Timestamp::now())
+                {
+                    counter!("report_future_timestamp").increment(1);
+                    info!(
+                        gift_wrap_id = %gift_wrap.id(),
+                        created_at = %gift_wrap.created_at(),
+                        "Rejected a gift wrap created too far in the future"
+                    );
+                    return Ok(());
+                }
+
+                if state
+                    .seen_ids
+                    .contains(&gift_wrap.id(), Timestamp::now().as_u64())
+                    || state.looking_up.contains(&gift_wrap.id())
+                {
+                    counter!("gift_wrap_duplicate").increment(1);
+                    info!(gift_wrap_id = %gift_wrap.id(), "Ignored a gift wrap seen before");
+                    return Ok(());
+                }
+
                 // 2) ...the domain model, which does the real work.
-                let report_request = match gift_wrap.extract_report_request(&state.keys) {
+                let signature_verification = state.config.signature_verification;
+                let result = match gift_wrap
+                    .extract_report_request_with(&state.keys, signature_verification)
+                {
+                    // Wraps sent before a rotation are still for the previous keys
+                    Err(GiftWrapError::Decryption(..)) if state.previous_keys_valid() => {
+                        let (previous_keys, _) = state.previous_keys.as_ref().unwrap();
+                        gift_wrap.extract_report_request_with(previous_keys, signature_verification)
+                    }
+                    result => result,
+                };
+
+                let report_request = match result {
                     Ok(report_request) => report_request,
+                    // Not an error on either side, just not for us
+                    Err(GiftWrapError::NonReportDm(gift_wrap_id, rumor_kind)) => {
+                        counter!("non_report_dm").increment(1);
+                        info!(
+                            %gift_wrap_id,
+                            %rumor_kind,
+                            "Ignored a DM that isn't a report request"
+                        );
+                        return Ok(());
+                    }
                     Err(e) => {
-                        error!("Error extracting report: {}", e);
+                        counter!("gift_wrap_error", "reason" => e.reason()).increment(1);
+                        error!(reason = e.reason(), "Error extracting report: {}", e);
                         return Ok(());
                     }
                 };
 
-                // 3) Resulting model output is used to create events
-                // that are sent to the output port for the next actor or any other
-                // IO needed
-                info!(
-                    "Request from {} to moderate {}",
-                    report_request.reporter_pubkey(),
-                    report_request.target()
-                );
+                report_funnel::record(FunnelStep::Unwrapped, report_request.target().label());
+
+                if !state.config.is_reportable(report_request.target()) {
+                    counter!("report_unreportable_kind").increment(1);
+                    info!(
+                        "Rejected request from {} to moderate {}, its kind can't be reported",
+                        report_request.reporter_pubkey(),
+                        report_request.target()
+                    );
+                    return Ok(());
+                }
+
+                if state
+                    .config
+                    .is_stale(report_request.target(), Timestamp::now())
+                {
+                    counter!("report_stale_event").increment(1);
+                    info!(
+                        "Rejected request from {} to moderate {}, the event is too old",
+                        report_request.reporter_pubkey(),
+                        report_request.target()
+                    );
+                    return Ok(());
+                }
 
-                state.message_parsed_output_port.send(report_request)
+                if report_request.is_self_report() {
+                    let policy = state.config.self_reports;
+                    counter!("self_report", "policy" => policy.name()).increment(1);
+                    if policy == SelfReportPolicy::Drop {
+                        info!(
+                            "Rejected request from {} to moderate {}, it's a self report",
+                            report_request.reporter_pubkey(),
+                            report_request.target()
+                        );
+                        return Ok(());
+                    }
+                }
+
+                // The relays and NIP-05 servers may take seconds to answer,
+                // other gift wraps are unwrapped meanwhile
+                if state.config.needs_lookups(report_request.target()) {
+                    let gift_wrap_id = gift_wrap.id();
+                    state.looking_up.insert(gift_wrap_id);
+                    let config = state.config.clone();
+                    let supervisor = state.supervisor.clone();
+                    tokio::spawn(async move {
+                        let outcome = look_up(&config, supervisor, &report_request).await;
+                        if let Err(e) = myself.cast(GiftUnwrapperMessage::LookedUp(
+                            gift_wrap_id,
+                            report_request,
+                            outcome,
+                        )) {
+                            error!("Failed to send back the lookups of {}: {}", gift_wrap_id, e);
+                        }
+                    });
+                    return Ok(());
+                }
+
+                state.accept(gift_wrap.id(), report_request);
+            }
+
+            GiftUnwrapperMessage::LookedUp(gift_wrap_id, report_request, outcome) => {
+                state.looking_up.remove(&gift_wrap_id);
+                match outcome {
+                    LookupOutcome::Passed => state.accept(gift_wrap_id, report_request),
+                    LookupOutcome::UnverifiedReporter => {
+                        counter!("report_unverified_reporter").increment(1);
+                        info!(
+                            "Rejected request from {} to moderate {}, the reporter has no verified NIP-05",
+                            report_request.reporter_pubkey(),
+                            report_request.target()
+                        );
+                    }
+                    LookupOutcome::EventNotFound => {
+                        counter!("report_event_not_found").increment(1);
+                        info!(
+                            "Rejected request from {} to moderate {}, the event wasn't found on our relays",
+                            report_request.reporter_pubkey(),
+                            report_request.target()
+                        );
+                    }
+                    // Not remembered as seen, so it's checked again if the
+                    // gift wrap comes again
+                    LookupOutcome::Failed(e) => {
+                        counter!("report_lookup_failed").increment(1);
+                        warn!(
+                            %gift_wrap_id,
+                            "Couldn't check the request from {} to moderate {}: {}",
+                            report_request.reporter_pubkey(),
+                            report_request.target(),
+                            e
+                        );
+                    }
+                }
             }
 
             // Subscribes a new actor to receive parsed messages through the output port.
             GiftUnwrapperMessage::SubscribeToEventUnwrapped(subscriber) => {
                 subscriber.subscribe_to_port(&state.message_parsed_output_port);
             }
+
+            GiftUnwrapperMessage::RotateKeys(keys) => {
+                info!("Rotating keys to {}", keys.public_key());
+                let previous_keys = std::mem::replace(&mut state.keys, keys);
+                let expires_at = Instant::now() + state.config.key_rotation_grace();
+                state.previous_keys = Some((previous_keys, expires_at));
+            }
         }
         Ok(())
     }
 }
 
+impl State {
+    fn previous_keys_valid(&self) -> bool {
+        matches!(&self.previous_keys, Some((_, expires_at)) if Instant::now() < *expires_at)
+    }
+
+    // Only accepted gift wraps are remembered, the others are checked again
+    // if they come again
+    fn accept(&mut self, gift_wrap_id: EventId, report_request: ReportRequest) {
+        self.seen_ids
+            .insert(gift_wrap_id, Timestamp::now().as_u64());
+        let report_request = self.language_detector.tag(report_request);
+
+        // 3) Resulting model output is used to create events
+        // that are sent to the output port for the next actor or any other
+        // IO needed
+        info!(
+            %gift_wrap_id,
+            "Request from {} to moderate {}",
+            report_request.reporter_pubkey(),
+            report_request.target()
+        );
+
+        report_funnel::record(FunnelStep::Valid, report_request.target().label());
+        self.message_parsed_output_port.send(report_request)
+    }
+}
+
+// Without a supervisor nobody can be verified and nothing is found
+async fn look_up(
+    config: &Config,
+    supervisor: Option<ActorRef<SupervisorMessage>>,
+    report_request: &ReportRequest,
+) -> LookupOutcome {
+    let Some(supervisor) = supervisor else {
+        return match config.require_reporter_nip05 {
+            true => LookupOutcome::UnverifiedReporter,
+            false => LookupOutcome::EventNotFound,
+        };
+    };
+
+    if config.require_reporter_nip05 {
+        match lookup_nip05(
+            &supervisor,
+            *report_request.reporter_pubkey(),
+            reportinator::nip05_lookup(),
+        )
+        .await
+        {
+            Ok(Some(_)) => {}
+            Ok(None) => return LookupOutcome::UnverifiedReporter,
+            Err(e) => return LookupOutcome::Failed(e.to_string()),
+        }
+    }
+
+    let Some(event) = report_request.target().event() else {
+        return LookupOutcome::Passed;
+    };
+    if !config.require_published_event {
+        return LookupOutcome::Passed;
+    }
+
+    match call_t!(
+        supervisor,
+        SupervisorMessage::FindEvent,
+        config.event_lookup_timeout_ms,
+        event.id
+    ) {
+        Ok(Ok(Some(_))) => LookupOutcome::Passed,
+        Ok(Ok(None)) => LookupOutcome::EventNotFound,
+        Ok(Err(e)) => LookupOutcome::Failed(format!(
+            "Failed to look up the reported event {}: {}",
+            event.id, e
+        )),
+        Err(e) => LookupOutcome::Failed(format!(
+            "Failed to look up the reported event {}: {}",
+            event.id, e
+        )),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::actors::TestActor;
     use crate::domain_objects::as_gift_wrap::AsGiftWrap;
-    use ractor::{cast, Actor};
+    use ractor::{cast, Actor, ActorProcessingErr};
     use serde_json::json;
     use std::sync::Arc;
     use tokio::sync::Mutex;
     use tokio::time::{sleep, Duration};
 
+    fn config(min_gift_wrap_pow: u8) -> Config {
+        Config {
+            min_gift_wrap_pow,
+            key_rotation_grace_secs: 600,
+            reportable_kinds: vec![],
+            max_event_age_secs: None,
+            max_future_skew_secs: 300,
+            self_reports: SelfReportPolicy::Flag,
+            require_reporter_nip05: false,
+            require_published_event: false,
+            event_lookup_timeout_ms: 2000,
+            signature_verification: SignatureVerification::Full,
+        }
+    }
+
+    fn reported_event(kind: Kind) -> ReportTarget {
+        EventBuilder::new(kind, "Reported", [])
+            .to_event(&Keys::generate())
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn test_allowed_kind_is_reportable() {
+        let config = Config {
+            reportable_kinds: vec![1, 30023],
+            ..config(0)
+        };
+
+        assert!(config.is_reportable(&reported_event(Kind::TextNote)));
+        assert!(config.is_reportable(&Keys::generate().public_key().into()));
+    }
+
+    #[test]
+    fn test_disallowed_kind_is_not_reportable() {
+        let config = Config {
+            reportable_kinds: vec![1, 30023],
+            ..config(0)
+        };
+
+        assert!(!config.is_reportable(&reported_event(Kind::EventDeletion)));
+        assert!(!config.is_reportable(&reported_event(Kind::Reporting)));
+    }
+
+    #[test]
+    fn test_empty_allowlist_allows_any_kind() {
+        assert!(config(0).is_reportable(&reported_event(Kind::EventDeletion)));
+    }
+
+    fn event_created_at(created_at: Timestamp) -> ReportTarget {
+        EventBuilder::text_note("Reported", [])
+            .custom_created_at(created_at)
+            .to_event(&Keys::generate())
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn test_fresh_event_is_not_stale() {
+        let config = Config {
+            max_event_age_secs: Some(3600),
+            ..config(0)
+        };
+        let now = Timestamp::now();
+
+        assert!(!config.is_stale(&event_created_at(now - 60), now));
+        assert!(!config.is_stale(&Keys::generate().public_key().into(), now));
+    }
+
+    #[test]
+    fn test_old_event_is_stale() {
+        let config = Config {
+            max_event_age_secs: Some(3600),
+            ..config(0)
+        };
+        let now = Timestamp::now();
+        let stale_event = event_created_at(now - 7200);
+
+        assert!(config.is_stale(&stale_event, now));
+        assert!(!super::config(0).is_stale(&stale_event, now));
+    }
+
+    #[test]
+    fn test_gift_wrap_within_the_skew_is_accepted() {
+        let config = Config {
+            max_future_skew_secs: 300,
+            ..config(0)
+        };
+        let now = Timestamp::now();
+
+        assert!(!config.is_from_the_future(now + 300, now));
+        assert!(!config.is_from_the_future(now, now));
+        // NIP-59 timestamps up to two days in the past
+        assert!(!config.is_from_the_future(now - 2 * 24 * 60 * 60, now));
+    }
+
+    #[test]
+    fn test_gift_wrap_beyond_the_skew_is_rejected() {
+        let config = Config {
+            max_future_skew_secs: 300,
+            ..config(0)
+        };
+        let now = Timestamp::now();
+
+        assert!(config.is_from_the_future(now + 301, now));
+        assert!(config.is_from_the_future(now + 24 * 60 * 60, now));
+    }
+
     #[tokio::test]
     async fn test_gift_unwrapper_with_event() {
         // Fake of course
@@ -125,6 +619,7 @@ mod tests {
             .as_gift_wrap(&sender_keys, &receiver_pubkey)
             .await
             .unwrap();
+        let gift_wrap_id = gift_wrapped_event.id();
 
         let messages_received = Arc::new(Mutex::new(Vec::<ReportRequest>::new()));
         let (receiver_actor_ref, receiver_actor_handle) =
@@ -132,10 +627,19 @@ mod tests {
                 .await
                 .unwrap();
 
-        let (parser_actor_ref, parser_handle) =
-            Actor::spawn(None, GiftUnwrapper, reportinator_keys)
-                .await
-                .unwrap();
+        let (parser_actor_ref, parser_handle) = Actor::spawn(
+            None,
+            GiftUnwrapper,
+            (
+                reportinator_keys,
+                config(0),
+                SeenIds::default(),
+                LanguageDetector::default(),
+                None,
+            ),
+        )
+        .await
+        .unwrap();
 
         cast!(
             parser_actor_ref,
@@ -161,7 +665,70 @@ mod tests {
         parser_handle.await.unwrap();
         receiver_actor_handle.await.unwrap();
 
-        assert_eq!(messages_received.lock().await.as_ref(), [report_request]);
+        assert_eq!(
+            messages_received.lock().await.as_ref(),
+            [report_request.with_gift_wrap_id(gift_wrap_id)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gift_unwrapper_rejects_low_pow() {
+        let reportinator_keys = Keys::generate();
+        let sender_keys = Keys::generate();
+
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            sender_keys.public_key(),
+            None,
+        );
+        let gift_wrapped_event = report_request
+            .as_gift_wrap(&sender_keys, &reportinator_keys.public_key())
+            .await
+            .unwrap();
+
+        let messages_received = Arc::new(Mutex::new(Vec::<ReportRequest>::new()));
+        let (receiver_actor_ref, receiver_actor_handle) =
+            Actor::spawn(None, TestActor::default(), Some(messages_received.clone()))
+                .await
+                .unwrap();
+
+        // A random id having 64 leading zero bits is as good as impossible
+        let (parser_actor_ref, parser_handle) = Actor::spawn(
+            None,
+            GiftUnwrapper,
+            (
+                reportinator_keys,
+                config(64),
+                SeenIds::default(),
+                LanguageDetector::default(),
+                None,
+            ),
+        )
+        .await
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(receiver_actor_ref.clone()))
+        )
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::UnwrapEvent(Some(gift_wrapped_event))
+        )
+        .unwrap();
+
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(1)).await;
+            parser_actor_ref.stop(None);
+            receiver_actor_ref.stop(None);
+        });
+
+        parser_handle.await.unwrap();
+        receiver_actor_handle.await.unwrap();
+
+        assert!(messages_received.lock().await.is_empty());
     }
 
     #[tokio::test]
@@ -189,6 +756,7 @@ mod tests {
             .as_gift_wrap(&sender_keys, &receiver_pubkey)
             .await
             .unwrap();
+        let gift_wrap_id = gift_wrapped_event.id();
 
         let messages_received = Arc::new(Mutex::new(Vec::<ReportRequest>::new()));
         let (receiver_actor_ref, receiver_actor_handle) =
@@ -196,10 +764,19 @@ mod tests {
                 .await
                 .unwrap();
 
-        let (parser_actor_ref, parser_handle) =
-            Actor::spawn(None, GiftUnwrapper, reportinator_keys)
-                .await
-                .unwrap();
+        let (parser_actor_ref, parser_handle) = Actor::spawn(
+            None,
+            GiftUnwrapper,
+            (
+                reportinator_keys,
+                config(0),
+                SeenIds::default(),
+                LanguageDetector::default(),
+                None,
+            ),
+        )
+        .await
+        .unwrap();
 
         cast!(
             parser_actor_ref,
@@ -225,6 +802,530 @@ mod tests {
         parser_handle.await.unwrap();
         receiver_actor_handle.await.unwrap();
 
-        assert_eq!(messages_received.lock().await.as_ref(), [report_request]);
+        assert_eq!(
+            messages_received.lock().await.as_ref(),
+            [report_request.with_gift_wrap_id(gift_wrap_id)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gift_unwrapper_accepts_previous_keys_after_rotation() {
+        let previous_keys = Keys::generate();
+        let rotated_keys = Keys::generate();
+        let sender_keys = Keys::generate();
+
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            sender_keys.public_key(),
+            None,
+        );
+        let in_flight_gift_wrap = report_request
+            .as_gift_wrap(&sender_keys, &previous_keys.public_key())
+            .await
+            .unwrap();
+        let new_gift_wrap = report_request
+            .as_gift_wrap(&sender_keys, &rotated_keys.public_key())
+            .await
+            .unwrap();
+        let in_flight_gift_wrap_id = in_flight_gift_wrap.id();
+        let new_gift_wrap_id = new_gift_wrap.id();
+
+        let messages_received = Arc::new(Mutex::new(Vec::<ReportRequest>::new()));
+        let (receiver_actor_ref, receiver_actor_handle) =
+            Actor::spawn(None, TestActor::default(), Some(messages_received.clone()))
+                .await
+                .unwrap();
+
+        let (parser_actor_ref, parser_handle) = Actor::spawn(
+            None,
+            GiftUnwrapper,
+            (
+                previous_keys,
+                config(0),
+                SeenIds::default(),
+                LanguageDetector::default(),
+                None,
+            ),
+        )
+        .await
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(receiver_actor_ref.clone()))
+        )
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::RotateKeys(rotated_keys)
+        )
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::UnwrapEvent(Some(in_flight_gift_wrap))
+        )
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::UnwrapEvent(Some(new_gift_wrap))
+        )
+        .unwrap();
+
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(1)).await;
+            parser_actor_ref.stop(None);
+            receiver_actor_ref.stop(None);
+        });
+
+        parser_handle.await.unwrap();
+        receiver_actor_handle.await.unwrap();
+
+        assert_eq!(
+            messages_received.lock().await.as_ref(),
+            [
+                report_request
+                    .clone()
+                    .with_gift_wrap_id(in_flight_gift_wrap_id),
+                report_request.with_gift_wrap_id(new_gift_wrap_id)
+            ]
+        );
+    }
+
+    async fn unwrap_self_report(self_reports: SelfReportPolicy) -> Vec<ReportRequest> {
+        let reportinator_keys = Keys::generate();
+        let sender_keys = Keys::generate();
+
+        let report_request = ReportRequest::new(
+            sender_keys.public_key().into(),
+            sender_keys.public_key(),
+            None,
+        );
+        let gift_wrapped_event = report_request
+            .as_gift_wrap(&sender_keys, &reportinator_keys.public_key())
+            .await
+            .unwrap();
+
+        let messages_received = Arc::new(Mutex::new(Vec::<ReportRequest>::new()));
+        let (receiver_actor_ref, receiver_actor_handle) =
+            Actor::spawn(None, TestActor::default(), Some(messages_received.clone()))
+                .await
+                .unwrap();
+
+        let config = Config {
+            self_reports,
+            ..config(0)
+        };
+        let (parser_actor_ref, parser_handle) = Actor::spawn(
+            None,
+            GiftUnwrapper,
+            (
+                reportinator_keys,
+                config,
+                SeenIds::default(),
+                LanguageDetector::default(),
+                None,
+            ),
+        )
+        .await
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(receiver_actor_ref.clone()))
+        )
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::UnwrapEvent(Some(gift_wrapped_event))
+        )
+        .unwrap();
+
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(1)).await;
+            parser_actor_ref.stop(None);
+            receiver_actor_ref.stop(None);
+        });
+
+        parser_handle.await.unwrap();
+        receiver_actor_handle.await.unwrap();
+
+        let messages_received = messages_received.lock().await;
+        messages_received.clone()
+    }
+
+    #[tokio::test]
+    async fn test_self_reports_are_dropped_or_flagged() {
+        assert!(unwrap_self_report(SelfReportPolicy::Drop).await.is_empty());
+
+        let flagged = unwrap_self_report(SelfReportPolicy::Flag).await;
+        assert_eq!(flagged.len(), 1);
+        assert!(flagged[0].is_self_report());
+    }
+
+    // Answers GetNip05 like the supervisor, only for the verified reporters
+    struct Nip05Resolver;
+
+    #[ractor::async_trait]
+    impl Actor for Nip05Resolver {
+        type Msg = SupervisorMessage;
+        type State = Vec<PublicKey>;
+        type Arguments = Vec<PublicKey>;
+
+        async fn pre_start(
+            &self,
+            _: ActorRef<Self::Msg>,
+            verified_reporters: Self::Arguments,
+        ) -> Result<Self::State, ActorProcessingErr> {
+            Ok(verified_reporters)
+        }
+
+        async fn handle(
+            &self,
+            _: ActorRef<Self::Msg>,
+            message: Self::Msg,
+            verified_reporters: &mut Self::State,
+        ) -> Result<(), ActorProcessingErr> {
+            if let SupervisorMessage::GetNip05(public_key, reply_port) = message {
+                let nip05 = verified_reporters
+                    .contains(&public_key)
+                    .then(|| "reporter@nos.social".to_string());
+                let _ = reply_port.send(Ok(nip05));
+            }
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requires_a_verified_reporter_nip05() {
+        let reportinator_keys = Keys::generate();
+        let verified_keys = Keys::generate();
+        let unverified_keys = Keys::generate();
+
+        let mut gift_wraps = Vec::new();
+        let mut verified_report_request = None;
+        for reporter_keys in [&verified_keys, &unverified_keys] {
+            let report_request = ReportRequest::new(
+                Keys::generate().public_key().into(),
+                reporter_keys.public_key(),
+                None,
+            );
+            let gift_wrap = report_request
+                .as_gift_wrap(reporter_keys, &reportinator_keys.public_key())
+                .await
+                .unwrap();
+            if reporter_keys.public_key() == verified_keys.public_key() {
+                verified_report_request = Some(report_request.with_gift_wrap_id(gift_wrap.id()));
+            }
+            gift_wraps.push(gift_wrap);
+        }
+
+        let messages_received = Arc::new(Mutex::new(Vec::<ReportRequest>::new()));
+        let (receiver_actor_ref, receiver_actor_handle) =
+            Actor::spawn(None, TestActor::default(), Some(messages_received.clone()))
+                .await
+                .unwrap();
+        let (resolver_ref, resolver_handle) =
+            Actor::spawn(None, Nip05Resolver, vec![verified_keys.public_key()])
+                .await
+                .unwrap();
+
+        let config = Config {
+            require_reporter_nip05: true,
+            ..config(0)
+        };
+        let (parser_actor_ref, parser_handle) = Actor::spawn(
+            None,
+            GiftUnwrapper,
+            (
+                reportinator_keys,
+                config,
+                SeenIds::default(),
+                LanguageDetector::default(),
+                Some(resolver_ref.clone()),
+            ),
+        )
+        .await
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(receiver_actor_ref.clone()))
+        )
+        .unwrap();
+
+        for gift_wrap in gift_wraps {
+            cast!(
+                parser_actor_ref,
+                GiftUnwrapperMessage::UnwrapEvent(Some(gift_wrap))
+            )
+            .unwrap();
+        }
+
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(1)).await;
+            parser_actor_ref.stop(None);
+            receiver_actor_ref.stop(None);
+            resolver_ref.stop(None);
+        });
+
+        parser_handle.await.unwrap();
+        receiver_actor_handle.await.unwrap();
+        resolver_handle.await.unwrap();
+
+        assert_eq!(
+            messages_received.lock().await.as_ref(),
+            [verified_report_request.unwrap()]
+        );
+    }
+
+    // Answers FindEvent like the supervisor, only for the published events
+    struct EventLookup;
+
+    #[ractor::async_trait]
+    impl Actor for EventLookup {
+        type Msg = SupervisorMessage;
+        type State = Vec<Event>;
+        type Arguments = Vec<Event>;
+
+        async fn pre_start(
+            &self,
+            _: ActorRef<Self::Msg>,
+            published_events: Self::Arguments,
+        ) -> Result<Self::State, ActorProcessingErr> {
+            Ok(published_events)
+        }
+
+        async fn handle(
+            &self,
+            _: ActorRef<Self::Msg>,
+            message: Self::Msg,
+            published_events: &mut Self::State,
+        ) -> Result<(), ActorProcessingErr> {
+            if let SupervisorMessage::FindEvent(event_id, reply_port) = message {
+                let _ = reply_port.send(Ok(published_events
+                    .iter()
+                    .find(|event| event.id == event_id)
+                    .cloned()));
+            }
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requires_the_reported_event_on_our_relays() {
+        let reportinator_keys = Keys::generate();
+        let reporter_keys = Keys::generate();
+        let published_event = EventBuilder::text_note("Published", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let unpublished_event = EventBuilder::text_note("Never published", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        let mut gift_wraps = Vec::new();
+        let mut expected_report_requests = Vec::new();
+        let targets: [ReportTarget; 3] = [
+            published_event.clone().into(),
+            unpublished_event.into(),
+            // Nothing to look up for pubkey reports
+            Keys::generate().public_key().into(),
+        ];
+        for (i, target) in targets.into_iter().enumerate() {
+            let report_request = ReportRequest::new(target, reporter_keys.public_key(), None);
+            let gift_wrap = report_request
+                .as_gift_wrap(&reporter_keys, &reportinator_keys.public_key())
+                .await
+                .unwrap();
+            if i != 1 {
+                expected_report_requests.push(report_request.with_gift_wrap_id(gift_wrap.id()));
+            }
+            gift_wraps.push(gift_wrap);
+        }
+
+        let messages_received = Arc::new(Mutex::new(Vec::<ReportRequest>::new()));
+        let (receiver_actor_ref, receiver_actor_handle) =
+            Actor::spawn(None, TestActor::default(), Some(messages_received.clone()))
+                .await
+                .unwrap();
+        let (lookup_ref, lookup_handle) =
+            Actor::spawn(None, EventLookup, vec![published_event.clone()])
+                .await
+                .unwrap();
+
+        let config = Config {
+            require_published_event: true,
+            ..config(0)
+        };
+        let (parser_actor_ref, parser_handle) = Actor::spawn(
+            None,
+            GiftUnwrapper,
+            (
+                reportinator_keys,
+                config,
+                SeenIds::default(),
+                LanguageDetector::default(),
+                Some(lookup_ref.clone()),
+            ),
+        )
+        .await
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(receiver_actor_ref.clone()))
+        )
+        .unwrap();
+
+        for gift_wrap in gift_wraps {
+            cast!(
+                parser_actor_ref,
+                GiftUnwrapperMessage::UnwrapEvent(Some(gift_wrap))
+            )
+            .unwrap();
+        }
+
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(1)).await;
+            parser_actor_ref.stop(None);
+            receiver_actor_ref.stop(None);
+            lookup_ref.stop(None);
+        });
+
+        parser_handle.await.unwrap();
+        receiver_actor_handle.await.unwrap();
+        lookup_handle.await.unwrap();
+
+        assert_eq!(
+            messages_received.lock().await.as_ref(),
+            expected_report_requests
+        );
+    }
+
+    // Fails the first lookup like an unreachable relay, finds the event after
+    struct FlakyEventLookup;
+
+    #[ractor::async_trait]
+    impl Actor for FlakyEventLookup {
+        type Msg = SupervisorMessage;
+        type State = (Event, bool);
+        type Arguments = Event;
+
+        async fn pre_start(
+            &self,
+            _: ActorRef<Self::Msg>,
+            published_event: Self::Arguments,
+        ) -> Result<Self::State, ActorProcessingErr> {
+            Ok((published_event, false))
+        }
+
+        async fn handle(
+            &self,
+            _: ActorRef<Self::Msg>,
+            message: Self::Msg,
+            (published_event, failed): &mut Self::State,
+        ) -> Result<(), ActorProcessingErr> {
+            if let SupervisorMessage::FindEvent(_, reply_port) = message {
+                let found = match failed {
+                    true => Ok(Some(published_event.clone())),
+                    false => Err("relay unreachable".to_string()),
+                };
+                *failed = true;
+                let _ = reply_port.send(found);
+            }
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_lookups_are_checked_again() {
+        let reportinator_keys = Keys::generate();
+        let reporter_keys = Keys::generate();
+        let published_event = EventBuilder::text_note("Published", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let report_request = ReportRequest::new(
+            published_event.clone().into(),
+            reporter_keys.public_key(),
+            None,
+        );
+        let gift_wrap = report_request
+            .as_gift_wrap(&reporter_keys, &reportinator_keys.public_key())
+            .await
+            .unwrap();
+        let expected_report_request = report_request.with_gift_wrap_id(gift_wrap.id());
+
+        let messages_received = Arc::new(Mutex::new(Vec::<ReportRequest>::new()));
+        let (receiver_actor_ref, receiver_actor_handle) =
+            Actor::spawn(None, TestActor::default(), Some(messages_received.clone()))
+                .await
+                .unwrap();
+        let (lookup_ref, lookup_handle) = Actor::spawn(None, FlakyEventLookup, published_event)
+            .await
+            .unwrap();
+
+        let config = Config {
+            require_published_event: true,
+            ..config(0)
+        };
+        let (parser_actor_ref, parser_handle) = Actor::spawn(
+            None,
+            GiftUnwrapper,
+            (
+                reportinator_keys,
+                config,
+                SeenIds::default(),
+                LanguageDetector::default(),
+                Some(lookup_ref.clone()),
+            ),
+        )
+        .await
+        .unwrap();
+
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(receiver_actor_ref.clone()))
+        )
+        .unwrap();
+
+        // The first lookup fails, so the gift wrap isn't remembered as seen
+        cast!(
+            parser_actor_ref,
+            GiftUnwrapperMessage::UnwrapEvent(Some(gift_wrap.clone()))
+        )
+        .unwrap();
+        sleep(Duration::from_millis(200)).await;
+        assert!(messages_received.lock().await.is_empty());
+
+        // A copy coming while the gift wrap is looked up again is dropped
+        for _ in 0..2 {
+            cast!(
+                parser_actor_ref,
+                GiftUnwrapperMessage::UnwrapEvent(Some(gift_wrap.clone()))
+            )
+            .unwrap();
+        }
+
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(1)).await;
+            parser_actor_ref.stop(None);
+            receiver_actor_ref.stop(None);
+            lookup_ref.stop(None);
+        });
+
+        parser_handle.await.unwrap();
+        receiver_actor_handle.await.unwrap();
+        lookup_handle.await.unwrap();
+
+        assert_eq!(
+            messages_received.lock().await.as_ref(),
+            [expected_report_request]
+        );
     }
 }
@@ -1,42 +1,122 @@
+use crate::actors::gift_unwrapper::LookupOutcome;
+use crate::actors::supervisor::AckTrigger;
+use crate::actors::utilities::mailbox::{self, Counted};
+use crate::actors::utilities::output_port_monitor;
+use crate::actors::utilities::report_funnel::{self, FunnelStep};
+use crate::actors::utilities::resolved_reports::Resolution;
+use crate::actors::utilities::self_test::DependencyCheck;
 use crate::domain_objects::*;
 use metrics::counter;
 use nostr_sdk::prelude::*;
-use ractor::{port::OutputPortSubscriber, RpcReplyPort};
+use ractor::{port::OutputPortSubscriber, ActorRef, RpcReplyPort};
 use std::fmt::Debug;
 use tracing::error;
 
 pub enum SupervisorMessage {
     Publish(ModeratedReport),
-    GetNip05(PublicKey, RpcReplyPort<Option<String>>),
+    // Replies with the verified NIP-05, None if there's none, an error if the
+    // lookup failed
+    GetNip05(PublicKey, RpcReplyPort<Result<Option<String>, String>>),
+    // Replies with the event if it's found on one of our relays, an error if
+    // the relays couldn't be asked
+    FindEvent(EventId, RpcReplyPort<Result<Option<Event>, String>>),
+    // Replies with an error message if the rotation couldn't be started
+    RotateKeys(Keys, RpcReplyPort<Result<(), String>>),
+    // Sent to itself once the grace period of a rotated key is over
+    ExpirePreviousKey(PublicKey),
+    // Sends the acknowledgment DM to the reporter if configured for the trigger
+    Acknowledge(ReportRequest, AckTrigger),
+    // Publishes a NIP-09 deletion for a report we published
+    Retract(EventId),
+    // Since, until and limit of a backfill of missed gift wraps
+    Backfill(Timestamp, Timestamp, usize),
+    // Replies false if the relay was already there
+    AddRelay(Url, RpcReplyPort<Result<bool, String>>),
+    // Replies false if the relay wasn't one of ours
+    RemoveRelay(Url, RpcReplyPort<Result<bool, String>>),
+    // Checks the relays, Pub/Sub and every Slack workspace concurrently
+    SelfTest(RpcReplyPort<Vec<DependencyCheck>>),
+    // Replies with the earlier resolution if the report request was already
+    // resolved, the new one is only recorded otherwise
+    Resolve(ReportRequest, Resolution, RpcReplyPort<Option<Resolution>>),
+    // Forgets the resolution of a report request whose action failed, if it's
+    // still this one
+    Unresolve(ReportRequest, Resolution),
+    // Replies true if none of the report requests of an expired Slack message
+    // was resolved, they then get the resolution if there's one
+    ExpireUnactioned(Vec<ReportRequest>, Option<Resolution>, RpcReplyPort<bool>),
+    // Sends the dead letters back through the dispatcher and the enqueuer
+    ReplayDeadLetters,
 }
 
 pub enum RelayEventDispatcherMessage {
     Connect,
     Reconnect,
+    SetReportinatorPublicKeys(Vec<PublicKey>),
     SubscribeToEventReceived(OutputPortSubscriber<Event>),
     EventReceived(Event),
     Publish(ModeratedReport),
-    GetNip05(PublicKey, RpcReplyPort<Option<String>>),
+    // Sent back once a report, published off the actor, was accepted or not
+    Published(ModeratedReport, bool),
+    // A gift wrapped DM and its receiver
+    PublishDirectMessage(Event, PublicKey),
+    // Looked up in the background so dispatching isn't held up
+    GetNip05(PublicKey, RpcReplyPort<Result<Option<String>, String>>),
+    // Looked up in the background so dispatching isn't held up
+    FindEvent(EventId, RpcReplyPort<Result<Option<Event>, String>>),
+    Retract(EventId),
+    // Fetches gift wraps created between since and until, at most limit of
+    // them, and dispatches them as if just received
+    Backfill(Timestamp, Timestamp, usize),
+    // Relay set changes leave the subscriptions on the other relays alone
+    AddRelay(Url, RpcReplyPort<Result<bool, String>>),
+    RemoveRelay(Url, RpcReplyPort<Result<bool, String>>),
+    // Fails if none of the relays is connected
+    SelfTest(RpcReplyPort<DependencyCheck>),
+}
+
+impl Counted for RelayEventDispatcherMessage {
+    fn is_counted(&self) -> bool {
+        matches!(
+            self,
+            RelayEventDispatcherMessage::Publish(_) | RelayEventDispatcherMessage::Published(..)
+        )
+    }
 }
 
 pub enum GiftUnwrapperMessage {
     // If an event couldn't be mapped to a GiftWrappedReportRequest, it will be None
     UnwrapEvent(Option<GiftWrappedReportRequest>),
     SubscribeToEventUnwrapped(OutputPortSubscriber<ReportRequest>),
+    // The previous keys keep decrypting for the configured grace period
+    RotateKeys(Keys),
+    // Sent back once the lookups of a report request, done off the actor,
+    // are over. Has the id of its gift wrap
+    LookedUp(EventId, ReportRequest, LookupOutcome),
+}
+
+impl Counted for GiftUnwrapperMessage {
+    fn is_counted(&self) -> bool {
+        matches!(self, GiftUnwrapperMessage::UnwrapEvent(_))
+    }
 }
 
 // How to subscribe to actors that publish DM messages like RelayEventDispatcher
 impl From<Event> for GiftUnwrapperMessage {
     fn from(event: Event) -> Self {
+        report_funnel::record(FunnelStep::Received, report_funnel::UNKNOWN_TARGET);
         let gift_wrapped_report_request = match GiftWrappedReportRequest::try_from(event) {
             Ok(gift) => Some(gift),
             Err(e) => {
                 counter!("event_received_error").increment(1);
-                error!("Failed to get gift wrap event: {}", e);
+                counter!("gift_wrap_error", "reason" => e.reason()).increment(1);
+                error!(reason = e.reason(), "Failed to get gift wrap event: {}", e);
                 None
             }
         };
 
+        output_port_monitor::delivered::<Event>();
+        mailbox::message_sent::<GiftUnwrapperMessage>();
         GiftUnwrapperMessage::UnwrapEvent(gift_wrapped_report_request)
     }
 }
@@ -44,23 +124,118 @@ impl From<Event> for GiftUnwrapperMessage {
 #[derive(Debug)]
 pub enum EventEnqueuerMessage {
     Enqueue(ReportRequest),
+    // Sent back by the retry actor with the number of the attempt
+    Retry(ReportRequest, u32),
+    // Sent by the retry actor once it moved the report request to the dead
+    // letter queue
+    GaveUp(ReportRequest),
+    SelfTest(RpcReplyPort<DependencyCheck>),
+}
+
+impl Counted for EventEnqueuerMessage {
+    fn is_counted(&self) -> bool {
+        matches!(self, EventEnqueuerMessage::Enqueue(_))
+    }
 }
 
 // How to subscribe to actors that publish EventToReport messages like GiftUnwrapper
 impl From<ReportRequest> for EventEnqueuerMessage {
     fn from(report_request: ReportRequest) -> Self {
+        mailbox::message_sent::<EventEnqueuerMessage>();
         EventEnqueuerMessage::Enqueue(report_request)
     }
 }
 
 #[derive(Debug)]
-pub enum SlackWriterMessage {
+pub enum NotificationWriterMessage {
     Write(ReportRequest),
+    // Flushes the batch of report requests pending for the target, unless
+    // that batch was already flushed and a later one started
+    Flush(PublicKey, u64),
+    // Writes the report requests deferred by quiet hours, if they are over
+    QuietHoursEnded,
+    // Sent back by the retry actor with the workspace that failed and the
+    // number of the attempt
+    Retry(ReportRequest, String, u32),
+    // Report requests from different reporters on the same content, written
+    // as a single message even for event targets
+    Escalate(Vec<ReportRequest>),
+}
+
+impl Counted for NotificationWriterMessage {
+    fn is_counted(&self) -> bool {
+        matches!(self, NotificationWriterMessage::Write(_))
+    }
+}
+
+impl From<ReportRequest> for NotificationWriterMessage {
+    fn from(report_request: ReportRequest) -> Self {
+        mailbox::message_sent::<NotificationWriterMessage>();
+        NotificationWriterMessage::Write(report_request)
+    }
+}
+
+#[derive(Debug)]
+pub enum ContentTrackerMessage {
+    Track(ReportRequest),
+    // Forgets the reports that are out of the window
+    Prune,
 }
 
-impl From<ReportRequest> for SlackWriterMessage {
+impl Counted for ContentTrackerMessage {
+    fn is_counted(&self) -> bool {
+        matches!(self, ContentTrackerMessage::Track(_))
+    }
+}
+
+impl From<ReportRequest> for ContentTrackerMessage {
     fn from(report_request: ReportRequest) -> Self {
-        SlackWriterMessage::Write(report_request)
+        mailbox::message_sent::<ContentTrackerMessage>();
+        ContentTrackerMessage::Track(report_request)
+    }
+}
+
+#[derive(Debug)]
+pub enum RelayHealthMessage {
+    // Sent on an interval, reconnects if too few relays are connected
+    Check,
+}
+
+// Only sent on an interval, so there's never more than one waiting
+impl Counted for RelayHealthMessage {
+    fn is_counted(&self) -> bool {
+        false
+    }
+}
+
+// Where a failed report request goes back to once its backoff is over
+#[derive(Clone)]
+pub enum RetryDestination {
+    EventEnqueuer(ActorRef<EventEnqueuerMessage>),
+    // The notification writer and the sink that failed
+    NotificationWriter(ActorRef<NotificationWriterMessage>, String),
+}
+
+impl RetryDestination {
+    /// Short label used for metrics and logs
+    pub fn name(&self) -> &'static str {
+        match self {
+            RetryDestination::EventEnqueuer(_) => "event_enqueuer",
+            RetryDestination::NotificationWriter(..) => "notification_writer",
+        }
+    }
+}
+
+pub enum RetryMessage {
+    // A report request that failed on the given attempt, the first one is 1
+    Failed(ReportRequest, RetryDestination, u32),
+    // Sent to itself once the backoff is over
+    Redispatch(ReportRequest, RetryDestination, u32),
+}
+
+impl Counted for RetryMessage {
+    fn is_counted(&self) -> bool {
+        matches!(self, RetryMessage::Failed(..))
     }
 }
 
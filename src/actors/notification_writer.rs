@@ -0,0 +1,1464 @@
+/// This module contains the NotificationWriter actor, which is responsible for
+/// telling moderators about report requests, through Slack unless another
+/// backend is configured
+use super::messages::SupervisorMessage;
+use crate::actors::messages::{NotificationWriterMessage, RetryDestination, RetryMessage};
+use crate::actors::utilities::auto_skip::AutoSkipRules;
+use crate::actors::utilities::downstream_limit::limited;
+use crate::actors::utilities::quiet_hours::QuietHours;
+use crate::actors::utilities::report_funnel::{self, FunnelStep};
+use crate::actors::utilities::self_test::DependencyCheck;
+use crate::actors::utilities::{mailbox, pending_work};
+use crate::adapters::slack_client_adapter::WorkspaceConfig;
+use crate::config::Configurable;
+use crate::domain_objects::{report_request::is_sampled, ReportRequest, ReportTarget};
+use crate::service_manager::actor_name;
+use anyhow::{ensure, Context, Result};
+use futures::future::join_all;
+use metrics::counter;
+use nostr_sdk::prelude::{PublicKey, Timestamp};
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Seconds to wait for more report requests on the same target before
+    // writing them as a single message. Zero disables batching.
+    #[serde(default)]
+    pub batch_window_secs: u64,
+    // A batch reaching this size is written right away without waiting for
+    // the window to end.
+    #[serde(default = "default_batch_max_size")]
+    pub batch_max_size: usize,
+    // Seconds after a message about a target during which further report
+    // requests on it are only counted, the count is shown on the next
+    // message. Zero disables the cooldown.
+    #[serde(default)]
+    pub cooldown_secs: u64,
+}
+
+fn default_batch_max_size() -> usize {
+    20
+}
+
+// Same for every backend, so not in the slack section
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "notifications"
+    }
+}
+
+impl Config {
+    fn cooldown(&self) -> Option<Duration> {
+        (self.cooldown_secs > 0).then(|| Duration::from_secs(self.cooldown_secs))
+    }
+}
+
+// When each target was last written to Slack, and the report requests on it
+// held back since
+#[derive(Default)]
+struct Cooldowns {
+    targets: HashMap<PublicKey, (Instant, Suppressed)>,
+}
+
+// Report requests held back by a cooldown, written along the next message on
+// their target. Past max_kept they are only counted
+#[derive(Debug, Default, PartialEq)]
+struct Suppressed {
+    report_requests: Vec<ReportRequest>,
+    count: usize,
+}
+
+impl Suppressed {
+    fn hold(&mut self, report_request: &ReportRequest, max_kept: usize) {
+        if self.report_requests.len() < max_kept {
+            self.report_requests.push(report_request.clone());
+        }
+        self.count += 1;
+    }
+}
+
+impl Cooldowns {
+    // Holds the report request back if its target is still on cooldown
+    fn suppresses(
+        &mut self,
+        report_request: &ReportRequest,
+        cooldown: Duration,
+        max_kept: usize,
+        now: Instant,
+    ) -> bool {
+        let target_pubkey = report_request.target().pubkey();
+        let Some((notified_at, suppressed)) = self.targets.get_mut(&target_pubkey) else {
+            return false;
+        };
+        if now.duration_since(*notified_at) >= cooldown {
+            return false;
+        }
+
+        suppressed.hold(report_request, max_kept);
+        true
+    }
+
+    // Starts the cooldown of the target, returns the report requests
+    // suppressed since its previous message
+    fn notified(
+        &mut self,
+        target_pubkey: PublicKey,
+        cooldown: Duration,
+        now: Instant,
+    ) -> Suppressed {
+        // Targets with held requests wait for their next message to show them
+        self.targets.retain(|_, (notified_at, suppressed)| {
+            suppressed.count > 0 || now.duration_since(*notified_at) < cooldown
+        });
+
+        self.targets
+            .insert(target_pubkey, (now, Suppressed::default()))
+            .map(|(_, suppressed)| suppressed)
+            .unwrap_or_default()
+    }
+
+    // The report requests held back on the target, leaving its cooldown as it
+    // was
+    fn take(&mut self, target_pubkey: &PublicKey) -> Suppressed {
+        self.targets
+            .get_mut(target_pubkey)
+            .map(|(_, suppressed)| std::mem::take(suppressed))
+            .unwrap_or_default()
+    }
+
+    // Everything still held back, on shutdown
+    fn drain(&mut self) -> Vec<Suppressed> {
+        self.targets
+            .drain()
+            .map(|(_, (_, suppressed))| suppressed)
+            .filter(|suppressed| suppressed.count > 0)
+            .collect()
+    }
+}
+
+// Report requests on a target waiting for the batch window to end. Each
+// batch gets a new generation so the timer of a batch flushed early, by
+// reaching the max size, doesn't cut the window of the next one short
+struct Batch {
+    generation: u64,
+    report_requests: Vec<ReportRequest>,
+}
+
+// What quiet hours hold back, replayed in the order it came in once they
+// are over
+enum Deferred {
+    Write(ReportRequest),
+    Escalate(Vec<ReportRequest>),
+    Retry(ReportRequest, String, u32),
+}
+
+impl Deferred {
+    fn report_requests(&self) -> &[ReportRequest] {
+        match self {
+            Deferred::Write(report_request) | Deferred::Retry(report_request, ..) => {
+                std::slice::from_ref(report_request)
+            }
+            Deferred::Escalate(report_requests) => report_requests,
+        }
+    }
+}
+
+/// The counters of a kind of sink, labeled with the name of the sink
+#[derive(Debug, Clone, Copy)]
+pub struct SinkMetrics {
+    label: &'static str,
+    written: &'static str,
+    failed: &'static str,
+    coalesced: &'static str,
+}
+
+pub const SLACK_METRICS: SinkMetrics = SinkMetrics {
+    label: "workspace",
+    written: "slack_write_message",
+    failed: "slack_write_message_error",
+    coalesced: "slack_coalesced_report_requests",
+};
+
+pub const WEBHOOK_METRICS: SinkMetrics = SinkMetrics {
+    label: "sink",
+    written: "webhook_post",
+    failed: "webhook_post_error",
+    coalesced: "webhook_coalesced_report_requests",
+};
+
+/// Where report requests are sent, like a Slack workspace, optionally only
+/// the ones sent by some reporters. A mirror only gets copies for a sample of
+/// the targets and doesn't count towards the report funnel or get retries.
+pub struct NotificationSink<T: NotificationPort> {
+    name: String,
+    // How the self test calls it
+    dependency: String,
+    metrics: SinkMetrics,
+    reporters: Vec<PublicKey>,
+    sample_rate: Option<f64>,
+    // Shared with the self test, which doesn't go through the writer
+    client: Arc<T>,
+}
+
+impl<T: NotificationPort> NotificationSink<T> {
+    /// Gets every report request
+    pub fn new(name: &str, metrics: SinkMetrics, client: T) -> Self {
+        Self {
+            name: name.to_string(),
+            dependency: name.to_string(),
+            metrics,
+            reporters: vec![],
+            sample_rate: None,
+            client: Arc::new(client),
+        }
+    }
+
+    pub fn for_workspace(config: &WorkspaceConfig, client: T) -> Result<Self> {
+        let reporters = config
+            .reporters
+            .iter()
+            .map(|reporter| {
+                PublicKey::from_str(reporter).with_context(|| {
+                    format!(
+                        "Invalid reporter {} for workspace {}",
+                        reporter, config.name
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(sample_rate) = config.sample_rate {
+            ensure!(
+                (0.0..=1.0).contains(&sample_rate),
+                "Sample rate {} for workspace {} is not between 0 and 1",
+                sample_rate,
+                config.name
+            );
+        }
+
+        Ok(Self {
+            name: config.name.clone(),
+            dependency: format!("slack:{}", config.name),
+            metrics: SLACK_METRICS,
+            reporters,
+            sample_rate: config.sample_rate,
+            client: Arc::new(client),
+        })
+    }
+
+    /// The self test of every sink, run concurrently outside of the writer
+    /// so it doesn't wait behind the messages being written
+    pub fn checks(sinks: &[Self]) -> SinkChecks<T> {
+        SinkChecks {
+            sinks: sinks
+                .iter()
+                .map(|sink| (sink.dependency.clone(), sink.client.clone()))
+                .collect(),
+        }
+    }
+
+    fn is_mirror(&self) -> bool {
+        self.sample_rate.is_some()
+    }
+
+    fn accepts(&self, report_request: &ReportRequest) -> bool {
+        let accepts_reporter =
+            self.reporters.is_empty() || self.reporters.contains(report_request.reporter_pubkey());
+
+        match self.sample_rate {
+            Some(sample_rate) => {
+                accepts_reporter && is_sampled(&report_request.target().pubkey(), sample_rate)
+            }
+            None => accepts_reporter,
+        }
+    }
+}
+
+pub struct SinkChecks<T: NotificationPort> {
+    sinks: Vec<(String, Arc<T>)>,
+}
+
+impl<T: NotificationPort> Clone for SinkChecks<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sinks: self.sinks.clone(),
+        }
+    }
+}
+
+impl<T: NotificationPort> SinkChecks<T> {
+    /// One check per sink, in the order of the sinks
+    pub async fn run(&self) -> Vec<DependencyCheck> {
+        join_all(self.sinks.iter().map(|(dependency, client)| {
+            DependencyCheck::run(dependency.clone(), client.check_connectivity())
+        }))
+        .await
+    }
+}
+
+pub struct NotificationWriter<T: NotificationPort> {
+    _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T: NotificationPort> Default for NotificationWriter<T> {
+    fn default() -> Self {
+        Self {
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+pub struct State<T: NotificationPort> {
+    sinks: Vec<NotificationSink<T>>,
+    config: Config,
+    pending_batches: HashMap<PublicKey, Batch>,
+    batch_generation: u64,
+    retry_actor: Option<ActorRef<RetryMessage>>,
+    auto_skip_rules: AutoSkipRules,
+    quiet_hours: QuietHours,
+    // Held back until quiet hours are over
+    deferred: Vec<Deferred>,
+    cooldowns: Cooldowns,
+}
+
+impl<T> NotificationWriter<T>
+where
+    T: NotificationPort,
+{
+    async fn flush(
+        &self,
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &mut State<T>,
+        target_pubkey: &PublicKey,
+    ) {
+        let Some(Batch {
+            report_requests, ..
+        }) = state.pending_batches.remove(target_pubkey)
+        else {
+            return;
+        };
+        pending_work::unbatched(report_requests.len());
+
+        let suppressed = Self::start_cooldown(state, *target_pubkey);
+        Self::write_with_suppressed(myself, state, report_requests, suppressed).await;
+    }
+
+    // Returns the report requests suppressed since the previous message on
+    // the target
+    fn start_cooldown(state: &mut State<T>, target_pubkey: PublicKey) -> Suppressed {
+        let Some(cooldown) = state.config.cooldown() else {
+            return Suppressed::default();
+        };
+
+        state
+            .cooldowns
+            .notified(target_pubkey, cooldown, Instant::now())
+    }
+
+    // The report requests held back by the cooldown go first, in the order
+    // they came in
+    async fn write_with_suppressed(
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &State<T>,
+        report_requests: Vec<ReportRequest>,
+        suppressed: Suppressed,
+    ) {
+        let Suppressed {
+            report_requests: mut all_requests,
+            count,
+        } = suppressed;
+        all_requests.extend(report_requests);
+
+        Self::write(myself, state, &all_requests, count).await;
+    }
+
+    // Batches the report request, or writes it right away when batching is
+    // disabled
+    async fn enqueue(
+        &self,
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &mut State<T>,
+        report_request: ReportRequest,
+    ) {
+        let target_pubkey = report_request.target().pubkey();
+        if let Some(cooldown) = state.config.cooldown() {
+            if state.cooldowns.suppresses(
+                &report_request,
+                cooldown,
+                state.config.batch_max_size,
+                Instant::now(),
+            ) {
+                counter!("slack_suppressed").increment(1);
+                info!(
+                    "Suppressing report request {}, its target is on cooldown",
+                    report_request.target()
+                );
+                return;
+            }
+        }
+
+        if state.config.batch_window_secs == 0 {
+            let suppressed = Self::start_cooldown(state, target_pubkey);
+            Self::write_with_suppressed(myself, state, vec![report_request], suppressed).await;
+            return;
+        }
+
+        // The first request of a batch starts its window
+        let batch = state
+            .pending_batches
+            .entry(target_pubkey)
+            .or_insert_with(|| {
+                state.batch_generation += 1;
+                let generation = state.batch_generation;
+                myself.send_after(
+                    Duration::from_secs(state.config.batch_window_secs),
+                    move || NotificationWriterMessage::Flush(target_pubkey, generation),
+                );
+
+                Batch {
+                    generation,
+                    report_requests: Vec::new(),
+                }
+            });
+        batch.report_requests.push(report_request);
+        let batch_len = batch.report_requests.len();
+        pending_work::batched(1);
+
+        if batch_len >= state.config.batch_max_size {
+            self.flush(myself, state, &target_pubkey).await;
+        }
+    }
+
+    // Quiet hours hold back everything but urgent report requests, as long
+    // as there's room for them
+    fn defers(state: &State<T>, report_requests: &[ReportRequest], now: u64) -> bool {
+        if !state.quiet_hours.is_quiet(now)
+            || report_requests
+                .iter()
+                .any(|report_request| state.quiet_hours.is_urgent(report_request))
+        {
+            return false;
+        }
+
+        if state.deferred.len() >= state.quiet_hours.max_deferred() {
+            counter!("slack_deferred_overflow").increment(1);
+            warn!(
+                "{} report requests already deferred, writing the next one right away",
+                state.deferred.len()
+            );
+            return false;
+        }
+
+        true
+    }
+
+    // The first deferred request schedules the check for the end of the
+    // quiet hours. Schedules that never end are checked again every hour
+    fn defer(
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &mut State<T>,
+        deferred: Deferred,
+        now: u64,
+    ) {
+        let report_requests = deferred.report_requests();
+        counter!("slack_deferred").increment(report_requests.len() as u64);
+        info!(
+            "Deferring {} report requests until quiet hours are over",
+            report_requests.len()
+        );
+
+        pending_work::batched(report_requests.len());
+        state.deferred.push(deferred);
+        if state.deferred.len() == 1 {
+            Self::schedule_quiet_hours_end(myself, state, now);
+        }
+    }
+
+    async fn replay(
+        &self,
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &mut State<T>,
+        deferred: Vec<Deferred>,
+    ) {
+        for deferred in deferred {
+            pending_work::unbatched(deferred.report_requests().len());
+            match deferred {
+                Deferred::Write(report_request) => {
+                    self.enqueue(myself, state, report_request).await
+                }
+                Deferred::Escalate(report_requests) => {
+                    Self::escalate(myself, state, report_requests).await
+                }
+                Deferred::Retry(report_request, sink_name, attempt) => {
+                    Self::write_retry(myself, state, report_request, sink_name, attempt).await
+                }
+            }
+        }
+    }
+
+    // Already grouped by the content tracker, so never batched or
+    // suppressed. What the cooldown held back on the author comes after, the
+    // first request still picks the escalation template
+    async fn escalate(
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &mut State<T>,
+        mut report_requests: Vec<ReportRequest>,
+    ) {
+        let suppressed = match report_requests.first() {
+            Some(report_request) => state.cooldowns.take(&report_request.target().pubkey()),
+            None => Suppressed::default(),
+        };
+        report_requests.extend(suppressed.report_requests);
+        Self::write(myself, state, &report_requests, suppressed.count).await;
+    }
+
+    async fn write_retry(
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &State<T>,
+        report_request: ReportRequest,
+        sink_name: String,
+        attempt: u32,
+    ) {
+        let Some(sink) = state.sinks.iter().find(|sink| sink.name == sink_name) else {
+            warn!("Dropping retry for unknown sink {}", sink_name);
+            return;
+        };
+
+        if Self::write_to_sink(sink, std::slice::from_ref(&report_request), 0).await {
+            report_funnel::record(FunnelStep::Slacked, report_request.target().label());
+        } else {
+            Self::retry(myself, state, report_request, &sink_name, attempt);
+        }
+    }
+
+    fn schedule_quiet_hours_end(
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &State<T>,
+        now: u64,
+    ) {
+        let remaining = state
+            .quiet_hours
+            .remaining(now)
+            .unwrap_or(Duration::from_secs(3600));
+        myself.send_after(remaining, || NotificationWriterMessage::QuietHoursEnded);
+    }
+
+    // Fans out to every sink accepting each request. A request counts as
+    // slacked once any sink got it
+    async fn write(
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &State<T>,
+        report_requests: &[ReportRequest],
+        suppressed: usize,
+    ) {
+        if report_requests.is_empty() {
+            return;
+        }
+
+        let mut slacked = vec![false; report_requests.len()];
+        for sink in &state.sinks {
+            let (indexes, accepted_requests): (Vec<usize>, Vec<ReportRequest>) = report_requests
+                .iter()
+                .enumerate()
+                .filter(|(_, report_request)| sink.accepts(report_request))
+                .map(|(index, report_request)| (index, report_request.clone()))
+                .unzip();
+
+            if accepted_requests.is_empty() {
+                continue;
+            }
+
+            let written = Self::write_to_sink(sink, &accepted_requests, suppressed).await;
+            if sink.is_mirror() {
+                continue;
+            }
+
+            if written {
+                indexes.into_iter().for_each(|index| slacked[index] = true);
+            } else {
+                for report_request in accepted_requests {
+                    Self::retry(myself, state, report_request, &sink.name, 1);
+                }
+            }
+        }
+
+        // A batch on a pubkey may mix reports of it and of its events
+        report_requests
+            .iter()
+            .zip(slacked)
+            .filter(|(_, slacked)| *slacked)
+            .for_each(|(report_request, _)| {
+                report_funnel::record(FunnelStep::Slacked, report_request.target().label())
+            });
+    }
+
+    async fn write_to_sink(
+        sink: &NotificationSink<T>,
+        report_requests: &[ReportRequest],
+        suppressed: usize,
+    ) -> bool {
+        let result = match report_requests {
+            [] => return false,
+            [report_request] => {
+                info!(
+                    "Sending report request {} to {}",
+                    report_request.target(),
+                    sink.name
+                );
+                limited(sink.client.notify(report_request, suppressed)).await
+            }
+            _ => {
+                info!(
+                    "Sending {} report requests for {} to {}",
+                    report_requests.len(),
+                    report_requests[0].target(),
+                    sink.name
+                );
+                counter!(sink.metrics.coalesced, sink.metrics.label => sink.name.clone())
+                    .increment(report_requests.len() as u64);
+                limited(sink.client.notify_aggregated(report_requests, suppressed)).await
+            }
+        };
+
+        if let Err(e) = result {
+            counter!(sink.metrics.failed, sink.metrics.label => sink.name.clone()).increment(1);
+            error!("Failed to notify {}: {}", sink.name, e);
+            return false;
+        }
+
+        counter!(sink.metrics.written, sink.metrics.label => sink.name.clone()).increment(1);
+        true
+    }
+
+    // Failed batches are retried one report request at a time
+    fn retry(
+        myself: &ActorRef<NotificationWriterMessage>,
+        state: &State<T>,
+        report_request: ReportRequest,
+        sink_name: &str,
+        attempt: u32,
+    ) {
+        let Some(retry_actor) = &state.retry_actor else {
+            return;
+        };
+
+        let destination =
+            RetryDestination::NotificationWriter(myself.clone(), sink_name.to_string());
+        let failed = RetryMessage::Failed(report_request, destination, attempt);
+        if let Err(e) = mailbox::cast(retry_actor, failed) {
+            error!("Failed to send report request to the retry actor: {}", e);
+        }
+    }
+}
+
+#[ractor::async_trait]
+impl<T> Actor for NotificationWriter<T>
+where
+    T: NotificationPort + Send + Sync + Sized + 'static,
+{
+    type Msg = NotificationWriterMessage;
+    type State = State<T>;
+    type Arguments = (
+        Vec<NotificationSink<T>>,
+        Config,
+        Option<ActorRef<RetryMessage>>,
+        AutoSkipRules,
+        QuietHours,
+    );
+
+    async fn pre_start(
+        &self,
+        _: ActorRef<Self::Msg>,
+        (sinks, config, retry_actor, auto_skip_rules, quiet_hours): Self::Arguments,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        mailbox::register::<Self::Msg>(actor_name::<Self>());
+        let state = State {
+            sinks,
+            config,
+            pending_batches: HashMap::new(),
+            batch_generation: 0,
+            retry_actor,
+            auto_skip_rules,
+            quiet_hours,
+            deferred: Vec::new(),
+            cooldowns: Cooldowns::default(),
+        };
+
+        Ok(state)
+    }
+
+    // Don't lose the batches that were still waiting for their window to
+    // end, or the report requests waiting for quiet hours to be over
+    async fn post_stop(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        // Deferred requests join the batches, flushed right after
+        let deferred = std::mem::take(&mut state.deferred);
+        self.replay(&myself, state, deferred).await;
+
+        let target_pubkeys: Vec<PublicKey> = state.pending_batches.keys().cloned().collect();
+        for target_pubkey in target_pubkeys {
+            self.flush(&myself, state, &target_pubkey).await;
+        }
+
+        for suppressed in state.cooldowns.drain() {
+            Self::write_with_suppressed(&myself, state, Vec::new(), suppressed).await;
+        }
+
+        Ok(())
+    }
+
+    async fn handle(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        let _handling = mailbox::handling(&message);
+        match message {
+            // TODO: We should break this dependency on ReportRequest
+            Self::Msg::Write(report_request) => {
+                if let ReportTarget::Event(_) = report_request.target() {
+                    info!("Ignoring event report request for notification writer");
+                    return Ok(());
+                }
+
+                // Combined targets also reach the event enqueuer, which
+                // counts them when skipped
+                let skipped = match report_request.target() {
+                    ReportTarget::EventAndPubkey(..) => {
+                        state.auto_skip_rules.matches(&report_request)
+                    }
+                    _ => state.auto_skip_rules.skips(&report_request),
+                };
+                if skipped {
+                    return Ok(());
+                }
+
+                let now = Timestamp::now().as_u64();
+                if Self::defers(state, std::slice::from_ref(&report_request), now) {
+                    Self::defer(&myself, state, Deferred::Write(report_request), now);
+                    return Ok(());
+                }
+
+                self.enqueue(&myself, state, report_request).await;
+            }
+            Self::Msg::QuietHoursEnded => {
+                let now = Timestamp::now().as_u64();
+                if state.quiet_hours.is_quiet(now) {
+                    Self::schedule_quiet_hours_end(&myself, state, now);
+                    return Ok(());
+                }
+
+                let deferred = std::mem::take(&mut state.deferred);
+                info!(
+                    "Quiet hours are over, writing {} deferred messages",
+                    deferred.len()
+                );
+                self.replay(&myself, state, deferred).await;
+            }
+            Self::Msg::Flush(target_pubkey, generation) => {
+                let is_current_batch = state
+                    .pending_batches
+                    .get(&target_pubkey)
+                    .is_some_and(|batch| batch.generation == generation);
+                if is_current_batch {
+                    self.flush(&myself, state, &target_pubkey).await;
+                }
+            }
+            Self::Msg::Escalate(report_requests) => {
+                let now = Timestamp::now().as_u64();
+                if Self::defers(state, &report_requests, now) {
+                    Self::defer(&myself, state, Deferred::Escalate(report_requests), now);
+                    return Ok(());
+                }
+
+                Self::escalate(&myself, state, report_requests).await;
+            }
+            Self::Msg::Retry(report_request, sink_name, attempt) => {
+                let now = Timestamp::now().as_u64();
+                if Self::defers(state, std::slice::from_ref(&report_request), now) {
+                    let deferred = Deferred::Retry(report_request, sink_name, attempt);
+                    Self::defer(&myself, state, deferred, now);
+                    return Ok(());
+                }
+
+                Self::write_retry(&myself, state, report_request, sink_name, attempt).await;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::actors::utilities::self_test::CHECK_TIMEOUT;
+    use nostr_sdk::prelude::Keys;
+    use ractor::cast;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Mutex;
+
+    #[derive(Clone)]
+    struct TestSlackClient {
+        requests_sent_to_slack: Arc<Mutex<Vec<ReportRequest>>>,
+        aggregated_requests_sent_to_slack: Arc<Mutex<Vec<Vec<ReportRequest>>>>,
+    }
+    impl TestSlackClient {
+        fn new() -> Self {
+            Self {
+                requests_sent_to_slack: Arc::new(Mutex::new(Vec::new())),
+                aggregated_requests_sent_to_slack: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+    }
+
+    #[ractor::async_trait]
+    impl NotificationPort for TestSlackClient {
+        async fn notify(&self, report_request: &ReportRequest, _suppressed: usize) -> Result<()> {
+            self.requests_sent_to_slack
+                .lock()
+                .await
+                .push(report_request.clone());
+            Ok(())
+        }
+
+        async fn notify_aggregated(
+            &self,
+            report_requests: &[ReportRequest],
+            _suppressed: usize,
+        ) -> Result<()> {
+            self.aggregated_requests_sent_to_slack
+                .lock()
+                .await
+                .push(report_requests.to_vec());
+            Ok(())
+        }
+    }
+
+    // A workspace whose check takes a while, failing if it has no answer
+    struct SlowCheckClient {
+        delay: Duration,
+        answer: Option<&'static str>,
+    }
+
+    #[ractor::async_trait]
+    impl NotificationPort for SlowCheckClient {
+        async fn notify(&self, _report_request: &ReportRequest, _suppressed: usize) -> Result<()> {
+            Ok(())
+        }
+
+        async fn notify_aggregated(
+            &self,
+            _report_requests: &[ReportRequest],
+            _suppressed: usize,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        async fn check_connectivity(&self) -> Result<String> {
+            tokio::time::sleep(self.delay).await;
+            match self.answer {
+                Some(answer) => Ok(answer.to_string()),
+                None => anyhow::bail!("invalid_auth"),
+            }
+        }
+    }
+
+    fn pubkey_report_request(pubkey_to_report: PublicKey) -> ReportRequest {
+        let report_request_string = json!({
+            "reportedPubkey": pubkey_to_report.to_string(),
+            "reporterPubkey": Keys::generate().public_key().to_string(),
+            "reporterText": "This is hateful. Report it!"
+        })
+        .to_string();
+
+        serde_json::from_str(&report_request_string).unwrap()
+    }
+
+    fn config(batch_window_secs: u64, batch_max_size: usize) -> Config {
+        Config {
+            batch_window_secs,
+            batch_max_size,
+            cooldown_secs: 0,
+        }
+    }
+
+    fn workspace(
+        name: &str,
+        reporters: Vec<PublicKey>,
+        client: TestSlackClient,
+    ) -> NotificationSink<TestSlackClient> {
+        let workspace_config = WorkspaceConfig {
+            name: name.to_string(),
+            token: "token".to_string(),
+            channel_id: "channel".into(),
+            reporters: reporters.iter().map(|reporter| reporter.to_hex()).collect(),
+            sample_rate: None,
+        };
+
+        NotificationSink::for_workspace(&workspace_config, client).unwrap()
+    }
+
+    use super::*;
+    #[tokio::test]
+    async fn test_notification_writer() {
+        let test_slack_client = TestSlackClient::new();
+
+        let (notification_writer_ref, notification_writer_handle) = Actor::spawn(
+            None,
+            NotificationWriter::default(),
+            (
+                vec![workspace("default", vec![], test_slack_client.clone())],
+                config(0, 20),
+                None,
+                AutoSkipRules::default(),
+                QuietHours::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let report_request = pubkey_report_request(Keys::generate().public_key());
+
+        cast!(
+            notification_writer_ref,
+            NotificationWriterMessage::Write(report_request.clone())
+        )
+        .unwrap();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            notification_writer_ref.stop(None);
+        });
+
+        notification_writer_handle.await.unwrap();
+
+        assert_eq!(
+            test_slack_client
+                .requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [report_request]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notification_writer_coalesces_requests_for_the_same_target() {
+        let test_slack_client = TestSlackClient::new();
+
+        // The window is longer than the test so batches are flushed on stop
+        let (notification_writer_ref, notification_writer_handle) = Actor::spawn(
+            None,
+            NotificationWriter::default(),
+            (
+                vec![workspace("default", vec![], test_slack_client.clone())],
+                config(60, 20),
+                None,
+                AutoSkipRules::default(),
+                QuietHours::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let stormed_pubkey = Keys::generate().public_key();
+        let stormed_requests: Vec<ReportRequest> = (0..3)
+            .map(|_| pubkey_report_request(stormed_pubkey))
+            .collect();
+        let lone_request = pubkey_report_request(Keys::generate().public_key());
+
+        for report_request in stormed_requests.iter().chain([&lone_request]) {
+            cast!(
+                notification_writer_ref,
+                NotificationWriterMessage::Write(report_request.clone())
+            )
+            .unwrap();
+        }
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            notification_writer_ref.stop(None);
+        });
+
+        notification_writer_handle.await.unwrap();
+
+        assert_eq!(
+            test_slack_client
+                .aggregated_requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [stormed_requests]
+        );
+        assert_eq!(
+            test_slack_client
+                .requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [lone_request]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notification_writer_flushes_full_batches_right_away() {
+        let test_slack_client = TestSlackClient::new();
+
+        let (notification_writer_ref, notification_writer_handle) = Actor::spawn(
+            None,
+            NotificationWriter::default(),
+            (
+                vec![workspace("default", vec![], test_slack_client.clone())],
+                config(60, 2),
+                None,
+                AutoSkipRules::default(),
+                QuietHours::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let stormed_pubkey = Keys::generate().public_key();
+        let stormed_requests: Vec<ReportRequest> = (0..2)
+            .map(|_| pubkey_report_request(stormed_pubkey))
+            .collect();
+
+        for report_request in &stormed_requests {
+            cast!(
+                notification_writer_ref,
+                NotificationWriterMessage::Write(report_request.clone())
+            )
+            .unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            test_slack_client
+                .aggregated_requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [stormed_requests]
+        );
+
+        notification_writer_ref.stop(None);
+        notification_writer_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timers_of_flushed_batches_dont_flush_later_ones() {
+        let test_slack_client = TestSlackClient::new();
+
+        let (notification_writer_ref, notification_writer_handle) = Actor::spawn(
+            None,
+            NotificationWriter::default(),
+            (
+                vec![workspace("default", vec![], test_slack_client.clone())],
+                config(1, 2),
+                None,
+                AutoSkipRules::default(),
+                QuietHours::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let stormed_pubkey = Keys::generate().public_key();
+        for _ in 0..2 {
+            cast!(
+                notification_writer_ref,
+                NotificationWriterMessage::Write(pubkey_report_request(stormed_pubkey))
+            )
+            .unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        let late_request = pubkey_report_request(stormed_pubkey);
+        cast!(
+            notification_writer_ref,
+            NotificationWriterMessage::Write(late_request.clone())
+        )
+        .unwrap();
+
+        // The timer of the full batch fired, the late request has its own
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        assert!(test_slack_client
+            .requests_sent_to_slack
+            .lock()
+            .await
+            .is_empty());
+
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        assert_eq!(
+            test_slack_client
+                .requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [late_request]
+        );
+
+        notification_writer_ref.stop(None);
+        notification_writer_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_notification_writer_fans_out_to_accepting_workspaces() {
+        let default_client = TestSlackClient::new();
+        let routed_client = TestSlackClient::new();
+
+        let routed_request = pubkey_report_request(Keys::generate().public_key());
+        let other_request = pubkey_report_request(Keys::generate().public_key());
+
+        let (notification_writer_ref, notification_writer_handle) = Actor::spawn(
+            None,
+            NotificationWriter::default(),
+            (
+                vec![
+                    workspace("default", vec![], default_client.clone()),
+                    workspace(
+                        "routed",
+                        vec![*routed_request.reporter_pubkey()],
+                        routed_client.clone(),
+                    ),
+                ],
+                config(0, 20),
+                None,
+                AutoSkipRules::default(),
+                QuietHours::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        for report_request in [&routed_request, &other_request] {
+            cast!(
+                notification_writer_ref,
+                NotificationWriterMessage::Write(report_request.clone())
+            )
+            .unwrap();
+        }
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            notification_writer_ref.stop(None);
+        });
+
+        notification_writer_handle.await.unwrap();
+
+        assert_eq!(
+            default_client.requests_sent_to_slack.lock().await.as_ref(),
+            [routed_request.clone(), other_request]
+        );
+        assert_eq!(
+            routed_client.requests_sent_to_slack.lock().await.as_ref(),
+            [routed_request]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_suppresses_rapid_reports_on_the_same_target() {
+        let test_slack_client = TestSlackClient::new();
+
+        let (notification_writer_ref, notification_writer_handle) = Actor::spawn(
+            None,
+            NotificationWriter::default(),
+            (
+                vec![workspace("default", vec![], test_slack_client.clone())],
+                Config {
+                    cooldown_secs: 60,
+                    ..config(0, 20)
+                },
+                None,
+                AutoSkipRules::default(),
+                QuietHours::default(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let reported_pubkey = Keys::generate().public_key();
+        let first_request = pubkey_report_request(reported_pubkey);
+        let suppressed_request = pubkey_report_request(reported_pubkey);
+        let other_target_request = pubkey_report_request(Keys::generate().public_key());
+        for report_request in [&first_request, &suppressed_request, &other_target_request] {
+            cast!(
+                notification_writer_ref,
+                NotificationWriterMessage::Write(report_request.clone())
+            )
+            .unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(
+            test_slack_client
+                .requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [first_request, other_target_request.clone()]
+        );
+
+        // Held back, not lost
+        notification_writer_ref.stop(None);
+        notification_writer_handle.await.unwrap();
+
+        assert_eq!(
+            test_slack_client
+                .requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [first_request, other_target_request, suppressed_request]
+        );
+    }
+
+    #[test]
+    fn test_the_next_message_after_a_cooldown_gets_the_suppressed_requests() {
+        let mut cooldowns = Cooldowns::default();
+        let cooldown = Duration::from_secs(60);
+        let reported_pubkey = Keys::generate().public_key();
+        let report_requests: Vec<ReportRequest> = (0..4)
+            .map(|_| pubkey_report_request(reported_pubkey))
+            .collect();
+        let start = Instant::now();
+
+        assert!(!cooldowns.suppresses(&report_requests[0], cooldown, 2, start));
+        assert_eq!(
+            cooldowns.notified(reported_pubkey, cooldown, start),
+            Suppressed::default()
+        );
+
+        let later = start + Duration::from_secs(10);
+        for report_request in &report_requests[1..] {
+            assert!(cooldowns.suppresses(report_request, cooldown, 2, later));
+        }
+
+        let after_cooldown = start + Duration::from_secs(61);
+        assert!(!cooldowns.suppresses(&report_requests[0], cooldown, 2, after_cooldown));
+        assert_eq!(
+            cooldowns.notified(reported_pubkey, cooldown, after_cooldown),
+            Suppressed {
+                report_requests: report_requests[1..3].to_vec(),
+                count: 3,
+            }
+        );
+
+        // Targets without suppressed report requests are forgotten
+        let other_pubkey = Keys::generate().public_key();
+        cooldowns.notified(other_pubkey, cooldown, start + Duration::from_secs(200));
+        assert_eq!(cooldowns.targets.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_quiet_hours_defer_all_but_urgent_report_requests() {
+        use crate::actors::utilities::quiet_hours::{
+            Config as QuietHoursConfig, QuietWindowConfig,
+        };
+
+        let test_slack_client = TestSlackClient::new();
+        // Quiet the whole day, every day
+        let quiet_hours = QuietHours::new(&QuietHoursConfig {
+            schedule: vec![QuietWindowConfig {
+                days: vec![],
+                start: "00:00".to_string(),
+                end: "00:00".to_string(),
+            }],
+            utc_offset_minutes: 0,
+            urgent_rules: vec![r"(?i)\bminors?\b".to_string()],
+            max_deferred: 2,
+        })
+        .unwrap();
+
+        let (notification_writer_ref, notification_writer_handle) = Actor::spawn(
+            None,
+            NotificationWriter::default(),
+            (
+                vec![workspace("default", vec![], test_slack_client.clone())],
+                config(0, 20),
+                None,
+                AutoSkipRules::default(),
+                quiet_hours,
+            ),
+        )
+        .await
+        .unwrap();
+
+        let deferred_request = pubkey_report_request(Keys::generate().public_key());
+        let urgent_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            Some("Sharing pictures of minors".to_string()),
+        );
+        let escalated_pubkey = Keys::generate().public_key();
+        let escalated_requests: Vec<ReportRequest> = (0..2)
+            .map(|_| pubkey_report_request(escalated_pubkey))
+            .collect();
+        // Past max_deferred
+        let overflowing_request = pubkey_report_request(Keys::generate().public_key());
+        for report_request in [&deferred_request, &urgent_request] {
+            cast!(
+                notification_writer_ref,
+                NotificationWriterMessage::Write(report_request.clone())
+            )
+            .unwrap();
+        }
+        cast!(
+            notification_writer_ref,
+            NotificationWriterMessage::Escalate(escalated_requests.clone())
+        )
+        .unwrap();
+        cast!(
+            notification_writer_ref,
+            NotificationWriterMessage::Write(overflowing_request.clone())
+        )
+        .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(
+            test_slack_client
+                .requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [urgent_request.clone(), overflowing_request.clone()]
+        );
+        assert!(test_slack_client
+            .aggregated_requests_sent_to_slack
+            .lock()
+            .await
+            .is_empty());
+
+        // Deferred requests are written on stop rather than lost
+        notification_writer_ref.stop(None);
+        notification_writer_handle.await.unwrap();
+
+        assert_eq!(
+            test_slack_client
+                .requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [urgent_request, overflowing_request, deferred_request]
+        );
+        assert_eq!(
+            test_slack_client
+                .aggregated_requests_sent_to_slack
+                .lock()
+                .await
+                .as_ref(),
+            [escalated_requests]
+        );
+    }
+}
+
+// Builds the client for a single workspace
+pub trait SlackNotifierBuilder: Send + Sync + 'static {
+    fn build(
+        &self,
+        config: WorkspaceConfig,
+        nostr_actor: ActorRef<SupervisorMessage>,
+    ) -> Result<impl NotificationPort>;
+}
+
+#[ractor::async_trait]
+pub trait NotificationPort: Send + Sync + 'static {
+    // Suppressed is the number of report requests on the same target held
+    // back by the cooldown since its previous message
+    async fn notify(&self, report_request: &ReportRequest, suppressed: usize) -> Result<()>;
+
+    // Writes a single message for several report requests on the same target
+    async fn notify_aggregated(
+        &self,
+        report_requests: &[ReportRequest],
+        suppressed: usize,
+    ) -> Result<()>;
+
+    // Lightweight call for the admin self test, answering with a short
+    // detail
+    async fn check_connectivity(&self) -> Result<String> {
+        Ok("Nothing to check".to_string())
+    }
+}
+
+// Allows choosing the backend at runtime from the config
+#[ractor::async_trait]
+impl NotificationPort for Box<dyn NotificationPort> {
+    async fn notify(&self, report_request: &ReportRequest, suppressed: usize) -> Result<()> {
+        (**self).notify(report_request, suppressed).await
+    }
+
+    async fn notify_aggregated(
+        &self,
+        report_requests: &[ReportRequest],
+        suppressed: usize,
+    ) -> Result<()> {
+        (**self)
+            .notify_aggregated(report_requests, suppressed)
+            .await
+    }
+
+    async fn check_connectivity(&self) -> Result<String> {
+        (**self).check_connectivity().await
+    }
+
+    #[tokio::test]
+    async fn test_sink_checks_run_concurrently() {
+        let sinks = vec![
+            NotificationSink::new(
+                "first",
+                WEBHOOK_METRICS,
+                SlowCheckClient {
+                    delay: Duration::from_millis(400),
+                    answer: Some("ok"),
+                },
+            ),
+            NotificationSink::new(
+                "second",
+                WEBHOOK_METRICS,
+                SlowCheckClient {
+                    delay: Duration::from_millis(400),
+                    answer: None,
+                },
+            ),
+            NotificationSink::new(
+                "third",
+                WEBHOOK_METRICS,
+                SlowCheckClient {
+                    delay: Duration::from_secs(2),
+                    answer: Some("too late"),
+                },
+            ),
+        ];
+
+        let started = Instant::now();
+        let checks = NotificationSink::checks(&sinks).run().await;
+
+        assert!(started.elapsed() < CHECK_TIMEOUT);
+        let outcomes: Vec<(&str, bool, &str)> = checks
+            .iter()
+            .map(|check| (check.dependency.as_str(), check.ok, check.detail.as_str()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("first", true, "ok"),
+                ("second", false, "invalid_auth"),
+                ("third", false, "Timed out after 600ms"),
+            ]
+        );
+    }
+}
@@ -1,16 +1,62 @@
 use crate::actors::messages::RelayEventDispatcherMessage;
-use crate::service_manager::ServiceManager;
-use anyhow::Result;
-use metrics::counter;
+use crate::actors::utilities::downstream_limit::limited;
+use crate::actors::utilities::exemplars;
+use crate::actors::utilities::mailbox;
+use crate::actors::utilities::output_port_monitor::OutputPortMonitor;
+use crate::actors::utilities::report_funnel::{self, FunnelStep};
+use crate::actors::utilities::self_test::DependencyCheck;
+use crate::config::{self, Configurable};
+use crate::dead_letter_queue::DeadLetterQueue;
+use crate::domain_objects::ModeratedReport;
+use crate::service_manager::{actor_name, ServiceManager};
+use anyhow::{anyhow, bail, Result};
+use metrics::{counter, histogram};
 use nostr_sdk::prelude::*;
-use ractor::{Actor, ActorProcessingErr, ActorRef, OutputPort};
+use ractor::{cast, Actor, ActorProcessingErr, ActorRef, OutputPort};
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, info_span, warn, Instrument};
+
+const BACKFILL_PACING: Duration = Duration::from_millis(20);
 
 pub struct RelayEventDispatcher<T: NostrPort> {
     _phantom: std::marker::PhantomData<T>,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Kinds of the events we subscribe to. Relays pushing anything else get
+    // it dropped before it reaches the other actors. Empty accepts any
+    #[serde(default = "default_expected_kinds")]
+    pub expected_kinds: Vec<u16>,
+}
+
+fn default_expected_kinds() -> Vec<u16> {
+    vec![Kind::GiftWrap.as_u16()]
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            expected_kinds: default_expected_kinds(),
+        }
+    }
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "nostr"
+    }
+}
+
+impl Config {
+    fn is_expected(&self, kind: Kind) -> bool {
+        self.expected_kinds.is_empty() || self.expected_kinds.contains(&kind.as_u16())
+    }
+}
+
 impl<T: NostrPort> Default for RelayEventDispatcher<T> {
     fn default() -> Self {
         Self {
@@ -20,8 +66,11 @@ impl<T: NostrPort> Default for RelayEventDispatcher<T> {
 }
 pub struct State<T: NostrPort> {
     event_received_output_port: OutputPort<Event>,
+    event_received_monitor: OutputPortMonitor<Event>,
     subscription_task_manager: Option<ServiceManager>,
     nostr_client: T,
+    dead_letter_queue: Option<DeadLetterQueue>,
+    config: Config,
 }
 
 impl<T> RelayEventDispatcher<T>
@@ -35,7 +84,9 @@ where
         action: &str,
     ) -> Result<()> {
         info!("{}", action);
-        if let Some(subscription_task_manager) = &state.subscription_task_manager {
+        // There's a single subscription task at a time, the previous one is
+        // fully stopped before the next is spawned
+        if let Some(subscription_task_manager) = state.subscription_task_manager.take() {
             subscription_task_manager.stop().await;
         }
 
@@ -51,12 +102,100 @@ where
     }
 }
 
+/// What our relays did with a published event, once each of them answered or
+/// the publish timeout passed. It's published if at least one relay accepted it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublishOutcome {
+    pub accepted: Vec<Url>,
+    // Along the reason the relay gave
+    pub rejected: Vec<(Url, String)>,
+    pub timed_out: Vec<Url>,
+}
+
+impl PublishOutcome {
+    pub fn is_published(&self) -> bool {
+        !self.accepted.is_empty()
+    }
+
+    /// Published, but not by all of our relays
+    pub fn is_partial(&self) -> bool {
+        self.is_published() && (!self.rejected.is_empty() || !self.timed_out.is_empty())
+    }
+
+    /// Errors unless a relay accepted the event, for callers that only care
+    /// about that
+    pub fn ensure_published(self) -> Result<Self> {
+        if !self.is_published() {
+            bail!("No relay accepted the event, {}", self);
+        }
+        Ok(self)
+    }
+
+    fn record(&self) {
+        counter!("publish_relay_accepted").increment(self.accepted.len() as u64);
+        counter!("publish_relay_rejected").increment(self.rejected.len() as u64);
+        counter!("publish_relay_timeout").increment(self.timed_out.len() as u64);
+    }
+}
+
+impl Display for PublishOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let rejected: Vec<String> = self
+            .rejected
+            .iter()
+            .map(|(url, reason)| format!("{} ({})", url, reason))
+            .collect();
+        let timed_out: Vec<String> = self.timed_out.iter().map(Url::to_string).collect();
+        let accepted: Vec<String> = self.accepted.iter().map(Url::to_string).collect();
+
+        write!(
+            f,
+            "accepted: [{}], rejected: [{}], timed out: [{}]",
+            accepted.join(", "),
+            rejected.join(", "),
+            timed_out.join(", ")
+        )
+    }
+}
+
 #[async_trait]
 pub trait NostrPort: Send + Sync + Clone + 'static {
     async fn connect(&self) -> Result<()>;
     async fn reconnect(&self) -> Result<()>;
-    async fn publish(&self, event: Event) -> Result<()>;
-    async fn get_nip05(&self, public_key: PublicKey) -> Option<String>;
+    async fn is_connected(&self) -> bool;
+    // Connected relays, and all of ours
+    async fn connected_relays(&self) -> (usize, usize);
+    // Each of our relays and whether it's connected
+    async fn relay_connections(&self) -> Vec<(Url, bool)>;
+    // Connects only these of our relays again, the rest keep their
+    // connection. Their subscriptions are sent again once connected
+    async fn reconnect_relays(&self, relays: Vec<Url>) -> Result<()>;
+    // Errors only when the event couldn't be sent at all, relays rejecting it
+    // or timing out are in the outcome
+    async fn publish(&self, event: Event) -> Result<PublishOutcome>;
+    // Only to the given relays, none of ours
+    async fn publish_to(&self, event: Event, relays: Vec<String>) -> Result<()>;
+    // Publishes a gift wrap to the receiver's DM relays, or ours if unknown
+    async fn publish_direct_message(&self, gift_wrap: Event, receiver: PublicKey) -> Result<()>;
+    // Subscriptions started afterwards only match gift wraps for these keys
+    async fn set_reportinator_public_keys(&self, public_keys: Vec<PublicKey>) -> Result<()>;
+    // Only verified NIP-05s, an error if the metadata or the NIP-05 server
+    // couldn't be reached
+    async fn get_nip05(&self, public_key: PublicKey) -> Result<Option<String>>;
+    async fn get_events(&self, filters: Vec<Filter>) -> Result<Vec<Event>>;
+    // Events matching the subscription filters created between since and
+    // until, both included, newest first and at most limit of them
+    async fn fetch_range(
+        &self,
+        since: Timestamp,
+        until: Timestamp,
+        limit: usize,
+    ) -> Result<Vec<Event>>;
+    // Adds a relay to the live client, subscribed with the current filters
+    // once connected. False if it was already there
+    async fn add_relay(&self, url: Url) -> Result<bool>;
+    // False if it wasn't one of our relays
+    async fn remove_relay(&self, url: Url) -> Result<bool>;
 
     async fn subscribe(
         &self,
@@ -69,19 +208,23 @@ pub trait NostrPort: Send + Sync + Clone + 'static {
 impl<T: NostrPort> Actor for RelayEventDispatcher<T> {
     type Msg = RelayEventDispatcherMessage;
     type State = State<T>;
-    type Arguments = T;
+    type Arguments = (T, Option<DeadLetterQueue>, Config);
 
     async fn pre_start(
         &self,
         _myself: ActorRef<Self::Msg>,
-        nostr_client: T,
+        (nostr_client, dead_letter_queue, config): (T, Option<DeadLetterQueue>, Config),
     ) -> Result<Self::State, ActorProcessingErr> {
+        mailbox::register::<Self::Msg>(actor_name::<Self>());
         let event_received_output_port = OutputPort::default();
 
         let state = State {
             event_received_output_port,
+            event_received_monitor: OutputPortMonitor::new("event_received"),
             subscription_task_manager: None,
             nostr_client,
+            dead_letter_queue,
+            config,
         };
 
         Ok(state)
@@ -106,6 +249,7 @@ impl<T: NostrPort> Actor for RelayEventDispatcher<T> {
         message: Self::Msg,
         state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
+        let _handling = mailbox::handling(&message);
         match message {
             // TODO: Connect and Reconnect should probably be instead Fetch with
             // a limit, which would be sent initially from main and then from
@@ -147,41 +291,432 @@ impl<T: NostrPort> Actor for RelayEventDispatcher<T> {
                 }
                 counter!("reconnect").increment(1);
             }
+            RelayEventDispatcherMessage::SetReportinatorPublicKeys(public_keys) => {
+                if let Err(e) = state
+                    .nostr_client
+                    .set_reportinator_public_keys(public_keys)
+                    .await
+                {
+                    counter!("resubscribe_error").increment(1);
+                    error!("Failed to update the subscription filters: {}", e);
+                    return Ok(());
+                }
+
+                if let Err(e) = self
+                    .handle_subscriptions(myself, state, "Resubscribing with rotated keys")
+                    .await
+                {
+                    counter!("resubscribe_error").increment(1);
+                    error!("Failed to resubscribe: {}", e);
+                    return Ok(());
+                }
+                counter!("resubscribe").increment(1);
+            }
             RelayEventDispatcherMessage::SubscribeToEventReceived(subscriber) => {
                 info!("Subscribing to {:?}", myself.get_name());
                 subscriber.subscribe_to_port(&state.event_received_output_port);
+                state.event_received_monitor.subscribed();
             }
             RelayEventDispatcherMessage::EventReceived(event) => {
+                if !state.config.is_expected(event.kind) {
+                    counter!("unexpected_kind_dropped", "kind" => event.kind.as_u16().to_string())
+                        .increment(1);
+                    debug!(
+                        "Dropped event {} of unexpected kind {}",
+                        event.id(),
+                        event.kind
+                    );
+                    return Ok(());
+                }
+
                 info!("Event received: {}", event.id());
+                // Gift wraps have their created_at randomized up to two days
+                // back, so only the tail of this tells backfill from live traffic
+                let event_age = Timestamp::now()
+                    .as_u64()
+                    .saturating_sub(event.created_at.as_u64());
+                histogram!("event_age_seconds").record(event_age as f64);
                 state.event_received_output_port.send(event);
+                state.event_received_monitor.sent();
                 counter!("event_received").increment(1);
             }
+            // The relays may take seconds to accept a report, events keep
+            // being dispatched meanwhile. Its outcome is counted as pending
+            // from now on, so shutdown still waits for the publish
             RelayEventDispatcherMessage::Publish(moderated_report) => {
-                if let Err(e) = state.nostr_client.publish(moderated_report.event()).await {
-                    counter!("publish_error").increment(1);
-                    error!("Failed to publish moderated report: {}", e);
-                    return Ok(());
+                let nostr_client = state.nostr_client.clone();
+                mailbox::message_sent::<Self::Msg>();
+                let trace_id = exemplars::trace_id(moderated_report.id());
+                tokio::spawn(
+                    async move {
+                        let published = publish_report(&nostr_client, &moderated_report).await;
+                        let report_id = moderated_report.id();
+                        if let Err(e) = myself.cast(RelayEventDispatcherMessage::Published(
+                            moderated_report,
+                            published,
+                        )) {
+                            mailbox::message_received::<RelayEventDispatcherMessage>();
+                            error!("Failed to send back the publishing of {}: {}", report_id, e);
+                        }
+                    }
+                    .instrument(info_span!("publish_report", trace_id = %trace_id)),
+                );
+            }
+            RelayEventDispatcherMessage::Published(moderated_report, published) => {
+                if published {
+                    publish_shadow_events(&state.nostr_client, &moderated_report);
+                    report_funnel::record(FunnelStep::Published, moderated_report.target_label());
+                    counter!(
+                        "report_published",
+                        "category" => moderated_report.category_label()
+                    )
+                    .increment(1);
+                    exemplars::record(
+                        "report_published",
+                        &[("category", moderated_report.category_label())],
+                        &exemplars::trace_id(moderated_report.id()),
+                    );
                 }
 
-                counter!("publish").increment(1);
-                info!(
-                    "Report {} published successfully",
-                    moderated_report.event().id()
-                );
+                if !published {
+                    if let Some(dead_letter_queue) = &state.dead_letter_queue {
+                        match dead_letter_queue.append(&moderated_report) {
+                            Ok(()) => counter!("dead_letter_written").increment(1),
+                            Err(e) => error!(
+                                "Failed to write report {} to the dead letter queue: {}",
+                                moderated_report.id(),
+                                e
+                            ),
+                        }
+                    }
+                }
             }
-            RelayEventDispatcherMessage::GetNip05(public_key, reply_port) => {
-                let maybe_nip05 = state.nostr_client.get_nip05(public_key).await;
+            // Sent in the background, looking up the DM relays of the
+            // receiver would hold up dispatching
+            RelayEventDispatcherMessage::PublishDirectMessage(gift_wrap, receiver) => {
+                let nostr_client = state.nostr_client.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = nostr_client
+                        .publish_direct_message(gift_wrap, receiver)
+                        .await
+                    {
+                        counter!("direct_message_publish_error").increment(1);
+                        error!("Failed to publish direct message to {}: {}", receiver, e);
+                        return;
+                    }
+
+                    counter!("direct_message_published").increment(1);
+                    info!("Direct message to {} published", receiver);
+                });
+            }
+            // Fetching pages through the relays for a while, so it's done in
+            // the background and live events keep being dispatched
+            RelayEventDispatcherMessage::Backfill(since, until, limit) => {
+                tokio::spawn(backfill(
+                    state.nostr_client.clone(),
+                    myself,
+                    since,
+                    until,
+                    limit,
+                ));
+            }
+            RelayEventDispatcherMessage::AddRelay(url, reply_port) => {
+                let result = state.nostr_client.add_relay(url.clone()).await;
+                match &result {
+                    Ok(true) => {
+                        counter!("relay_added").increment(1);
+                        info!("Relay {} added", url);
+                    }
+                    Ok(false) => info!("Relay {} was already added", url),
+                    Err(e) => error!("Failed to add relay {}: {}", url, e),
+                }
+
+                if !reply_port.is_closed() {
+                    reply_port.send(result.map_err(|e| e.to_string()))?;
+                }
+            }
+            RelayEventDispatcherMessage::RemoveRelay(url, reply_port) => {
+                let result = state.nostr_client.remove_relay(url.clone()).await;
+                match &result {
+                    Ok(true) => {
+                        counter!("relay_removed").increment(1);
+                        info!("Relay {} removed", url);
+                    }
+                    Ok(false) => info!("Relay {} was not one of ours", url),
+                    Err(e) => error!("Failed to remove relay {}: {}", url, e),
+                }
 
                 if !reply_port.is_closed() {
-                    reply_port.send(maybe_nip05)?;
+                    reply_port.send(result.map_err(|e| e.to_string()))?;
                 }
             }
+            RelayEventDispatcherMessage::SelfTest(reply_port) => {
+                let check = DependencyCheck::run("relays", async {
+                    let (connected, total) = state.nostr_client.connected_relays().await;
+                    if connected == 0 {
+                        bail!("None of the {} relays is connected", total);
+                    }
+
+                    Ok(format!("{} of {} relays connected", connected, total))
+                })
+                .await;
+
+                if !reply_port.is_closed() {
+                    reply_port.send(check)?;
+                }
+            }
+            RelayEventDispatcherMessage::GetNip05(public_key, reply_port) => {
+                let nostr_client = state.nostr_client.clone();
+                tokio::spawn(async move {
+                    let result = nostr_client
+                        .get_nip05(public_key)
+                        .await
+                        .map_err(|e| e.to_string());
+
+                    if !reply_port.is_closed() {
+                        let _ = reply_port.send(result);
+                    }
+                });
+            }
+            RelayEventDispatcherMessage::FindEvent(event_id, reply_port) => {
+                let nostr_client = state.nostr_client.clone();
+                tokio::spawn(async move {
+                    let filter = Filter::new().id(event_id).limit(1);
+                    let found = match limited(nostr_client.get_events(vec![filter])).await {
+                        Ok(events) => Ok(events.into_iter().find(|event| event.id == event_id)),
+                        Err(e) => {
+                            error!("Failed to look up event {}: {}", event_id, e);
+                            Err(e.to_string())
+                        }
+                    };
+
+                    if !reply_port.is_closed() {
+                        let _ = reply_port.send(found);
+                    }
+                });
+            }
+            // Finding the report events takes a relay round trip, done in
+            // the background so dispatching isn't held up
+            RelayEventDispatcherMessage::Retract(report_id) => {
+                let nostr_client = state.nostr_client.clone();
+                let reportinator_keys = config::reportinator::signing_keys();
+                let report_kinds = config::reportinator::config().published_kinds();
+
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        retract(&nostr_client, report_id, &reportinator_keys, &report_kinds).await
+                    {
+                        counter!("report_retract_error").increment(1);
+                        error!("Failed to retract report {}: {}", report_id, e);
+                        return;
+                    }
+
+                    counter!("report_retracted").increment(1);
+                    info!("Report {} retracted", report_id);
+                });
+            }
         }
 
         Ok(())
     }
 }
 
+async fn backfill<T: NostrPort>(
+    nostr_client: T,
+    dispatcher: ActorRef<RelayEventDispatcherMessage>,
+    since: Timestamp,
+    until: Timestamp,
+    limit: usize,
+) {
+    let events = match nostr_client.fetch_range(since, until, limit).await {
+        Ok(events) => events,
+        Err(e) => {
+            counter!("backfill_error").increment(1);
+            error!("Failed to backfill from {} to {}: {}", since, until, e);
+            return;
+        }
+    };
+
+    counter!("backfill_fetched").increment(events.len() as u64);
+    info!(
+        "Backfilling {} events from {} to {}",
+        events.len(),
+        since,
+        until
+    );
+    reinject(dispatcher, events).await;
+}
+
+// Backfilled events go through EventReceived like live ones. They are paced
+// so the output port buffer isn't overrun by a whole range at once
+async fn reinject(dispatcher: ActorRef<RelayEventDispatcherMessage>, events: Vec<Event>) {
+    // Oldest first, as they would have arrived
+    for event in events.into_iter().rev() {
+        if let Err(e) = cast!(
+            dispatcher,
+            RelayEventDispatcherMessage::EventReceived(event)
+        ) {
+            error!("Failed to reinject backfilled event: {}", e);
+            return;
+        }
+        tokio::time::sleep(BACKFILL_PACING).await;
+    }
+}
+
+// Only reports signed with our current keys can be retracted, NIP-09
+// deletions must come from the author of the deleted events. The other kinds
+// of the same report, label events included, are found through their shared
+// targets and timestamp.
+async fn retract<T: NostrPort>(
+    nostr_client: &T,
+    report_id: EventId,
+    reportinator_keys: &Keys,
+    report_kinds: &[Kind],
+) -> Result<()> {
+    let report = nostr_client
+        .get_events(vec![Filter::new().id(report_id).limit(1)])
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Report {} not found on our relays", report_id))?;
+
+    if report.author() != reportinator_keys.public_key() || !report_kinds.contains(&report.kind) {
+        bail!(
+            "{} is not a report published with our current keys",
+            report_id
+        );
+    }
+
+    let sibling_filter = Filter::new()
+        .author(reportinator_keys.public_key())
+        .kinds(report_kinds.to_vec())
+        .since(report.created_at)
+        .until(report.created_at);
+    let siblings = nostr_client.get_events(vec![sibling_filter]).await?;
+
+    let mut report_events = vec![report.clone()];
+    report_events.extend(
+        siblings
+            .into_iter()
+            .filter(|event| event.id != report.id && same_targets(event, &report)),
+    );
+
+    let retraction = ModeratedReport::retraction(&report_events, reportinator_keys)?;
+    limited(nostr_client.publish(retraction))
+        .await?
+        .ensure_published()?;
+    Ok(())
+}
+
+fn same_targets(event: &Event, report: &Event) -> bool {
+    event.public_keys().eq(report.public_keys()) && event.event_ids().eq(report.event_ids())
+}
+
+// Every event of the report must be accepted by at least one relay. Relays
+// rejecting it or timing out are logged and metered but don't fail it
+async fn publish_report<T: NostrPort>(
+    nostr_client: &T,
+    moderated_report: &ModeratedReport,
+) -> bool {
+    let trace_id = exemplars::trace_id(moderated_report.id());
+    let mut published = true;
+    for event in moderated_report.events() {
+        let outcome = match limited(nostr_client.publish(event.clone())).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                counter!("publish_error").increment(1);
+                exemplars::record("publish_error", &[], &trace_id);
+                error!(
+                    "Failed to publish moderated report {} of kind {}: {}",
+                    event.id(),
+                    event.kind,
+                    e
+                );
+                published = false;
+                continue;
+            }
+        };
+        outcome.record();
+
+        if !outcome.is_published() {
+            counter!("publish_error").increment(1);
+            exemplars::record("publish_error", &[], &trace_id);
+            error!(
+                "Moderated report {} of kind {} was not accepted by any relay, {}",
+                event.id(),
+                event.kind,
+                outcome
+            );
+            published = false;
+            continue;
+        }
+
+        if outcome.is_partial() {
+            counter!("publish_partial").increment(1);
+            warn!(
+                "Report {} of kind {} only partially published, {}",
+                event.id(),
+                event.kind,
+                outcome
+            );
+        }
+
+        counter!("publish").increment(1);
+        exemplars::record("publish", &[], &trace_id);
+        info!(
+            "Report {} of kind {} published successfully to {} relays",
+            event.id(),
+            event.kind,
+            outcome.accepted.len()
+        );
+    }
+
+    published
+}
+
+// Shadow reports are only for comparison, so they're sent in the background
+// and their failures don't touch the regular report
+fn publish_shadow_events<T: NostrPort>(nostr_client: &T, moderated_report: &ModeratedReport) {
+    let shadow_events = moderated_report.shadow_events().to_vec();
+    if shadow_events.is_empty() {
+        return;
+    }
+
+    let shadow_relays = config::reportinator::shadow_relays().to_vec();
+    if shadow_relays.is_empty() {
+        for event in shadow_events {
+            counter!("shadow_report_logged").increment(1);
+            info!(
+                "Shadow report for {}: {}",
+                moderated_report.id(),
+                event.as_json()
+            );
+        }
+        return;
+    }
+
+    let nostr_client = nostr_client.clone();
+    let report_id = moderated_report.id();
+    tokio::spawn(async move {
+        for event in shadow_events {
+            let shadow_event_id = event.id;
+            match limited(nostr_client.publish_to(event, shadow_relays.clone())).await {
+                Ok(()) => {
+                    counter!("shadow_report_published").increment(1);
+                    info!(
+                        "Shadow report {} for {} published",
+                        shadow_event_id, report_id
+                    );
+                }
+                Err(e) => {
+                    counter!("shadow_report_publish_error").increment(1);
+                    error!("Failed to publish shadow report for {}: {}", report_id, e);
+                }
+            }
+        }
+    });
+}
+
 // We don't want to run long running tasks from inside an actor message handle
 // so we spawn a task specifically for this. See
 // https://github.com/slawlor/ractor/issues/133#issuecomment-1666947314
@@ -207,9 +742,10 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::actors::TestActor;
+    use crate::actors::{ScriptedNostrService, TestActor};
     use pretty_assertions::assert_eq;
-    use ractor::{cast, concurrency::Duration};
+    use ractor::{call_t, cast, concurrency::Duration, RpcReplyPort};
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
     use tokio::sync::mpsc;
     use tokio::sync::Mutex;
@@ -221,6 +757,9 @@ mod tests {
         events_to_dispatch: Vec<Event>,
         event_sender: mpsc::Sender<Option<Event>>,
         event_receiver: Arc<Mutex<mpsc::Receiver<Option<Event>>>>,
+        active_subscriptions: Arc<AtomicUsize>,
+        max_active_subscriptions: Arc<AtomicUsize>,
+        relays: Arc<Mutex<Vec<Url>>>,
     }
 
     impl TestNostrService {
@@ -231,6 +770,11 @@ mod tests {
                 events_to_dispatch,
                 event_sender,
                 event_receiver: Arc::new(Mutex::new(event_receiver)),
+                active_subscriptions: Arc::new(AtomicUsize::new(0)),
+                max_active_subscriptions: Arc::new(AtomicUsize::new(0)),
+                relays: Arc::new(Mutex::new(vec![
+                    Url::parse("wss://relay.example.com").unwrap()
+                ])),
             }
         }
 
@@ -251,12 +795,90 @@ mod tests {
         async fn reconnect(&self) -> Result<()> {
             Ok(())
         }
-        async fn publish(&self, _event: Event) -> Result<()> {
+        async fn is_connected(&self) -> bool {
+            true
+        }
+        async fn connected_relays(&self) -> (usize, usize) {
+            (1, 1)
+        }
+        async fn relay_connections(&self) -> Vec<(Url, bool)> {
+            self.relays
+                .lock()
+                .await
+                .iter()
+                .map(|relay| (relay.clone(), true))
+                .collect()
+        }
+        async fn reconnect_relays(&self, _relays: Vec<Url>) -> Result<()> {
+            Ok(())
+        }
+        async fn publish(&self, _event: Event) -> Result<PublishOutcome> {
+            Ok(PublishOutcome {
+                accepted: self.relays.lock().await.clone(),
+                ..Default::default()
+            })
+        }
+        async fn publish_to(&self, _event: Event, _relays: Vec<String>) -> Result<()> {
+            Ok(())
+        }
+        async fn publish_direct_message(
+            &self,
+            _gift_wrap: Event,
+            _receiver: PublicKey,
+        ) -> Result<()> {
+            Ok(())
+        }
+        async fn set_reportinator_public_keys(&self, _public_keys: Vec<PublicKey>) -> Result<()> {
             Ok(())
         }
 
-        async fn get_nip05(&self, _public_key: PublicKey) -> Option<String> {
-            None
+        async fn get_nip05(&self, _public_key: PublicKey) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        async fn get_events(&self, filters: Vec<Filter>) -> Result<Vec<Event>> {
+            Ok(self
+                .events_to_dispatch
+                .iter()
+                .filter(|event| filters.iter().any(|filter| filter.match_event(event)))
+                .cloned()
+                .collect())
+        }
+
+        async fn fetch_range(
+            &self,
+            since: Timestamp,
+            until: Timestamp,
+            limit: usize,
+        ) -> Result<Vec<Event>> {
+            let mut events: Vec<Event> = self
+                .events_to_dispatch
+                .iter()
+                .filter(|event| event.created_at >= since && event.created_at <= until)
+                .cloned()
+                .collect();
+            events.sort_by_key(|event| std::cmp::Reverse(event.created_at));
+            events.truncate(limit);
+
+            Ok(events)
+        }
+
+        async fn add_relay(&self, url: Url) -> Result<bool> {
+            let mut relays = self.relays.lock().await;
+            if relays.contains(&url) {
+                return Ok(false);
+            }
+
+            relays.push(url);
+            Ok(true)
+        }
+
+        async fn remove_relay(&self, url: Url) -> Result<bool> {
+            let mut relays = self.relays.lock().await;
+            let relays_before = relays.len();
+            relays.retain(|relay| relay != &url);
+
+            Ok(relays.len() < relays_before)
         }
 
         async fn subscribe(
@@ -264,6 +886,10 @@ mod tests {
             cancellation_token: CancellationToken,
             dispatcher_actor: ActorRef<RelayEventDispatcherMessage>,
         ) -> Result<(), anyhow::Error> {
+            let active_subscriptions = self.active_subscriptions.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_active_subscriptions
+                .fetch_max(active_subscriptions, Ordering::SeqCst);
+
             let event_sender_clone = self.event_sender.clone();
             tokio::spawn(async move {
                 cancellation_token.cancelled().await;
@@ -278,6 +904,7 @@ mod tests {
                 .expect("Failed to cast event to dispatcher");
             }
 
+            self.active_subscriptions.fetch_sub(1, Ordering::SeqCst);
             Ok(())
         }
     }
@@ -298,7 +925,7 @@ mod tests {
         let (dispatcher_ref, dispatcher_handle) = Actor::spawn(
             None,
             RelayEventDispatcher::default(),
-            test_nostr_subscriber.clone(),
+            (test_nostr_subscriber.clone(), None, Config::default()),
         )
         .await
         .unwrap();
@@ -335,4 +962,306 @@ mod tests {
             [first_event, second_event]
         );
     }
+
+    #[tokio::test]
+    async fn test_drops_events_of_unexpected_kinds() {
+        let gift_wrap = EventBuilder::new(Kind::GiftWrap, "Expected", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let text_note = EventBuilder::text_note("Pushed by a misbehaving relay", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        let mut test_nostr_subscriber = TestNostrService::new(vec![gift_wrap.clone(), text_note]);
+
+        let (dispatcher_ref, dispatcher_handle) = Actor::spawn(
+            None,
+            RelayEventDispatcher::default(),
+            (test_nostr_subscriber.clone(), None, Config::default()),
+        )
+        .await
+        .unwrap();
+
+        let received_messages = Arc::new(Mutex::new(Vec::<Event>::new()));
+        let (receiver_ref, receiver_handle) =
+            Actor::spawn(None, TestActor::default(), Some(received_messages.clone()))
+                .await
+                .unwrap();
+
+        cast!(
+            dispatcher_ref,
+            RelayEventDispatcherMessage::SubscribeToEventReceived(Box::new(receiver_ref.clone()))
+        )
+        .unwrap();
+
+        cast!(dispatcher_ref, RelayEventDispatcherMessage::Connect).unwrap();
+
+        test_nostr_subscriber.next_event().await.unwrap();
+        test_nostr_subscriber.next_event().await.unwrap();
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        dispatcher_ref.stop(None);
+        receiver_ref.stop(None);
+        dispatcher_handle.await.unwrap();
+        receiver_handle.await.unwrap();
+
+        assert_eq!(received_messages.lock().await.as_ref(), [gift_wrap]);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_reinjects_events_in_range() {
+        let now = Timestamp::now();
+        let gift_wrap_created_at = |secs_ago: u64| {
+            EventBuilder::new(Kind::GiftWrap, "Missed", [])
+                .custom_created_at(now - secs_ago)
+                .to_event(&Keys::generate())
+                .unwrap()
+        };
+        let too_old = gift_wrap_created_at(1000);
+        let older = gift_wrap_created_at(500);
+        let newer = gift_wrap_created_at(200);
+        let too_new = gift_wrap_created_at(10);
+
+        let test_nostr_subscriber =
+            TestNostrService::new(vec![too_new, newer.clone(), too_old, older.clone()]);
+
+        let (dispatcher_ref, dispatcher_handle) = Actor::spawn(
+            None,
+            RelayEventDispatcher::default(),
+            (test_nostr_subscriber, None, Config::default()),
+        )
+        .await
+        .unwrap();
+
+        let received_messages = Arc::new(Mutex::new(Vec::<Event>::new()));
+        let (receiver_ref, receiver_handle) =
+            Actor::spawn(None, TestActor::default(), Some(received_messages.clone()))
+                .await
+                .unwrap();
+
+        cast!(
+            dispatcher_ref,
+            RelayEventDispatcherMessage::SubscribeToEventReceived(Box::new(receiver_ref.clone()))
+        )
+        .unwrap();
+
+        cast!(
+            dispatcher_ref,
+            RelayEventDispatcherMessage::Backfill(now - 600, now - 100, 10)
+        )
+        .unwrap();
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        dispatcher_ref.stop(None);
+        receiver_ref.stop(None);
+        dispatcher_handle.await.unwrap();
+        receiver_handle.await.unwrap();
+
+        assert_eq!(received_messages.lock().await.as_ref(), [older, newer]);
+    }
+
+    #[tokio::test]
+    async fn test_rapid_reconnects_keep_a_single_subscription_task() {
+        let test_nostr_subscriber = TestNostrService::new(vec![]);
+
+        let (dispatcher_ref, dispatcher_handle) = Actor::spawn(
+            None,
+            RelayEventDispatcher::default(),
+            (test_nostr_subscriber.clone(), None, Config::default()),
+        )
+        .await
+        .unwrap();
+
+        cast!(dispatcher_ref, RelayEventDispatcherMessage::Connect).unwrap();
+        for _ in 0..5 {
+            cast!(dispatcher_ref, RelayEventDispatcherMessage::Reconnect).unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(
+            test_nostr_subscriber
+                .active_subscriptions
+                .load(Ordering::SeqCst),
+            1
+        );
+        assert_eq!(
+            test_nostr_subscriber
+                .max_active_subscriptions
+                .load(Ordering::SeqCst),
+            1
+        );
+
+        dispatcher_ref.stop(None);
+        dispatcher_handle.await.unwrap();
+
+        assert_eq!(
+            test_nostr_subscriber
+                .active_subscriptions
+                .load(Ordering::SeqCst),
+            0
+        );
+    }
+
+    type ChangeRelays = fn(Url, RpcReplyPort<Result<bool, String>>) -> RelayEventDispatcherMessage;
+
+    async fn change_relays(
+        dispatcher_ref: &ActorRef<RelayEventDispatcherMessage>,
+        message: ChangeRelays,
+        url: &Url,
+    ) -> Result<bool, String> {
+        call_t!(dispatcher_ref, message, 100, url.clone()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_relay_changes_dont_resubscribe() {
+        let test_nostr_subscriber = TestNostrService::new(vec![]);
+
+        let (dispatcher_ref, dispatcher_handle) = Actor::spawn(
+            None,
+            RelayEventDispatcher::default(),
+            (test_nostr_subscriber.clone(), None, Config::default()),
+        )
+        .await
+        .unwrap();
+
+        cast!(dispatcher_ref, RelayEventDispatcherMessage::Connect).unwrap();
+
+        let new_relay = Url::parse("wss://new.example.com").unwrap();
+        let existing_relay = Url::parse("wss://relay.example.com").unwrap();
+        let add = RelayEventDispatcherMessage::AddRelay as ChangeRelays;
+        let remove = RelayEventDispatcherMessage::RemoveRelay as ChangeRelays;
+        // Duplicates and relays that are already gone leave things unchanged
+        for (message, url, changed) in [
+            (add, &new_relay, true),
+            (add, &new_relay, false),
+            (remove, &existing_relay, true),
+            (remove, &existing_relay, false),
+        ] {
+            assert_eq!(
+                change_relays(&dispatcher_ref, message, url).await,
+                Ok(changed)
+            );
+        }
+
+        assert_eq!(
+            test_nostr_subscriber.relays.lock().await.as_ref(),
+            [new_relay]
+        );
+        assert_eq!(
+            test_nostr_subscriber
+                .max_active_subscriptions
+                .load(Ordering::SeqCst),
+            1
+        );
+
+        dispatcher_ref.stop(None);
+        dispatcher_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retracts_only_reports_we_published() {
+        let reportinator_keys = Keys::generate();
+        let our_report = EventBuilder::new(Kind::Reporting, "Spam.", [])
+            .to_event(&reportinator_keys)
+            .unwrap();
+        let someone_elses_report = EventBuilder::new(Kind::Reporting, "Spam.", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let not_a_report = EventBuilder::text_note("Hello", [])
+            .to_event(&reportinator_keys)
+            .unwrap();
+
+        let nostr_client = TestNostrService::new(vec![
+            our_report.clone(),
+            someone_elses_report.clone(),
+            not_a_report.clone(),
+        ]);
+        let report_kinds = [Kind::Reporting];
+
+        assert!(retract(
+            &nostr_client,
+            our_report.id,
+            &reportinator_keys,
+            &report_kinds
+        )
+        .await
+        .is_ok());
+        for report_id in [
+            someone_elses_report.id,
+            not_a_report.id,
+            EventId::all_zeros(),
+        ] {
+            assert!(
+                retract(&nostr_client, report_id, &reportinator_keys, &report_kinds)
+                    .await
+                    .is_err()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publishes_reports_accepted_by_some_relays() {
+        let url = |url: &str| Url::parse(url).unwrap();
+        let nostr_client = ScriptedNostrService::default();
+        let report = EventBuilder::new(Kind::Reporting, "Spam.", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let moderated_report: ModeratedReport =
+            serde_json::from_value(serde_json::json!({ "events": [report] })).unwrap();
+
+        nostr_client
+            .set_publish_outcome(PublishOutcome {
+                accepted: vec![url("wss://accepted.example.com")],
+                rejected: vec![(url("wss://rejected.example.com"), "blocked".to_string())],
+                timed_out: vec![url("wss://slow.example.com")],
+            })
+            .await;
+        assert!(publish_report(&nostr_client, &moderated_report).await);
+
+        nostr_client
+            .set_publish_outcome(PublishOutcome {
+                rejected: vec![(url("wss://rejected.example.com"), "blocked".to_string())],
+                timed_out: vec![url("wss://slow.example.com")],
+                ..Default::default()
+            })
+            .await;
+        assert!(!publish_report(&nostr_client, &moderated_report).await);
+    }
+
+    #[tokio::test]
+    async fn test_slow_publishes_dont_hold_up_the_dispatcher() {
+        let nostr_client = ScriptedNostrService::default();
+        nostr_client
+            .set_publish_delay(Duration::from_millis(300))
+            .await;
+        let report = EventBuilder::new(Kind::Reporting, "Spam.", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let moderated_report: ModeratedReport =
+            serde_json::from_value(serde_json::json!({ "events": [report] })).unwrap();
+
+        let (dispatcher_ref, dispatcher_handle) = Actor::spawn(
+            None,
+            RelayEventDispatcher::default(),
+            (nostr_client.clone(), None, Config::default()),
+        )
+        .await
+        .unwrap();
+
+        cast!(
+            dispatcher_ref,
+            RelayEventDispatcherMessage::Publish(moderated_report)
+        )
+        .unwrap();
+        let check = call_t!(dispatcher_ref, RelayEventDispatcherMessage::SelfTest, 100).unwrap();
+        assert!(check.ok);
+        assert!(nostr_client.published_events().await.is_empty());
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(nostr_client.published_events().await.len(), 1);
+
+        dispatcher_ref.stop(None);
+        dispatcher_handle.await.unwrap();
+    }
 }
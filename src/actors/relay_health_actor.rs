@@ -0,0 +1,388 @@
+/// This module contains the RelayHealthActor, which checks the relay
+/// connections on an interval and connects the disconnected relays again when
+/// too few are connected. The dispatcher only reconnects by itself once the
+/// subscription loop returns, which silent disconnects never trigger
+use crate::actors::messages::RelayHealthMessage;
+use crate::actors::utilities::mailbox;
+use crate::actors::utilities::relay_status;
+use crate::actors::NostrPort;
+use crate::config::Configurable;
+use crate::service_manager::actor_name;
+use anyhow::{bail, Result};
+use metrics::counter;
+use nostr_sdk::prelude::Url;
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_check_interval_secs")]
+    pub check_interval_secs: u64,
+    // Connected share of our relays, from 0 to 1, below which we reconnect
+    #[serde(default = "default_min_connected_ratio")]
+    pub min_connected_ratio: f64,
+    // Wait after a reconnect before the next one, doubled on every reconnect
+    // that didn't make us healthy again
+    #[serde(default = "default_reconnect_backoff_secs")]
+    pub reconnect_backoff_secs: u64,
+    #[serde(default = "default_max_reconnect_backoff_secs")]
+    pub max_reconnect_backoff_secs: u64,
+    // Relays seen for less than this may still be connecting for the first
+    // time, they don't count until connected
+    #[serde(default = "default_new_relay_grace_secs")]
+    pub new_relay_grace_secs: u64,
+}
+
+fn default_check_interval_secs() -> u64 {
+    60
+}
+
+fn default_min_connected_ratio() -> f64 {
+    0.5
+}
+
+fn default_reconnect_backoff_secs() -> u64 {
+    60
+}
+
+fn default_max_reconnect_backoff_secs() -> u64 {
+    960
+}
+
+fn default_new_relay_grace_secs() -> u64 {
+    120
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "relay_health"
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.check_interval_secs == 0 {
+            bail!("check_interval_secs must be greater than 0");
+        }
+        if !(0.0..=1.0).contains(&self.min_connected_ratio) {
+            bail!("min_connected_ratio must be between 0 and 1");
+        }
+        if self.max_reconnect_backoff_secs < self.reconnect_backoff_secs {
+            bail!("max_reconnect_backoff_secs must be at least reconnect_backoff_secs");
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval_secs)
+    }
+
+    // Without relays there's nothing to reconnect to
+    fn is_healthy(&self, connected: usize, total: usize) -> bool {
+        total == 0 || (connected as f64 / total as f64) >= self.min_connected_ratio
+    }
+}
+
+// What the checks remember between them
+struct Health {
+    config: Config,
+    // When each of our relays was first seen
+    first_seen: HashMap<Url, Instant>,
+    // No reconnecting before then
+    next_reconnect_at: Option<Instant>,
+    // Wait after the next reconnect
+    backoff: Duration,
+}
+
+impl Health {
+    fn new(config: Config) -> Self {
+        Self {
+            backoff: Duration::from_secs(config.reconnect_backoff_secs),
+            config,
+            first_seen: HashMap::new(),
+            next_reconnect_at: None,
+        }
+    }
+
+    // The disconnected relays to connect again, none while enough are
+    // connected or the backoff of the previous reconnect lasts
+    fn check(&mut self, relays: &[(Url, bool)], now: Instant) -> Vec<Url> {
+        self.first_seen
+            .retain(|seen, _| relays.iter().any(|(url, _)| url == seen));
+        for (url, _) in relays {
+            self.first_seen.entry(url.clone()).or_insert(now);
+        }
+
+        let grace = Duration::from_secs(self.config.new_relay_grace_secs);
+        let settled: Vec<&(Url, bool)> = relays
+            .iter()
+            .filter(|(url, connected)| {
+                *connected
+                    || self
+                        .first_seen
+                        .get(url)
+                        .is_some_and(|first_seen| now.duration_since(*first_seen) >= grace)
+            })
+            .collect();
+        let connected = settled.iter().filter(|(_, connected)| *connected).count();
+
+        if self.config.is_healthy(connected, settled.len()) {
+            self.next_reconnect_at = None;
+            self.backoff = Duration::from_secs(self.config.reconnect_backoff_secs);
+            return vec![];
+        }
+
+        if self.next_reconnect_at.is_some_and(|at| now < at) {
+            counter!("relay_health_backoff").increment(1);
+            return vec![];
+        }
+
+        self.next_reconnect_at = Some(now + self.backoff);
+        self.backoff =
+            (self.backoff * 2).min(Duration::from_secs(self.config.max_reconnect_backoff_secs));
+
+        settled
+            .into_iter()
+            .filter(|(_, connected)| !connected)
+            .map(|(url, _)| url.clone())
+            .collect()
+    }
+}
+
+pub struct RelayHealthActor<T> {
+    _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T> Default for RelayHealthActor<T> {
+    fn default() -> Self {
+        Self {
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+pub struct State<T: NostrPort> {
+    nostr_client: T,
+    health: Health,
+}
+
+#[ractor::async_trait]
+impl<T: NostrPort> Actor for RelayHealthActor<T> {
+    type Msg = RelayHealthMessage;
+    type State = State<T>;
+    type Arguments = (T, Config);
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        (nostr_client, config): (T, Config),
+    ) -> Result<Self::State, ActorProcessingErr> {
+        mailbox::register::<Self::Msg>(actor_name::<Self>());
+        myself.send_interval(config.check_interval(), || RelayHealthMessage::Check);
+
+        Ok(State {
+            nostr_client,
+            health: Health::new(config),
+        })
+    }
+
+    async fn handle(
+        &self,
+        _: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        let _handling = mailbox::handling(&message);
+        match message {
+            RelayHealthMessage::Check => {
+                let relays = state.nostr_client.relay_connections().await;
+                let connected = relays.iter().filter(|(_, connected)| *connected).count();
+                relay_status::record(connected, relays.len());
+
+                let disconnected = state.health.check(&relays, Instant::now());
+                if disconnected.is_empty() {
+                    return Ok(());
+                }
+
+                counter!("relay_health_reconnect").increment(1);
+                warn!(
+                    "Only {} of {} relays are connected, reconnecting {}",
+                    connected,
+                    relays.len(),
+                    disconnected
+                        .iter()
+                        .map(|url| url.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+
+                if let Err(e) = state.nostr_client.reconnect_relays(disconnected).await {
+                    error!("Failed to reconnect the disconnected relays: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::ScriptedNostrService;
+    use ractor::cast;
+
+    fn config(min_connected_ratio: f64) -> Config {
+        Config {
+            check_interval_secs: 60,
+            min_connected_ratio,
+            reconnect_backoff_secs: 60,
+            max_reconnect_backoff_secs: 240,
+            new_relay_grace_secs: 0,
+        }
+    }
+
+    fn relays(connected: &[bool]) -> Vec<(Url, bool)> {
+        connected
+            .iter()
+            .enumerate()
+            .map(|(i, connected)| {
+                let url = Url::parse(&format!("wss://relay{}.example.com", i)).unwrap();
+                (url, *connected)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_the_disconnected_relays_when_too_few_are_connected() {
+        let nostr_client = ScriptedNostrService::default();
+        nostr_client
+            .set_relays(relays(&[true, true, false, false]))
+            .await;
+
+        let (relay_health_ref, relay_health_handle) = Actor::spawn(
+            None,
+            RelayHealthActor::default(),
+            (nostr_client.clone(), config(0.5)),
+        )
+        .await
+        .unwrap();
+
+        // Half of them is still enough
+        cast!(relay_health_ref, RelayHealthMessage::Check).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(nostr_client.reconnected_relays().await.is_empty());
+
+        // A silent disconnect, the subscription loop never returned
+        let relays = relays(&[true, false, false, false]);
+        nostr_client.set_relays(relays.clone()).await;
+        cast!(relay_health_ref, RelayHealthMessage::Check).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            nostr_client.reconnected_relays().await,
+            relays[1..]
+                .iter()
+                .map(|(url, _)| url.clone())
+                .collect::<Vec<_>>()
+        );
+
+        // Still backing off from that reconnect
+        cast!(relay_health_ref, RelayHealthMessage::Check).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(nostr_client.reconnected_relays().await.len(), 3);
+
+        relay_health_ref.stop(None);
+        relay_health_handle.await.unwrap();
+    }
+
+    #[test]
+    fn test_reconnects_back_off_until_healthy() {
+        let mut health = Health::new(config(0.5));
+        let unhealthy = relays(&[true, false, false]);
+        let start = Instant::now();
+
+        assert_eq!(health.check(&unhealthy, start).len(), 2);
+        assert!(health
+            .check(&unhealthy, start + Duration::from_secs(59))
+            .is_empty());
+        assert_eq!(
+            health
+                .check(&unhealthy, start + Duration::from_secs(60))
+                .len(),
+            2
+        );
+        // Doubled after a reconnect that didn't help
+        assert!(health
+            .check(&unhealthy, start + Duration::from_secs(179))
+            .is_empty());
+        assert_eq!(
+            health
+                .check(&unhealthy, start + Duration::from_secs(180))
+                .len(),
+            2
+        );
+
+        // Healthy again, the next reconnect is right away
+        assert!(health
+            .check(
+                &relays(&[true, true, false]),
+                start + Duration::from_secs(181)
+            )
+            .is_empty());
+        assert_eq!(
+            health
+                .check(&unhealthy, start + Duration::from_secs(182))
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_new_relays_get_a_grace_period() {
+        let mut health = Health::new(Config {
+            new_relay_grace_secs: 120,
+            ..config(0.5)
+        });
+        let start = Instant::now();
+        let mut connections = relays(&[true, true]);
+        health.check(&connections, start);
+
+        // Added later and not connected yet, they don't count
+        connections.extend((0..3).map(|i| {
+            let url = Url::parse(&format!("wss://new{}.example.com", i)).unwrap();
+            (url, false)
+        }));
+        let added = start + Duration::from_secs(300);
+        assert!(health.check(&connections, added).is_empty());
+        assert!(health
+            .check(&connections, added + Duration::from_secs(119))
+            .is_empty());
+
+        // Still not connected once the grace period is over
+        assert_eq!(
+            health
+                .check(&connections, added + Duration::from_secs(120))
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_health_thresholds() {
+        assert!(config(0.5).is_healthy(0, 0));
+        assert!(config(0.0).is_healthy(0, 3));
+        assert!(!config(0.5).is_healthy(0, 3));
+        assert!(config(1.0).is_healthy(3, 3));
+        assert!(!config(1.0).is_healthy(2, 3));
+        assert!(config(1.5).validate().is_err());
+        assert!(Config {
+            max_reconnect_backoff_secs: 30,
+            ..config(0.5)
+        }
+        .validate()
+        .is_err());
+    }
+}
@@ -0,0 +1,415 @@
+/// This module contains the RetryActor, which gives report requests that
+/// failed downstream a few more tries before moving them to a dead letter queue
+use crate::actors::messages::{
+    EventEnqueuerMessage, NotificationWriterMessage, RetryDestination, RetryMessage,
+};
+use crate::actors::utilities::{mailbox, pending_work};
+use crate::config::Configurable;
+use crate::dead_letter_queue::DeadLetterQueue;
+use crate::domain_objects::ReportRequest;
+use crate::service_manager::actor_name;
+use metrics::counter;
+use ractor::{Actor, ActorProcessingErr, ActorRef};
+use serde::Deserialize;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Attempts, the first one included, before giving up on a report request
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    // Delay before the first retry, doubled on each following one
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    1000
+}
+
+fn default_max_backoff_secs() -> u64 {
+    300
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "retry"
+    }
+}
+
+impl Config {
+    // Backoff after the given failed attempt
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = Duration::from_millis(
+            self.initial_backoff_ms
+                .saturating_mul(2u64.saturating_pow(exponent)),
+        );
+
+        delay.min(Duration::from_secs(self.max_backoff_secs))
+    }
+}
+
+pub struct RetryActor;
+
+pub struct State {
+    config: Config,
+    dead_letter_queue: Option<DeadLetterQueue>,
+}
+
+impl State {
+    fn give_up(&self, report_request: &ReportRequest, destination: &RetryDestination) {
+        counter!("retry_exhausted", "destination" => destination.name()).increment(1);
+        error!(
+            "Giving up on report request {} for {}",
+            report_request.target(),
+            destination.name()
+        );
+
+        let Some(dead_letter_queue) = &self.dead_letter_queue else {
+            return;
+        };
+
+        if let Err(e) = dead_letter_queue.append(report_request) {
+            error!(
+                "Failed to add report request to the dead letter queue: {}",
+                e
+            );
+        }
+    }
+
+    // Lets the report requests held behind it through. Fails if the
+    // destination is gone, nothing is held then anyway
+    fn release(report_request: &ReportRequest, destination: &RetryDestination) {
+        if let RetryDestination::EventEnqueuer(event_enqueuer) = destination {
+            let _ = event_enqueuer.cast(EventEnqueuerMessage::GaveUp(report_request.clone()));
+        }
+    }
+}
+
+#[ractor::async_trait]
+impl Actor for RetryActor {
+    type Msg = RetryMessage;
+    type State = State;
+    type Arguments = (Option<DeadLetterQueue>, Config);
+
+    async fn pre_start(
+        &self,
+        _: ActorRef<Self::Msg>,
+        (dead_letter_queue, config): (Option<DeadLetterQueue>, Config),
+    ) -> Result<Self::State, ActorProcessingErr> {
+        mailbox::register::<Self::Msg>(actor_name::<Self>());
+
+        Ok(State {
+            config,
+            dead_letter_queue,
+        })
+    }
+
+    async fn handle(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        let _handling = mailbox::handling(&message);
+        match message {
+            RetryMessage::Failed(report_request, destination, attempt) => {
+                if attempt >= state.config.max_attempts {
+                    state.give_up(&report_request, &destination);
+                    State::release(&report_request, &destination);
+                    return Ok(());
+                }
+
+                let delay = state.config.backoff(attempt);
+                counter!("retry_scheduled", "destination" => destination.name()).increment(1);
+                info!(
+                    "Retrying report request {} for {} in {:?}",
+                    report_request.target(),
+                    destination.name(),
+                    delay
+                );
+
+                pending_work::retry_scheduled();
+                myself.send_after(delay, move || {
+                    RetryMessage::Redispatch(report_request, destination, attempt + 1)
+                });
+            }
+            RetryMessage::Redispatch(report_request, destination, attempt) => {
+                pending_work::retry_redispatched();
+                let redispatched = match &destination {
+                    RetryDestination::EventEnqueuer(event_enqueuer) => event_enqueuer
+                        .cast(EventEnqueuerMessage::Retry(report_request.clone(), attempt))
+                        .is_ok(),
+                    RetryDestination::NotificationWriter(notification_writer, sink) => {
+                        notification_writer
+                            .cast(NotificationWriterMessage::Retry(
+                                report_request.clone(),
+                                sink.clone(),
+                                attempt,
+                            ))
+                            .is_ok()
+                    }
+                };
+
+                // The destination stopped while the report request waited
+                if !redispatched {
+                    warn!(
+                        "Couldn't send report request {} back to {}",
+                        report_request.target(),
+                        destination.name()
+                    );
+                    state.give_up(&report_request, &destination);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::utilities::auto_skip::AutoSkipRules;
+    use crate::actors::utilities::reporter_queue::{Config as ReporterQueueConfig, ReporterQueue};
+    use crate::actors::{EventEnqueuer, PubsubPort};
+    use crate::dead_letter_queue::Config as DeadLetterConfig;
+    use anyhow::{bail, Result};
+    use nostr_sdk::prelude::*;
+    use ractor::cast;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    // Fails the given number of publish calls before succeeding
+    #[derive(Clone)]
+    struct FlakyPublisher {
+        failures_left: Arc<Mutex<u32>>,
+        published_events: Arc<Mutex<Vec<ReportRequest>>>,
+    }
+
+    impl FlakyPublisher {
+        fn failing(failures: u32) -> Self {
+            Self {
+                failures_left: Arc::new(Mutex::new(failures)),
+                published_events: Arc::new(Mutex::new(Vec::new())),
+            }
+        }
+    }
+
+    #[ractor::async_trait]
+    impl PubsubPort for FlakyPublisher {
+        async fn publish_event(&mut self, event: &ReportRequest) -> Result<()> {
+            let mut failures_left = self.failures_left.lock().await;
+            if *failures_left > 0 {
+                *failures_left -= 1;
+                bail!("Pub/Sub is down");
+            }
+
+            self.published_events.lock().await.push(event.clone());
+            Ok(())
+        }
+    }
+
+    fn config(max_attempts: u32) -> Config {
+        Config {
+            max_attempts,
+            initial_backoff_ms: 10,
+            max_backoff_secs: 1,
+        }
+    }
+
+    fn dead_letter_queue(name: &str) -> (DeadLetterQueue, std::path::PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("{}_{}.jsonl", name, Timestamp::now().as_u64()));
+        let _ = std::fs::remove_file(&path);
+
+        let dead_letter_queue = DeadLetterQueue::for_report_requests(&DeadLetterConfig {
+            path: String::new(),
+            report_requests_path: path.to_string_lossy().to_string(),
+        });
+        (dead_letter_queue, path)
+    }
+
+    fn report_request() -> ReportRequest {
+        report_request_from(Keys::generate().public_key())
+    }
+
+    fn report_request_from(reporter_pubkey: PublicKey) -> ReportRequest {
+        let reported_event = EventBuilder::text_note("I hate you!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        ReportRequest::new(reported_event.into(), reporter_pubkey, None)
+    }
+
+    async fn enqueue_with_retries(
+        publisher: FlakyPublisher,
+        max_attempts: u32,
+        dead_letter_queue: DeadLetterQueue,
+        report_requests: Vec<ReportRequest>,
+        reporter_queue: ReporterQueue,
+    ) {
+        let (retry_actor_ref, retry_actor_handle) = Actor::spawn(
+            None,
+            RetryActor,
+            (Some(dead_letter_queue), config(max_attempts)),
+        )
+        .await
+        .unwrap();
+
+        let (event_enqueuer_ref, event_enqueuer_handle) = Actor::spawn(
+            None,
+            EventEnqueuer::default(),
+            (
+                publisher,
+                Some(retry_actor_ref.clone()),
+                AutoSkipRules::default(),
+                reporter_queue,
+            ),
+        )
+        .await
+        .unwrap();
+
+        for report_request in report_requests {
+            cast!(
+                event_enqueuer_ref,
+                EventEnqueuerMessage::Enqueue(report_request)
+            )
+            .unwrap();
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        event_enqueuer_ref.stop(None);
+        retry_actor_ref.stop(None);
+        event_enqueuer_handle.await.unwrap();
+        retry_actor_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_the_publish_succeeds() {
+        let publisher = FlakyPublisher::failing(2);
+        let (dead_letter_queue, path) = dead_letter_queue("test_retries_until_success");
+        let report_request = report_request();
+
+        enqueue_with_retries(
+            publisher.clone(),
+            3,
+            dead_letter_queue.clone(),
+            vec![report_request.clone()],
+            ReporterQueue::default(),
+        )
+        .await;
+
+        assert_eq!(
+            publisher.published_events.lock().await.as_ref(),
+            [report_request]
+        );
+        assert!(dead_letter_queue
+            .read::<ReportRequest>()
+            .unwrap()
+            .is_empty());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_to_the_dead_letter_queue() {
+        let publisher = FlakyPublisher::failing(u32::MAX);
+        let (dead_letter_queue, path) = dead_letter_queue("test_gives_up_to_dlq");
+        let report_request = report_request();
+
+        enqueue_with_retries(
+            publisher.clone(),
+            3,
+            dead_letter_queue.clone(),
+            vec![report_request.clone()],
+            ReporterQueue::default(),
+        )
+        .await;
+
+        assert!(publisher.published_events.lock().await.is_empty());
+        assert_eq!(*publisher.failures_left.lock().await, u32::MAX - 3);
+        assert_eq!(
+            dead_letter_queue.read::<ReportRequest>().unwrap(),
+            vec![report_request]
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_keeps_the_order_of_each_reporter() {
+        let publisher = FlakyPublisher::failing(1);
+        let (dead_letter_queue, path) = dead_letter_queue("test_keeps_reporter_order");
+        let reporter_pubkey = Keys::generate().public_key();
+        let first_request = report_request_from(reporter_pubkey);
+        let second_request = report_request_from(reporter_pubkey);
+        let other_reporter_request = report_request();
+
+        enqueue_with_retries(
+            publisher.clone(),
+            3,
+            dead_letter_queue,
+            vec![
+                first_request.clone(),
+                second_request.clone(),
+                other_reporter_request.clone(),
+            ],
+            ReporterQueue::new(&ReporterQueueConfig { per_reporter: true }),
+        )
+        .await;
+
+        // The second request waited for the first one to be retried, the
+        // other reporter didn't
+        assert_eq!(
+            publisher.published_events.lock().await.as_ref(),
+            [other_reporter_request, first_request, second_request]
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_releases_the_reporter_after_giving_up() {
+        let publisher = FlakyPublisher::failing(3);
+        let (dead_letter_queue, path) = dead_letter_queue("test_releases_reporter");
+        let reporter_pubkey = Keys::generate().public_key();
+        let first_request = report_request_from(reporter_pubkey);
+        let second_request = report_request_from(reporter_pubkey);
+
+        enqueue_with_retries(
+            publisher.clone(),
+            3,
+            dead_letter_queue.clone(),
+            vec![first_request.clone(), second_request.clone()],
+            ReporterQueue::new(&ReporterQueueConfig { per_reporter: true }),
+        )
+        .await;
+
+        assert_eq!(
+            publisher.published_events.lock().await.as_ref(),
+            [second_request]
+        );
+        assert_eq!(
+            dead_letter_queue.read::<ReportRequest>().unwrap(),
+            vec![first_request]
+        );
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_max() {
+        let config = config(10);
+
+        assert_eq!(config.backoff(1), Duration::from_millis(10));
+        assert_eq!(config.backoff(2), Duration::from_millis(20));
+        assert_eq!(config.backoff(3), Duration::from_millis(40));
+        assert_eq!(config.backoff(40), Duration::from_secs(1));
+    }
+}
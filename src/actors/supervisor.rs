@@ -1,14 +1,75 @@
 use crate::actors::{
-    messages::{GiftUnwrapperMessage, RelayEventDispatcherMessage, SupervisorMessage},
-    EventEnqueuer, GiftUnwrapper, NostrPort, PubsubPort, RelayEventDispatcher,
-    SlackClientPortBuilder, SlackWriter,
+    gift_unwrapper,
+    messages::{
+        EventEnqueuerMessage, GiftUnwrapperMessage, RelayEventDispatcherMessage, SupervisorMessage,
+    },
+    notification_writer::{SinkChecks, WEBHOOK_METRICS},
+    utilities::{
+        auto_skip::AutoSkipRules,
+        dedup::SeenIds,
+        language_detection::LanguageDetector,
+        mailbox,
+        output_port_subscriber::subscribe_with,
+        quiet_hours::QuietHours,
+        reporter_queue::ReporterQueue,
+        resolved_reports::ResolvedReports,
+        self_test::{DependencyCheck, CHECK_TIMEOUT},
+    },
+    ContentTracker, EventEnqueuer, GiftUnwrapper, NostrPort, NotificationPort, NotificationSink,
+    NotificationWriter, PubsubPort, RelayEventDispatcher, RelayHealthActor, RetryActor,
+    SlackNotifierBuilder,
 };
-use crate::config::Config;
-use anyhow::Result;
+use crate::adapters::slack_client_adapter::Config as SlackConfig;
+use crate::adapters::WebhookNotifier;
+use crate::config::{self, Config, Configurable, NotificationBackend, NotificationsConfig};
+use crate::dead_letter_queue::{self, DeadLetterQueue};
+use crate::domain_objects::{as_gift_wrap::gift_wrapped_message, ReportRequest};
+use anyhow::{anyhow, bail, Result};
 use metrics::counter;
 use nostr_sdk::prelude::*;
 use ractor::{call_t, cast, Actor, ActorProcessingErr, ActorRef, SupervisionEvent};
-use tracing::error;
+use serde::Deserialize;
+use tracing::{error, info};
+
+/// When the reporter gets a gift wrapped acknowledgment DM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AckTrigger {
+    // Once the gift wrap is unwrapped into a valid report request
+    Acceptance,
+    // Once a moderator reports or skips it in Slack
+    Decision,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AckConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_send_on")]
+    pub send_on: AckTrigger,
+    #[serde(default = "default_message")]
+    pub message: String,
+}
+
+fn default_send_on() -> AckTrigger {
+    AckTrigger::Decision
+}
+
+fn default_message() -> String {
+    "Thanks for your report, our moderators will take it from here.".to_string()
+}
+
+impl Configurable for AckConfig {
+    fn key() -> &'static str {
+        "acknowledgment"
+    }
+}
+
+impl AckConfig {
+    fn should_send(&self, trigger: AckTrigger) -> bool {
+        self.enabled && self.send_on == trigger
+    }
+}
 
 pub struct Supervisor<T, U, V> {
     config: Config,
@@ -22,6 +83,68 @@ impl<T, U, V> Supervisor<T, U, V> {
             _phantom: std::marker::PhantomData,
         }
     }
+
+    // The unwrapper gets the new keys first so it decrypts with both before
+    // relays start sending wraps for the new public key. The subscription
+    // keeps matching the previous public key until its grace period is over
+    fn rotate_keys(
+        &self,
+        myself: ActorRef<SupervisorMessage>,
+        state: &mut State,
+        keys: Keys,
+    ) -> Result<()> {
+        let public_key = keys.public_key();
+        if public_key == state.public_key {
+            bail!("The new key is already in use");
+        }
+        let grace = self
+            .config
+            .get::<gift_unwrapper::Config>()?
+            .key_rotation_grace();
+
+        cast!(
+            state.gift_unwrapper,
+            GiftUnwrapperMessage::RotateKeys(keys.clone())
+        )
+        .map_err(|e| anyhow!("Failed to rotate the gift unwrapper keys: {}", e))?;
+
+        config::reportinator::set_signing_keys(keys);
+
+        cast!(
+            state.event_dispatcher,
+            RelayEventDispatcherMessage::SetReportinatorPublicKeys(vec![
+                public_key,
+                state.public_key
+            ])
+        )
+        .map_err(|e| anyhow!("Failed to resubscribe with the new public key: {}", e))?;
+
+        let previous_public_key = std::mem::replace(&mut state.public_key, public_key);
+        state.previous_public_key = Some(previous_public_key);
+        myself.send_after(grace, move || {
+            SupervisorMessage::ExpirePreviousKey(previous_public_key)
+        });
+
+        counter!("key_rotated").increment(1);
+        info!(
+            "Rotated keys from {} to {}, previous key accepted for {:?}",
+            previous_public_key, public_key, grace
+        );
+        Ok(())
+    }
+}
+
+pub struct State {
+    event_dispatcher: ActorRef<RelayEventDispatcherMessage>,
+    gift_unwrapper: ActorRef<GiftUnwrapperMessage>,
+    // Only asked directly for the admin self test
+    event_enqueuer: ActorRef<EventEnqueuerMessage>,
+    sink_checks: SinkChecks<Box<dyn NotificationPort>>,
+    public_key: PublicKey,
+    // Still subscribed to while its grace period lasts
+    previous_public_key: Option<PublicKey>,
+    ack_config: AckConfig,
+    resolved_reports: ResolvedReports,
 }
 
 #[ractor::async_trait]
@@ -29,27 +152,42 @@ impl<T, U, V> Actor for Supervisor<T, U, V>
 where
     T: NostrPort,
     U: PubsubPort,
-    V: SlackClientPortBuilder,
+    V: SlackNotifierBuilder,
 {
     type Msg = SupervisorMessage;
-    type State = ActorRef<RelayEventDispatcherMessage>;
-    type Arguments = (T, U, V, Keys);
+    type State = State;
+    // There's no Slack builder with another notification backend
+    type Arguments = (T, U, Option<V>, Keys);
 
     async fn pre_start(
         &self,
         myself: ActorRef<Self::Msg>,
-        (nostr_subscriber, google_publisher, slack_writer_builder, reportinator_keys): (
+        (nostr_subscriber, google_publisher, slack_notifier_builder, reportinator_keys): (
             T,
             U,
-            V,
+            Option<V>,
             Keys,
         ),
     ) -> Result<Self::State, ActorProcessingErr> {
+        let public_key = reportinator_keys.public_key();
+
         // Spawn actors and wire them together
         let (event_dispatcher, _event_dispatcher_handle) = Actor::spawn_linked(
             Some("event_dispatcher".to_string()),
             RelayEventDispatcher::default(),
-            nostr_subscriber,
+            (
+                nostr_subscriber.clone(),
+                Some(DeadLetterQueue::new(&self.config.get()?)),
+                self.config.get()?,
+            ),
+            myself.get_cell(),
+        )
+        .await?;
+
+        let (_relay_health, _relay_health_handle) = Actor::spawn_linked(
+            Some("relay_health".to_string()),
+            RelayHealthActor::default(),
+            (nostr_subscriber, self.config.get()?),
             myself.get_cell(),
         )
         .await?;
@@ -57,7 +195,13 @@ where
         let (gift_unwrapper, _gift_unwrapper_handle) = Actor::spawn_linked(
             Some("gift_unwrapper".to_string()),
             GiftUnwrapper,
-            reportinator_keys,
+            (
+                reportinator_keys,
+                self.config.get()?,
+                SeenIds::load(&self.config.get()?)?,
+                LanguageDetector::new(&self.config.get()?)?,
+                Some(myself.clone()),
+            ),
             myself.get_cell(),
         )
         .await?;
@@ -67,69 +211,234 @@ where
             RelayEventDispatcherMessage::SubscribeToEventReceived(Box::new(gift_unwrapper.clone()))
         )?;
 
+        let (retry_actor, _retry_actor_handle) = Actor::spawn_linked(
+            Some("retry_actor".to_string()),
+            RetryActor,
+            (
+                Some(DeadLetterQueue::for_report_requests(&self.config.get()?)),
+                self.config.get()?,
+            ),
+            myself.get_cell(),
+        )
+        .await?;
+
+        let auto_skip_rules = AutoSkipRules::new(&self.config.get()?)?;
+
         let (event_enqueuer, _event_enqueuer_handle) = Actor::spawn_linked(
             Some("event_enqueuer".to_string()),
             EventEnqueuer::default(),
-            google_publisher,
+            (
+                google_publisher,
+                Some(retry_actor.clone()),
+                auto_skip_rules.clone(),
+                ReporterQueue::new(&self.config.get()?),
+                Some(DeadLetterQueue::for_report_requests(&self.config.get()?)),
+            ),
             myself.get_cell(),
         )
         .await?;
 
-        let slack_client_port = slack_writer_builder.build(self.config.get()?, myself.clone())?;
+        let notifications_config = self.config.get::<NotificationsConfig>()?;
+        info!(
+            "Using the {} notification backend",
+            notifications_config.backend
+        );
 
-        let (slack_writer, _slack_writer_handle) = Actor::spawn_linked(
-            Some("slack_writer".to_string()),
-            SlackWriter::default(),
-            slack_client_port,
+        let mut notification_sinks: Vec<NotificationSink<Box<dyn NotificationPort>>> = Vec::new();
+        match notifications_config.backend {
+            NotificationBackend::Slack => {
+                let Some(slack_notifier_builder) = slack_notifier_builder else {
+                    return Err(
+                        anyhow!("The slack notification backend needs a Slack client").into(),
+                    );
+                };
+                let slack_config = self.config.get::<SlackConfig>()?;
+                for workspace_config in slack_config.workspaces() {
+                    let slack_client_port: Box<dyn NotificationPort> = Box::new(
+                        slack_notifier_builder.build(workspace_config.clone(), myself.clone())?,
+                    );
+                    notification_sinks.push(NotificationSink::for_workspace(
+                        &workspace_config,
+                        slack_client_port,
+                    )?);
+                }
+            }
+            NotificationBackend::Webhook => {
+                let webhook_notifier: Box<dyn NotificationPort> =
+                    Box::new(WebhookNotifier::new(&self.config.get()?)?);
+                notification_sinks.push(NotificationSink::new(
+                    "webhook",
+                    WEBHOOK_METRICS,
+                    webhook_notifier,
+                ));
+            }
+        }
+
+        let sink_checks = NotificationSink::checks(&notification_sinks);
+        let (notification_writer, _notification_writer_handle) = Actor::spawn_linked(
+            Some("notification_writer".to_string()),
+            NotificationWriter::default(),
+            (
+                notification_sinks,
+                self.config.get()?,
+                Some(retry_actor),
+                auto_skip_rules,
+                QuietHours::new(&self.config.get()?)?,
+            ),
+            myself.get_cell(),
+        )
+        .await?;
+
+        let (content_tracker, _content_tracker_handle) = Actor::spawn_linked(
+            Some("content_tracker".to_string()),
+            ContentTracker,
+            (self.config.get()?, Some(notification_writer.clone())),
             myself.get_cell(),
         )
         .await?;
 
         cast!(
             gift_unwrapper,
-            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(event_enqueuer))
+            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(event_enqueuer.clone()))
         )?;
 
         cast!(
             gift_unwrapper,
-            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(slack_writer))
+            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(notification_writer.clone()))
         )?;
 
+        cast!(
+            gift_unwrapper,
+            GiftUnwrapperMessage::SubscribeToEventUnwrapped(Box::new(content_tracker))
+        )?;
+
+        let ack_config = self.config.get::<AckConfig>()?;
+        if ack_config.should_send(AckTrigger::Acceptance) {
+            let supervisor = myself.clone();
+            let (acknowledger, _, _) =
+                subscribe_with(Some(myself.get_cell()), move |report_request| {
+                    if let Err(e) = supervisor.cast(SupervisorMessage::Acknowledge(
+                        report_request,
+                        AckTrigger::Acceptance,
+                    )) {
+                        error!("Failed to acknowledge report request: {}", e);
+                    }
+                })
+                .await?;
+            cast!(
+                gift_unwrapper,
+                GiftUnwrapperMessage::SubscribeToEventUnwrapped(acknowledger)
+            )?;
+        }
+
         // Connect as the last message once everything is wired up
         cast!(event_dispatcher, RelayEventDispatcherMessage::Connect)?;
 
-        Ok(event_dispatcher)
+        Ok(State {
+            event_dispatcher,
+            gift_unwrapper,
+            event_enqueuer,
+            sink_checks,
+            public_key,
+            previous_public_key: None,
+            ack_config,
+            resolved_reports: ResolvedReports::default(),
+        })
     }
 
     async fn handle(
         &self,
-        _myself: ActorRef<Self::Msg>,
+        myself: ActorRef<Self::Msg>,
         message: Self::Msg,
-        event_dispatcher: &mut Self::State,
+        state: &mut Self::State,
     ) -> Result<(), ActorProcessingErr> {
         match message {
             Self::Msg::Publish(report) => {
-                if let Err(e) = cast!(
-                    event_dispatcher,
-                    RelayEventDispatcherMessage::Publish(report)
+                if let Err(e) = mailbox::cast(
+                    &state.event_dispatcher,
+                    RelayEventDispatcherMessage::Publish(report),
                 ) {
                     error!("Failed to publish report: {}", e);
                 }
             }
-            Self::Msg::GetNip05(request, reply_port) => {
-                let result = match call_t!(
-                    event_dispatcher,
-                    RelayEventDispatcherMessage::GetNip05,
-                    100,
-                    request
+            Self::Msg::ReplayDeadLetters => {
+                let moderated_reports = DeadLetterQueue::new(&self.config.get()?);
+                if let Err(e) = dead_letter_queue::replay(&moderated_reports, |report| {
+                    mailbox::cast(
+                        &state.event_dispatcher,
+                        RelayEventDispatcherMessage::Publish(report),
+                    )
+                    .map_err(|e| anyhow!("Failed to send the report to the dispatcher: {}", e))
+                }) {
+                    error!("Failed to replay dead letter reports: {}", e);
+                }
+
+                let report_requests = DeadLetterQueue::for_report_requests(&self.config.get()?);
+                if let Err(e) = dead_letter_queue::replay(&report_requests, |report_request| {
+                    mailbox::cast(
+                        &state.event_enqueuer,
+                        EventEnqueuerMessage::Enqueue(report_request),
+                    )
+                    .map_err(|e| {
+                        anyhow!("Failed to send the report request to the enqueuer: {}", e)
+                    })
+                }) {
+                    error!("Failed to replay dead letter report requests: {}", e);
+                }
+            }
+            // The dispatcher answers directly, the lookups may take a while
+            // and the callers have their own timeouts
+            Self::Msg::GetNip05(public_key, reply_port) => {
+                if let Err(e) = cast!(
+                    state.event_dispatcher,
+                    RelayEventDispatcherMessage::GetNip05(public_key, reply_port)
+                ) {
+                    error!("Failed to look up the NIP-05 of {}: {}", public_key, e);
+                }
+            }
+            Self::Msg::FindEvent(event_id, reply_port) => {
+                if let Err(e) = cast!(
+                    state.event_dispatcher,
+                    RelayEventDispatcherMessage::FindEvent(event_id, reply_port)
                 ) {
-                    Ok(Some(nip05)) => Some(nip05),
-                    Ok(None) => None,
-                    Err(e) => {
-                        error!("Failed to get nip05: {}", e);
-                        None
+                    error!("Failed to look up event {}: {}", event_id, e);
+                }
+            }
+            Self::Msg::RotateKeys(keys, reply_port) => {
+                let result = self.rotate_keys(myself, state, keys).map_err(|e| {
+                    counter!("key_rotation_error").increment(1);
+                    error!("Failed to rotate keys: {}", e);
+                    e.to_string()
+                });
+
+                if !reply_port.is_closed() {
+                    if let Err(e) = reply_port.send(result) {
+                        error!("Failed to send reply: {}", e);
                     }
-                };
+                }
+            }
+            Self::Msg::Acknowledge(report_request, trigger) => {
+                if state.ack_config.should_send(trigger) {
+                    acknowledge(state, &report_request).await;
+                }
+            }
+            Self::Msg::Retract(report_id) => {
+                info!("Retracting report {}", report_id);
+                if let Err(e) = cast!(
+                    state.event_dispatcher,
+                    RelayEventDispatcherMessage::Retract(report_id)
+                ) {
+                    error!("Failed to retract report {}: {}", report_id, e);
+                }
+            }
+            Self::Msg::AddRelay(url, reply_port) => {
+                let result = call_t!(
+                    state.event_dispatcher,
+                    RelayEventDispatcherMessage::AddRelay,
+                    400,
+                    url
+                )
+                .unwrap_or_else(|e| Err(e.to_string()));
 
                 if !reply_port.is_closed() {
                     if let Err(e) = reply_port.send(result) {
@@ -137,6 +446,90 @@ where
                     }
                 }
             }
+            Self::Msg::RemoveRelay(url, reply_port) => {
+                let result = call_t!(
+                    state.event_dispatcher,
+                    RelayEventDispatcherMessage::RemoveRelay,
+                    400,
+                    url
+                )
+                .unwrap_or_else(|e| Err(e.to_string()));
+
+                if !reply_port.is_closed() {
+                    if let Err(e) = reply_port.send(result) {
+                        error!("Failed to send reply: {}", e);
+                    }
+                }
+            }
+            Self::Msg::Backfill(since, until, limit) => {
+                if let Err(e) = cast!(
+                    state.event_dispatcher,
+                    RelayEventDispatcherMessage::Backfill(since, until, limit)
+                ) {
+                    error!("Failed to start backfill: {}", e);
+                }
+            }
+            // Answered from a task so a slow dependency doesn't hold up the
+            // supervisor
+            Self::Msg::SelfTest(reply_port) => {
+                let event_dispatcher = state.event_dispatcher.clone();
+                let event_enqueuer = state.event_enqueuer.clone();
+                let sink_checks = state.sink_checks.clone();
+
+                tokio::spawn(async move {
+                    let checks = self_test(event_dispatcher, event_enqueuer, sink_checks).await;
+
+                    if !reply_port.is_closed() {
+                        if let Err(e) = reply_port.send(checks) {
+                            error!("Failed to send reply: {}", e);
+                        }
+                    }
+                });
+            }
+            Self::Msg::Resolve(report_request, resolution, reply_port) => {
+                let earlier_resolution =
+                    state
+                        .resolved_reports
+                        .resolve(&report_request, resolution, Timestamp::now());
+
+                if !reply_port.is_closed() {
+                    if let Err(e) = reply_port.send(earlier_resolution) {
+                        error!("Failed to send reply: {}", e);
+                    }
+                }
+            }
+            Self::Msg::Unresolve(report_request, resolution) => {
+                state
+                    .resolved_reports
+                    .unresolve(&report_request, &resolution);
+            }
+            Self::Msg::ExpireUnactioned(report_requests, resolution, reply_port) => {
+                let expired =
+                    state
+                        .resolved_reports
+                        .expire(&report_requests, resolution, Timestamp::now());
+
+                if !reply_port.is_closed() {
+                    if let Err(e) = reply_port.send(expired) {
+                        error!("Failed to send reply: {}", e);
+                    }
+                }
+            }
+            Self::Msg::ExpirePreviousKey(public_key) => {
+                // A newer rotation already replaced it
+                if state.previous_public_key != Some(public_key) {
+                    return Ok(());
+                }
+
+                info!("Grace period for {} is over", public_key);
+                state.previous_public_key = None;
+                if let Err(e) = cast!(
+                    state.event_dispatcher,
+                    RelayEventDispatcherMessage::SetReportinatorPublicKeys(vec![state.public_key])
+                ) {
+                    error!("Failed to resubscribe without the previous key: {}", e);
+                }
+            }
         }
         Ok(())
     }
@@ -168,3 +561,213 @@ where
         Ok(())
     }
 }
+
+// Each actor checks the dependency it holds the client for. An actor that
+// doesn't answer in time fails its dependency with the timeout as latency.
+// The notification sinks are checked directly, the writer may be busy
+async fn self_test(
+    event_dispatcher: ActorRef<RelayEventDispatcherMessage>,
+    event_enqueuer: ActorRef<EventEnqueuerMessage>,
+    sink_checks: SinkChecks<Box<dyn NotificationPort>>,
+) -> Vec<DependencyCheck> {
+    let timeout_ms = CHECK_TIMEOUT.as_millis() as u64;
+    let failed = |dependency: &str, e: String| DependencyCheck::failed(dependency, timeout_ms, e);
+
+    let (relays, pubsub, slack) = tokio::join!(
+        call_t!(
+            event_dispatcher,
+            RelayEventDispatcherMessage::SelfTest,
+            timeout_ms
+        ),
+        call_t!(event_enqueuer, EventEnqueuerMessage::SelfTest, timeout_ms),
+        sink_checks.run(),
+    );
+
+    let mut checks = vec![
+        relays.unwrap_or_else(|e| failed("relays", e.to_string())),
+        pubsub.unwrap_or_else(|e| failed("pubsub", e.to_string())),
+    ];
+    checks.extend(slack);
+
+    checks
+}
+
+// The reporter relay list may be unknown, in that case the DM goes to our own
+// relays, see `NostrPort::publish_direct_message`
+async fn acknowledge(state: &State, report_request: &ReportRequest) {
+    let reporter_pubkey = *report_request.reporter_pubkey();
+    let gift_wrap = match gift_wrapped_message(
+        &config::reportinator::signing_keys(),
+        &reporter_pubkey,
+        state.ack_config.message.clone(),
+    )
+    .await
+    {
+        Ok(gift_wrap) => gift_wrap,
+        Err(e) => {
+            counter!("direct_message_publish_error").increment(1);
+            error!(
+                "Failed to gift wrap acknowledgment for {}: {}",
+                reporter_pubkey, e
+            );
+            return;
+        }
+    };
+
+    if let Err(e) = cast!(
+        state.event_dispatcher,
+        RelayEventDispatcherMessage::PublishDirectMessage(gift_wrap, reporter_pubkey)
+    ) {
+        error!("Failed to publish acknowledgment: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::{RecordingPublisher, RecordingSlackClientBuilder, ScriptedNostrService};
+    use crate::domain_objects::as_gift_wrap::AsGiftWrap;
+    use tokio::time::{sleep, Duration};
+
+    async fn gift_wrap(
+        report_request: &ReportRequest,
+        reporter_keys: &Keys,
+        reportinator_keys: &Keys,
+    ) -> Event {
+        let gift_wrap = report_request
+            .as_gift_wrap(reporter_keys, &reportinator_keys.public_key())
+            .await
+            .unwrap();
+
+        Event::from_json(gift_wrap.as_json()).unwrap()
+    }
+
+    // Runs the whole pipeline, as start_server wires it, on the delivered
+    // gift wraps
+    async fn run_supervisor(
+        nostr_service: ScriptedNostrService,
+        pubsub_publisher: RecordingPublisher,
+        slack_client_builder: RecordingSlackClientBuilder,
+        reportinator_keys: Keys,
+    ) {
+        let (supervisor_ref, supervisor_handle) = Actor::spawn(
+            None,
+            Supervisor::new(Config::new("config").unwrap()),
+            (
+                nostr_service,
+                pubsub_publisher,
+                Some(slack_client_builder),
+                reportinator_keys,
+            ),
+        )
+        .await
+        .unwrap();
+
+        sleep(Duration::from_secs(1)).await;
+        supervisor_ref.stop(None);
+        supervisor_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pubkey_report_requests_reach_slack() {
+        let reportinator_keys = Keys::generate();
+        let reporter_keys = Keys::generate();
+
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            reporter_keys.public_key(),
+            Some("This is hateful. Report it!".to_string()),
+        );
+        let gift_wrap = gift_wrap(&report_request, &reporter_keys, &reportinator_keys).await;
+        let gift_wrap_id = gift_wrap.id();
+
+        let pubsub_publisher = RecordingPublisher::default();
+        let slack_client_builder = RecordingSlackClientBuilder::default();
+        run_supervisor(
+            ScriptedNostrService::delivering(vec![gift_wrap]),
+            pubsub_publisher.clone(),
+            slack_client_builder.clone(),
+            reportinator_keys,
+        )
+        .await;
+
+        let messages = slack_client_builder.messages().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].workspace, "default");
+        assert_eq!(
+            messages[0].report_requests,
+            [report_request.with_gift_wrap_id(gift_wrap_id)]
+        );
+        assert!(pubsub_publisher.published_events().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_report_requests_are_routed_by_target() {
+        let reportinator_keys = Keys::generate();
+        let reporter_keys = Keys::generate();
+
+        let reported_event = EventBuilder::text_note("I hate you!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let event_report_request =
+            ReportRequest::new(reported_event.into(), reporter_keys.public_key(), None);
+        let pubkey_report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            reporter_keys.public_key(),
+            Some("Impersonating me".to_string()),
+        );
+
+        let event_gift_wrap =
+            gift_wrap(&event_report_request, &reporter_keys, &reportinator_keys).await;
+        let pubkey_gift_wrap =
+            gift_wrap(&pubkey_report_request, &reporter_keys, &reportinator_keys).await;
+        let event_report_request = event_report_request.with_gift_wrap_id(event_gift_wrap.id());
+        let pubkey_report_request = pubkey_report_request.with_gift_wrap_id(pubkey_gift_wrap.id());
+
+        let pubsub_publisher = RecordingPublisher::default();
+        let slack_client_builder = RecordingSlackClientBuilder::default();
+        run_supervisor(
+            ScriptedNostrService::delivering(vec![event_gift_wrap, pubkey_gift_wrap]),
+            pubsub_publisher.clone(),
+            slack_client_builder.clone(),
+            reportinator_keys,
+        )
+        .await;
+
+        assert_eq!(
+            pubsub_publisher.published_events().await,
+            [event_report_request]
+        );
+        let messages = slack_client_builder.messages().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].report_requests, [pubkey_report_request]);
+    }
+
+    #[tokio::test]
+    async fn test_self_test_checks_every_dependency() {
+        let (supervisor_ref, supervisor_handle) = Actor::spawn(
+            None,
+            Supervisor::new(Config::new("config").unwrap()),
+            (
+                ScriptedNostrService::delivering(vec![]),
+                RecordingPublisher::default(),
+                Some(RecordingSlackClientBuilder::default()),
+                Keys::generate(),
+            ),
+        )
+        .await
+        .unwrap();
+
+        let checks = call_t!(supervisor_ref, SupervisorMessage::SelfTest, 1000).unwrap();
+
+        let dependencies: Vec<&str> = checks
+            .iter()
+            .map(|check| check.dependency.as_str())
+            .collect();
+        assert_eq!(dependencies, ["relays", "pubsub", "slack:default"]);
+        assert!(checks.iter().all(|check| check.ok));
+
+        supervisor_ref.stop(None);
+        supervisor_handle.await.unwrap();
+    }
+}
@@ -1,3 +1,31 @@
+pub mod auto_skip;
+pub mod category_suggestions;
+pub mod dedup;
+pub mod downstream_limit;
+pub mod exemplars;
+pub mod language_detection;
+pub mod mailbox;
+pub mod output_port_monitor;
+pub mod output_port_subscriber;
+pub mod pending_work;
+pub mod quiet_hours;
+#[cfg(test)]
+pub mod recording_slack_client;
+pub mod relay_status;
+pub mod report_funnel;
+pub mod reporter_queue;
+pub mod resolved_reports;
+pub mod self_test;
+#[cfg(test)]
+pub use recording_slack_client::RecordingSlackClientBuilder;
+#[cfg(test)]
+pub mod recording_publisher;
+#[cfg(test)]
+pub use recording_publisher::RecordingPublisher;
+#[cfg(test)]
+pub mod scripted_nostr_service;
+#[cfg(test)]
+pub use scripted_nostr_service::ScriptedNostrService;
 #[cfg(test)]
 pub mod test_actor;
 #[cfg(test)]
@@ -0,0 +1,139 @@
+//! Skips report requests that match a configured rule before they reach
+//! moderation, for reports moderators already know are benign.
+use crate::config::Configurable;
+use crate::domain_objects::ReportRequest;
+use anyhow::{Context, Result};
+use metrics::counter;
+use regex::Regex;
+use serde::Deserialize;
+use tracing::info;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Regexes matched against the reported event content and the reporter
+    // text. Prefix with (?i) for case insensitive keywords
+    #[serde(default)]
+    pub rules: Vec<String>,
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "auto_skip"
+    }
+}
+
+/// The compiled rules, cheap to clone
+#[derive(Debug, Clone, Default)]
+pub struct AutoSkipRules {
+    rules: Vec<Regex>,
+}
+
+impl AutoSkipRules {
+    pub fn new(config: &Config) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                Regex::new(rule).with_context(|| format!("Invalid auto skip rule {}", rule))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Logs and counts the report request when it's skipped
+    pub fn skips(&self, report_request: &ReportRequest) -> bool {
+        let Some(rule) = self.matching_rule(report_request) else {
+            return false;
+        };
+
+        counter!("report_autoskipped", "target" => report_request.target().label()).increment(1);
+        info!(
+            "Auto skipping report request {} matching rule {}",
+            report_request.target(),
+            rule
+        );
+        true
+    }
+
+    /// Whether the report request would be skipped, without logging or
+    /// counting it. For a second look at a request already counted elsewhere
+    pub fn matches(&self, report_request: &ReportRequest) -> bool {
+        self.matching_rule(report_request).is_some()
+    }
+
+    fn matching_rule(&self, report_request: &ReportRequest) -> Option<&str> {
+        let texts = matchable_texts(report_request);
+
+        self.rules
+            .iter()
+            .find(|rule| texts.iter().any(|text| rule.is_match(text)))
+            .map(|rule| rule.as_str())
+    }
+}
+
+/// The reported event content, if any, and the reporter text, which is what
+/// rules on report requests are matched against
+pub fn matchable_texts(report_request: &ReportRequest) -> Vec<&str> {
+    let reported_content = report_request
+        .target()
+        .event()
+        .map(|event| event.content.as_str());
+
+    reported_content
+        .into_iter()
+        .chain(report_request.reporter_text().map(|text| text.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::*;
+
+    fn auto_skip_rules(rules: &[&str]) -> AutoSkipRules {
+        AutoSkipRules::new(&Config {
+            rules: rules.iter().map(|rule| rule.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    fn report_request(content: &str, reporter_text: &str) -> ReportRequest {
+        let reported_event = EventBuilder::text_note(content, [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        ReportRequest::new(
+            reported_event.into(),
+            Keys::generate().public_key(),
+            Some(reporter_text.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_skips_matching_report_requests() {
+        let rules = auto_skip_rules(&[r"(?i)\bgm\b", "^test$"]);
+
+        assert!(rules.skips(&report_request("GM nostr!", "This is hateful")));
+        assert!(rules.skips(&report_request("I hate you!!", "test")));
+        assert!(rules.matches(&report_request("I hate you!!", "test")));
+    }
+
+    #[test]
+    fn test_keeps_non_matching_report_requests() {
+        let rules = auto_skip_rules(&[r"(?i)\bgm\b", "^test$"]);
+
+        assert!(!rules.skips(&report_request("I hate you!!", "This is a test")));
+        assert!(!AutoSkipRules::default().skips(&report_request("gm", "test")));
+        assert!(!rules.matches(&report_request("I hate you!!", "This is a test")));
+    }
+
+    #[test]
+    fn test_rejects_invalid_rules() {
+        let result = AutoSkipRules::new(&Config {
+            rules: vec!["(unclosed".to_string()],
+        });
+
+        assert!(result.is_err());
+    }
+}
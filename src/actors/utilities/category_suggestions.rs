@@ -0,0 +1,181 @@
+//! Suggests a moderation category from the reporter text, so its button can
+//! be highlighted in Slack. The suggestion is only a hint, moderators still
+//! pick the category.
+use crate::config::Configurable;
+use crate::domain_objects::{ModerationCategory, ReportRequest};
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Tried in order, the first rule with a keyword in the reporter text wins
+    #[serde(default)]
+    pub rules: Vec<SuggestionRuleConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SuggestionRuleConfig {
+    // Slug of the suggested category, like spam or impersonation
+    pub category: String,
+    // Whole words or phrases, in any case
+    pub keywords: Vec<String>,
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "category_suggestions"
+    }
+}
+
+/// The compiled rules, cheap to clone
+#[derive(Debug, Clone, Default)]
+pub struct CategorySuggestions {
+    rules: Vec<(ModerationCategory, Regex)>,
+}
+
+impl CategorySuggestions {
+    pub fn new(config: &Config) -> Result<Self> {
+        let rules = config
+            .rules
+            .iter()
+            .map(|rule| {
+                let category = ModerationCategory::from_str(&rule.category)
+                    .with_context(|| format!("Invalid suggested category {}", rule.category))?;
+                if rule.keywords.is_empty() {
+                    bail!("The suggestion rule for {} has no keywords", category);
+                }
+
+                let keywords: Vec<String> = rule
+                    .keywords
+                    .iter()
+                    .map(|keyword| regex::escape(keyword.trim()))
+                    .collect();
+                let regex = Regex::new(&format!(r"(?i)\b(?:{})\b", keywords.join("|")))
+                    .with_context(|| format!("Invalid keywords for {}", category))?;
+
+                Ok((category, regex))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn suggest(&self, report_request: &ReportRequest) -> Option<ModerationCategory> {
+        let reporter_text = report_request.reporter_text()?;
+
+        self.rules
+            .iter()
+            .find(|(_, regex)| regex.is_match(reporter_text))
+            .map(|(category, _)| *category)
+    }
+
+    /// The category suggested for most of the report requests, the earliest
+    /// one on ties
+    pub fn suggest_for_all<'a>(
+        &self,
+        report_requests: impl IntoIterator<Item = &'a ReportRequest>,
+    ) -> Option<ModerationCategory> {
+        let suggestions: Vec<ModerationCategory> = report_requests
+            .into_iter()
+            .filter_map(|report_request| self.suggest(report_request))
+            .collect();
+
+        suggestions
+            .iter()
+            .rev()
+            .max_by_key(|category| suggestions.iter().filter(|other| other == category).count())
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::*;
+
+    fn category_suggestions() -> CategorySuggestions {
+        let rule = |category: &str, keywords: &[&str]| SuggestionRuleConfig {
+            category: category.to_string(),
+            keywords: keywords.iter().map(|keyword| keyword.to_string()).collect(),
+        };
+
+        CategorySuggestions::new(&Config {
+            rules: vec![
+                rule("spam", &["spam", "scam", "bot"]),
+                rule("impersonation", &["fake account", "pretending"]),
+                rule("harassment", &["harass", "bully"]),
+            ],
+        })
+        .unwrap()
+    }
+
+    fn report_request(reporter_text: Option<&str>) -> ReportRequest {
+        ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            reporter_text.map(|text| text.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_suggests_categories_from_keywords() {
+        let suggestions = category_suggestions();
+        let suggest = |text| suggestions.suggest(&report_request(Some(text)));
+
+        assert_eq!(suggest("Obvious SPAM bot"), Some(ModerationCategory::Spam));
+        assert_eq!(
+            suggest("This is a fake account of my friend"),
+            Some(ModerationCategory::Impersonation)
+        );
+        assert_eq!(
+            suggest("They keep trying to bully me"),
+            Some(ModerationCategory::Harassment)
+        );
+        // Earlier rules win, keywords only match whole words
+        assert_eq!(
+            suggest("A bot pretending to be me"),
+            Some(ModerationCategory::Spam)
+        );
+        assert_eq!(suggest("Robots everywhere, harassment"), None);
+        assert_eq!(suggestions.suggest(&report_request(None)), None);
+        assert_eq!(
+            CategorySuggestions::default().suggest(&report_request(Some("spam"))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_suggests_the_most_common_category() {
+        let suggestions = category_suggestions();
+        let report_requests = vec![
+            report_request(Some("Fake account")),
+            report_request(Some("spam")),
+            report_request(None),
+            report_request(Some("scam")),
+        ];
+
+        assert_eq!(
+            suggestions.suggest_for_all(&report_requests),
+            Some(ModerationCategory::Spam)
+        );
+        assert_eq!(
+            suggestions.suggest_for_all(&report_requests[..2]),
+            Some(ModerationCategory::Impersonation)
+        );
+    }
+
+    #[test]
+    fn test_rejects_unknown_categories_and_empty_rules() {
+        let rule = |category: &str, keywords: Vec<String>| Config {
+            rules: vec![SuggestionRuleConfig {
+                category: category.to_string(),
+                keywords,
+            }],
+        };
+
+        assert!(CategorySuggestions::new(&rule("spamm", vec!["spam".to_string()])).is_err());
+        assert!(CategorySuggestions::new(&rule("spam", vec![])).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! Remembers the gift wraps we already handled so the copies other relays
+//! send, or the overlap of a resubscription after a restart, are dropped
+//! before decrypting them again. With the file backend the ids survive
+//! restarts, a JSON line per id with its expiration.
+use crate::config::Configurable;
+use anyhow::{bail, Context, Result};
+use nostr_sdk::prelude::{EventId, Timestamp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use tracing::{error, info, warn};
+
+// Fewer lines than this are never worth compacting
+const MIN_COMPACTION_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupBackend {
+    // Lost on restart
+    #[default]
+    Memory,
+    File,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub backend: DedupBackend,
+    // JSONL file used by the file backend
+    #[serde(default = "default_path")]
+    pub path: String,
+    // How long an id is remembered
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+fn default_path() -> String {
+    "dedup.jsonl".to_string()
+}
+
+fn default_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "dedup"
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.ttl_secs == 0 {
+            bail!("ttl_secs must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SeenEntry {
+    id: EventId,
+    expires_at: u64,
+}
+
+#[derive(Debug)]
+pub struct SeenIds {
+    ttl_secs: u64,
+    expirations: HashMap<EventId, u64>,
+    path: Option<PathBuf>,
+    // Ids written since the last compaction, expired ones included
+    written: usize,
+    compact_at: usize,
+}
+
+impl Default for SeenIds {
+    fn default() -> Self {
+        Self::in_memory(default_ttl_secs())
+    }
+}
+
+impl SeenIds {
+    pub fn in_memory(ttl_secs: u64) -> Self {
+        Self {
+            ttl_secs,
+            expirations: HashMap::new(),
+            path: None,
+            written: 0,
+            compact_at: MIN_COMPACTION_LINES,
+        }
+    }
+
+    /// Loads the ids still remembered from the file of the file backend
+    pub fn load(config: &Config) -> Result<Self> {
+        let mut seen_ids = Self::in_memory(config.ttl_secs);
+        if config.backend == DedupBackend::Memory {
+            return Ok(seen_ids);
+        }
+
+        let path = PathBuf::from(&config.path);
+        if path.exists() {
+            let file =
+                File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+            let now = Timestamp::now().as_u64();
+
+            for line in BufReader::new(file).lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                // A crash while appending leaves the last line cut
+                let Ok(entry) = serde_json::from_str::<SeenEntry>(&line) else {
+                    warn!("Ignoring invalid dedup entry: {}", line);
+                    continue;
+                };
+
+                seen_ids.written += 1;
+                if entry.expires_at > now {
+                    seen_ids.expirations.insert(entry.id, entry.expires_at);
+                }
+            }
+        }
+
+        info!(
+            "Loaded {} seen ids from {}",
+            seen_ids.expirations.len(),
+            path.display()
+        );
+        seen_ids.path = Some(path);
+        seen_ids.compact_at = seen_ids.next_compaction();
+        Ok(seen_ids)
+    }
+
+    pub fn contains(&self, id: &EventId, now: u64) -> bool {
+        self.expirations
+            .get(id)
+            .is_some_and(|expires_at| *expires_at > now)
+    }
+
+    /// Returns true the first time an id is seen while it's remembered
+    pub fn insert(&mut self, id: EventId, now: u64) -> bool {
+        if self.contains(&id, now) {
+            return false;
+        }
+
+        let expires_at = now + self.ttl_secs;
+        self.expirations.insert(id, expires_at);
+        self.written += 1;
+
+        // Losing the id only means a duplicate could get through later
+        let result = if self.written >= self.compact_at {
+            self.compact(now)
+        } else {
+            self.append(&SeenEntry { id, expires_at })
+        };
+        if let Err(e) = result {
+            error!("Failed to persist seen id {}: {}", id, e);
+        }
+
+        true
+    }
+
+    fn append(&self, entry: &SeenEntry) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    // Forgets the expired ids and rewrites the file with the rest through a
+    // rename, so a crash never leaves it half written
+    fn compact(&mut self, now: u64) -> Result<()> {
+        self.expirations.retain(|_, expires_at| *expires_at > now);
+        self.written = self.expirations.len();
+        self.compact_at = self.next_compaction();
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let tmp_path = path.with_extension("jsonl.tmp");
+        let mut file = File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        for (id, expires_at) in &self.expirations {
+            let entry = SeenEntry {
+                id: *id,
+                expires_at: *expires_at,
+            };
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        }
+        file.sync_all()?;
+
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+
+    fn next_compaction(&self) -> usize {
+        (self.expirations.len() * 2).max(MIN_COMPACTION_LINES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::*;
+
+    fn event_id() -> EventId {
+        EventBuilder::text_note("I hate you!!", [])
+            .to_event(&Keys::generate())
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn test_seen_ids_survive_a_restart() {
+        let path =
+            std::env::temp_dir().join(format!("test_dedup_{}.jsonl", Timestamp::now().as_u64()));
+        let _ = fs::remove_file(&path);
+        let config = Config {
+            backend: DedupBackend::File,
+            path: path.to_string_lossy().to_string(),
+            ttl_secs: 60,
+        };
+        let now = Timestamp::now().as_u64();
+        let (first_id, second_id, expired_id) = (event_id(), event_id(), event_id());
+
+        let mut seen_ids = SeenIds::load(&config).unwrap();
+        assert!(seen_ids.insert(first_id, now));
+        assert!(!seen_ids.insert(first_id, now));
+        assert!(seen_ids.insert(second_id, now));
+        assert!(seen_ids.insert(expired_id, now - 120));
+
+        let mut seen_ids = SeenIds::load(&config).unwrap();
+        assert!(!seen_ids.insert(first_id, now));
+        assert!(!seen_ids.insert(second_id, now));
+        assert!(seen_ids.insert(expired_id, now));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ids_are_forgotten_after_the_ttl() {
+        let mut seen_ids = SeenIds::in_memory(60);
+        let id = event_id();
+
+        assert!(seen_ids.insert(id, 1000));
+        assert!(!seen_ids.insert(id, 1059));
+        assert!(seen_ids.insert(id, 1060));
+    }
+}
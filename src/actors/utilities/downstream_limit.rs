@@ -0,0 +1,140 @@
+//! Caps the calls to Slack, Pub/Sub and the relays in flight at once across
+//! all actors. Each actor only makes one call at a time, but with several
+//! workspaces and retries a report storm can still open enough connections
+//! to run out of file descriptors. Past the limit calls wait for a permit.
+use crate::config::Configurable;
+use anyhow::{bail, Result};
+use metrics::counter;
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::Semaphore;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Unset doesn't limit the calls
+    #[serde(default = "default_max_concurrent_calls")]
+    pub max_concurrent_calls: Option<usize>,
+}
+
+fn default_max_concurrent_calls() -> Option<usize> {
+    Some(64)
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "downstream"
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.max_concurrent_calls == Some(0) {
+            bail!("max_concurrent_calls must be greater than 0");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DownstreamLimit {
+    semaphore: Option<Arc<Semaphore>>,
+}
+
+impl DownstreamLimit {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            semaphore: config
+                .max_concurrent_calls
+                .map(|permits| Arc::new(Semaphore::new(permits))),
+        }
+    }
+
+    /// Runs the call once a permit is free. The permit is released when the
+    /// call returns, errors included
+    pub async fn run<F: Future>(&self, call: F) -> F::Output {
+        let Some(semaphore) = &self.semaphore else {
+            return call.await;
+        };
+
+        let _permit = match semaphore.try_acquire() {
+            Ok(permit) => permit,
+            Err(_) => {
+                counter!("downstream_call_waited").increment(1);
+                semaphore
+                    .acquire()
+                    .await
+                    .expect("The downstream semaphore is never closed")
+            }
+        };
+
+        call.await
+    }
+}
+
+static DOWNSTREAM_LIMIT: OnceLock<DownstreamLimit> = OnceLock::new();
+
+/// Sets the limit shared by every `limited` call, once on startup
+pub fn set_limit(config: &Config) {
+    let _ = DOWNSTREAM_LIMIT.set(DownstreamLimit::new(config));
+}
+
+/// Runs the call within the shared limit. Unlimited until `set_limit`, like
+/// in tests
+pub async fn limited<F: Future>(call: F) -> F::Output {
+    match DOWNSTREAM_LIMIT.get() {
+        Some(downstream_limit) => downstream_limit.run(call).await,
+        None => call.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_concurrent_calls_never_exceed_the_limit() {
+        let downstream_limit = DownstreamLimit::new(&Config {
+            max_concurrent_calls: Some(3),
+        });
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+        let calls: Vec<_> = (0..20)
+            .map(|i| {
+                let downstream_limit = downstream_limit.clone();
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+
+                tokio::spawn(async move {
+                    downstream_limit
+                        .run(async {
+                            let now_in_flight = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                            max_in_flight.fetch_max(now_in_flight, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(10)).await;
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                            // Failed calls give their permit back too
+                            match i % 2 {
+                                0 => Ok(()),
+                                _ => Err(anyhow!("Slack is down")),
+                            }
+                        })
+                        .await
+                })
+            })
+            .collect();
+
+        let mut failed = 0;
+        for call in calls {
+            if call.await.unwrap().is_err() {
+                failed += 1;
+            }
+        }
+
+        assert_eq!(failed, 10);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 3);
+        assert_eq!(downstream_limit.semaphore.unwrap().available_permits(), 3);
+    }
+}
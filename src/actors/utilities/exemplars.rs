@@ -0,0 +1,101 @@
+//! The trace id of the latest increment of the publish and error counters,
+//! so a spike in Prometheus can be followed to the logs of a report behind
+//! it. The metrics crate has no exemplars, so they're kept here, next to the
+//! counters, and added when `/metrics` is rendered as OpenMetrics.
+use nostr_sdk::prelude::EventId;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A counter series, its name and its labels sorted by name
+pub type Series = (String, Vec<(String, String)>);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exemplar {
+    pub trace_id: String,
+    // Seconds since the epoch
+    pub timestamp: f64,
+}
+
+static EXEMPLARS: Mutex<BTreeMap<Series, Exemplar>> = Mutex::new(BTreeMap::new());
+
+/// The trace id of everything done to publish a report, the first half of
+/// its id. It's the 16 bytes trace ids have elsewhere, and is logged by the
+/// publish span so the logs of the report can be found from the exemplar
+pub fn trace_id(report_id: EventId) -> String {
+    report_id.to_hex()[..32].to_string()
+}
+
+pub fn series(name: &str, labels: &[(&str, &str)]) -> Series {
+    let mut labels: Vec<(String, String)> = labels
+        .iter()
+        .map(|(label, value)| (label.to_string(), value.to_string()))
+        .collect();
+    labels.sort();
+    (name.to_string(), labels)
+}
+
+/// Called next to the increment of the counter, with its same name and
+/// labels. Only the latest trace id of each series is kept
+pub fn record(name: &str, labels: &[(&str, &str)], trace_id: &str) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+
+    EXEMPLARS.lock().expect("Exemplars lock poisoned").insert(
+        series(name, labels),
+        Exemplar {
+            trace_id: trace_id.to_string(),
+            timestamp,
+        },
+    );
+}
+
+/// The latest exemplar of every series recorded so far
+pub fn snapshot() -> BTreeMap<Series, Exemplar> {
+    EXEMPLARS.lock().expect("Exemplars lock poisoned").clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::*;
+
+    #[test]
+    fn test_keeps_the_latest_trace_id_of_each_series() {
+        let first = trace_id(EventId::from_hex("0".repeat(64)).unwrap());
+        assert_eq!(first, "0".repeat(32));
+
+        record(
+            "test_exemplar_counter",
+            &[("target", "event"), ("category", "spam")],
+            &first,
+        );
+        record(
+            "test_exemplar_counter",
+            &[("category", "spam"), ("target", "event")],
+            "4bf92f3577b34da6a3ce929d0e0e4736",
+        );
+        record(
+            "test_exemplar_counter",
+            &[("category", "hate"), ("target", "event")],
+            &first,
+        );
+
+        let exemplars = snapshot();
+        let spam = series(
+            "test_exemplar_counter",
+            &[("category", "spam"), ("target", "event")],
+        );
+        assert_eq!(
+            exemplars[&spam].trace_id,
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        let hate = series(
+            "test_exemplar_counter",
+            &[("target", "event"), ("category", "hate")],
+        );
+        assert_eq!(exemplars[&hate].trace_id, first);
+    }
+}
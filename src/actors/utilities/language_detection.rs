@@ -0,0 +1,173 @@
+//! Tags report requests with the language of the reporter text, or of the
+//! reported event content when there's none, so moderators can route them by
+//! language. Only a hint, undetected or unreliable languages are left out.
+use crate::config::Configurable;
+use crate::domain_objects::ReportRequest;
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use whatlang::Lang;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub enabled: bool,
+    // From 0 to 1, detections below it are left out
+    #[serde(default = "default_min_confidence")]
+    pub min_confidence: f64,
+}
+
+fn default_min_confidence() -> f64 {
+    0.5
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "language_detection"
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(0.0..=1.0).contains(&self.min_confidence) {
+            bail!(
+                "min_confidence {} is not between 0 and 1",
+                self.min_confidence
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Disabled by default, like when the section is missing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LanguageDetector {
+    // None when disabled
+    min_confidence: Option<f64>,
+}
+
+impl LanguageDetector {
+    pub fn new(config: &Config) -> Result<Self> {
+        config.validate()?;
+
+        Ok(Self {
+            min_confidence: config.enabled.then_some(config.min_confidence),
+        })
+    }
+
+    /// The ISO 639-3 code of the detected language, like `eng` or `spa`
+    pub fn detect(&self, report_request: &ReportRequest) -> Option<String> {
+        let min_confidence = self.min_confidence?;
+        let text = match (
+            report_request.reporter_text(),
+            report_request.target().event(),
+        ) {
+            (Some(reporter_text), _) => reporter_text.as_str(),
+            (None, Some(event)) => event.content.as_str(),
+            (None, None) => return None,
+        };
+
+        let info = whatlang::detect(text)?;
+        if info.confidence() < min_confidence {
+            return None;
+        }
+
+        Some(info.lang().code().to_string())
+    }
+
+    /// The report request with its detected language, if any
+    pub fn tag(&self, report_request: ReportRequest) -> ReportRequest {
+        let lang = self.detect(&report_request);
+        report_request.with_lang(lang)
+    }
+}
+
+/// English name of an ISO 639-3 code, the code itself if unknown
+pub fn language_name(code: &str) -> String {
+    Lang::from_code(code)
+        .map(|lang| lang.eng_name().to_string())
+        .unwrap_or_else(|| code.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain_objects::ReportTarget;
+    use nostr_sdk::prelude::*;
+
+    // Short texts don't get much confidence, the languages are checked instead
+    fn language_detector() -> LanguageDetector {
+        LanguageDetector::new(&Config {
+            enabled: true,
+            min_confidence: 0.0,
+        })
+        .unwrap()
+    }
+
+    fn report_request(target: ReportTarget, reporter_text: Option<&str>) -> ReportRequest {
+        ReportRequest::new(
+            target,
+            Keys::generate().public_key(),
+            reporter_text.map(|text| text.to_string()),
+        )
+    }
+
+    fn pubkey_report_request(reporter_text: &str) -> ReportRequest {
+        report_request(Keys::generate().public_key().into(), Some(reporter_text))
+    }
+
+    #[test]
+    fn test_detects_the_language_of_the_reporter_text() {
+        let language_detector = language_detector();
+
+        assert_eq!(
+            language_detector.detect(&pubkey_report_request(
+                "This account keeps sending me threatening messages every day"
+            )),
+            Some("eng".to_string())
+        );
+        assert_eq!(
+            language_detector.detect(&pubkey_report_request(
+                "Esta cuenta me envía mensajes amenazantes todos los días"
+            )),
+            Some("spa".to_string())
+        );
+        assert_eq!(
+            language_detector.detect(&pubkey_report_request(
+                "Dieses Konto schickt mir jeden Tag bedrohliche Nachrichten"
+            )),
+            Some("deu".to_string())
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_the_reported_content() {
+        let reported_event = EventBuilder::text_note(
+            "Je vais te retrouver et tu vas le regretter pendant longtemps",
+            [],
+        )
+        .to_event(&Keys::generate())
+        .unwrap();
+
+        assert_eq!(
+            language_detector().detect(&report_request(reported_event.into(), None)),
+            Some("fra".to_string())
+        );
+        assert_eq!(
+            language_detector().detect(&report_request(Keys::generate().public_key().into(), None)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_disabled_detector_detects_nothing() {
+        let report_request =
+            pubkey_report_request("This account keeps sending me threatening messages every day");
+
+        assert_eq!(LanguageDetector::default().detect(&report_request), None);
+        assert_eq!(LanguageDetector::default().tag(report_request).lang(), None);
+    }
+
+    #[test]
+    fn test_language_name() {
+        assert_eq!(language_name("spa"), "Spanish");
+        assert_eq!(language_name("xyz"), "xyz");
+    }
+}
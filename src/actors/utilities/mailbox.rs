@@ -0,0 +1,189 @@
+//! Ractor doesn't expose how many messages are waiting in an actor mailbox,
+//! so we keep our own count. Only the messages carrying the traffic between
+//! our actors are counted, as `Counted` tells. They are counted as sent when
+//! converted for an output port subscriber or cast with `cast`, and as
+//! received once the actor handled them, see `handling`.
+use crate::service_manager::simplify_type_name;
+use metrics::counter;
+use ractor::{ActorRef, Message, MessagingErr};
+use std::any::{type_name, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing::warn;
+
+struct Mailbox {
+    actor_name: String,
+    len: Arc<AtomicUsize>,
+}
+
+static MAILBOXES: OnceLock<Mutex<HashMap<TypeId, Mailbox>>> = OnceLock::new();
+
+fn with_mailbox<M: 'static, R>(f: impl FnOnce(&mut Mailbox) -> R) -> R {
+    let mut mailboxes = MAILBOXES
+        .get_or_init(Default::default)
+        .lock()
+        .expect("Mailbox registry lock poisoned");
+
+    // Messages can be sent before the actor registers, until then the
+    // mailbox is named after the message type
+    let mailbox = mailboxes
+        .entry(TypeId::of::<M>())
+        .or_insert_with(|| Mailbox {
+            actor_name: simplify_type_name(type_name::<M>()),
+            len: Arc::default(),
+        });
+
+    f(mailbox)
+}
+
+/// The messages of an actor that go through its mailbox count
+pub trait Counted: 'static {
+    fn is_counted(&self) -> bool;
+}
+
+/// Names the mailbox of the actor handling messages of type `M`
+pub fn register<M: 'static>(actor_name: String) {
+    with_mailbox::<M, _>(|mailbox| mailbox.actor_name = actor_name);
+}
+
+pub fn message_sent<M: 'static>() {
+    with_mailbox::<M, _>(|mailbox| mailbox.len.fetch_add(1, Ordering::Relaxed));
+}
+
+/// Casts the message counted as sent, the count is undone if the actor is
+/// gone
+pub fn cast<M: Message + Counted>(actor: &ActorRef<M>, message: M) -> Result<(), MessagingErr<M>> {
+    message_sent::<M>();
+    actor.cast(message).map_err(|e| {
+        message_received::<M>();
+        e
+    })
+}
+
+/// Taken at the start of `handle` for every message and held until it
+/// returns, whichever way. Counted messages stay pending until then, so
+/// shutdown also waits for the one in flight
+pub fn handling<M: Counted>(message: &M) -> Handling<M> {
+    Handling {
+        counted: message.is_counted(),
+        _message: PhantomData,
+    }
+}
+
+#[must_use]
+pub struct Handling<M: Counted> {
+    counted: bool,
+    _message: PhantomData<fn() -> M>,
+}
+
+impl<M: Counted> Drop for Handling<M> {
+    fn drop(&mut self) {
+        if self.counted {
+            message_received::<M>();
+        }
+    }
+}
+
+pub fn message_received<M: 'static>() {
+    with_mailbox::<M, _>(|mailbox| {
+        // Counted messages cast directly, like in tests, were never counted
+        // as sent. The count stays at zero but the mismatch between
+        // `Counted` and `message_sent` is reported, drift would go unseen
+        let underflow = mailbox
+            .len
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |len| {
+                len.checked_sub(1)
+            })
+            .is_err();
+
+        if underflow {
+            counter!("mailbox_count_underflow", "actor" => mailbox.actor_name.clone()).increment(1);
+            warn!(
+                "{} handled a counted message that was never counted as sent",
+                mailbox.actor_name
+            );
+        }
+    });
+}
+
+/// Pending messages of the actor handling messages of type `M`
+pub fn pending<M: 'static>() -> usize {
+    with_mailbox::<M, _>(|mailbox| mailbox.len.load(Ordering::Relaxed))
+}
+
+/// Current pending messages for each known mailbox
+pub fn mailbox_lens() -> Vec<(String, usize)> {
+    let mailboxes = MAILBOXES
+        .get_or_init(Default::default)
+        .lock()
+        .expect("Mailbox registry lock poisoned");
+
+    mailboxes
+        .values()
+        .map(|mailbox| {
+            (
+                mailbox.actor_name.clone(),
+                mailbox.len.load(Ordering::Relaxed),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestMailboxMessage;
+
+    fn test_mailbox_len() -> Option<usize> {
+        mailbox_lens()
+            .into_iter()
+            .find(|(name, _)| name == "TestMailboxActor")
+            .map(|(_, len)| len)
+    }
+
+    #[test]
+    fn test_mailbox_counts_pending_messages() {
+        register::<TestMailboxMessage>("TestMailboxActor".to_string());
+
+        message_sent::<TestMailboxMessage>();
+        message_sent::<TestMailboxMessage>();
+        message_sent::<TestMailboxMessage>();
+        message_received::<TestMailboxMessage>();
+
+        assert_eq!(test_mailbox_len(), Some(2));
+        assert_eq!(pending::<TestMailboxMessage>(), 2);
+
+        message_received::<TestMailboxMessage>();
+        message_received::<TestMailboxMessage>();
+        message_received::<TestMailboxMessage>();
+
+        assert_eq!(test_mailbox_len(), Some(0));
+    }
+
+    enum TestCountedMessage {
+        Counted,
+        NotCounted,
+    }
+
+    impl Counted for TestCountedMessage {
+        fn is_counted(&self) -> bool {
+            matches!(self, TestCountedMessage::Counted)
+        }
+    }
+
+    #[test]
+    fn test_counted_messages_are_pending_until_handled() {
+        message_sent::<TestCountedMessage>();
+        message_sent::<TestCountedMessage>();
+
+        let handling_counted = handling(&TestCountedMessage::Counted);
+        drop(handling(&TestCountedMessage::NotCounted));
+        assert_eq!(pending::<TestCountedMessage>(), 2);
+
+        drop(handling_counted);
+        assert_eq!(pending::<TestCountedMessage>(), 1);
+    }
+}
@@ -0,0 +1,117 @@
+//! Ractor output ports forward messages through a broadcast channel with a
+//! hardcoded buffer, subscribers falling further behind than that silently
+//! lose messages. We count what a port sends and what its subscribers convert
+//! to get a signal before the backpressure redesign lands.
+use metrics::counter;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Size of the ractor output port broadcast buffer
+pub const OUTPUT_PORT_BUFFER: u64 = 10;
+
+const WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+static DELIVERIES: OnceLock<Mutex<HashMap<TypeId, Arc<AtomicU64>>>> = OnceLock::new();
+
+fn deliveries<T: 'static>() -> Arc<AtomicU64> {
+    DELIVERIES
+        .get_or_init(Default::default)
+        .lock()
+        .expect("Output port deliveries lock poisoned")
+        .entry(TypeId::of::<T>())
+        .or_default()
+        .clone()
+}
+
+/// Records that a subscriber got a `T` out of an output port, call it where
+/// the message is converted for the subscriber
+pub fn delivered<T: 'static>() {
+    deliveries::<T>().fetch_add(1, Ordering::Relaxed);
+}
+
+/// Tracks an output port sending messages of type `T`
+pub struct OutputPortMonitor<T> {
+    port_name: &'static str,
+    subscribers: u64,
+    sent: u64,
+    delivered: Arc<AtomicU64>,
+    last_warning: Option<Instant>,
+    _phantom: PhantomData<T>,
+}
+
+impl<T: 'static> OutputPortMonitor<T> {
+    pub fn new(port_name: &'static str) -> Self {
+        let delivered = deliveries::<T>();
+
+        Self {
+            port_name,
+            subscribers: 0,
+            // Level with what earlier ports of the same type delivered
+            sent: delivered.load(Ordering::Relaxed),
+            delivered,
+            last_warning: None,
+            _phantom: PhantomData,
+        }
+    }
+
+    pub fn subscribed(&mut self) {
+        self.subscribers += 1;
+    }
+
+    /// Call after every send. Returns true, counts and warns at most once a
+    /// minute when the subscribers are behind by more than the buffer
+    pub fn sent(&mut self) -> bool {
+        self.sent += self.subscribers;
+        let in_flight = self
+            .sent
+            .saturating_sub(self.delivered.load(Ordering::Relaxed));
+        if in_flight <= OUTPUT_PORT_BUFFER {
+            return false;
+        }
+
+        counter!("output_port_overflow", "port" => self.port_name).increment(1);
+        let throttled = self
+            .last_warning
+            .is_some_and(|last_warning| last_warning.elapsed() < WARNING_INTERVAL);
+        if !throttled {
+            self.last_warning = Some(Instant::now());
+            warn!(
+                port = self.port_name,
+                in_flight,
+                buffer = OUTPUT_PORT_BUFFER,
+                "Output port subscribers are falling behind, messages may be dropped"
+            );
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestPortMessage;
+
+    #[test]
+    fn test_detects_subscribers_falling_behind() {
+        let mut monitor = OutputPortMonitor::<TestPortMessage>::new("test_port");
+        monitor.subscribed();
+
+        for _ in 0..OUTPUT_PORT_BUFFER {
+            assert!(!monitor.sent());
+        }
+        assert!(monitor.sent());
+
+        // Catching up clears it
+        for _ in 0..=OUTPUT_PORT_BUFFER {
+            delivered::<TestPortMessage>();
+        }
+        assert!(!monitor.sent());
+    }
+}
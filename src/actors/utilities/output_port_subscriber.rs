@@ -0,0 +1,131 @@
+//! Closure subscribers for output ports. Subscribing an actor needs its
+//! message type to implement `From` the port message, which is boilerplate
+//! for ad-hoc consumers like metrics taps. A closure subscriber is a small
+//! actor running the closure for every message the port sends, subscribed as
+//! any other `ActorRef`, so that path is unchanged.
+use crate::actors::utilities::output_port_monitor;
+use ractor::{
+    port::OutputPortSubscriber, Actor, ActorCell, ActorProcessingErr, ActorRef, SpawnErr,
+};
+use std::marker::PhantomData;
+use tokio::task::JoinHandle;
+
+/// A message sent by the output port, as received by a closure subscriber
+pub struct Delivered<T>(pub T);
+
+// Counted like any other subscriber, see `output_port_monitor`
+impl<T: 'static> From<T> for Delivered<T> {
+    fn from(message: T) -> Self {
+        output_port_monitor::delivered::<T>();
+        Delivered(message)
+    }
+}
+
+type OnMessage<T> = Box<dyn FnMut(T) + Send + 'static>;
+
+/// The subscriber for the port, and the actor behind it with its handle
+pub type ClosureSubscription<T> = (
+    OutputPortSubscriber<T>,
+    ActorRef<Delivered<T>>,
+    JoinHandle<()>,
+);
+
+pub struct ClosureSubscriber<T> {
+    _phantom: PhantomData<fn(T)>,
+}
+
+/// Spawns the actor running `on_message`, linked to `supervisor` if given,
+/// and returns it ready to be sent to the actor owning the port, e.g. in
+/// `SubscribeToEventUnwrapped`. Stop the returned actor to unsubscribe.
+pub async fn subscribe_with<T, F>(
+    supervisor: Option<ActorCell>,
+    on_message: F,
+) -> Result<ClosureSubscription<T>, SpawnErr>
+where
+    T: Clone + Send + Sync + 'static,
+    F: FnMut(T) + Send + 'static,
+{
+    let subscriber = ClosureSubscriber {
+        _phantom: PhantomData,
+    };
+    let on_message = Box::new(on_message) as OnMessage<T>;
+    let (actor_ref, handle) = match supervisor {
+        Some(supervisor) => Actor::spawn_linked(None, subscriber, on_message, supervisor).await?,
+        None => Actor::spawn(None, subscriber, on_message).await?,
+    };
+
+    Ok((Box::new(actor_ref.clone()), actor_ref, handle))
+}
+
+#[ractor::async_trait]
+impl<T> Actor for ClosureSubscriber<T>
+where
+    T: Send + 'static,
+{
+    type Msg = Delivered<T>;
+    type State = OnMessage<T>;
+    type Arguments = OnMessage<T>;
+
+    async fn pre_start(
+        &self,
+        _: ActorRef<Self::Msg>,
+        on_message: OnMessage<T>,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        Ok(on_message)
+    }
+
+    async fn handle(
+        &self,
+        _: ActorRef<Self::Msg>,
+        Delivered(message): Self::Msg,
+        on_message: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        on_message(message);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ractor::port::OutputPort;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_closure_subscribers_get_every_message() {
+        let output_port = OutputPort::<String>::default();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let lengths = Arc::new(Mutex::new(Vec::new()));
+
+        let (subscriber, subscriber_ref, subscriber_handle) = subscribe_with(None, {
+            let received = received.clone();
+            move |message: String| received.lock().unwrap().push(message)
+        })
+        .await
+        .unwrap();
+        subscriber.subscribe_to_port(&output_port);
+
+        // Independent of the first one
+        let (other_subscriber, other_subscriber_ref, other_subscriber_handle) =
+            subscribe_with(None, {
+                let lengths = lengths.clone();
+                move |message: String| lengths.lock().unwrap().push(message.len())
+            })
+            .await
+            .unwrap();
+        other_subscriber.subscribe_to_port(&output_port);
+
+        output_port.send("first".to_string());
+        output_port.send("second".to_string());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        subscriber_ref.stop(None);
+        other_subscriber_ref.stop(None);
+        subscriber_handle.await.unwrap();
+        other_subscriber_handle.await.unwrap();
+
+        assert_eq!(*received.lock().unwrap(), vec!["first", "second"]);
+        assert_eq!(*lengths.lock().unwrap(), vec![5, 6]);
+    }
+}
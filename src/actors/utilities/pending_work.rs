@@ -0,0 +1,56 @@
+//! A single number for autoscalers: the messages waiting in actor mailboxes
+//! plus the report requests parked in retry backoffs and Slack batches. It's
+//! all read from atomics, so sampling it never blocks the pipeline.
+use super::mailbox;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static RETRYING: AtomicUsize = AtomicUsize::new(0);
+static BATCHED: AtomicUsize = AtomicUsize::new(0);
+
+/// A report request started waiting for its retry backoff
+pub fn retry_scheduled() {
+    RETRYING.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A report request done waiting for its retry backoff
+pub fn retry_redispatched() {
+    decrement(&RETRYING, 1);
+}
+
+/// Report requests added to a Slack batch
+pub fn batched(count: usize) {
+    BATCHED.fetch_add(count, Ordering::Relaxed);
+}
+
+/// Report requests flushed out of a Slack batch
+pub fn unbatched(count: usize) {
+    decrement(&BATCHED, count);
+}
+
+/// Pending messages of every mailbox, retries and batched report requests
+pub fn total() -> usize {
+    let mailboxes: usize = mailbox::mailbox_lens().iter().map(|(_, len)| len).sum();
+
+    mailboxes + RETRYING.load(Ordering::Relaxed) + BATCHED.load(Ordering::Relaxed)
+}
+
+fn decrement(pending: &AtomicUsize, count: usize) {
+    let _ = pending.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |len| {
+        Some(len.saturating_sub(count))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decrement_never_goes_below_zero() {
+        let pending = AtomicUsize::new(2);
+
+        decrement(&pending, 1);
+        assert_eq!(pending.load(Ordering::Relaxed), 1);
+        decrement(&pending, 5);
+        assert_eq!(pending.load(Ordering::Relaxed), 0);
+    }
+}
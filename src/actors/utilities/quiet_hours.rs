@@ -0,0 +1,317 @@
+//! Quiet hours hold back the Slack notifications of report requests that can
+//! wait, like at night, until the schedule says moderators are around again.
+//! Urgent report requests are always written right away.
+use crate::actors::utilities::auto_skip::matchable_texts;
+use crate::config::Configurable;
+use crate::domain_objects::ReportRequest;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::time::Duration;
+
+const SECS_PER_DAY: i64 = 86400;
+const MINUTES_PER_DAY: u32 = 1440;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    // Quiet hours are off when empty
+    #[serde(default)]
+    pub schedule: Vec<QuietWindowConfig>,
+    // Offset of the schedule times from UTC, like 60 for CET or -300 for EST
+    #[serde(default)]
+    pub utc_offset_minutes: i32,
+    // Regexes matched against the reported event content and the reporter
+    // text of report requests that notify even during quiet hours, for severe
+    // categories like sexual/minors
+    #[serde(default)]
+    pub urgent_rules: Vec<String>,
+    // Past this many held back report requests the rest are written right
+    // away, quiet hours never hold an unbounded backlog in memory
+    #[serde(default = "default_max_deferred")]
+    pub max_deferred: usize,
+}
+
+fn default_max_deferred() -> usize {
+    1000
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "quiet_hours"
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuietWindowConfig {
+    // Days the window starts on, every day when empty
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    // HH:MM, local to utc_offset_minutes. A window ending before it starts
+    // runs past midnight, one starting and ending at the same time lasts the
+    // whole day
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ];
+
+    // The unix epoch was a Thursday
+    fn from_days_since_epoch(days: i64) -> Self {
+        Self::ALL[(days + 3).rem_euclid(7) as usize]
+    }
+
+    fn previous(self) -> Self {
+        let index = Self::ALL.iter().position(|day| *day == self).unwrap();
+        Self::ALL[(index + 6) % 7]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct QuietWindow {
+    days: Vec<Weekday>,
+    // Minutes since local midnight
+    start: u32,
+    end: u32,
+}
+
+impl QuietWindow {
+    fn starts_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    fn contains(&self, day: Weekday, minute: u32) -> bool {
+        if self.start < self.end {
+            return self.starts_on(day) && (self.start..self.end).contains(&minute);
+        }
+
+        // Past midnight, the tail belongs to the window of the day before
+        (self.starts_on(day) && minute >= self.start)
+            || (self.starts_on(day.previous()) && minute < self.end)
+    }
+}
+
+/// The compiled schedule and urgent rules, cheap to clone
+#[derive(Debug, Clone, Default)]
+pub struct QuietHours {
+    windows: Vec<QuietWindow>,
+    utc_offset_secs: i64,
+    urgent_rules: Vec<Regex>,
+    max_deferred: usize,
+}
+
+impl QuietHours {
+    pub fn new(config: &Config) -> Result<Self> {
+        let windows = config
+            .schedule
+            .iter()
+            .map(|window| {
+                Ok(QuietWindow {
+                    days: window.days.clone(),
+                    start: parse_time(&window.start)?,
+                    end: parse_time(&window.end)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let urgent_rules = config
+            .urgent_rules
+            .iter()
+            .map(|rule| Regex::new(rule).with_context(|| format!("Invalid urgent rule {}", rule)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            windows,
+            utc_offset_secs: i64::from(config.utc_offset_minutes) * 60,
+            urgent_rules,
+            max_deferred: config.max_deferred,
+        })
+    }
+
+    /// Whether the unix timestamp falls in quiet hours
+    pub fn is_quiet(&self, now: u64) -> bool {
+        let local_secs = now as i64 + self.utc_offset_secs;
+        let day = Weekday::from_days_since_epoch(local_secs.div_euclid(SECS_PER_DAY));
+        let minute = (local_secs.rem_euclid(SECS_PER_DAY) / 60) as u32;
+
+        self.windows
+            .iter()
+            .any(|window| window.contains(day, minute))
+    }
+
+    /// How long until the quiet hours the timestamp falls in are over. None
+    /// when it's not quiet, or when it's quiet the whole week
+    pub fn remaining(&self, now: u64) -> Option<Duration> {
+        if !self.is_quiet(now) {
+            return None;
+        }
+
+        // To the start of the next minute, then a minute at a time
+        let mut end = now - now % 60 + 60;
+        for _ in 0..7 * MINUTES_PER_DAY {
+            if !self.is_quiet(end) {
+                return Some(Duration::from_secs(end - now));
+            }
+            end += 60;
+        }
+
+        None
+    }
+
+    pub fn max_deferred(&self) -> usize {
+        self.max_deferred
+    }
+
+    pub fn is_urgent(&self, report_request: &ReportRequest) -> bool {
+        let texts = matchable_texts(report_request);
+
+        self.urgent_rules
+            .iter()
+            .any(|rule| texts.iter().any(|text| rule.is_match(text)))
+    }
+}
+
+fn parse_time(time: &str) -> Result<u32> {
+    let Some((hours, minutes)) = time.split_once(':') else {
+        bail!("Invalid quiet hours time {}, expected HH:MM", time);
+    };
+
+    let hours: u32 = hours
+        .parse()
+        .with_context(|| format!("Invalid quiet hours time {}", time))?;
+    let minutes: u32 = minutes
+        .parse()
+        .with_context(|| format!("Invalid quiet hours time {}", time))?;
+    if hours > 23 || minutes > 59 {
+        bail!("Invalid quiet hours time {}", time);
+    }
+
+    Ok(hours * 60 + minutes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::*;
+
+    // Monday 2024-01-01 00:00 UTC
+    const MONDAY: u64 = 1704067200;
+    const HOUR: u64 = 3600;
+
+    fn quiet_hours(schedule: &[(&[Weekday], &str, &str)], utc_offset_minutes: i32) -> QuietHours {
+        QuietHours::new(&Config {
+            schedule: schedule
+                .iter()
+                .map(|(days, start, end)| QuietWindowConfig {
+                    days: days.to_vec(),
+                    start: start.to_string(),
+                    end: end.to_string(),
+                })
+                .collect(),
+            utc_offset_minutes,
+            urgent_rules: vec![r"(?i)\b(minor|child)".to_string()],
+            max_deferred: default_max_deferred(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_nights_are_quiet() {
+        let quiet_hours = quiet_hours(&[(&[], "22:00", "07:00")], 0);
+
+        assert!(quiet_hours.is_quiet(MONDAY + 3 * HOUR));
+        assert!(quiet_hours.is_quiet(MONDAY + 23 * HOUR));
+        assert!(!quiet_hours.is_quiet(MONDAY + 7 * HOUR));
+        assert!(!quiet_hours.is_quiet(MONDAY + 12 * HOUR));
+        assert_eq!(
+            quiet_hours.remaining(MONDAY + 3 * HOUR),
+            Some(Duration::from_secs(4 * HOUR))
+        );
+        assert_eq!(quiet_hours.remaining(MONDAY + 12 * HOUR), None);
+    }
+
+    #[test]
+    fn test_windows_only_start_on_their_days() {
+        // The whole weekend, from Friday night to Monday morning
+        let quiet_hours = quiet_hours(
+            &[
+                (&[Weekday::Fri], "20:00", "00:00"),
+                (&[Weekday::Sat, Weekday::Sun], "00:00", "00:00"),
+                (&[Weekday::Mon], "00:00", "08:00"),
+            ],
+            0,
+        );
+        let friday = MONDAY + 4 * 24 * HOUR;
+
+        assert!(!quiet_hours.is_quiet(friday + 12 * HOUR));
+        assert!(quiet_hours.is_quiet(friday + 21 * HOUR));
+        assert!(quiet_hours.is_quiet(friday + 36 * HOUR));
+        assert!(quiet_hours.is_quiet(MONDAY + 7 * 24 * HOUR + 2 * HOUR));
+        assert!(!quiet_hours.is_quiet(MONDAY + 7 * 24 * HOUR + 9 * HOUR));
+        assert_eq!(
+            quiet_hours.remaining(friday + 21 * HOUR),
+            Some(Duration::from_secs(59 * HOUR))
+        );
+    }
+
+    #[test]
+    fn test_schedule_is_local_to_the_offset() {
+        // 22:00 to 07:00 in UTC-5 is 03:00 to 12:00 UTC
+        let quiet_hours = quiet_hours(&[(&[], "22:00", "07:00")], -300);
+
+        assert!(quiet_hours.is_quiet(MONDAY + 4 * HOUR));
+        assert!(!quiet_hours.is_quiet(MONDAY + 2 * HOUR));
+        assert!(!quiet_hours.is_quiet(MONDAY + 13 * HOUR));
+    }
+
+    #[test]
+    fn test_urgent_report_requests() {
+        let quiet_hours = quiet_hours(&[], 0);
+        let report_request = |reporter_text: &str| {
+            ReportRequest::new(
+                Keys::generate().public_key().into(),
+                Keys::generate().public_key(),
+                Some(reporter_text.to_string()),
+            )
+        };
+
+        assert!(quiet_hours.is_urgent(&report_request("Posting pictures of a minor")));
+        assert!(!quiet_hours.is_urgent(&report_request("Spam")));
+    }
+
+    #[test]
+    fn test_rejects_invalid_times() {
+        for time in ["7", "24:00", "07:60", "ab:cd"] {
+            let config = Config {
+                schedule: vec![QuietWindowConfig {
+                    days: vec![],
+                    start: time.to_string(),
+                    end: "08:00".to_string(),
+                }],
+                ..Config::default()
+            };
+
+            assert!(QuietHours::new(&config).is_err(), "{}", time);
+        }
+    }
+}
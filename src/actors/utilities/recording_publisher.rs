@@ -0,0 +1,28 @@
+//! A `PubsubPort` for tests that records the report requests instead of
+//! publishing them.
+use crate::actors::PubsubPort;
+use crate::domain_objects::ReportRequest;
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// Clones share the same buffer, so one can be handed to the actors and
+/// another kept for the assertions
+#[derive(Clone, Default)]
+pub struct RecordingPublisher {
+    published_events: Arc<Mutex<Vec<ReportRequest>>>,
+}
+
+impl RecordingPublisher {
+    pub async fn published_events(&self) -> Vec<ReportRequest> {
+        self.published_events.lock().await.clone()
+    }
+}
+
+#[ractor::async_trait]
+impl PubsubPort for RecordingPublisher {
+    async fn publish_event(&mut self, event: &ReportRequest) -> Result<()> {
+        self.published_events.lock().await.push(event.clone());
+        Ok(())
+    }
+}
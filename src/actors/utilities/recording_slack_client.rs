@@ -0,0 +1,80 @@
+//! A `SlackNotifierBuilder` for tests that records the messages instead of
+//! posting them, so the whole actor wiring can run without network.
+use crate::actors::messages::SupervisorMessage;
+use crate::actors::{NotificationPort, SlackNotifierBuilder};
+use crate::adapters::slack_client_adapter::WorkspaceConfig;
+use crate::domain_objects::ReportRequest;
+use anyhow::Result;
+use ractor::ActorRef;
+use slack_morphism::prelude::SlackChannelId;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// A message posted to a workspace, with the report requests it was for
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedSlackMessage {
+    pub workspace: String,
+    pub channel_id: SlackChannelId,
+    pub report_requests: Vec<ReportRequest>,
+}
+
+pub type RecordedSlackMessages = Arc<Mutex<Vec<RecordedSlackMessage>>>;
+
+/// Every client it builds records into the same shared buffer
+#[derive(Clone, Default)]
+pub struct RecordingSlackClientBuilder {
+    messages: RecordedSlackMessages,
+}
+
+impl RecordingSlackClientBuilder {
+    pub async fn messages(&self) -> Vec<RecordedSlackMessage> {
+        self.messages.lock().await.clone()
+    }
+}
+
+impl SlackNotifierBuilder for RecordingSlackClientBuilder {
+    fn build(
+        &self,
+        config: WorkspaceConfig,
+        _nostr_actor: ActorRef<SupervisorMessage>,
+    ) -> Result<impl NotificationPort> {
+        Ok(RecordingSlackClient {
+            workspace: config.name,
+            channel_id: config.channel_id,
+            messages: self.messages.clone(),
+        })
+    }
+}
+
+pub struct RecordingSlackClient {
+    workspace: String,
+    channel_id: SlackChannelId,
+    messages: RecordedSlackMessages,
+}
+
+impl RecordingSlackClient {
+    async fn record(&self, report_requests: &[ReportRequest]) {
+        self.messages.lock().await.push(RecordedSlackMessage {
+            workspace: self.workspace.clone(),
+            channel_id: self.channel_id.clone(),
+            report_requests: report_requests.to_vec(),
+        });
+    }
+}
+
+#[ractor::async_trait]
+impl NotificationPort for RecordingSlackClient {
+    async fn notify(&self, report_request: &ReportRequest, _suppressed: usize) -> Result<()> {
+        self.record(std::slice::from_ref(report_request)).await;
+        Ok(())
+    }
+
+    async fn notify_aggregated(
+        &self,
+        report_requests: &[ReportRequest],
+        _suppressed: usize,
+    ) -> Result<()> {
+        self.record(report_requests).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,26 @@
+//! The relay connections as last seen by the RelayHealthActor. The status
+//! page reads them from here, so serving it never reaches the dispatcher.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static CHECKED: AtomicBool = AtomicBool::new(false);
+static CONNECTED: AtomicUsize = AtomicUsize::new(0);
+static TOTAL: AtomicUsize = AtomicUsize::new(0);
+
+/// Called on every health check with the connected relays out of all of ours
+pub fn record(connected: usize, total: usize) {
+    CONNECTED.store(connected, Ordering::Relaxed);
+    TOTAL.store(total, Ordering::Relaxed);
+    CHECKED.store(true, Ordering::Relaxed);
+}
+
+/// Connected and total relays of the last check, none before the first one
+pub fn last_check() -> Option<(usize, usize)> {
+    if !CHECKED.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    Some((
+        CONNECTED.load(Ordering::Relaxed),
+        TOTAL.load(Ordering::Relaxed),
+    ))
+}
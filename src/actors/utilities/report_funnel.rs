@@ -0,0 +1,46 @@
+//! One counter per step a report request goes through, all labeled with the
+//! `target` type (`event`, `pubkey`, or `unknown` while still encrypted) so
+//! the conversion between steps can be graphed.
+use metrics::counter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunnelStep {
+    // A gift wrap arrived from the relays
+    Received,
+    // It was decrypted into a report request
+    Unwrapped,
+    // The report request passed every check and is sent for moderation
+    Valid,
+    // Sent to the pubsub topic for automatic moderation
+    Enqueued,
+    // Written to slack for manual moderation
+    Slacked,
+    // All the report events were confirmed by the relays
+    Published,
+    // A moderator decided not to report it
+    Skipped,
+}
+
+impl FunnelStep {
+    pub fn counter_name(&self) -> &'static str {
+        match self {
+            FunnelStep::Received => "report_funnel_received",
+            FunnelStep::Unwrapped => "report_funnel_unwrapped",
+            FunnelStep::Valid => "report_funnel_valid",
+            FunnelStep::Enqueued => "report_funnel_enqueued",
+            FunnelStep::Slacked => "report_funnel_slacked",
+            FunnelStep::Published => "report_funnel_published",
+            FunnelStep::Skipped => "report_funnel_skipped",
+        }
+    }
+}
+
+pub const UNKNOWN_TARGET: &str = "unknown";
+
+pub fn record(step: FunnelStep, target: &'static str) {
+    record_many(step, target, 1);
+}
+
+pub fn record_many(step: FunnelStep, target: &'static str, count: u64) {
+    counter!(step.counter_name(), "target" => target).increment(count);
+}
@@ -0,0 +1,154 @@
+//! Keeps the report requests of each reporter in the order they came. A
+//! request waiting for a retry would otherwise be overtaken by the later ones
+//! of the same reporter, so when enabled those are held behind it until it's
+//! published or given up. Other reporters are never held back. Only the
+//! Pub/Sub path is ordered, Slack notifications are retried independently.
+use crate::config::Configurable;
+use crate::domain_objects::ReportRequest;
+use nostr_sdk::prelude::PublicKey;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub per_reporter: bool,
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "ordering"
+    }
+}
+
+/// Disabled by default, like when the section is missing
+#[derive(Debug, Default)]
+pub struct ReporterQueue {
+    enabled: bool,
+    // Reporters with a request waiting for a retry, and the requests held
+    // behind it
+    held: HashMap<PublicKey, VecDeque<ReportRequest>>,
+}
+
+impl ReporterQueue {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            enabled: config.per_reporter,
+            held: HashMap::new(),
+        }
+    }
+
+    /// Holds the report request if an earlier one from the same reporter is
+    /// still waiting, gives it back to be handled now otherwise
+    pub fn hold(&mut self, report_request: ReportRequest) -> Option<ReportRequest> {
+        match self.held.get_mut(report_request.reporter_pubkey()) {
+            Some(held) => {
+                held.push_back(report_request);
+                None
+            }
+            None => Some(report_request),
+        }
+    }
+
+    /// The report request failed and waits for a retry, later ones from the
+    /// same reporter are held until it's released
+    pub fn block(&mut self, report_request: &ReportRequest) {
+        if !self.enabled {
+            return;
+        }
+
+        self.held
+            .entry(*report_request.reporter_pubkey())
+            .or_default();
+    }
+
+    /// The waiting report request of the reporter is done with, published or
+    /// given up. Gives the next held one, the reporter is no longer held back
+    /// once none is left
+    pub fn release(&mut self, reporter_pubkey: &PublicKey) -> Option<ReportRequest> {
+        let held = self.held.get_mut(reporter_pubkey)?;
+        let next = held.pop_front();
+        if next.is_none() {
+            self.held.remove(reporter_pubkey);
+        }
+
+        next
+    }
+
+    /// Every held report request, in order for each reporter. Nothing is held
+    /// back anymore after this
+    pub fn drain(&mut self) -> Vec<ReportRequest> {
+        self.held.drain().flat_map(|(_, held)| held).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::Keys;
+
+    fn report_request(reporter_keys: &Keys, reporter_text: &str) -> ReportRequest {
+        ReportRequest::new(
+            Keys::generate().public_key().into(),
+            reporter_keys.public_key(),
+            Some(reporter_text.to_string()),
+        )
+    }
+
+    #[test]
+    fn test_holds_later_requests_of_a_blocked_reporter() {
+        let mut reporter_queue = ReporterQueue::new(&Config { per_reporter: true });
+        let reporter_keys = Keys::generate();
+        let first = report_request(&reporter_keys, "First");
+        let second = report_request(&reporter_keys, "Second");
+        let third = report_request(&reporter_keys, "Third");
+        let other_reporter = report_request(&Keys::generate(), "Other");
+
+        assert_eq!(reporter_queue.hold(first.clone()), Some(first.clone()));
+        reporter_queue.block(&first);
+
+        assert_eq!(reporter_queue.hold(second.clone()), None);
+        assert_eq!(reporter_queue.hold(third.clone()), None);
+        assert_eq!(
+            reporter_queue.hold(other_reporter.clone()),
+            Some(other_reporter)
+        );
+
+        let reporter_pubkey = reporter_keys.public_key();
+        assert_eq!(reporter_queue.release(&reporter_pubkey), Some(second));
+        assert_eq!(reporter_queue.release(&reporter_pubkey), Some(third));
+        assert_eq!(reporter_queue.release(&reporter_pubkey), None);
+
+        let fourth = report_request(&reporter_keys, "Fourth");
+        assert_eq!(reporter_queue.hold(fourth.clone()), Some(fourth));
+    }
+
+    #[test]
+    fn test_drains_the_held_requests() {
+        let mut reporter_queue = ReporterQueue::new(&Config { per_reporter: true });
+        let reporter_keys = Keys::generate();
+        let first = report_request(&reporter_keys, "First");
+        let second = report_request(&reporter_keys, "Second");
+        let third = report_request(&reporter_keys, "Third");
+
+        reporter_queue.block(&first);
+        assert_eq!(reporter_queue.hold(second.clone()), None);
+        assert_eq!(reporter_queue.hold(third.clone()), None);
+
+        assert_eq!(reporter_queue.drain(), vec![second, third]);
+        assert_eq!(reporter_queue.release(&reporter_keys.public_key()), None);
+    }
+
+    #[test]
+    fn test_disabled_queue_holds_nothing() {
+        let mut reporter_queue = ReporterQueue::default();
+        let reporter_keys = Keys::generate();
+        let first = report_request(&reporter_keys, "First");
+        let second = report_request(&reporter_keys, "Second");
+
+        reporter_queue.block(&first);
+
+        assert_eq!(reporter_queue.hold(second.clone()), Some(second));
+        assert_eq!(reporter_queue.release(&reporter_keys.public_key()), None);
+    }
+}
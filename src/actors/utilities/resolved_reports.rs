@@ -0,0 +1,266 @@
+//! Remembers which report requests a moderator already reported or skipped,
+//! so clicks on stale copies of the Slack message, like the mirrored one or a
+//! second moderator's, don't act on them again. Kept in memory by the
+//! supervisor, a restart forgets them.
+use crate::domain_objects::{ModerationCategory, ReportRequest};
+use nostr_sdk::prelude::{PublicKey, Timestamp};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+// Long enough for anyone to get back to an old Slack message
+const RESOLUTION_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Who resolved a report request and how, no category if it was skipped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    pub moderator: String,
+    pub category: Option<ModerationCategory>,
+}
+
+impl Display for Resolution {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.category {
+            Some(category) => write!(f, "Resolved by {} as `{}`", self.moderator, category.slug()),
+            None => write!(f, "Resolved by {} as skipped", self.moderator),
+        }
+    }
+}
+
+// The same target reported by the same reporter, whichever message it's in
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ReportKey {
+    target_id: String,
+    reporter_pubkey: PublicKey,
+}
+
+impl From<&ReportRequest> for ReportKey {
+    fn from(report_request: &ReportRequest) -> Self {
+        Self {
+            target_id: report_request.target().id(),
+            reporter_pubkey: *report_request.reporter_pubkey(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ResolvedReports {
+    ttl_secs: u64,
+    // With their expiration
+    resolutions: HashMap<ReportKey, (Resolution, u64)>,
+}
+
+impl Default for ResolvedReports {
+    fn default() -> Self {
+        Self::new(RESOLUTION_TTL_SECS)
+    }
+}
+
+impl ResolvedReports {
+    pub fn new(ttl_secs: u64) -> Self {
+        Self {
+            ttl_secs,
+            resolutions: HashMap::new(),
+        }
+    }
+
+    /// Records the resolution unless the request was already resolved, the
+    /// earlier resolution is returned then and kept
+    pub fn resolve(
+        &mut self,
+        report_request: &ReportRequest,
+        resolution: Resolution,
+        now: Timestamp,
+    ) -> Option<Resolution> {
+        let now = now.as_u64();
+        self.prune(now);
+
+        let key = ReportKey::from(report_request);
+        if let Some((earlier_resolution, _)) = self.resolutions.get(&key) {
+            return Some(earlier_resolution.clone());
+        }
+
+        self.resolutions
+            .insert(key, (resolution, now + self.ttl_secs));
+        None
+    }
+
+    /// Forgets the resolution of a request whose action failed, so it can be
+    /// acted on again. A different resolution is left alone
+    pub fn unresolve(&mut self, report_request: &ReportRequest, resolution: &Resolution) {
+        let key = ReportKey::from(report_request);
+        if matches!(self.resolutions.get(&key), Some((recorded, _)) if recorded == resolution) {
+            self.resolutions.remove(&key);
+        }
+    }
+
+    /// For a Slack message that expired, true if none of its report requests
+    /// was resolved. They then get the resolution, if any, like a skip
+    pub fn expire(
+        &mut self,
+        report_requests: &[ReportRequest],
+        resolution: Option<Resolution>,
+        now: Timestamp,
+    ) -> bool {
+        let now = now.as_u64();
+        self.prune(now);
+
+        if report_requests.iter().any(|report_request| {
+            self.resolutions
+                .contains_key(&ReportKey::from(report_request))
+        }) {
+            return false;
+        }
+
+        if let Some(resolution) = resolution {
+            for report_request in report_requests {
+                self.resolutions.insert(
+                    ReportKey::from(report_request),
+                    (resolution.clone(), now + self.ttl_secs),
+                );
+            }
+        }
+        true
+    }
+
+    fn prune(&mut self, now: u64) {
+        self.resolutions
+            .retain(|_, (_, expires_at)| *expires_at > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::*;
+
+    fn resolution(moderator: &str, category: Option<ModerationCategory>) -> Resolution {
+        Resolution {
+            moderator: moderator.to_string(),
+            category,
+        }
+    }
+
+    #[test]
+    fn test_keeps_the_first_resolution() {
+        let mut resolved_reports = ResolvedReports::default();
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+        let now = Timestamp::now();
+
+        assert_eq!(
+            resolved_reports.resolve(
+                &report_request,
+                resolution("daniel", Some(ModerationCategory::Spam)),
+                now
+            ),
+            None
+        );
+        assert_eq!(
+            resolved_reports.resolve(&report_request, resolution("martin", None), now),
+            Some(resolution("daniel", Some(ModerationCategory::Spam)))
+        );
+
+        // Another reporter of the same target is a request of its own
+        let other_report_request = ReportRequest::new(
+            report_request.target().clone(),
+            Keys::generate().public_key(),
+            None,
+        );
+        assert_eq!(
+            resolved_reports.resolve(&other_report_request, resolution("martin", None), now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_forgets_resolutions_after_the_ttl() {
+        let mut resolved_reports = ResolvedReports::new(60);
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+        let now = Timestamp::now();
+
+        resolved_reports.resolve(&report_request, resolution("daniel", None), now);
+
+        assert_eq!(
+            resolved_reports.resolve(&report_request, resolution("martin", None), now + 61),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unresolves_only_the_same_resolution() {
+        let mut resolved_reports = ResolvedReports::default();
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+        let now = Timestamp::now();
+
+        resolved_reports.resolve(&report_request, resolution("daniel", None), now);
+        resolved_reports.unresolve(&report_request, &resolution("martin", None));
+        assert_eq!(
+            resolved_reports.resolve(&report_request, resolution("martin", None), now),
+            Some(resolution("daniel", None))
+        );
+
+        resolved_reports.unresolve(&report_request, &resolution("daniel", None));
+        assert_eq!(
+            resolved_reports.resolve(&report_request, resolution("martin", None), now),
+            None
+        );
+    }
+
+    #[test]
+    fn test_expires_only_unresolved_report_requests() {
+        let mut resolved_reports = ResolvedReports::default();
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+        let resolved_report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+        let now = Timestamp::now();
+
+        resolved_reports.resolve(&resolved_report_request, resolution("daniel", None), now);
+
+        assert!(!resolved_reports.expire(
+            &[report_request.clone(), resolved_report_request],
+            None,
+            now
+        ));
+        assert!(resolved_reports.expire(
+            &[report_request.clone()],
+            Some(resolution("expiry", None)),
+            now
+        ));
+
+        // A later click finds it skipped by the expiry
+        assert_eq!(
+            resolved_reports.resolve(&report_request, resolution("martin", None), now),
+            Some(resolution("expiry", None))
+        );
+    }
+
+    #[test]
+    fn test_resolution_display() {
+        assert_eq!(
+            resolution("daniel", Some(ModerationCategory::Hate)).to_string(),
+            "Resolved by daniel as `hate`"
+        );
+        assert_eq!(
+            resolution("daniel", None).to_string(),
+            "Resolved by daniel as skipped"
+        );
+    }
+}
@@ -0,0 +1,161 @@
+//! A `NostrPort` for tests that delivers a fixed list of events once
+//! subscribed and records what gets published, without any relay.
+use crate::actors::messages::RelayEventDispatcherMessage;
+use crate::actors::{NostrPort, PublishOutcome};
+use anyhow::Result;
+use nostr_sdk::prelude::*;
+use ractor::{cast, ActorRef};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Clone)]
+pub struct ScriptedNostrService {
+    events_to_deliver: Vec<Event>,
+    published_events: Arc<Mutex<Vec<Event>>>,
+    // Our relays and whether they're connected, a single connected one by
+    // default
+    relays: Arc<Mutex<Vec<(Url, bool)>>>,
+    reconnected_relays: Arc<Mutex<Vec<Url>>>,
+    // What publish returns, accepted by a single relay by default
+    publish_outcome: Arc<Mutex<PublishOutcome>>,
+    // How long publish takes, nothing by default
+    publish_delay: Arc<Mutex<Duration>>,
+}
+
+impl Default for ScriptedNostrService {
+    fn default() -> Self {
+        Self::delivering(vec![])
+    }
+}
+
+impl ScriptedNostrService {
+    pub fn delivering(events_to_deliver: Vec<Event>) -> Self {
+        Self {
+            events_to_deliver,
+            published_events: Arc::new(Mutex::new(Vec::new())),
+            relays: Arc::new(Mutex::new(vec![(
+                Url::parse("wss://relay.example.com").unwrap(),
+                true,
+            )])),
+            reconnected_relays: Arc::new(Mutex::new(Vec::new())),
+            publish_outcome: Arc::new(Mutex::new(PublishOutcome {
+                accepted: vec![Url::parse("wss://relay.example.com").unwrap()],
+                ..Default::default()
+            })),
+            publish_delay: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Changes our relays and which of them are connected from now on.
+    /// Reconnecting doesn't connect them
+    pub async fn set_relays(&self, relays: Vec<(Url, bool)>) {
+        *self.relays.lock().await = relays;
+    }
+
+    /// Changes what publish returns from now on
+    pub async fn set_publish_outcome(&self, publish_outcome: PublishOutcome) {
+        *self.publish_outcome.lock().await = publish_outcome;
+    }
+
+    /// Makes publish take this long from now on
+    pub async fn set_publish_delay(&self, publish_delay: Duration) {
+        *self.publish_delay.lock().await = publish_delay;
+    }
+
+    /// The relays asked to reconnect, in order, once per time
+    pub async fn reconnected_relays(&self) -> Vec<Url> {
+        self.reconnected_relays.lock().await.clone()
+    }
+
+    /// Reports and gift wrapped DMs, in the order they were published
+    pub async fn published_events(&self) -> Vec<Event> {
+        self.published_events.lock().await.clone()
+    }
+}
+
+#[async_trait]
+impl NostrPort for ScriptedNostrService {
+    async fn connect(&self) -> Result<()> {
+        Ok(())
+    }
+    async fn reconnect(&self) -> Result<()> {
+        let relays = self.relays.lock().await.clone();
+        self.reconnected_relays
+            .lock()
+            .await
+            .extend(relays.into_iter().map(|(url, _)| url));
+        Ok(())
+    }
+    async fn is_connected(&self) -> bool {
+        let (connected, _) = self.connected_relays().await;
+        connected > 0
+    }
+    async fn connected_relays(&self) -> (usize, usize) {
+        let relays = self.relays.lock().await;
+        let connected = relays.iter().filter(|(_, connected)| *connected).count();
+        (connected, relays.len())
+    }
+    async fn relay_connections(&self) -> Vec<(Url, bool)> {
+        self.relays.lock().await.clone()
+    }
+    async fn reconnect_relays(&self, relays: Vec<Url>) -> Result<()> {
+        self.reconnected_relays.lock().await.extend(relays);
+        Ok(())
+    }
+    async fn publish(&self, event: Event) -> Result<PublishOutcome> {
+        let publish_delay = *self.publish_delay.lock().await;
+        tokio::time::sleep(publish_delay).await;
+        self.published_events.lock().await.push(event);
+        Ok(self.publish_outcome.lock().await.clone())
+    }
+    async fn publish_to(&self, event: Event, _relays: Vec<String>) -> Result<()> {
+        self.published_events.lock().await.push(event);
+        Ok(())
+    }
+    async fn publish_direct_message(&self, gift_wrap: Event, _receiver: PublicKey) -> Result<()> {
+        self.published_events.lock().await.push(gift_wrap);
+        Ok(())
+    }
+    async fn set_reportinator_public_keys(&self, _public_keys: Vec<PublicKey>) -> Result<()> {
+        Ok(())
+    }
+    async fn get_nip05(&self, _public_key: PublicKey) -> Result<Option<String>> {
+        Ok(None)
+    }
+    async fn get_events(&self, _filters: Vec<Filter>) -> Result<Vec<Event>> {
+        Ok(vec![])
+    }
+    async fn fetch_range(
+        &self,
+        _since: Timestamp,
+        _until: Timestamp,
+        _limit: usize,
+    ) -> Result<Vec<Event>> {
+        Ok(vec![])
+    }
+    async fn add_relay(&self, _url: Url) -> Result<bool> {
+        Ok(true)
+    }
+    async fn remove_relay(&self, _url: Url) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn subscribe(
+        &self,
+        cancellation_token: CancellationToken,
+        dispatcher_actor: ActorRef<RelayEventDispatcherMessage>,
+    ) -> Result<(), anyhow::Error> {
+        for event in &self.events_to_deliver {
+            cast!(
+                dispatcher_actor,
+                RelayEventDispatcherMessage::EventReceived(event.clone())
+            )
+            .expect("Failed to cast event to dispatcher");
+        }
+
+        cancellation_token.cancelled().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! Results of the admin self test, one per dependency. Each check is a
+//! lightweight call made through the clients the actors already hold.
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+// Checks still running after this are reported as timed out. Below the
+// timeout of the HTTP routes so the report always gets back
+pub const CHECK_TIMEOUT: Duration = Duration::from_millis(800);
+
+// A single check gives up after this, so the checks an actor runs
+// concurrently are all back before CHECK_TIMEOUT
+const DEPENDENCY_TIMEOUT: Duration = Duration::from_millis(600);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyCheck {
+    pub dependency: String,
+    pub ok: bool,
+    pub latency_ms: u64,
+    // What the dependency answered, or why it failed
+    pub detail: String,
+}
+
+impl DependencyCheck {
+    /// Times the check, which answers with a short detail on success. A
+    /// check that doesn't answer in time fails
+    pub async fn run<F>(dependency: impl Into<String>, check: F) -> Self
+    where
+        F: Future<Output = Result<String>>,
+    {
+        let started = Instant::now();
+        let result = tokio::time::timeout(DEPENDENCY_TIMEOUT, check)
+            .await
+            .unwrap_or_else(|_| {
+                Err(anyhow!(
+                    "Timed out after {}ms",
+                    DEPENDENCY_TIMEOUT.as_millis()
+                ))
+            });
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        match result {
+            Ok(detail) => Self {
+                dependency: dependency.into(),
+                ok: true,
+                latency_ms,
+                detail,
+            },
+            Err(e) => Self::failed(dependency, latency_ms, e.to_string()),
+        }
+    }
+
+    /// For checks that couldn't be run at all, like when the actor holding
+    /// the client doesn't answer
+    pub fn failed(dependency: impl Into<String>, latency_ms: u64, detail: String) -> Self {
+        Self {
+            dependency: dependency.into(),
+            ok: false,
+            latency_ms,
+            detail,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dependency_check_reports_the_outcome_and_latency() {
+        let check = DependencyCheck::run("pubsub", async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            Ok("Topic found".to_string())
+        })
+        .await;
+
+        assert!(check.ok);
+        assert_eq!(check.detail, "Topic found");
+        assert!(check.latency_ms >= 20);
+
+        let check =
+            DependencyCheck::run("slack:default", async { Err(anyhow!("invalid_auth")) }).await;
+
+        assert!(!check.ok);
+        assert_eq!(check.dependency, "slack:default");
+        assert_eq!(check.detail, "invalid_auth");
+    }
+
+    #[tokio::test]
+    async fn test_dependency_check_times_out() {
+        let check = DependencyCheck::run("slack:default", async {
+            tokio::time::sleep(CHECK_TIMEOUT).await;
+            Ok("Too late".to_string())
+        })
+        .await;
+
+        assert!(!check.ok);
+        assert_eq!(check.detail, "Timed out after 600ms");
+        assert!(check.latency_ms < CHECK_TIMEOUT.as_millis() as u64);
+    }
+}
@@ -1,15 +1,38 @@
+pub mod decision_store;
+pub mod file_report_sink;
+pub use file_report_sink::FileReportSink;
 pub mod google_publisher;
 pub use google_publisher::GooglePublisher;
 pub mod http_server;
 pub use http_server::HttpServer;
+#[cfg(feature = "kafka")]
+pub mod kafka_publisher;
+#[cfg(feature = "kafka")]
+pub use kafka_publisher::KafkaPublisher;
+pub mod noop_publisher;
+pub use noop_publisher::NoopPublisher;
 pub mod nostr_service;
+pub mod payload_encryption;
 pub use nostr_service::NostrService;
+#[cfg(feature = "postgres")]
+pub mod pg_decision_store;
 pub mod slack_client_adapter;
 pub use slack_client_adapter::SlackClientAdapterBuilder;
+pub mod slack_expiry;
+pub mod slack_markdown;
+pub mod slack_templates;
+pub mod webhook_notifier;
+pub use webhook_notifier::WebhookNotifier;
 
 use crate::actors::messages::SupervisorMessage;
-use nostr_sdk::prelude::{nip19::*, PublicKey};
-use ractor::{call_t, ActorRef};
+use crate::config::reportinator::{self, Nip05Lookup};
+use anyhow::{anyhow, bail, Result};
+use metrics::counter;
+use nostr_sdk::prelude::{nip19::*, Event, PublicKey};
+use ractor::{call_t, ActorRef, RactorErr};
+use tracing::{debug, warn};
+
+const NJUMP_BASE_URL: &str = "https://njump.me";
 
 // This function attempts to generate an njump link for a given public key,
 // following a specific order of preference:
@@ -23,21 +46,184 @@ async fn njump_or_pubkey(
     message_dispatcher: ActorRef<SupervisorMessage>,
     pubkey: PublicKey,
 ) -> String {
-    let Ok(maybe_reporter_nip05) =
-        call_t!(message_dispatcher, SupervisorMessage::GetNip05, 100, pubkey)
-    else {
-        return pubkey
-            .to_bech32()
-            .map(|npub| format!("https://njump.me/{}", npub))
-            .unwrap_or_else(|_| pubkey.to_string());
-    };
-
-    if let Some(nip05) = maybe_reporter_nip05 {
-        format!("https://njump.me/{}", nip05)
+    let maybe_nip05 = lookup_nip05(&message_dispatcher, pubkey, reportinator::nip05_lookup())
+        .await
+        .unwrap_or_else(|e| {
+            warn!("{}", e);
+            None
+        });
+
+    if let Some(nip05) = maybe_nip05 {
+        format!("{}/{}", NJUMP_BASE_URL, nip05)
     } else {
-        pubkey
-            .to_bech32()
-            .map(|npub| format!("https://njump.me/{}", npub))
-            .unwrap_or_else(|_| pubkey.to_string())
+        njump_npub_or_pubkey(pubkey)
+    }
+}
+
+// The last two steps of `njump_or_pubkey`, without looking up the NIP-05
+fn njump_npub_or_pubkey(pubkey: PublicKey) -> String {
+    pubkey
+        .to_bech32()
+        .map(|npub| format!("{}/{}", NJUMP_BASE_URL, npub))
+        .unwrap_or_else(|_| pubkey.to_string())
+}
+
+// Only timeouts are retried, a missing NIP-05 is an answer. Only verified
+// NIP-05s are returned, see `NostrPort::get_nip05`. Fails if the lookup
+// failed or every attempt timed out
+pub async fn lookup_nip05(
+    message_dispatcher: &ActorRef<SupervisorMessage>,
+    pubkey: PublicKey,
+    lookup: Nip05Lookup,
+) -> Result<Option<String>> {
+    for attempt in 1..=lookup.attempts.max(1) {
+        match call_t!(
+            message_dispatcher,
+            SupervisorMessage::GetNip05,
+            lookup.timeout_ms,
+            pubkey
+        ) {
+            Ok(result) => {
+                return result
+                    .map_err(|e| anyhow!("Failed to look up the NIP-05 of {}: {}", pubkey, e))
+            }
+            Err(RactorErr::Timeout) => {
+                counter!("nip05_lookup_timeout").increment(1);
+                debug!(
+                    "NIP-05 lookup for {} timed out, attempt {} of {}",
+                    pubkey, attempt, lookup.attempts
+                );
+            }
+            Err(e) => bail!("Failed to look up the NIP-05 of {}: {}", pubkey, e),
+        }
+    }
+
+    bail!(
+        "The NIP-05 lookup of {} timed out {} times",
+        pubkey,
+        lookup.attempts.max(1)
+    )
+}
+
+// Njump link to a reported event, so moderators can open it in context. The
+// nevent carries the author and relay hints, falling back to a note1 link
+// and then to the plain id.
+//    https://njump.me/nevent1qqs...
+fn njump_event_link(event: &Event, relay_hints: &[String]) -> String {
+    let mut nevent = Nip19Event::new(event.id, relay_hints.iter().cloned());
+    nevent.author = Some(event.pubkey);
+
+    nevent
+        .to_bech32()
+        .or_else(|_| event.id.to_bech32())
+        .map(|bech32| format!("{}/{}", NJUMP_BASE_URL, bech32))
+        .unwrap_or_else(|_| event.id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::{EventBuilder, FromBech32, Keys};
+    use ractor::{Actor, ActorProcessingErr};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // Answers GetNip05 with the NIP-05, once the given delay of each lookup
+    // is over
+    struct SlowSupervisor;
+
+    #[ractor::async_trait]
+    impl Actor for SlowSupervisor {
+        type Msg = SupervisorMessage;
+        type State = (Option<String>, Vec<Duration>, Arc<AtomicUsize>);
+        type Arguments = (Option<String>, Vec<Duration>, Arc<AtomicUsize>);
+
+        async fn pre_start(
+            &self,
+            _: ActorRef<Self::Msg>,
+            arguments: Self::Arguments,
+        ) -> Result<Self::State, ActorProcessingErr> {
+            Ok(arguments)
+        }
+
+        async fn handle(
+            &self,
+            _: ActorRef<Self::Msg>,
+            message: Self::Msg,
+            (nip05, delays, lookups): &mut Self::State,
+        ) -> Result<(), ActorProcessingErr> {
+            if let SupervisorMessage::GetNip05(_, reply_port) = message {
+                let lookup = lookups.fetch_add(1, Ordering::SeqCst);
+                let delay = delays.get(lookup).copied().unwrap_or_default();
+                let nip05 = nip05.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let _ = reply_port.send(Ok(nip05));
+                });
+            }
+
+            Ok(())
+        }
+    }
+
+    // None when the lookup failed
+    async fn lookup(nip05: Option<&str>, delays: Vec<Duration>) -> (Option<Option<String>>, usize) {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let (supervisor_ref, supervisor_handle) = Actor::spawn(
+            None,
+            SlowSupervisor,
+            (nip05.map(str::to_string), delays, lookups.clone()),
+        )
+        .await
+        .unwrap();
+
+        let found = lookup_nip05(
+            &supervisor_ref,
+            Keys::generate().public_key(),
+            Nip05Lookup {
+                timeout_ms: 50,
+                attempts: 2,
+            },
+        )
+        .await
+        .ok();
+
+        supervisor_ref.stop(None);
+        supervisor_handle.await.unwrap();
+        (found, lookups.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn test_retries_nip05_lookups_that_time_out() {
+        let slow = Duration::from_millis(200);
+
+        assert_eq!(
+            lookup(Some("daniel@nos.social"), vec![slow]).await,
+            (Some(Some("daniel@nos.social".to_string())), 2)
+        );
+        // Bounded by the attempts, then it failed
+        assert_eq!(
+            lookup(Some("daniel@nos.social"), vec![slow, slow, slow]).await,
+            (None, 2)
+        );
+        // No NIP-05 is an answer, not retried
+        assert_eq!(lookup(None, vec![]).await, (Some(None), 1));
+    }
+
+    #[test]
+    fn test_njump_event_link() {
+        let event = EventBuilder::text_note("I hate you!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let relay_hints = vec!["wss://relay.nos.social".to_string()];
+
+        let link = njump_event_link(&event, &relay_hints);
+
+        let nevent = link.strip_prefix("https://njump.me/").unwrap();
+        let decoded = Nip19Event::from_bech32(nevent).unwrap();
+        assert_eq!(decoded.event_id, event.id);
+        assert_eq!(decoded.author, Some(event.pubkey));
+        assert_eq!(decoded.relays, relay_hints);
     }
 }
@@ -0,0 +1,111 @@
+use crate::config::Configurable;
+use crate::domain_objects::{ModerationCategory, ReportRequest, ReportTarget};
+use anyhow::Result;
+use nostr_sdk::prelude::*;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Where moderator decisions are persisted: none or postgres. Postgres
+    // requires building with the `postgres` feature.
+    #[serde(default = "default_store")]
+    pub store: String,
+    #[serde(default)]
+    pub database_url: Option<String>,
+    // Topic of google.project_id the decisions are also published to, for
+    // automated consumers. Unset doesn't publish them
+    #[serde(default)]
+    pub pubsub_topic: Option<String>,
+}
+
+fn default_store() -> String {
+    "none".to_string()
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "decisions"
+    }
+}
+
+/// A moderator decision taken from Slack, either publishing a report or
+/// skipping the request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModeratorDecision {
+    pub report_id: Option<EventId>,
+    pub category: Option<ModerationCategory>,
+    pub moderator: String,
+    pub target: ReportTarget,
+    pub reporter_pubkey: PublicKey,
+    pub decided_at: Timestamp,
+}
+
+impl ModeratorDecision {
+    pub fn new(
+        report_request: &ReportRequest,
+        report_id: Option<EventId>,
+        category: Option<ModerationCategory>,
+        moderator: String,
+    ) -> Self {
+        Self {
+            report_id,
+            category,
+            moderator,
+            target: report_request.target().clone(),
+            reporter_pubkey: *report_request.reporter_pubkey(),
+            decided_at: Timestamp::now(),
+        }
+    }
+
+    pub fn target_type(&self) -> &'static str {
+        self.target.label()
+    }
+
+    // Event id or pubkey being reported
+    pub fn target_id(&self) -> String {
+        self.target.id()
+    }
+}
+
+#[ractor::async_trait]
+pub trait DecisionStore: Send + Sync + 'static {
+    async fn save(&self, decision: &ModeratorDecision) -> Result<()>;
+}
+
+// Every store gets the decision, one failing doesn't keep it from the others
+#[ractor::async_trait]
+impl DecisionStore for Vec<Arc<dyn DecisionStore>> {
+    async fn save(&self, decision: &ModeratorDecision) -> Result<()> {
+        let mut result = Ok(());
+        for store in self {
+            if let Err(e) = store.save(decision).await {
+                result = Err(e);
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+pub use in_memory::InMemoryDecisionStore;
+
+#[cfg(test)]
+mod in_memory {
+    use super::*;
+    use tokio::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    pub struct InMemoryDecisionStore {
+        pub decisions: Arc<Mutex<Vec<ModeratorDecision>>>,
+    }
+
+    #[ractor::async_trait]
+    impl DecisionStore for InMemoryDecisionStore {
+        async fn save(&self, decision: &ModeratorDecision) -> Result<()> {
+            self.decisions.lock().await.push(decision.clone());
+            Ok(())
+        }
+    }
+}
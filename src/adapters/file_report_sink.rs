@@ -0,0 +1,283 @@
+use crate::actors::PubsubPort;
+use crate::config::{self, Configurable};
+use crate::domain_objects::{ReportRequest, ReportTarget};
+use anyhow::{bail, Context, Result};
+use metrics::counter;
+use nostr_sdk::prelude::Timestamp;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // JSONL file the report requests are appended to
+    #[serde(default = "default_path")]
+    pub path: String,
+    // The file is rotated before it would grow past this size
+    #[serde(default = "default_max_file_bytes")]
+    pub max_file_bytes: Option<u64>,
+    // The file is rotated once its first line is this old
+    #[serde(default)]
+    pub max_file_age_secs: Option<u64>,
+}
+
+fn default_path() -> String {
+    "reports/report_requests.jsonl".to_string()
+}
+
+fn default_max_file_bytes() -> Option<u64> {
+    Some(100 * 1024 * 1024)
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "file_sink"
+    }
+}
+
+/// Appends report requests to a local JSONL file instead of publishing them,
+/// for deployments without Pub/Sub. Rotated files are renamed with the time
+/// of the rotation, like report_requests.1718000000.jsonl, and never deleted
+pub struct FileReportSink {
+    path: PathBuf,
+    max_file_bytes: Option<u64>,
+    max_file_age_secs: Option<u64>,
+    // When the first line of the current file was written. Files found on
+    // startup are as old as the process
+    started_at: u64,
+}
+
+impl FileReportSink {
+    pub fn new(config: &Config) -> Result<Self> {
+        let path = PathBuf::from(&config.path);
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        Ok(Self {
+            path,
+            max_file_bytes: config.max_file_bytes,
+            max_file_age_secs: config.max_file_age_secs,
+            started_at: Timestamp::now().as_u64(),
+        })
+    }
+
+    fn append(&mut self, line: &str, now: u64) -> Result<()> {
+        let size = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == ErrorKind::NotFound => 0,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", self.path.display()))
+            }
+        };
+
+        if size == 0 {
+            self.started_at = now;
+        } else if self.should_rotate(size, line.len() as u64 + 1, now) {
+            self.rotate(now)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    fn should_rotate(&self, size: u64, incoming: u64, now: u64) -> bool {
+        let too_big = self
+            .max_file_bytes
+            .is_some_and(|max_file_bytes| size + incoming > max_file_bytes);
+        let too_old = self.max_file_age_secs.is_some_and(|max_file_age_secs| {
+            now.saturating_sub(self.started_at) >= max_file_age_secs
+        });
+
+        too_big || too_old
+    }
+
+    fn rotate(&mut self, now: u64) -> Result<()> {
+        let rotated_path = rotated_path(&self.path, now);
+        fs::rename(&self.path, &rotated_path).with_context(|| {
+            format!(
+                "Failed to rotate {} to {}",
+                self.path.display(),
+                rotated_path.display()
+            )
+        })?;
+
+        counter!("file_sink_rotated").increment(1);
+        info!("Rotated report requests file to {}", rotated_path.display());
+        self.started_at = now;
+        Ok(())
+    }
+}
+
+// The time goes before the extension, with a suffix if there was already a
+// rotation in the same second
+fn rotated_path(path: &Path, now: u64) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut rotated_path = path.with_file_name(format!("{}.{}{}", stem, now, extension));
+    let mut suffix = 1;
+    while rotated_path.exists() {
+        rotated_path = path.with_file_name(format!("{}.{}-{}{}", stem, now, suffix, extension));
+        suffix += 1;
+    }
+
+    rotated_path
+}
+
+#[ractor::async_trait]
+impl PubsubPort for FileReportSink {
+    async fn publish_event(&mut self, report_request: &ReportRequest) -> Result<()> {
+        if let ReportTarget::Pubkey(_) = report_request.target() {
+            bail!("Cannot write event with Pubkey target to the report requests file")
+        }
+
+        let truncated = report_request
+            .with_truncated_content(config::reportinator::max_reported_content_chars());
+        // A truncated event no longer matches its signature, so the line
+        // says so
+        let line = match truncated {
+            Cow::Owned(truncated) => serde_json::to_value(&truncated).map(|mut value| {
+                value["contentTruncated"] = true.into();
+                value.to_string()
+            }),
+            Cow::Borrowed(report_request) => serde_json::to_string(report_request),
+        }
+        .context("Failed to serialize event to JSON")?;
+
+        self.append(&line, Timestamp::now().as_u64())?;
+        info!("Event written to {}", self.path.display());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::*;
+
+    fn sink(
+        name: &str,
+        max_file_bytes: Option<u64>,
+        max_file_age_secs: Option<u64>,
+    ) -> FileReportSink {
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, Timestamp::now().as_u64()));
+        let _ = fs::remove_dir_all(&dir);
+
+        FileReportSink::new(&Config {
+            path: dir
+                .join("report_requests.jsonl")
+                .to_string_lossy()
+                .to_string(),
+            max_file_bytes,
+            max_file_age_secs,
+        })
+        .unwrap()
+    }
+
+    // Every file in the directory of the sink, sorted by name, with its lines
+    fn files(sink: &FileReportSink) -> Vec<(String, Vec<String>)> {
+        let mut files: Vec<(String, Vec<String>)> = fs::read_dir(sink.path.parent().unwrap())
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let lines = fs::read_to_string(&path)
+                    .unwrap()
+                    .lines()
+                    .map(str::to_string)
+                    .collect();
+                (
+                    path.file_name().unwrap().to_string_lossy().to_string(),
+                    lines,
+                )
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[tokio::test]
+    async fn test_writes_report_requests_as_jsonl() {
+        let mut sink = sink("test_file_sink_writes", None, None);
+        let reported_event = EventBuilder::text_note("I hate you!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let report_request =
+            ReportRequest::new(reported_event.into(), Keys::generate().public_key(), None);
+
+        sink.publish_event(&report_request).await.unwrap();
+
+        let files = files(&sink);
+        assert_eq!(files.len(), 1);
+        let written: ReportRequest = serde_json::from_str(&files[0].1[0]).unwrap();
+        assert_eq!(written, report_request);
+        fs::remove_dir_all(sink.path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rotates_by_size_and_age() {
+        let mut sink = sink("test_file_sink_rotates", Some(10), Some(60));
+
+        sink.append("first", 1000).unwrap();
+        // Would grow past 10 bytes
+        sink.append("second", 1001).unwrap();
+        // A minute after the first line of the current file
+        sink.append("3", 1061).unwrap();
+
+        assert_eq!(
+            files(&sink),
+            vec![
+                (
+                    "report_requests.1001.jsonl".to_string(),
+                    vec!["first".to_string()]
+                ),
+                (
+                    "report_requests.1061.jsonl".to_string(),
+                    vec!["second".to_string()]
+                ),
+                ("report_requests.jsonl".to_string(), vec!["3".to_string()]),
+            ]
+        );
+        fs::remove_dir_all(sink.path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_rotations_in_the_same_second_get_a_suffix() {
+        let mut sink = sink("test_file_sink_same_second", Some(1), None);
+
+        for line in ["a", "b", "c"] {
+            sink.append(line, 1000).unwrap();
+        }
+
+        let names: Vec<String> = files(&sink).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            vec![
+                "report_requests.1000-1.jsonl",
+                "report_requests.1000.jsonl",
+                "report_requests.jsonl",
+            ]
+        );
+        fs::remove_dir_all(sink.path.parent().unwrap()).unwrap();
+    }
+}
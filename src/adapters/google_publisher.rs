@@ -1,46 +1,262 @@
 use crate::actors::PubsubPort;
+use crate::adapters::decision_store::{DecisionStore, ModeratorDecision};
+use crate::adapters::payload_encryption::{
+    PayloadKey, ENCRYPTION_ATTRIBUTE, ENCRYPTION_SCHEME, MAX_PAYLOAD_BYTES,
+};
+use crate::config::{self, Configurable};
+use crate::domain_objects::report_request::TRUNCATED_MARKER;
 use crate::domain_objects::{ReportRequest, ReportTarget};
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use gcloud_sdk::{
-    google::pubsub::v1::{publisher_client::PublisherClient, PublishRequest, PubsubMessage},
+    google::pubsub::v1::{
+        publisher_client::PublisherClient, GetTopicRequest, PublishRequest, PubsubMessage,
+    },
+    tonic::{Code, Status},
     *,
 };
-use tracing::info;
+use metrics::counter;
+use nostr_sdk::hashes::{sha256::Hash as Sha256Hash, Hash};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub project_id: String,
+    pub topic: String,
+    // Retries after the first publish attempt, only for auth and transient
+    // errors. Permanent errors are never retried.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    // Delay before the first retry, doubled on each following one
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    // Sets the reported account as the ordering key, so subscriptions with
+    // message ordering get the reports of an account in order
+    #[serde(default)]
+    pub message_ordering: bool,
+    // Hex symmetric key to encrypt the payload with, plaintext when unset.
+    // Consumers decrypt it with payload_encryption::decode_report_request
+    #[serde(default)]
+    pub encryption_key: Option<String>,
+}
+
+/// Attribute with a key that's the same for every publish of a report, so
+/// subscribers can drop the duplicates of retried publishes
+pub const IDEMPOTENCY_KEY_ATTRIBUTE: &str = "idempotency_key";
+/// Set to true when the reported event content was cut to
+/// reportinator.max_reported_content_chars, its signature won't verify
+pub const CONTENT_TRUNCATED_ATTRIBUTE: &str = "content_truncated";
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    500
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "google"
+    }
+}
 
-pub struct GooglePublisher {
-    pubsub_client: GoogleApi<PublisherClient<GoogleAuthMiddleware>>,
+impl Config {
+    pub fn encryption_key(&self) -> Result<Option<PayloadKey>> {
+        self.encryption_key
+            .as_deref()
+            .map(PayloadKey::from_hex)
+            .transpose()
+    }
+}
+
+// The part of the Google client we use, so it can be faked in tests
+#[ractor::async_trait]
+pub trait PublisherApi: Send + Sync + 'static {
+    async fn publish(&self, request: PublishRequest) -> Result<(), Status>;
+    // Fails if the topic doesn't exist or we can't see it
+    async fn get_topic(&self, topic: String) -> Result<(), Status>;
+    // Recreates the client so a fresh token is fetched
+    async fn refresh(&mut self) -> Result<()>;
+}
+
+pub struct GoogleApiPublisher {
+    client: GoogleApi<PublisherClient<GoogleAuthMiddleware>>,
     google_full_topic: String,
 }
-impl GooglePublisher {
-    pub async fn create() -> Result<Self> {
-        let google_project_id = "pub-verse-app";
-        let google_topic = "nostr-events";
-        let google_full_topic = format!("projects/{}/topics/{}", google_project_id, google_topic);
-
-        let pubsub_client: GoogleApi<PublisherClient<GoogleAuthMiddleware>> =
-            GoogleApi::from_function(
-                PublisherClient::new,
-                "https://pubsub.googleapis.com",
-                Some(google_full_topic.clone()),
-            )
+
+impl GoogleApiPublisher {
+    pub async fn create(google_full_topic: String) -> Result<Self> {
+        let client = Self::client(&google_full_topic).await?;
+
+        Ok(Self {
+            client,
+            google_full_topic,
+        })
+    }
+
+    async fn client(
+        google_full_topic: &str,
+    ) -> Result<GoogleApi<PublisherClient<GoogleAuthMiddleware>>> {
+        Ok(GoogleApi::from_function(
+            PublisherClient::new,
+            "https://pubsub.googleapis.com",
+            Some(google_full_topic.to_string()),
+        )
+        .await?)
+    }
+}
+
+#[ractor::async_trait]
+impl PublisherApi for GoogleApiPublisher {
+    async fn publish(&self, request: PublishRequest) -> Result<(), Status> {
+        self.client.get().publish(request).await?;
+        Ok(())
+    }
+
+    async fn get_topic(&self, topic: String) -> Result<(), Status> {
+        self.client
+            .get()
+            .get_topic(GetTopicRequest { topic })
             .await?;
+        Ok(())
+    }
+
+    async fn refresh(&mut self) -> Result<()> {
+        self.client = Self::client(&self.google_full_topic).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PublishError {
+    Auth,
+    Transient,
+    Permanent,
+}
+
+impl From<&Status> for PublishError {
+    fn from(status: &Status) -> Self {
+        match status.code() {
+            Code::Unauthenticated => PublishError::Auth,
+            Code::Unavailable
+            | Code::DeadlineExceeded
+            | Code::ResourceExhausted
+            | Code::Aborted
+            | Code::Internal => PublishError::Transient,
+            _ => PublishError::Permanent,
+        }
+    }
+}
+
+pub struct GooglePublisher<A: PublisherApi = GoogleApiPublisher> {
+    api: A,
+    google_full_topic: String,
+    max_retries: u32,
+    retry_backoff: Duration,
+    message_ordering: bool,
+    encryption_key: Option<PayloadKey>,
+}
+
+impl GooglePublisher {
+    pub async fn create(config: &Config) -> Result<Self> {
+        let google_full_topic = format!("projects/{}/topics/{}", config.project_id, config.topic);
+        let api = GoogleApiPublisher::create(google_full_topic.clone()).await?;
+
+        GooglePublisher::new(api, google_full_topic, config)
+    }
+}
 
+impl<A: PublisherApi> GooglePublisher<A> {
+    pub fn new(api: A, google_full_topic: String, config: &Config) -> Result<Self> {
         Ok(Self {
-            pubsub_client,
+            api,
             google_full_topic,
+            max_retries: config.max_retries,
+            retry_backoff: Duration::from_millis(config.retry_backoff_ms),
+            message_ordering: config.message_ordering,
+            encryption_key: config.encryption_key()?,
         })
     }
+
+    async fn publish_with_retries(&mut self, request: PublishRequest) -> Result<()> {
+        let mut delay = self.retry_backoff;
+        let mut retries = 0;
+
+        loop {
+            let Err(status) = self.api.publish(request.clone()).await else {
+                return Ok(());
+            };
+
+            let publish_error = PublishError::from(&status);
+            if publish_error == PublishError::Permanent {
+                counter!("google_publish_permanent_error").increment(1);
+                return Err(anyhow!(status)).context("Failed to publish event");
+            }
+
+            if retries >= self.max_retries {
+                counter!("google_publish_retries_exhausted").increment(1);
+                return Err(anyhow!(status))
+                    .context(format!("Failed to publish event after {} retries", retries));
+            }
+
+            if publish_error == PublishError::Auth {
+                counter!("google_publish_auth_retry").increment(1);
+                warn!("Auth error publishing event, refreshing client: {}", status);
+                if let Err(e) = self.api.refresh().await {
+                    error!("Failed to refresh Google client: {}", e);
+                }
+            } else {
+                counter!("google_publish_transient_retry").increment(1);
+                warn!("Transient error publishing event, retrying: {}", status);
+            }
+
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            retries += 1;
+        }
+    }
 }
 
 #[ractor::async_trait]
-impl PubsubPort for GooglePublisher {
+impl<A: PublisherApi> PubsubPort for GooglePublisher<A> {
     async fn publish_event(&mut self, report_request: &ReportRequest) -> Result<()> {
         if let ReportTarget::Pubkey(_) = report_request.target() {
             bail!("Cannot publish event with Pubkey target to Google Pubsub")
         }
+        let ordering_key = match self.message_ordering {
+            true => report_request.target().pubkey().to_hex(),
+            false => String::new(),
+        };
+        let max_bytes = self.encryption_key.as_ref().map(|_| MAX_PAYLOAD_BYTES);
+        let (payload, mut data) = fitted_payload(
+            report_request,
+            config::reportinator::max_reported_content_chars(),
+            max_bytes,
+        )?;
+        let mut attributes = HashMap::from([(
+            IDEMPOTENCY_KEY_ATTRIBUTE.to_string(),
+            idempotency_key(report_request),
+        )]);
+        if let Cow::Owned(_) = payload {
+            attributes.insert(CONTENT_TRUNCATED_ATTRIBUTE.to_string(), "true".to_string());
+        }
+        if let Some(encryption_key) = &self.encryption_key {
+            data = encryption_key.encrypt(&data)?;
+            attributes.insert(
+                ENCRYPTION_ATTRIBUTE.to_string(),
+                ENCRYPTION_SCHEME.to_string(),
+            );
+        }
+
         let pubsub_message = PubsubMessage {
-            data: serde_json::to_vec(report_request)
-                .context("Failed to serialize event to JSON")?,
+            data,
+            attributes,
+            ordering_key,
             ..Default::default()
         };
 
@@ -49,14 +265,462 @@ impl PubsubPort for GooglePublisher {
             messages: vec![pubsub_message],
         };
 
-        self.pubsub_client
-            .get()
-            .publish(request)
-            .await
-            .context("Failed to publish event")?;
+        self.publish_with_retries(request).await?;
 
         info!("Event published successfully");
 
         Ok(())
     }
+
+    async fn check_connectivity(&self) -> Result<String> {
+        self.api
+            .get_topic(self.google_full_topic.clone())
+            .await
+            .map_err(|status| anyhow!(status))
+            .with_context(|| format!("Failed to get topic {}", self.google_full_topic))?;
+
+        Ok(format!("Topic {} found", self.google_full_topic))
+    }
+}
+
+// The payload with the content cut to max_chars, and further when it's still
+// over max_bytes. Every character cut takes at least a byte off the JSON, so
+// one more cut is enough
+fn fitted_payload(
+    report_request: &ReportRequest,
+    max_chars: Option<usize>,
+    max_bytes: Option<usize>,
+) -> Result<(Cow<'_, ReportRequest>, Vec<u8>)> {
+    let payload = report_request.with_truncated_content(max_chars);
+    let data = serde_json::to_vec(payload.as_ref()).context("Failed to serialize event to JSON")?;
+    let Some(max_bytes) = max_bytes.filter(|max_bytes| data.len() > *max_bytes) else {
+        return Ok((payload, data));
+    };
+
+    let content_chars = report_request
+        .target()
+        .event()
+        .map_or(0, |event| event.content.chars().count());
+    let kept_chars = content_chars.min(max_chars.unwrap_or(usize::MAX));
+    let Some(fitting_chars) =
+        kept_chars.checked_sub(data.len() - max_bytes + TRUNCATED_MARKER.len())
+    else {
+        bail!(
+            "The report request is {} bytes, it can't be cut to the {} bytes that can be encrypted",
+            data.len(),
+            max_bytes
+        );
+    };
+
+    let payload = report_request.with_truncated_content(Some(fitting_chars));
+    let data = serde_json::to_vec(payload.as_ref()).context("Failed to serialize event to JSON")?;
+    if data.len() > max_bytes {
+        bail!(
+            "The report request is still {} bytes once cut, over the {} bytes that can be encrypted",
+            data.len(),
+            max_bytes
+        );
+    }
+
+    Ok((payload, data))
+}
+
+/// Publishes the moderator decisions taken in Slack to their own topic of the
+/// same project, so automated consumers learn what became of the report
+/// requests. Decisions carry no reported content and are never encrypted.
+/// They're published in order by a background task, Slack actions don't
+/// wait for the publish and its retries
+pub struct GoogleDecisionPublisher {
+    sender: mpsc::Sender<ModeratorDecision>,
+}
+
+// Decisions waiting for the background task, more are dropped
+const MAX_PENDING_DECISIONS: usize = 1000;
+
+impl GoogleDecisionPublisher {
+    pub async fn create(config: &Config, topic: &str) -> Result<Self> {
+        let config = Config {
+            topic: topic.to_string(),
+            message_ordering: false,
+            encryption_key: None,
+            ..config.clone()
+        };
+
+        Ok(Self::new(GooglePublisher::create(&config).await?))
+    }
+}
+
+impl GoogleDecisionPublisher {
+    pub fn new<A: PublisherApi>(publisher: GooglePublisher<A>) -> Self {
+        let (sender, receiver) = mpsc::channel(MAX_PENDING_DECISIONS);
+        tokio::spawn(publish_decisions(publisher, receiver));
+
+        Self { sender }
+    }
+}
+
+// Ends once the GoogleDecisionPublisher is dropped
+async fn publish_decisions<A: PublisherApi>(
+    mut publisher: GooglePublisher<A>,
+    mut receiver: mpsc::Receiver<ModeratorDecision>,
+) {
+    while let Some(decision) = receiver.recv().await {
+        if let Err(e) = publish_decision(&mut publisher, &decision).await {
+            counter!("decision_store_error").increment(1);
+            error!("Failed to publish moderator decision: {}", e);
+        }
+    }
+}
+
+async fn publish_decision<A: PublisherApi>(
+    publisher: &mut GooglePublisher<A>,
+    decision: &ModeratorDecision,
+) -> Result<()> {
+    let data = serde_json::to_vec(&DecisionMessage::from(decision))
+        .context("Failed to serialize decision to JSON")?;
+
+    let request = PublishRequest {
+        topic: publisher.google_full_topic.clone(),
+        messages: vec![PubsubMessage {
+            data,
+            ..Default::default()
+        }],
+    };
+    publisher.publish_with_retries(request).await?;
+
+    counter!("decision_published").increment(1);
+    info!("Decision of {} published", decision.moderator);
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DecisionMessage<'a> {
+    // processed or skipped
+    decision: &'static str,
+    // Only for processed decisions, the id of the published report
+    report_id: Option<String>,
+    category: Option<&'static str>,
+    moderator: &'a str,
+    target_type: &'static str,
+    target_id: String,
+    reporter_pubkey: String,
+    decided_at: u64,
+}
+
+impl<'a> From<&'a ModeratorDecision> for DecisionMessage<'a> {
+    fn from(decision: &'a ModeratorDecision) -> Self {
+        Self {
+            decision: match decision.report_id {
+                Some(_) => "processed",
+                None => "skipped",
+            },
+            report_id: decision.report_id.map(|report_id| report_id.to_hex()),
+            category: decision.category.map(|category| category.slug()),
+            moderator: &decision.moderator,
+            target_type: decision.target_type(),
+            target_id: decision.target_id(),
+            reporter_pubkey: decision.reporter_pubkey.to_hex(),
+            decided_at: decision.decided_at.as_u64(),
+        }
+    }
+}
+
+// Only queues the decision, failures to publish it are counted by the
+// background task
+#[ractor::async_trait]
+impl DecisionStore for GoogleDecisionPublisher {
+    async fn save(&self, decision: &ModeratorDecision) -> Result<()> {
+        self.sender.try_send(decision.clone()).map_err(|e| match e {
+            mpsc::error::TrySendError::Full(_) => anyhow!(
+                "{} decisions already waiting to be published, this one is dropped",
+                MAX_PENDING_DECISIONS
+            ),
+            mpsc::error::TrySendError::Closed(_) => anyhow!("The decision publisher stopped"),
+        })
+    }
+}
+
+// Hash of the reported content and the reporter, which don't change between
+// retries of the same report
+fn idempotency_key(report_request: &ReportRequest) -> String {
+    let target_id = report_request.target().id();
+
+    Sha256Hash::hash(format!("{}:{}", target_id, report_request.reporter_pubkey()).as_bytes())
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::payload_encryption::decode_report_request;
+    use crate::domain_objects::ModerationCategory;
+    use nostr_sdk::prelude::*;
+    use serde_json::{json, Value};
+    use std::collections::VecDeque;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct MockPublisherApi {
+        // Errors returned by the next publish calls, success once empty
+        failures: Arc<Mutex<VecDeque<Status>>>,
+        published: Arc<Mutex<Vec<PublishRequest>>>,
+        refreshes: Arc<Mutex<u32>>,
+    }
+
+    impl MockPublisherApi {
+        fn failing_with(failures: Vec<Status>) -> Self {
+            Self {
+                failures: Arc::new(Mutex::new(failures.into())),
+                ..Default::default()
+            }
+        }
+    }
+
+    #[ractor::async_trait]
+    impl PublisherApi for MockPublisherApi {
+        async fn publish(&self, request: PublishRequest) -> Result<(), Status> {
+            if let Some(status) = self.failures.lock().await.pop_front() {
+                return Err(status);
+            }
+
+            self.published.lock().await.push(request);
+            Ok(())
+        }
+
+        async fn get_topic(&self, _topic: String) -> Result<(), Status> {
+            Ok(())
+        }
+
+        async fn refresh(&mut self) -> Result<()> {
+            *self.refreshes.lock().await += 1;
+            Ok(())
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            project_id: "test".to_string(),
+            topic: "test".to_string(),
+            max_retries: 3,
+            retry_backoff_ms: 1,
+            message_ordering: false,
+            encryption_key: None,
+        }
+    }
+
+    fn report_request() -> ReportRequest {
+        let reported_event = EventBuilder::text_note("I hate you!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        ReportRequest::new(reported_event.into(), Keys::generate().public_key(), None)
+    }
+
+    #[tokio::test]
+    async fn test_refreshes_and_retries_on_auth_error() {
+        let api = MockPublisherApi::failing_with(vec![Status::unauthenticated("Token expired")]);
+        let mut publisher =
+            GooglePublisher::new(api.clone(), "topic".to_string(), &config()).unwrap();
+
+        publisher.publish_event(&report_request()).await.unwrap();
+
+        assert_eq!(*api.refreshes.lock().await, 1);
+        assert_eq!(api.published.lock().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_on_permanent_error() {
+        let api = MockPublisherApi::failing_with(vec![Status::not_found("No such topic")]);
+        let mut publisher =
+            GooglePublisher::new(api.clone(), "topic".to_string(), &config()).unwrap();
+
+        assert!(publisher.publish_event(&report_request()).await.is_err());
+
+        assert_eq!(*api.refreshes.lock().await, 0);
+        assert!(api.published.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let api = MockPublisherApi::failing_with(vec![
+            Status::unavailable("Down"),
+            Status::unavailable("Down"),
+            Status::unavailable("Down"),
+            Status::unavailable("Down"),
+        ]);
+        let mut publisher =
+            GooglePublisher::new(api.clone(), "topic".to_string(), &config()).unwrap();
+
+        assert!(publisher.publish_event(&report_request()).await.is_err());
+        assert!(api.published.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sets_the_idempotency_key_attribute() {
+        let api = MockPublisherApi::default();
+        let config = Config {
+            message_ordering: true,
+            ..config()
+        };
+        let mut publisher =
+            GooglePublisher::new(api.clone(), "topic".to_string(), &config).unwrap();
+        let report_request = report_request();
+
+        publisher.publish_event(&report_request).await.unwrap();
+        publisher.publish_event(&report_request).await.unwrap();
+
+        let published = api.published.lock().await;
+        let messages: Vec<&PubsubMessage> = published
+            .iter()
+            .flat_map(|request| &request.messages)
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].attributes.get(IDEMPOTENCY_KEY_ATTRIBUTE),
+            Some(&idempotency_key(&report_request))
+        );
+        assert_eq!(messages[0].attributes, messages[1].attributes);
+        assert_eq!(
+            messages[0].ordering_key,
+            report_request.target().pubkey().to_hex()
+        );
+
+        let other_report_request = ReportRequest::new(
+            report_request.target().clone(),
+            Keys::generate().public_key(),
+            None,
+        );
+        assert_ne!(
+            idempotency_key(&other_report_request),
+            idempotency_key(&report_request)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_encrypts_the_payload_when_configured() {
+        let api = MockPublisherApi::default();
+        let encryption_key =
+            "4f3c2b1a0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b".to_string();
+        let config = Config {
+            encryption_key: Some(encryption_key.clone()),
+            ..config()
+        };
+        let mut publisher =
+            GooglePublisher::new(api.clone(), "topic".to_string(), &config).unwrap();
+        let report_request = report_request();
+
+        publisher.publish_event(&report_request).await.unwrap();
+
+        let published = api.published.lock().await;
+        let message = &published[0].messages[0];
+        assert_eq!(
+            message.attributes.get(ENCRYPTION_ATTRIBUTE).unwrap(),
+            ENCRYPTION_SCHEME
+        );
+        assert!(serde_json::from_slice::<ReportRequest>(&message.data).is_err());
+
+        let key = PayloadKey::from_hex(&encryption_key).unwrap();
+        assert_eq!(
+            decode_report_request(&message.data, &message.attributes, Some(&key)).unwrap(),
+            report_request
+        );
+        assert!(decode_report_request(&message.data, &message.attributes, None).is_err());
+    }
+
+    #[test]
+    fn test_cuts_the_content_to_the_encryptable_size() {
+        let reported_event = EventBuilder::text_note("é".repeat(40000), [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let report_request =
+            ReportRequest::new(reported_event.into(), Keys::generate().public_key(), None);
+
+        let (payload, data) = fitted_payload(&report_request, None, None).unwrap();
+        assert!(matches!(payload, Cow::Borrowed(_)));
+        assert!(data.len() > MAX_PAYLOAD_BYTES);
+
+        let (payload, data) =
+            fitted_payload(&report_request, Some(35000), Some(MAX_PAYLOAD_BYTES)).unwrap();
+        assert!(matches!(payload, Cow::Owned(_)));
+        assert!(data.len() <= MAX_PAYLOAD_BYTES);
+        assert_eq!(
+            payload.target().event().unwrap().id,
+            report_request.target().event().unwrap().id
+        );
+
+        // Small enough already
+        let (payload, _) =
+            fitted_payload(&report_request, Some(100), Some(MAX_PAYLOAD_BYTES)).unwrap();
+        let content = &payload.target().event().unwrap().content;
+        assert_eq!(
+            content.chars().count(),
+            100 + TRUNCATED_MARKER.chars().count()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_publishes_moderator_decisions() {
+        let api = MockPublisherApi::default();
+        let decision_publisher = GoogleDecisionPublisher::new(
+            GooglePublisher::new(api.clone(), "decisions".to_string(), &config()).unwrap(),
+        );
+        let report_request = report_request();
+        let report_id = EventBuilder::text_note("Report", [])
+            .to_event(&Keys::generate())
+            .unwrap()
+            .id;
+
+        let processed = ModeratorDecision::new(
+            &report_request,
+            Some(report_id),
+            Some(ModerationCategory::Spam),
+            "daniel".to_string(),
+        );
+        let skipped = ModeratorDecision::new(&report_request, None, None, "martin".to_string());
+        decision_publisher.save(&processed).await.unwrap();
+        decision_publisher.save(&skipped).await.unwrap();
+
+        // Published in the background
+        for _ in 0..100 {
+            if api.published.lock().await.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let published = api.published.lock().await;
+        assert!(published.iter().all(|request| request.topic == "decisions"));
+        let messages: Vec<Value> = published
+            .iter()
+            .flat_map(|request| &request.messages)
+            .map(|message| serde_json::from_slice(&message.data).unwrap())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                json!({
+                    "decision": "processed",
+                    "reportId": report_id.to_hex(),
+                    "category": "spam",
+                    "moderator": "daniel",
+                    "targetType": report_request.target().label(),
+                    "targetId": report_request.target().id(),
+                    "reporterPubkey": report_request.reporter_pubkey().to_hex(),
+                    "decidedAt": processed.decided_at.as_u64(),
+                }),
+                json!({
+                    "decision": "skipped",
+                    "reportId": null,
+                    "category": null,
+                    "moderator": "martin",
+                    "targetType": report_request.target().label(),
+                    "targetId": report_request.target().id(),
+                    "reporterPubkey": report_request.reporter_pubkey().to_hex(),
+                    "decidedAt": skipped.decided_at.as_u64(),
+                }),
+            ]
+        );
+    }
 }
@@ -1,20 +1,31 @@
+mod admin_route;
 mod app_errors;
+mod metrics_route;
+mod root_route;
 mod router;
+mod scale_route;
 mod slack_interactions_route;
+mod slack_report_message;
+mod stats_route;
 use crate::actors::messages::SupervisorMessage;
-use crate::config::Config as ConfigTree;
-use anyhow::{Context, Result};
+use crate::adapters::decision_store::{self, DecisionStore};
+use crate::adapters::google_publisher::{self, GoogleDecisionPublisher};
+use crate::adapters::slack_templates::SlackTemplates;
+use crate::config::{Config as ConfigTree, NotificationBackend, NotificationsConfig};
+use anyhow::{bail, Context, Result};
 use axum::Router;
 use handlebars::Handlebars;
+use metrics_exporter_prometheus::PrometheusHandle;
 use ractor::ActorRef;
 use reportinator_server::config::Configurable;
+use reqwest::Client as ReqwestClient;
 use router::create_router;
+pub use router::install_metrics_recorder;
 use serde::Deserialize;
 use std::net::SocketAddr;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
 use tracing::info;
 
@@ -22,6 +33,25 @@ use tracing::info;
 pub struct Config {
     bind_addr: String,
     bind_port: u16,
+    // Outbound requests, like Slack responses, share one pooled client
+    #[serde(default = "default_outbound_timeout_ms")]
+    outbound_timeout_ms: u64,
+    #[serde(default = "default_outbound_pool_idle_secs")]
+    outbound_pool_idle_secs: u64,
+    #[serde(default = "default_outbound_pool_max_idle_per_host")]
+    outbound_pool_max_idle_per_host: usize,
+}
+
+fn default_outbound_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_outbound_pool_idle_secs() -> u64 {
+    90
+}
+
+fn default_outbound_pool_max_idle_per_host() -> usize {
+    8
 }
 
 impl Configurable for Config {
@@ -34,6 +64,10 @@ impl Configurable for Config {
 pub struct WebAppState {
     hb: Arc<Handlebars<'static>>,
     event_dispatcher: ActorRef<SupervisorMessage>,
+    decision_store: Option<Arc<dyn DecisionStore>>,
+    slack_templates: Arc<SlackTemplates>,
+    // Cheap to clone, all clones share the connection pool
+    http_client: ReqwestClient,
 }
 
 pub struct HttpServer;
@@ -41,12 +75,97 @@ impl HttpServer {
     pub async fn run(
         config: ConfigTree,
         event_dispatcher: ActorRef<SupervisorMessage>,
+        metrics_handle: PrometheusHandle,
         cancellation_token: CancellationToken,
     ) -> Result<()> {
-        let router = create_router(&config, event_dispatcher)?;
+        let decision_store = create_decision_store(&config).await?;
+        let router = create_router(&config, event_dispatcher, decision_store, metrics_handle)?;
 
         start_http_server(&config.get()?, router, cancellation_token).await
     }
+
+    // Loads every config section used by the HTTP server so errors surface
+    // before anything is started
+    pub fn check_config(config: &ConfigTree) -> Result<()> {
+        create_http_client(&config.get::<Config>()?)?;
+        if config.get::<NotificationsConfig>()?.backend == NotificationBackend::Slack {
+            config.get::<slack_interactions_route::Config>()?;
+        }
+        config.get::<admin_route::Config>()?;
+        check_decision_store_config(&config.get()?)?;
+        if config
+            .get::<decision_store::Config>()?
+            .pubsub_topic
+            .is_some()
+        {
+            config.get::<google_publisher::Config>()?;
+        }
+        SlackTemplates::load(&config.get()?)?;
+        router::check_config(&config.get()?)
+    }
+}
+
+fn create_http_client(config: &Config) -> Result<ReqwestClient> {
+    ReqwestClient::builder()
+        .timeout(Duration::from_millis(config.outbound_timeout_ms))
+        .pool_idle_timeout(Duration::from_secs(config.outbound_pool_idle_secs))
+        .pool_max_idle_per_host(config.outbound_pool_max_idle_per_host)
+        .build()
+        .context("Failed to build the outbound HTTP client")
+}
+
+fn check_decision_store_config(config: &decision_store::Config) -> Result<()> {
+    if config
+        .pubsub_topic
+        .as_deref()
+        .is_some_and(|topic| topic.trim().is_empty())
+    {
+        bail!("decisions.pubsub_topic is empty, leave it unset to not publish decisions");
+    }
+
+    match config.store.as_str() {
+        "none" => Ok(()),
+        "postgres" if !cfg!(feature = "postgres") => {
+            bail!("Decision store 'postgres' requires building with the postgres feature")
+        }
+        "postgres" if config.database_url.is_none() => {
+            bail!("Decision store 'postgres' requires decisions.database_url")
+        }
+        "postgres" => Ok(()),
+        store => bail!("Unknown decision store '{}'", store),
+    }
+}
+
+async fn create_decision_store(config: &ConfigTree) -> Result<Option<Arc<dyn DecisionStore>>> {
+    let decisions_config = config.get::<decision_store::Config>()?;
+    check_decision_store_config(&decisions_config)?;
+    let mut stores: Vec<Arc<dyn DecisionStore>> = Vec::new();
+
+    #[cfg(feature = "postgres")]
+    if decisions_config.store == "postgres" {
+        use crate::adapters::pg_decision_store::PgDecisionStore;
+
+        // Presence was already checked above
+        let database_url = decisions_config.database_url.as_deref().unwrap_or_default();
+        let store = PgDecisionStore::create(database_url).await?;
+        info!("Persisting moderator decisions to Postgres");
+        stores.push(Arc::new(store));
+    }
+
+    if let Some(topic) = &decisions_config.pubsub_topic {
+        let publisher = GoogleDecisionPublisher::create(&config.get()?, topic).await?;
+        info!(
+            "Publishing moderator decisions to the Pub/Sub topic {}",
+            topic
+        );
+        stores.push(Arc::new(publisher));
+    }
+
+    Ok(match stores.len() {
+        0 => None,
+        1 => stores.pop(),
+        _ => Some(Arc::new(stores)),
+    })
 }
 
 async fn start_http_server(
@@ -75,9 +194,11 @@ async fn await_shutdown(
 ) {
     cancellation_token.cancelled().await;
     info!("Shutdown signal received.");
-    match timeout(Duration::from_secs(5), server_future).await {
-        Ok(_) => info!("HTTP service exited successfully."),
-        Err(e) => info!("HTTP service exited after timeout: {}", e),
+    // The drain deadline is enforced by the service manager
+    match server_future.await {
+        Ok(Ok(())) => info!("HTTP service exited successfully."),
+        Ok(Err(e)) => info!("HTTP service exited with error: {}", e),
+        Err(e) => info!("HTTP service task failed: {}", e),
     }
 }
 
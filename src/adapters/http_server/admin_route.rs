@@ -0,0 +1,379 @@
+use super::app_errors::AppError;
+use super::WebAppState;
+use crate::actors::messages::SupervisorMessage;
+use crate::actors::utilities::self_test::CHECK_TIMEOUT;
+use crate::config::{self, Configurable};
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use nostr_sdk::prelude::*;
+use ractor::{call_t, cast};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Bearer token required by the admin routes. They are disabled when it's
+    // unset, blank or left as the <NOT_SET> placeholder
+    #[serde(default)]
+    admin_token: Option<String>,
+    // Upper bound of the events fetched by a single backfill
+    #[serde(default = "default_max_backfill_events")]
+    max_backfill_events: usize,
+}
+
+fn default_max_backfill_events() -> usize {
+    1000
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "http"
+    }
+}
+
+impl Config {
+    // A blank token would let anyone in
+    fn admin_token(&self) -> Option<String> {
+        let admin_token = self.admin_token.as_ref()?;
+        if admin_token.trim().is_empty() || admin_token == config::NOT_SET {
+            warn!("http.admin_token is not set, the admin routes are disabled");
+            return None;
+        }
+
+        Some(admin_token.clone())
+    }
+}
+
+#[derive(Clone)]
+struct AdminToken(String);
+
+#[derive(Clone, Copy)]
+struct MaxBackfillEvents(usize);
+
+#[derive(Deserialize)]
+struct RotateKeyRequest {
+    // nsec or hex secret key
+    secret_key: String,
+}
+
+#[derive(Deserialize)]
+struct RelaysRequest {
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct BackfillRequest {
+    // Unix timestamps, both included
+    since: u64,
+    until: u64,
+    // Capped at max_backfill_events, which is also the default
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+impl BackfillRequest {
+    fn limit(&self, max_backfill_events: usize) -> usize {
+        self.limit
+            .unwrap_or(max_backfill_events)
+            .min(max_backfill_events)
+    }
+}
+
+pub fn admin_route(config: &Config) -> Router<WebAppState> {
+    let Some(admin_token) = config.admin_token() else {
+        return Router::new();
+    };
+
+    Router::new()
+        .route(
+            "/admin/rotate-key",
+            post(rotate_key_handler).layer(Extension(AdminToken(admin_token.clone()))),
+        )
+        .route(
+            "/admin/relays",
+            post(relays_handler).layer(Extension(AdminToken(admin_token.clone()))),
+        )
+        .route(
+            "/admin/selftest",
+            get(self_test_handler).layer(Extension(AdminToken(admin_token.clone()))),
+        )
+        .route(
+            "/admin/backfill",
+            post(backfill_handler)
+                .layer(Extension(AdminToken(admin_token)))
+                .layer(Extension(MaxBackfillEvents(config.max_backfill_events))),
+        )
+}
+
+async fn rotate_key_handler(
+    Extension(AdminToken(admin_token)): Extension<AdminToken>,
+    State(WebAppState {
+        event_dispatcher: message_dispatcher,
+        ..
+    }): State<WebAppState>,
+    headers: HeaderMap,
+    Json(request): Json<RotateKeyRequest>,
+) -> Result<Json<Value>, AppError> {
+    if !authorized(&headers, &admin_token) {
+        return Err(AppError::unauthorized());
+    }
+
+    let keys = Keys::parse(request.secret_key.trim())
+        .map_err(|_| AppError::invalid_request("Invalid secret key"))?;
+    let public_key = keys.public_key();
+
+    call_t!(message_dispatcher, SupervisorMessage::RotateKeys, 500, keys)?
+        .map_err(|e| anyhow!(e))?;
+
+    info!(
+        "Signing key rotated through the admin route to {}",
+        public_key
+    );
+    Ok(Json(json!({ "publicKey": public_key.to_bech32()? })))
+}
+
+// Relays already there, or already gone, are reported as unchanged. Changes
+// last until the next restart, the configured relays are used again then
+async fn relays_handler(
+    Extension(AdminToken(admin_token)): Extension<AdminToken>,
+    State(WebAppState {
+        event_dispatcher: message_dispatcher,
+        ..
+    }): State<WebAppState>,
+    headers: HeaderMap,
+    Json(request): Json<RelaysRequest>,
+) -> Result<Json<Value>, AppError> {
+    if !authorized(&headers, &admin_token) {
+        return Err(AppError::unauthorized());
+    }
+
+    // Everything is validated before the relay set is touched
+    let to_add = parse_relay_urls(&request.add)?;
+    let to_remove = parse_relay_urls(&request.remove)?;
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut unchanged = Vec::new();
+    for url in to_add {
+        let changed = call_t!(
+            message_dispatcher,
+            SupervisorMessage::AddRelay,
+            500,
+            url.clone()
+        )?
+        .map_err(|e| anyhow!(e))?;
+        match changed {
+            true => added.push(url.to_string()),
+            false => unchanged.push(url.to_string()),
+        }
+    }
+    for url in to_remove {
+        let changed = call_t!(
+            message_dispatcher,
+            SupervisorMessage::RemoveRelay,
+            500,
+            url.clone()
+        )?
+        .map_err(|e| anyhow!(e))?;
+        match changed {
+            true => removed.push(url.to_string()),
+            false => unchanged.push(url.to_string()),
+        }
+    }
+
+    info!(
+        "Relays changed through the admin route, added: {:?}, removed: {:?}",
+        added, removed
+    );
+    Ok(Json(json!({
+        "added": added,
+        "removed": removed,
+        "unchanged": unchanged,
+    })))
+}
+
+fn parse_relay_urls(relays: &[String]) -> Result<Vec<Url>, AppError> {
+    let mut urls: Vec<Url> = Vec::new();
+    for relay in relays {
+        let url = Url::parse(relay.trim())
+            .ok()
+            .filter(|url| matches!(url.scheme(), "ws" | "wss") && url.host_str().is_some())
+            .ok_or_else(|| {
+                AppError::invalid_request(&format!("{} is not a ws or wss relay URL", relay))
+            })?;
+
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+
+    Ok(urls)
+}
+
+// The backfill runs in the background, progress is in the logs and the
+// backfill_fetched counter
+async fn backfill_handler(
+    Extension(AdminToken(admin_token)): Extension<AdminToken>,
+    Extension(MaxBackfillEvents(max_backfill_events)): Extension<MaxBackfillEvents>,
+    State(WebAppState {
+        event_dispatcher: message_dispatcher,
+        ..
+    }): State<WebAppState>,
+    headers: HeaderMap,
+    Json(request): Json<BackfillRequest>,
+) -> Result<Json<Value>, AppError> {
+    if !authorized(&headers, &admin_token) {
+        return Err(AppError::unauthorized());
+    }
+
+    if request.since > request.until {
+        return Err(AppError::invalid_request("since must not be after until"));
+    }
+
+    let limit = request.limit(max_backfill_events);
+    cast!(
+        message_dispatcher,
+        SupervisorMessage::Backfill(
+            Timestamp::from(request.since),
+            Timestamp::from(request.until),
+            limit
+        )
+    )?;
+
+    info!(
+        "Backfill from {} to {} of up to {} events requested through the admin route",
+        request.since, request.until, limit
+    );
+    Ok(Json(json!({
+        "since": request.since,
+        "until": request.until,
+        "limit": limit,
+    })))
+}
+
+// Checks the relays, Pub/Sub and each Slack workspace right now through the
+// clients already in use. Failed checks are in the report, not the status
+async fn self_test_handler(
+    Extension(AdminToken(admin_token)): Extension<AdminToken>,
+    State(WebAppState {
+        event_dispatcher: message_dispatcher,
+        ..
+    }): State<WebAppState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    if !authorized(&headers, &admin_token) {
+        return Err(AppError::unauthorized());
+    }
+
+    // The supervisor answers once every check is done or timed out
+    let timeout_ms = CHECK_TIMEOUT.as_millis() as u64 + 100;
+    let checks = call_t!(message_dispatcher, SupervisorMessage::SelfTest, timeout_ms)?;
+
+    let ok = checks.iter().all(|check| check.ok);
+    info!("Self test run through the admin route, all ok: {}", ok);
+    Ok(Json(json!({
+        "ok": ok,
+        "checks": checks,
+    })))
+}
+
+fn authorized(headers: &HeaderMap, admin_token: &str) -> bool {
+    let Some(token) = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+
+    !admin_token.trim().is_empty() && constant_time_eq(token.as_bytes(), admin_token.as_bytes())
+}
+
+// Doesn't leak how much of the token matched through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_requires_the_bearer_token() {
+        assert!(authorized(&headers("Bearer secret"), "secret"));
+        assert!(!authorized(&headers("Bearer wrong!"), "secret"));
+        assert!(!authorized(&headers("Bearer secre"), "secret"));
+        assert!(!authorized(&headers("secret"), "secret"));
+        assert!(!authorized(&HeaderMap::new(), "secret"));
+    }
+
+    #[test]
+    fn test_blank_tokens_disable_the_admin_routes() {
+        let config = |admin_token: Option<&str>| Config {
+            admin_token: admin_token.map(str::to_string),
+            max_backfill_events: default_max_backfill_events(),
+        };
+
+        assert_eq!(
+            config(Some("secret")).admin_token(),
+            Some("secret".to_string())
+        );
+        assert_eq!(config(None).admin_token(), None);
+        assert_eq!(config(Some("")).admin_token(), None);
+        assert_eq!(config(Some("  ")).admin_token(), None);
+        assert_eq!(config(Some(config::NOT_SET)).admin_token(), None);
+        assert!(!authorized(&headers("Bearer "), ""));
+    }
+
+    #[test]
+    fn test_parses_relay_urls() {
+        let relays = vec![
+            "wss://relay.example.com".to_string(),
+            " wss://relay.example.com ".to_string(),
+            "ws://localhost:7777".to_string(),
+        ];
+
+        assert_eq!(
+            parse_relay_urls(&relays).unwrap(),
+            vec![
+                Url::parse("wss://relay.example.com").unwrap(),
+                Url::parse("ws://localhost:7777").unwrap(),
+            ]
+        );
+        assert!(parse_relay_urls(&["https://relay.example.com".to_string()]).is_err());
+        assert!(parse_relay_urls(&["not a url".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_backfill_limit_is_capped() {
+        let request = |limit| BackfillRequest {
+            since: 0,
+            until: 1,
+            limit,
+        };
+
+        assert_eq!(request(None).limit(1000), 1000);
+        assert_eq!(request(Some(10)).limit(1000), 10);
+        assert_eq!(request(Some(5000)).limit(1000), 1000);
+    }
+}
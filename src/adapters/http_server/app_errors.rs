@@ -1,6 +1,6 @@
 use anyhow::Error;
 use axum::{
-    http::StatusCode,
+    http::{header::RETRY_AFTER, StatusCode},
     response::{IntoResponse, Response},
 };
 use tracing::error;
@@ -10,6 +10,10 @@ enum AppErrorKind {
     General(Error),
     // TODO: Let's be more specific later
     SlackParsingError(String),
+    Unauthorized,
+    InvalidRequest(String),
+    // Seconds after which the client can retry
+    ServiceUnavailable(String, u64),
 }
 
 #[derive(Debug)]
@@ -25,6 +29,21 @@ impl AppError {
     pub fn slack_parsing_error(context: &str) -> Self {
         Self::new(AppErrorKind::SlackParsingError(context.to_string()))
     }
+
+    pub fn unauthorized() -> Self {
+        Self::new(AppErrorKind::Unauthorized)
+    }
+
+    pub fn invalid_request(context: &str) -> Self {
+        Self::new(AppErrorKind::InvalidRequest(context.to_string()))
+    }
+
+    pub fn service_unavailable(context: &str, retry_after_secs: u64) -> Self {
+        Self::new(AppErrorKind::ServiceUnavailable(
+            context.to_string(),
+            retry_after_secs,
+        ))
+    }
 }
 
 impl IntoResponse for AppError {
@@ -41,6 +60,23 @@ impl IntoResponse for AppError {
                 format!("Slack parsing error: {}.", context),
             )
                 .into_response(),
+            AppErrorKind::Unauthorized => {
+                (StatusCode::UNAUTHORIZED, "Unauthorized.").into_response()
+            }
+            AppErrorKind::InvalidRequest(context) => (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid request: {}.", context),
+            )
+                .into_response(),
+            AppErrorKind::ServiceUnavailable(context, retry_after_secs) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [(RETRY_AFTER, retry_after_secs.to_string())],
+                format!(
+                    "Service unavailable: {}. Retry in {} seconds.",
+                    context, retry_after_secs
+                ),
+            )
+                .into_response(),
         }
     }
 }
@@ -0,0 +1,185 @@
+use crate::actors::utilities::exemplars::{self, Exemplar, Series};
+use axum::{
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use metrics_exporter_prometheus::PrometheusHandle;
+use std::collections::{BTreeMap, HashSet};
+
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Scrapers asking for OpenMetrics get the exemplars when they're enabled,
+/// the rest keep getting the Prometheus text
+pub fn metrics_route(metrics_handle: PrometheusHandle, exemplars_enabled: bool) -> Router {
+    Router::new().route(
+        "/metrics",
+        get(move |headers: HeaderMap| async move {
+            if !exemplars_enabled || !accepts_openmetrics(&headers) {
+                return metrics_handle.render().into_response();
+            }
+
+            let body = openmetrics(&metrics_handle.render(), &exemplars::snapshot());
+            ([(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)], body).into_response()
+        }),
+    )
+}
+
+fn accepts_openmetrics(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/openmetrics-text"))
+}
+
+// The Prometheus handle only renders text, so it's rewritten. Counter
+// samples get the `_total` suffix OpenMetrics requires and the exemplar of
+// their series, if any. Blank lines aren't allowed and the end is marked
+fn openmetrics(rendered_metrics: &str, exemplars: &BTreeMap<Series, Exemplar>) -> String {
+    let mut counters = HashSet::new();
+    let mut lines = Vec::new();
+
+    for line in rendered_metrics.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if line.starts_with('#') {
+            if let Some((name, "counter")) = line
+                .strip_prefix("# TYPE ")
+                .and_then(|metric_type| metric_type.split_once(' '))
+            {
+                counters.insert(name);
+            }
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let Some((series, value)) = line.rsplit_once(' ') else {
+            lines.push(line.to_string());
+            continue;
+        };
+        let (name, labels) = match series.split_once('{') {
+            Some((name, labels)) => (name, Some(labels)),
+            None => (series, None),
+        };
+        if !counters.contains(name) {
+            lines.push(line.to_string());
+            continue;
+        }
+
+        let mut sample = match labels {
+            Some(labels) => format!("{}_total{{{} {}", name, labels, value),
+            None => format!("{}_total {}", name, value),
+        };
+        let key = (name.to_string(), parse_labels(labels.unwrap_or_default()));
+        if let Some(exemplar) = exemplars.get(&key) {
+            sample.push_str(&format!(
+                " # {{trace_id=\"{}\"}} 1 {:.3}",
+                exemplar.trace_id, exemplar.timestamp
+            ));
+        }
+        lines.push(sample);
+    }
+
+    lines.push("# EOF\n".to_string());
+    lines.join("\n")
+}
+
+// `category="spam",target="event"}` into its labels sorted by name, like
+// `exemplars::series`. Escaped quotes and backslashes are unescaped
+fn parse_labels(labels: &str) -> Vec<(String, String)> {
+    let mut parsed = Vec::new();
+    let mut rest = labels.trim_end_matches('}');
+
+    while let Some((label, after_label)) = rest.split_once("=\"") {
+        let mut value = String::new();
+        let mut chars = after_label.char_indices();
+        let mut end = after_label.len();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, escaped)) => value.push(escaped),
+                    None => {}
+                },
+                '"' => {
+                    end = i + 1;
+                    break;
+                }
+                c => value.push(c),
+            }
+        }
+
+        parsed.push((label.trim_start_matches(',').to_string(), value));
+        rest = &after_label[end..];
+    }
+
+    parsed.sort();
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters_get_their_exemplars() {
+        let rendered_metrics = r#"# HELP report_published Number of reports published
+# TYPE report_published counter
+report_published{category="spam"} 3
+report_published{category="hate"} 1
+
+# TYPE publish counter
+publish 4
+
+# TYPE content_report_count summary
+content_report_count{quantile="0.5"} 2
+content_report_count_sum 6
+content_report_count_count 3
+"#;
+        let exemplar = |trace_id: &str| Exemplar {
+            trace_id: trace_id.to_string(),
+            timestamp: 1700000000.5,
+        };
+        let exemplars = BTreeMap::from([
+            (
+                exemplars::series("report_published", &[("category", "spam")]),
+                exemplar("4bf92f3577b34da6a3ce929d0e0e4736"),
+            ),
+            (
+                exemplars::series("publish", &[]),
+                exemplar("00f067aa0ba902b7a3ce929d0e0e4736"),
+            ),
+        ]);
+
+        assert_eq!(
+            openmetrics(rendered_metrics, &exemplars),
+            r#"# HELP report_published Number of reports published
+# TYPE report_published counter
+report_published_total{category="spam"} 3 # {trace_id="4bf92f3577b34da6a3ce929d0e0e4736"} 1 1700000000.500
+report_published_total{category="hate"} 1
+# TYPE publish counter
+publish_total 4 # {trace_id="00f067aa0ba902b7a3ce929d0e0e4736"} 1 1700000000.500
+# TYPE content_report_count summary
+content_report_count{quantile="0.5"} 2
+content_report_count_sum 6
+content_report_count_count 3
+# EOF
+"#
+        );
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        assert_eq!(
+            parse_labels(r#"target="event",category="spam \"quoted\""}"#),
+            vec![
+                ("category".to_string(), "spam \"quoted\"".to_string()),
+                ("target".to_string(), "event".to_string()),
+            ]
+        );
+        assert!(parse_labels("").is_empty());
+    }
+}
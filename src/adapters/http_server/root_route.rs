@@ -0,0 +1,73 @@
+use super::app_errors::AppError;
+use super::WebAppState;
+use crate::actors::utilities::relay_status;
+use axum::{extract::State, response::Html, routing::get, Extension, Router};
+use serde_json::{json, Value};
+use std::time::Instant;
+
+// Routes are created at startup, the uptime is counted from then
+#[derive(Clone, Copy)]
+struct StartedAt(Instant);
+
+/// The landing page, `root.hbs` rendered with the status of the server. A
+/// template that ignores some or all of the fields still renders
+pub fn root_route() -> Router<WebAppState> {
+    Router::new().route(
+        "/",
+        get(serve_root_page).layer(Extension(StartedAt(Instant::now()))),
+    )
+}
+
+async fn serve_root_page(
+    State(web_app_state): State<WebAppState>,
+    Extension(StartedAt(started_at)): Extension<StartedAt>,
+) -> Result<Html<String>, AppError> {
+    let context = root_context(relay_status::last_check(), started_at);
+    let body = web_app_state.hb.render("root", &context)?;
+
+    Ok(Html(body))
+}
+
+// The page is public, so only the uptime and the relays seen by the last
+// health check. Version and counters stay behind /stats and /metrics
+fn root_context(relays: Option<(usize, usize)>, started_at: Instant) -> Value {
+    let relays = match relays {
+        Some((connected, total)) => json!({
+            "ok": connected > 0,
+            "detail": format!("{} of {} relays connected", connected, total),
+        }),
+        None => json!({
+            "ok": false,
+            "detail": "Not checked yet",
+        }),
+    };
+
+    json!({
+        "uptimeSecs": started_at.elapsed().as_secs(),
+        "relays": relays,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_context() {
+        let context = root_context(Some((2, 3)), Instant::now());
+
+        assert_eq!(context["uptimeSecs"], json!(0));
+        assert_eq!(context["relays"]["ok"], json!(true));
+        assert_eq!(
+            context["relays"]["detail"],
+            json!("2 of 3 relays connected")
+        );
+        assert_eq!(context.get("version"), None);
+        assert_eq!(context.get("stats"), None);
+
+        let context = root_context(None, Instant::now());
+
+        assert_eq!(context["relays"]["ok"], json!(false));
+        assert_eq!(context["relays"]["detail"], json!("Not checked yet"));
+    }
+}
@@ -1,27 +1,49 @@
+use super::admin_route::admin_route;
+use super::metrics_route::metrics_route;
+use super::root_route::root_route;
+use super::scale_route::scale_route;
 use super::slack_interactions_route::slack_interactions_route;
-use super::WebAppState;
+use super::stats_route::stats_route;
+use super::{create_http_client, WebAppState};
 use crate::actors::messages::SupervisorMessage;
-use crate::config::Config as ConfigTree;
+use crate::adapters::decision_store::DecisionStore;
+use crate::adapters::slack_templates::SlackTemplates;
+use crate::config::{Config as ConfigTree, NotificationBackend, NotificationsConfig};
 use anyhow::Result;
-use axum::{extract::State, http::HeaderMap, response::Html};
-use axum::{response::IntoResponse, routing::get, Router};
+use axum::Router;
 use handlebars::Handlebars;
-use metrics::describe_counter;
-use metrics_exporter_prometheus::PrometheusBuilder;
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
 use ractor::ActorRef;
 use reportinator_server::config::Configurable;
+use reqwest::Client as ReqwestClient;
 use serde::Deserialize;
-use serde_json::json;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
 use tower_http::LatencyUnit;
 use tower_http::{timeout::TimeoutLayer, trace::DefaultOnFailure};
-use tracing::Level;
+use tracing::{warn, Level};
+
+// From live traffic to gift wraps backfilled days later
+const DELAY_BUCKETS_SECS: [f64; 12] = [
+    0.1, 0.5, 1.0, 5.0, 15.0, 60.0, 300.0, 900.0, 3600.0, 21600.0, 86400.0, 172800.0,
+];
+
+// Used when templates_dir has no root.hbs, so / still renders
+const FALLBACK_ROOT_TEMPLATE: &str = include_str!("../../../templates/root.hbs");
+
+// The global recorder, or why it couldn't be installed
+static METRICS_HANDLE: OnceLock<Result<PrometheusHandle, String>> = OnceLock::new();
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub templates_dir: String,
+    // Renders /metrics as OpenMetrics, with the trace ids of the publish and
+    // error counters as exemplars, for scrapers asking for it
+    #[serde(default)]
+    pub exemplars: bool,
 }
 
 impl Configurable for Config {
@@ -30,13 +52,22 @@ impl Configurable for Config {
     }
 }
 
+/// The metrics routes render the given handle, see `install_metrics_recorder`
+/// and `detached_metrics_handle`
 pub fn create_router(
     config: &ConfigTree,
     message_dispatcher: ActorRef<SupervisorMessage>,
+    decision_store: Option<Arc<dyn DecisionStore>>,
+    metrics_handle: PrometheusHandle,
 ) -> Result<Router> {
-    let web_app_state = create_web_app_state(&config.get()?, message_dispatcher)?;
-
-    let metrics_handle = setup_metrics()?;
+    let router_config: Config = config.get()?;
+    let web_app_state = create_web_app_state(
+        &router_config,
+        message_dispatcher,
+        decision_store,
+        SlackTemplates::load(&config.get()?)?,
+        create_http_client(&config.get()?)?,
+    )?;
 
     let tracing_layer = TraceLayer::new_for_http()
         .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
@@ -47,62 +78,635 @@ pub fn create_router(
         )
         .on_failure(DefaultOnFailure::new().level(Level::ERROR));
 
-    Ok(Router::new()
-        // TODO: Move this one away to its own file too
-        .route("/", get(serve_root_page))
-        .merge(slack_interactions_route(&config.get()?)?)
+    // Only Slack messages have buttons to click
+    let mut router = Router::new().merge(root_route());
+    if config.get::<NotificationsConfig>()?.backend == NotificationBackend::Slack {
+        router = router.merge(slack_interactions_route(&config.get()?)?);
+    }
+
+    Ok(router
+        .merge(admin_route(&config.get()?))
         .layer(tracing_layer)
         .layer(TimeoutLayer::new(Duration::from_secs(1)))
         .with_state(web_app_state)
-        .route("/metrics", get(|| async move { metrics_handle.render() })))
+        .merge(stats_route(metrics_handle.clone()))
+        .merge(scale_route())
+        .merge(metrics_route(metrics_handle, router_config.exemplars)))
+}
+
+// A missing template is not an error, the embedded one is used instead
+pub fn check_config(config: &Config) -> Result<()> {
+    register_root_template(&mut Handlebars::new(), config)
+}
+
+fn root_template_path(config: &Config) -> PathBuf {
+    Path::new(&config.templates_dir).join("root.hbs")
 }
 
 fn create_web_app_state(
     config: &Config,
     message_dispatcher: ActorRef<SupervisorMessage>,
+    decision_store: Option<Arc<dyn DecisionStore>>,
+    slack_templates: SlackTemplates,
+    http_client: ReqwestClient,
 ) -> Result<WebAppState> {
     let mut hb = Handlebars::new();
-
-    hb.register_template_file("root", format!("{}/root.hbs", config.templates_dir))
-        .map_err(|e| anyhow::anyhow!("Failed to load template: {}", e))?;
+    register_root_template(&mut hb, config)?;
 
     Ok(WebAppState {
         hb: Arc::new(hb),
         event_dispatcher: message_dispatcher,
+        decision_store,
+        slack_templates: Arc::new(slack_templates),
+        http_client,
     })
 }
 
-fn setup_metrics() -> Result<metrics_exporter_prometheus::PrometheusHandle, anyhow::Error> {
+fn register_root_template(hb: &mut Handlebars<'static>, config: &Config) -> Result<()> {
+    let root_template_path = root_template_path(config);
+    if !root_template_path.exists() {
+        warn!(
+            "Template not found at {}, using the embedded one",
+            root_template_path.display()
+        );
+        hb.register_template_string("root", FALLBACK_ROOT_TEMPLATE)
+            .map_err(|e| anyhow::anyhow!("Failed to load the embedded template: {}", e))?;
+        return Ok(());
+    }
+
+    hb.register_template_file("root", &root_template_path)
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to load template at {}: {}",
+                root_template_path.display(),
+                e
+            )
+        })?;
+    Ok(())
+}
+
+/// Installs the global Prometheus recorder. It can only be installed once per
+/// process, later calls get a handle to the same one.
+pub fn install_metrics_recorder() -> Result<PrometheusHandle> {
+    METRICS_HANDLE
+        .get_or_init(|| {
+            let prometheus_handle = PrometheusBuilder::new()
+                .set_buckets_for_metric(
+                    Matcher::Full("event_age_seconds".to_string()),
+                    &DELAY_BUCKETS_SECS,
+                )
+                .and_then(|builder| {
+                    builder.set_buckets_for_metric(
+                        Matcher::Full("subscription_first_event_seconds".to_string()),
+                        &DELAY_BUCKETS_SECS,
+                    )
+                })
+                .and_then(|builder| builder.install_recorder())
+                .map_err(|e| e.to_string())?;
+            describe_metrics();
+            Ok(prometheus_handle)
+        })
+        .clone()
+        .map_err(|e| anyhow::anyhow!("Failed to install the metrics recorder: {}", e))
+}
+
+/// A handle for a recorder that is not installed globally, so nothing is
+/// recorded. For tests sharing a process with another router.
+#[cfg(test)]
+pub fn detached_metrics_handle() -> PrometheusHandle {
+    PrometheusBuilder::new().build_recorder().handle()
+}
+
+// Descriptions go to the global recorder so it must be installed first
+fn describe_metrics() {
     describe_counter!("actor_panicked", "Number of actors that panicked");
     describe_counter!("event_received", "Number of events received");
     describe_counter!("event_received_error", "Number of errors receiving events");
+    describe_counter!(
+        "gift_wrap_error",
+        "Number of gift wraps that couldn't be turned into report requests, by reason"
+    );
+    describe_counter!(
+        "non_report_dm",
+        "Number of gift wrapped DMs we could decrypt that weren't report requests"
+    );
+    describe_counter!(
+        "downstream_call_waited",
+        "Number of Slack, Pub/Sub and relay calls that waited for the concurrency limit"
+    );
+    describe_counter!(
+        "gift_wrap_duplicate",
+        "Number of gift wraps ignored because they were already handled"
+    );
+    describe_counter!(
+        "report_rejected_low_pow",
+        "Number of gift wraps rejected for not having enough proof of work"
+    );
+    describe_counter!(
+        "report_unreportable_kind",
+        "Number of report requests rejected for targeting an event kind that can't be reported"
+    );
+    describe_counter!(
+        "report_future_timestamp",
+        "Number of gift wraps rejected for a created_at further ahead than max_future_skew_secs"
+    );
+    describe_counter!(
+        "report_stale_event",
+        "Number of report requests rejected for targeting an event older than max_event_age_secs"
+    );
+    describe_counter!(
+        "unexpected_kind_dropped",
+        "Number of events dropped because relays pushed a kind we don't subscribe to"
+    );
+    describe_counter!(
+        "report_unverified_reporter",
+        "Number of report requests dropped for not having a verified reporter NIP-05"
+    );
+    describe_counter!(
+        "report_event_not_found",
+        "Number of report requests dropped for reporting an event not found on our relays"
+    );
+    describe_counter!(
+        "report_lookup_failed",
+        "Number of report requests whose NIP-05 or event lookup failed or timed out"
+    );
+    describe_counter!(
+        "self_report",
+        "Number of report requests for the reporter's own account or events, by policy"
+    );
+    describe_counter!(
+        "backfill_fetched",
+        "Number of events fetched from relays by admin backfills"
+    );
+    describe_counter!(
+        "backfill_error",
+        "Number of admin backfills that failed to fetch events"
+    );
+    describe_counter!(
+        "content_escalated",
+        "Number of contents escalated to Slack for being reported by many accounts"
+    );
+    describe_counter!(
+        "content_tracker_evicted",
+        "Number of tracked contents forgotten to stay under content_tracking.max_contents"
+    );
+    describe_counter!("relay_added", "Number of relays added at runtime");
+    describe_counter!("relay_removed", "Number of relays removed at runtime");
     describe_counter!("publish", "Number of events published");
     describe_counter!("publish_error", "Number of errors publishing events");
+    describe_counter!(
+        "publish_partial",
+        "Number of events published while some of our relays rejected them or timed out"
+    );
+    describe_counter!(
+        "publish_relay_accepted",
+        "Number of relays that accepted a published event"
+    );
+    describe_counter!(
+        "publish_relay_rejected",
+        "Number of relays that rejected a published event"
+    );
+    describe_counter!(
+        "publish_relay_timeout",
+        "Number of relays that didn't confirm a published event in time"
+    );
+    describe_counter!(
+        "shadow_report_published",
+        "Number of shadow reports published to the shadow relays"
+    );
+    describe_counter!(
+        "shadow_report_publish_error",
+        "Number of errors publishing shadow reports"
+    );
+    describe_counter!(
+        "shadow_report_logged",
+        "Number of shadow reports only logged, for lack of shadow relays"
+    );
+    describe_counter!(
+        "report_published",
+        "Number of moderated reports with all their events published, by category"
+    );
+    describe_counter!(
+        "dead_letter_written",
+        "Number of reports written to the dead letter queue"
+    );
+    describe_counter!(
+        "dead_letter_replayed",
+        "Number of dead letters sent back to the dispatcher or the enqueuer"
+    );
+    describe_counter!(
+        "dead_letter_replay_error",
+        "Number of dead letters that couldn't be sent back and were kept"
+    );
+    describe_counter!(
+        "direct_message_published",
+        "Number of gift wrapped DMs published, like acknowledgments to reporters"
+    );
+    describe_counter!(
+        "direct_message_publish_error",
+        "Number of errors creating or publishing gift wrapped DMs"
+    );
+    describe_counter!(
+        "relay_policy_skipped",
+        "Number of relays from other users' lists skipped because reportinator.relay_policy doesn't allow them"
+    );
+    describe_counter!(
+        "direct_message_relays_unknown",
+        "Number of DMs sent to our relays because the receiver has no DM relay list we may use"
+    );
+    describe_counter!(
+        "outbox_published",
+        "Number of events also sent to the NIP-65 relays of the accounts they tag"
+    );
+    describe_counter!(
+        "outbox_publish_error",
+        "Number of errors sending events to outbox relays"
+    );
     describe_counter!("events_enqueued", "Number of events enqueued to cleanstr");
     describe_counter!(
         "events_enqueued_error",
         "Number of errors enqueuing events to cleanstr"
     );
+    describe_counter!(
+        "events_enqueued_invalid_signature",
+        "Number of report requests not enqueued because the reported event signature doesn't verify"
+    );
+    describe_counter!(
+        "reporter_ordering_held",
+        "Number of report requests held behind an earlier one from the same reporter"
+    );
+    describe_counter!(
+        "reporter_ordering_dead_lettered",
+        "Number of held report requests moved to the dead letter queue on shutdown"
+    );
+    describe_counter!(
+        "google_publish_auth_retry",
+        "Number of Google PubSub publish retries after refreshing the client on auth errors"
+    );
+    describe_counter!(
+        "google_publish_transient_retry",
+        "Number of Google PubSub publish retries after transient errors"
+    );
+    describe_counter!(
+        "google_publish_permanent_error",
+        "Number of Google PubSub publish errors that are not retried"
+    );
+    describe_counter!(
+        "google_publish_retries_exhausted",
+        "Number of Google PubSub publishes that failed after all retries"
+    );
     describe_counter!("connect", "Number of new nostr client connections");
     describe_counter!("connect_error", "Number of errors connecting to nostr");
     describe_counter!("reconnect", "Number of reconnections to nostr");
     describe_counter!("reconnect_error", "Number of errors reconnecting to nostr");
     describe_counter!("slack_write_message", "Number of writes to slack");
+    describe_counter!(
+        "webhook_post",
+        "Number of report request notifications posted to the webhook"
+    );
+    describe_counter!(
+        "webhook_post_error",
+        "Number of errors when posting to the webhook"
+    );
+    describe_counter!(
+        "webhook_coalesced_report_requests",
+        "Number of report requests posted to the webhook as part of a batch"
+    );
     describe_counter!(
         "slack_write_message_error",
         "Number of errors when writing to slack"
     );
+    describe_counter!(
+        "slack_deferred",
+        "Number of report requests held back from slack during quiet hours"
+    );
+    describe_counter!(
+        "slack_deferred_overflow",
+        "Number of report requests written during quiet hours because max_deferred were already held back"
+    );
+    describe_counter!(
+        "file_sink_rotated",
+        "Number of rotations of the report requests file of the file backend"
+    );
+    describe_counter!(
+        "nip05_lookup_timeout",
+        "Number of NIP-05 lookups for njump links that timed out, retries included"
+    );
+    describe_counter!(
+        "account_links_timeout",
+        "Number of accounts shown with their npub in Slack messages because their NIP-05 lookups ran past the deadline"
+    );
+    describe_counter!(
+        "slack_suppressed",
+        "Number of report requests not written to slack because their target was on cooldown"
+    );
+    describe_counter!(
+        "retry_scheduled",
+        "Number of failed report requests scheduled to be retried"
+    );
+    describe_counter!(
+        "retry_exhausted",
+        "Number of report requests moved to the dead letter queue after all retries"
+    );
+    describe_counter!(
+        "slack_action_parse_error",
+        "Number of Slack actions that couldn't be parsed, by reason"
+    );
+    describe_counter!(
+        "slack_unauthorized_action",
+        "Number of Slack actions ignored because the user isn't in authorized_slack_users"
+    );
+    describe_counter!(
+        "decision_store_error",
+        "Number of errors persisting moderator decisions"
+    );
+    describe_counter!(
+        "decision_published",
+        "Number of moderator decisions published to the decisions Pub/Sub topic"
+    );
+    describe_counter!(
+        "report_autoskipped",
+        "Number of report requests skipped for matching an auto skip rule"
+    );
+    describe_counter!(
+        "category_suggested",
+        "Number of Slack messages with a suggested category, by category"
+    );
+    describe_counter!(
+        "report_retracted",
+        "Number of reports retracted with a NIP-09 deletion"
+    );
+    describe_counter!(
+        "report_retract_error",
+        "Number of report retractions that failed, including reports we didn't publish"
+    );
+    describe_counter!("key_rotated", "Number of signing key rotations");
+    describe_counter!(
+        "key_rotation_error",
+        "Number of signing key rotations that failed"
+    );
+    describe_counter!(
+        "resubscribe",
+        "Number of relay resubscriptions after a key rotation"
+    );
+    describe_counter!(
+        "resubscribe_error",
+        "Number of errors resubscribing after a key rotation"
+    );
+    describe_counter!(
+        "slack_coalesced_report_requests",
+        "Number of report requests written to slack as part of a batch"
+    );
+
+    // Report funnel, every counter is labeled with the target type
+    describe_counter!(
+        "report_funnel_received",
+        "Number of gift wraps received from the relays"
+    );
+    describe_counter!(
+        "report_funnel_unwrapped",
+        "Number of gift wraps decrypted into report requests"
+    );
+    describe_counter!(
+        "report_funnel_valid",
+        "Number of report requests that passed every check and were sent for moderation"
+    );
+    describe_counter!(
+        "report_funnel_enqueued",
+        "Number of report requests sent to the pubsub topic for automatic moderation"
+    );
+    describe_counter!(
+        "report_funnel_slacked",
+        "Number of report requests written to slack for manual moderation"
+    );
+    describe_counter!(
+        "report_funnel_published",
+        "Number of reports whose events were all confirmed by the relays"
+    );
+    describe_counter!(
+        "supervisor_unavailable",
+        "Number of Slack actions rejected because the supervisor was not running"
+    );
+    describe_counter!(
+        "report_already_resolved",
+        "Number of Slack actions ignored because the report request was already resolved"
+    );
+    describe_counter!(
+        "report_resolution_undone",
+        "Number of Slack actions that failed or timed out before publishing, their report requests can be acted on again"
+    );
+    describe_counter!(
+        "report_expired_unactioned",
+        "Number of Slack messages that expired with no moderator action"
+    );
+    describe_counter!(
+        "slack_expiry_overflow",
+        "Number of Slack messages that won't expire because too many were already waiting"
+    );
+    describe_counter!(
+        "report_funnel_skipped",
+        "Number of report requests moderators decided not to report"
+    );
 
-    let prometheus_builder = PrometheusBuilder::new();
-    let prometheus_handle = prometheus_builder.install_recorder()?;
-    Ok(prometheus_handle)
+    describe_counter!(
+        "output_port_overflow",
+        "Number of sends on an output port while its subscribers lagged more than its buffer"
+    );
+
+    describe_counter!(
+        "relay_health_reconnect",
+        "Number of reconnects forced because too few relays were connected"
+    );
+
+    describe_counter!(
+        "relay_health_backoff",
+        "Number of health checks that didn't reconnect because the previous reconnect was too recent"
+    );
+
+    describe_gauge!(
+        "actor_mailbox_len",
+        "Number of messages waiting to be handled by each actor"
+    );
+    describe_counter!(
+        "mailbox_count_underflow",
+        "Number of counted messages handled by each actor without being counted as sent"
+    );
+
+    describe_histogram!(
+        "event_age_seconds",
+        Unit::Seconds,
+        "Age of the events when received, from their created_at"
+    );
+    describe_histogram!(
+        "content_report_count",
+        "Reporters of the same content within the tracking window, on each report"
+    );
+    describe_histogram!(
+        "subscription_first_event_seconds",
+        Unit::Seconds,
+        "Time from subscribing to the relays to receiving the first event"
+    );
 }
 
-async fn serve_root_page(
-    State(web_app_state): State<WebAppState>,
-    _headers: HeaderMap,
-) -> impl IntoResponse {
-    let body = web_app_state.hb.render("root", &json!({})).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::TestActor;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_routers_can_share_a_process() {
+        let config = ConfigTree::new("config").unwrap();
+        let (test_actor_ref, test_actor_handle) = TestActor::<SupervisorMessage>::spawn_default()
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            let router = create_router(
+                &config,
+                test_actor_ref.clone(),
+                None,
+                detached_metrics_handle(),
+            )
+            .unwrap();
+
+            let response = router
+                .oneshot(
+                    Request::builder()
+                        .uri("/metrics")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        test_actor_ref.stop(None);
+        test_actor_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_metrics_recorder_is_installed_once() {
+        let config = ConfigTree::new("config").unwrap();
+        let (test_actor_ref, test_actor_handle) = TestActor::<SupervisorMessage>::spawn_default()
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            let router = create_router(
+                &config,
+                test_actor_ref.clone(),
+                None,
+                install_metrics_recorder().unwrap(),
+            )
+            .unwrap();
+            metrics::counter!("test_metrics_recorder_is_installed_once").increment(1);
+
+            let response = router
+                .oneshot(
+                    Request::builder()
+                        .uri("/metrics")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert!(
+                String::from_utf8_lossy(&body).contains("test_metrics_recorder_is_installed_once")
+            );
+        }
+
+        test_actor_ref.stop(None);
+        test_actor_handle.await.unwrap();
+    }
+
+    #[test]
+    fn test_missing_templates_dir_uses_embedded_template() {
+        let config = Config {
+            templates_dir: "/nonexistent/reportinator/templates".to_string(),
+            exemplars: false,
+        };
+        let mut hb = Handlebars::new();
+
+        register_root_template(&mut hb, &config).unwrap();
+
+        let page = hb
+            .render(
+                "root",
+                &json!({
+                    "uptimeSecs": 42,
+                    "relays": {"ok": true, "detail": "2 of 3 relays connected"},
+                }),
+            )
+            .unwrap();
+        assert!(page.contains("Reportinator Bot"));
+        assert!(page.contains("42 seconds"));
+        assert!(page.contains("2 of 3 relays connected"));
+        assert!(check_config(&config).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_root_page_renders_the_embedded_template() {
+        let config = Config {
+            templates_dir: "/nonexistent/reportinator/templates".to_string(),
+            exemplars: false,
+        };
+        let (test_actor_ref, test_actor_handle) = TestActor::<SupervisorMessage>::spawn_default()
+            .await
+            .unwrap();
+        let web_app_state = create_web_app_state(
+            &config,
+            test_actor_ref.clone(),
+            None,
+            SlackTemplates::default(),
+            ReqwestClient::new(),
+        )
+        .unwrap();
+
+        let response = root_route()
+            .with_state(web_app_state)
+            .oneshot(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
 
-    Html(body)
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page = String::from_utf8_lossy(&body);
+        assert!(page.contains("Reportinator Bot"));
+        assert!(page.contains("Uptime: 0 seconds"));
+        assert!(page.contains("Relays: "));
+        assert!(!page.contains(env!("CARGO_PKG_VERSION")));
+
+        test_actor_ref.stop(None);
+        test_actor_handle.await.unwrap();
+    }
+
+    #[test]
+    fn test_invalid_template_error_names_the_path() {
+        let templates_dir = std::env::temp_dir().join("test_invalid_template_error_names_the_path");
+        std::fs::create_dir_all(&templates_dir).unwrap();
+        std::fs::write(templates_dir.join("root.hbs"), "{{#if}}").unwrap();
+        let config = Config {
+            templates_dir: templates_dir.to_string_lossy().to_string(),
+            exemplars: false,
+        };
+
+        let error = register_root_template(&mut Handlebars::new(), &config).unwrap_err();
+
+        assert!(error
+            .to_string()
+            .contains(&templates_dir.join("root.hbs").display().to_string()));
+        std::fs::remove_dir_all(templates_dir).unwrap();
+    }
 }
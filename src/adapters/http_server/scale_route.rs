@@ -0,0 +1,11 @@
+use crate::actors::utilities::pending_work;
+use axum::{routing::get, Router};
+
+// Pending work as plain text, for autoscalers like KEDA to add replicas
+// during report storms
+pub fn scale_route() -> Router {
+    Router::new().route(
+        "/scale",
+        get(|| async { pending_work::total().to_string() }),
+    )
+}
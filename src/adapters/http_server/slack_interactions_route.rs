@@ -1,17 +1,28 @@
 use super::app_errors::AppError;
+use super::slack_report_message::{AccountLinks, SlackDecision, SlackReportMessage};
 use super::WebAppState;
 use crate::actors::messages::SupervisorMessage;
-use crate::config::Configurable;
-use crate::adapters::njump_or_pubkey;
-use crate::domain_objects::{ReportRequest, ReportTarget};
-use anyhow::{anyhow, Result};
+use crate::actors::supervisor::AckTrigger;
+use crate::actors::utilities::report_funnel::{self, FunnelStep};
+use crate::actors::utilities::resolved_reports::Resolution;
+use crate::adapters::decision_store::{DecisionStore, ModeratorDecision};
+use crate::adapters::slack_client_adapter::{
+    CO_REPORTERS_BLOCK_ID, REPORT_TYPE_ACTION_ID, REPORT_TYPE_BLOCK_ID,
+};
+use crate::adapters::slack_markdown::markdown_sections;
+use crate::adapters::slack_templates::SlackTemplates;
+use crate::config::{self, Configurable};
+use crate::domain_objects::{is_truncated_event, ModerationCategory, ReportRequest, ReportTarget};
+use anyhow::{anyhow, bail, Result};
 use axum::{extract::State, routing::post, Extension, Router};
+use metrics::counter;
 use nostr_sdk::prelude::*;
-use ractor::{cast, ActorRef};
+use ractor::{call_t, cast, ActorRef, ActorStatus};
 use reqwest::Client as ReqwestClient;
 use serde::Deserialize;
 use serde_json::{json, Value};
 use slack_morphism::prelude::*;
+use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 use std::sync::Arc;
 use tracing::{debug, error, info};
@@ -19,27 +30,111 @@ use tracing::{debug, error, info};
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     signing_secret: SlackSigningSecret,
+    // Additional workspaces, each one with its own interactions route
+    #[serde(default)]
+    workspaces: Vec<WorkspaceConfig>,
+    // Hosts the response_url of an interaction may point to. The payload is
+    // signed, but we still don't POST to wherever it says
+    #[serde(default = "default_response_url_hosts")]
+    response_url_hosts: Vec<String>,
+    // Slack user ids allowed to click the moderation buttons, in every
+    // workspace. Empty means everyone in the channel
+    #[serde(default)]
+    authorized_slack_users: Vec<String>,
+}
+
+fn default_response_url_hosts() -> Vec<String> {
+    vec!["hooks.slack.com".to_string()]
+}
+
+#[derive(Debug, Clone)]
+struct ResponseUrlHosts(Arc<Vec<String>>);
+
+#[derive(Debug, Clone)]
+struct AuthorizedSlackUsers(Arc<Vec<String>>);
+
+#[derive(Debug, Clone, Deserialize)]
+struct WorkspaceConfig {
+    name: String,
+    signing_secret: SlackSigningSecret,
 }
 
 impl Configurable for Config {
     fn key() -> &'static str {
         "slack"
     }
+
+    fn validate(&self) -> Result<()> {
+        check_signing_secret("slack.signing_secret", &self.signing_secret)?;
+        for workspace in &self.workspaces {
+            check_signing_secret(
+                &format!("the signing_secret of the {} workspace", workspace.name),
+                &workspace.signing_secret,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// Signing secrets are hex strings. A placeholder left as <NOT_SET> is listed
+// by --check-config with the other missing values instead
+fn check_signing_secret(name: &str, signing_secret: &SlackSigningSecret) -> Result<()> {
+    let signing_secret = signing_secret.value();
+    if signing_secret == config::NOT_SET {
+        return Ok(());
+    }
+
+    if signing_secret.trim().is_empty() {
+        bail!("{} is empty, set APP__SLACK__SIGNING_SECRET", name);
+    }
+
+    if !signing_secret.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!(
+            "{} is not a Slack signing secret, it must only have hex characters",
+            name
+        );
+    }
+
+    Ok(())
 }
 
 pub fn slack_interactions_route(config: &Config) -> Result<Router<WebAppState>> {
     let client = prepare_slack_client()?;
     let listener_environment = prepare_listener_environment(client);
     let listener = SlackEventsAxumListener::<SlackHyperHttpsConnector>::new(listener_environment);
-    let slack_layer = listener
-        .events_layer(&config.signing_secret)
-        .with_event_extractor(SlackEventsExtractors::interaction_event());
+    let response_url_hosts = ResponseUrlHosts(Arc::new(config.response_url_hosts.clone()));
+    let authorized_slack_users =
+        AuthorizedSlackUsers(Arc::new(config.authorized_slack_users.clone()));
 
-    let route = Router::new().route(
+    // Interactions are verified with the signing secret of the workspace
+    // they come from, so each workspace gets its own route
+    let mut route = Router::new().route(
         "/slack/interactions",
-        post(slack_interaction_handler).layer(slack_layer),
+        post(slack_interaction_handler)
+            .layer(
+                listener
+                    .events_layer(&config.signing_secret)
+                    .with_event_extractor(SlackEventsExtractors::interaction_event()),
+            )
+            .layer(Extension(response_url_hosts.clone()))
+            .layer(Extension(authorized_slack_users.clone())),
     );
 
+    for workspace in &config.workspaces {
+        route = route.route(
+            &format!("/slack/{}/interactions", workspace.name),
+            post(slack_interaction_handler)
+                .layer(
+                    listener
+                        .events_layer(&workspace.signing_secret)
+                        .with_event_extractor(SlackEventsExtractors::interaction_event()),
+                )
+                .layer(Extension(response_url_hosts.clone()))
+                .layer(Extension(authorized_slack_users.clone())),
+        );
+    }
+
     Ok(route)
 }
 
@@ -60,213 +155,589 @@ fn prepare_listener_environment(
 async fn slack_interaction_handler(
     State(WebAppState {
         event_dispatcher: message_dispatcher,
+        decision_store,
+        slack_templates,
+        http_client,
         ..
     }): State<WebAppState>,
     Extension(event): Extension<SlackInteractionEvent>,
+    Extension(ResponseUrlHosts(response_url_hosts)): Extension<ResponseUrlHosts>,
+    Extension(AuthorizedSlackUsers(authorized_slack_users)): Extension<AuthorizedSlackUsers>,
 ) -> Result<(), AppError> {
     let SlackInteractionEvent::BlockActions(block_actions_event) = event else {
         return Ok(());
     };
 
-    let (response_url, slack_username, report_request, maybe_category) =
-        parse_slack_action(block_actions_event)?;
+    let event_value = serde_json::to_value(block_actions_event)
+        .map_err(|e| anyhow!("Failed to convert block_actions_event to Value: {:?}", e))?;
 
-    let message = slack_message(
-        message_dispatcher,
+    // Checked before anything can be sent to the response_url
+    if let Err(e) = check_response_url(&event_value, &response_url_hosts) {
+        counter!("slack_action_parse_error", "reason" => e.reason()).increment(1);
+        error!(reason = e.reason(), "Rejected Slack action: {}", e);
+        return Err(AppError::slack_parsing_error(&e.to_string()));
+    }
+
+    // Nothing is published or retracted for the other users, they only get
+    // told. Slack still gets a 200 so it doesn't show its own error
+    if let Err(e) = check_slack_user(&event_value, &authorized_slack_users) {
+        counter!("slack_unauthorized_action").increment(1);
+        info!("Ignored Slack action: {}", e);
+        if let Some(response_url) = response_url(&event_value) {
+            send_slack_error(&http_client, response_url.as_ref(), &e).await;
+        }
+        return Ok(());
+    }
+
+    // Picking a report type only changes the state of the message, which
+    // comes along with the next button click
+    if event_value["actions"][0]["action_id"].as_str() == Some(REPORT_TYPE_ACTION_ID) {
+        return Ok(());
+    }
+
+    if let Some(maybe_report_id) = parse_retract_action(&event_value) {
+        let report_id = match maybe_report_id {
+            Ok(report_id) => report_id,
+            Err(e) => return Err(reject_slack_action(&http_client, &event_value, e).await),
+        };
+
+        return retract_report(&http_client, message_dispatcher, &event_value, report_id).await;
+    }
+
+    let full_event = match fetch_truncated_event(&message_dispatcher, &event_value).await {
+        Ok(full_event) => full_event,
+        Err(e) => return Err(reject_slack_action(&http_client, &event_value, e).await),
+    };
+
+    let (response_url, slack_username, report_request, choice) =
+        match parse_slack_action(&event_value, full_event) {
+            Ok(parsed_action) => parsed_action,
+            Err(e) => return Err(reject_slack_action(&http_client, &event_value, e).await),
+        };
+
+    let co_reporters = parse_co_reporters(&event_value);
+    let target = report_request.target().clone();
+    let outcome = slack_message(
+        message_dispatcher.clone(),
+        decision_store,
+        &slack_templates,
         report_request,
-        maybe_category,
+        choice,
         slack_username,
     )
     .await?;
 
-    send_slack_response(response_url.as_ref(), &message).await?;
+    match outcome {
+        SlackActionOutcome::Resolved {
+            message,
+            report_id,
+            resolution,
+        } => {
+            acknowledge_co_reporters(&message_dispatcher, &target, &co_reporters);
+            send_slack_response(
+                &http_client,
+                response_url.as_ref(),
+                &message,
+                report_id,
+                &resolution,
+            )
+            .await?;
+        }
+        SlackActionOutcome::AlreadyResolved(resolution) => {
+            send_slack_note(
+                &http_client,
+                response_url.as_ref(),
+                &format!("ℹ️ This report was already resolved: {}.", resolution),
+            )
+            .await;
+        }
+    }
 
     Ok(())
 }
 
+async fn reject_slack_action(
+    http_client: &ReqwestClient,
+    event_value: &Value,
+    error: SlackActionError,
+) -> AppError {
+    counter!("slack_action_parse_error", "reason" => error.reason()).increment(1);
+    if let Some(response_url) = response_url(event_value) {
+        send_slack_error(http_client, response_url.as_ref(), &error).await;
+    }
+
+    AppError::slack_parsing_error(&error.to_string())
+}
+
+// The deletion is published in the background, the dispatcher checks that
+// the report is one of ours
+async fn retract_report(
+    http_client: &ReqwestClient,
+    message_dispatcher: ActorRef<SupervisorMessage>,
+    event_value: &Value,
+    report_id: EventId,
+) -> Result<(), AppError> {
+    let Some(response_url) = response_url(event_value) else {
+        return Err(reject_slack_action(
+            http_client,
+            event_value,
+            SlackActionError::MissingResponseUrl,
+        )
+        .await);
+    };
+    let Some(slack_username) = event_value["user"]["username"].as_str() else {
+        return Err(reject_slack_action(
+            http_client,
+            event_value,
+            SlackActionError::MissingUsername,
+        )
+        .await);
+    };
+
+    check_supervisor(&message_dispatcher)?;
+    cast!(message_dispatcher, SupervisorMessage::Retract(report_id))?;
+    info!(
+        "Retraction of report {} requested by {}",
+        report_id, slack_username
+    );
+
+    let res = http_client
+        .post(response_url.as_ref())
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "response_type": "in_channel",
+                "replace_original": false,
+                "text": format!(
+                    "↩️ Retraction of report `{}` requested by {}",
+                    report_id, slack_username
+                ),
+            })
+            .to_string(),
+        )
+        .send()
+        .await?;
+
+    if !res.status().is_success() {
+        error!("Failed to confirm retraction. Status: {}", res.status());
+    }
+
+    Ok(())
+}
+
+// What becomes of the Slack message after an action
+#[derive(Debug)]
+enum SlackActionOutcome {
+    Resolved {
+        message: String,
+        report_id: Option<EventId>,
+        resolution: Resolution,
+    },
+    // An earlier click, maybe on another copy of the message, already
+    // resolved it so nothing was done
+    AlreadyResolved(Resolution),
+}
+
 async fn slack_message(
     message_dispatcher: ActorRef<SupervisorMessage>,
+    decision_store: Option<Arc<dyn DecisionStore>>,
+    slack_templates: &SlackTemplates,
     report_request: ReportRequest,
-    maybe_category: Option<Report>,
+    choice: ModeratorChoice,
     slack_username: String,
-) -> Result<String, AppError> {
-    let reporter_nip05_markdown = njump_or_pubkey(
-        message_dispatcher.clone(),
-        *report_request.reporter_pubkey(),
-    )
-    .await;
+) -> Result<SlackActionOutcome, AppError> {
+    check_supervisor(&message_dispatcher)?;
+
+    let maybe_category = choice.category;
+    // Built before resolving so an invalid request can still be retried
+    let maybe_moderated_report = report_request.report(maybe_category, choice.report_type)?;
+    let resolution = Resolution {
+        moderator: slack_username.clone(),
+        category: maybe_category,
+    };
+    if let Some(earlier_resolution) =
+        resolve(&message_dispatcher, &report_request, resolution.clone()).await
+    {
+        counter!("report_already_resolved").increment(1);
+        info!(
+            "Ignored action of {} on a report request already resolved: {}",
+            slack_username, earlier_resolution
+        );
+        return Ok(SlackActionOutcome::AlreadyResolved(earlier_resolution));
+    }
+    let claim = ResolutionClaim {
+        message_dispatcher: message_dispatcher.clone(),
+        report_request: report_request.clone(),
+        resolution: resolution.clone(),
+        kept: false,
+    };
 
-    let reported_nip05_markdown =
-        njump_or_pubkey(message_dispatcher.clone(), report_request.target().pubkey()).await;
+    let account_links = AccountLinks::resolve(&message_dispatcher, &report_request).await;
 
-    if let Some(moderated_report) = report_request.report(maybe_category.clone())? {
+    if let Some(moderated_report) = maybe_moderated_report {
         let report_id = moderated_report.id();
         cast!(
             message_dispatcher,
             SupervisorMessage::Publish(moderated_report)
         )?;
+        // Published now, acting again would publish it twice
+        claim.keep();
+
+        let decision = ModeratorDecision::new(
+            &report_request,
+            Some(report_id),
+            maybe_category.clone(),
+            slack_username.clone(),
+        );
+        save_decision(decision_store, &decision).await;
+        acknowledge_decision(&message_dispatcher, &report_request);
 
-        let message = slack_processed_message(
+        let message = SlackReportMessage::new(
             slack_username,
-            maybe_category.unwrap(),
-            report_id,
-            reporter_nip05_markdown,
-            report_request,
-            reported_nip05_markdown,
+            &report_request,
+            account_links,
+            SlackDecision::Processed {
+                category: maybe_category.unwrap(),
+                report_id,
+            },
         );
-        return Ok(message);
+        return Ok(SlackActionOutcome::Resolved {
+            message: message.render(slack_templates)?,
+            report_id: Some(report_id),
+            resolution,
+        });
     }
 
-    Ok(slack_skipped_message(
+    report_funnel::record(FunnelStep::Skipped, report_request.target().label());
+    let decision = ModeratorDecision::new(&report_request, None, None, slack_username.clone());
+    save_decision(decision_store, &decision).await;
+    acknowledge_decision(&message_dispatcher, &report_request);
+
+    let message = SlackReportMessage::new(
         slack_username,
-        reporter_nip05_markdown,
-        report_request,
-        reported_nip05_markdown,
-    ))
+        &report_request,
+        account_links,
+        SlackDecision::Skipped,
+    );
+    let message = message.render(slack_templates)?;
+    claim.keep();
+    Ok(SlackActionOutcome::Resolved {
+        message,
+        report_id: None,
+        resolution,
+    })
 }
 
-fn slack_processed_message(
-    slack_username: String,
-    category: Report,
-    report_id: EventId,
-    reporter_nip05_markdown: String,
+// A resolution recorded for an action that isn't done yet. Unless kept, it's
+// undone when dropped, by an error or by the route timing out, so the report
+// request can be acted on again instead of staying resolved but unpublished
+struct ResolutionClaim {
+    message_dispatcher: ActorRef<SupervisorMessage>,
     report_request: ReportRequest,
-    reported_nip05_markdown: String,
-) -> String {
-    let target_message = match report_request.target() {
-        ReportTarget::Event(event) => format!(
-            r#"
-            *Reported Pubkey:* {}
-            *Reported Event Id:* `{}`
-            *Reported Event content:*
-            ```
-            {}
-            ```
-            "#,
-            reported_nip05_markdown, event.id, event.content
-        ),
-        ReportTarget::Pubkey(_) => format!(
-            r#"
-            *Reported Pubkey:* {}
-            "#,
-            reported_nip05_markdown
-        ),
-    };
+    resolution: Resolution,
+    kept: bool,
+}
+
+impl ResolutionClaim {
+    fn keep(mut self) {
+        self.kept = true;
+    }
+}
+
+impl Drop for ResolutionClaim {
+    fn drop(&mut self) {
+        if self.kept {
+            return;
+        }
+
+        counter!("report_resolution_undone").increment(1);
+        info!(
+            "Action of {} failed before it was done, undoing its resolution",
+            self.resolution.moderator
+        );
+        if let Err(e) = cast!(
+            self.message_dispatcher,
+            SupervisorMessage::Unresolve(self.report_request.clone(), self.resolution.clone())
+        ) {
+            error!("Failed to undo the resolution of a report request: {}", e);
+        }
+    }
+}
+
+// A supervisor that can't answer doesn't block moderation, the action goes
+// through as if it was the first one
+async fn resolve(
+    message_dispatcher: &ActorRef<SupervisorMessage>,
+    report_request: &ReportRequest,
+    resolution: Resolution,
+) -> Option<Resolution> {
+    match call_t!(
+        message_dispatcher,
+        SupervisorMessage::Resolve,
+        100,
+        report_request.clone(),
+        resolution
+    ) {
+        Ok(earlier_resolution) => earlier_resolution,
+        Err(e) => {
+            error!("Failed to resolve report request: {}", e);
+            None
+        }
+    }
+}
+
+// Leaves room for the rest of the action in the 1s the route has
+const FIND_EVENT_TIMEOUT_MS: u64 = 700;
+
+// Suggested wait before retrying when the supervisor is down, long enough
+// for the process to be restarted
+const SUPERVISOR_RETRY_AFTER_SECS: u64 = 30;
+
+// Without a running supervisor nothing can be published, so it's checked
+// before the NIP-05 lookups of the message and anything else is attempted
+fn check_supervisor(message_dispatcher: &ActorRef<SupervisorMessage>) -> Result<(), AppError> {
+    match message_dispatcher.get_status() {
+        ActorStatus::Running | ActorStatus::Upgrading => Ok(()),
+        status => {
+            counter!("supervisor_unavailable").increment(1);
+            error!(
+                "Supervisor unavailable for a Slack action, status: {:?}",
+                status
+            );
+            Err(AppError::service_unavailable(
+                "the supervisor is not running",
+                SUPERVISOR_RETRY_AFTER_SECS,
+            ))
+        }
+    }
+}
 
-    let reason = match report_request.reporter_text() {
-        Some(text) => format!(
-            r#"
-            *Reporter Reason:*
-            ```
-            {}
-            ```
-            "#,
-            text
-        ),
-        None => "".to_string(),
+// A failure to persist the decision is logged but doesn't fail the
+// interaction, the report was already handled
+async fn save_decision(
+    decision_store: Option<Arc<dyn DecisionStore>>,
+    decision: &ModeratorDecision,
+) {
+    let Some(decision_store) = decision_store else {
+        return;
     };
 
-    let message = format!(
-        r#"
-        🚩 *New Moderation Report* 🚩
+    if let Err(e) = decision_store.save(decision).await {
+        counter!("decision_store_error").increment(1);
+        error!("Failed to save moderator decision: {}", e);
+    }
+}
 
-        *Report Confirmed By:* {}
-        *Categorized As:* `{}`
-        *Report Id:* `{}`
+// The supervisor decides if the reporter gets a DM for decisions
+fn acknowledge_decision(
+    message_dispatcher: &ActorRef<SupervisorMessage>,
+    report_request: &ReportRequest,
+) {
+    if let Err(e) = cast!(
+        message_dispatcher,
+        SupervisorMessage::Acknowledge(report_request.clone(), AckTrigger::Decision)
+    ) {
+        error!("Failed to acknowledge decision: {}", e);
+    }
+}
 
-        *Requested By*: {}
-        {}
+// Everyone who reported an aggregated message hears about the decision, not
+// only the reporter its buttons carry
+fn acknowledge_co_reporters(
+    message_dispatcher: &ActorRef<SupervisorMessage>,
+    target: &ReportTarget,
+    co_reporters: &[PublicKey],
+) {
+    for co_reporter in co_reporters {
+        let report_request = ReportRequest::new(target.clone(), *co_reporter, None);
+        acknowledge_decision(message_dispatcher, &report_request);
+    }
+}
 
-        {}
-        "#,
-        slack_username, category, report_id, reporter_nip05_markdown, reason, target_message,
-    );
+const RETRACT_ACTION_ID: &str = "retract";
 
-    let trimmed_string = message
-        .lines()
-        .map(|line| line.trim())
-        .collect::<Vec<&str>>()
-        .join("\n");
+/// What a moderator picked: the category of the button, none to skip, and
+/// maybe a NIP-56 report type used instead of the one of the category
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ModeratorChoice {
+    category: Option<ModerationCategory>,
+    report_type: Option<Report>,
+}
 
-    trimmed_string
+/// Why a Slack action couldn't be turned into a report request. Except for a
+/// missing or disallowed response_url, these are explained to the moderator in
+/// Slack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SlackActionError {
+    MissingResponseUrl,
+    DisallowedResponseUrl(String),
+    MissingUsername,
+    MissingActionId,
+    MissingTarget,
+    InvalidReportedPubkey(String),
+    InvalidReportedEvent(String),
+    InvalidReporterPubkey(String),
+    InvalidReportRequest(String),
+    InvalidReportId(String),
+    UnauthorizedUser(String),
+    TruncatedEventUnavailable(String),
 }
 
-fn slack_skipped_message(
-    slack_username: String,
-    reporter_nip05_markdown: String,
-    report_request: ReportRequest,
-    reported_nip05_markdown: String,
-) -> String {
-    let target_message = match report_request.target() {
-        ReportTarget::Event(event) => format!(
-            r#"
-            *Reported Pubkey:* {}
-            *Reported Event Id:* `{}`
-            *Reported Event content:*
-            ```
-            {}
-            ```
-            "#,
-            reported_nip05_markdown, event.id, event.content
-        ),
-        ReportTarget::Pubkey(_) => format!(
-            r#"
-            *Reported Pubkey:* {}
-            "#,
-            reported_nip05_markdown
-        ),
-    };
+impl SlackActionError {
+    /// Short label used for metrics and log fields
+    fn reason(&self) -> &'static str {
+        match self {
+            SlackActionError::MissingResponseUrl => "missing_response_url",
+            SlackActionError::DisallowedResponseUrl(_) => "disallowed_response_url",
+            SlackActionError::MissingUsername => "missing_username",
+            SlackActionError::MissingActionId => "missing_action_id",
+            SlackActionError::MissingTarget => "missing_target",
+            SlackActionError::InvalidReportedPubkey(_) => "invalid_reported_pubkey",
+            SlackActionError::InvalidReportedEvent(_) => "invalid_reported_event",
+            SlackActionError::InvalidReporterPubkey(_) => "invalid_reporter_pubkey",
+            SlackActionError::InvalidReportRequest(_) => "invalid_report_request",
+            SlackActionError::InvalidReportId(_) => "invalid_report_id",
+            SlackActionError::UnauthorizedUser(_) => "unauthorized_user",
+            SlackActionError::TruncatedEventUnavailable(_) => "truncated_event_unavailable",
+        }
+    }
+}
 
-    let reason = match report_request.reporter_text() {
-        Some(text) => format!(
-            r#"
-            *Reporter Reason:*
-            ```
-            {}
-            ```
-            "#,
-            text
-        ),
-        None => "".to_string(),
+impl Display for SlackActionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SlackActionError::MissingResponseUrl => {
+                write!(f, "The action has no valid response_url")
+            }
+            SlackActionError::DisallowedResponseUrl(url) => {
+                write!(f, "The response_url {} is not an allowed https host", url)
+            }
+            SlackActionError::MissingUsername => write!(f, "The action has no Slack username"),
+            SlackActionError::MissingActionId => write!(f, "The clicked button has no action id"),
+            SlackActionError::MissingTarget => write!(
+                f,
+                "The message has neither a `reportedEvent` nor a `reportedPubkey` block"
+            ),
+            SlackActionError::InvalidReportedPubkey(pubkey) => write!(
+                f,
+                "The `reportedPubkey` block has `{}`, which is not a hex pubkey",
+                pubkey
+            ),
+            SlackActionError::InvalidReportedEvent(e) => write!(
+                f,
+                "The `reportedEvent` block is not a valid Nostr event: {}",
+                e
+            ),
+            SlackActionError::InvalidReporterPubkey(pubkey) => write!(
+                f,
+                "The clicked button has `{}` as the reporter, which is not a hex pubkey",
+                pubkey
+            ),
+            SlackActionError::InvalidReportRequest(e) => {
+                write!(f, "The report request is not valid: {}", e)
+            }
+            SlackActionError::InvalidReportId(report_id) => write!(
+                f,
+                "The retract button has `{}`, which is not a report id",
+                report_id
+            ),
+            SlackActionError::UnauthorizedUser(user_id) => write!(
+                f,
+                "Slack user {} is not permitted to moderate reports",
+                user_id
+            ),
+            SlackActionError::TruncatedEventUnavailable(e) => write!(
+                f,
+                "The reported event was truncated and couldn't be fetched again: {}",
+                e
+            ),
+        }
+    }
+}
+
+fn response_url(event_value: &Value) -> Option<Url> {
+    event_value["response_url"].as_str()?.parse::<Url>().ok()
+}
+
+// A missing response_url is left for the parsing to report
+fn check_response_url(
+    event_value: &Value,
+    response_url_hosts: &[String],
+) -> Result<(), SlackActionError> {
+    let Some(response_url) = response_url(event_value) else {
+        return Ok(());
     };
 
-    let message = format!(
-        r#"
-        ⏭️ *Moderation Report Skipped* ⏭️
+    let allowed = response_url.scheme() == "https"
+        && response_url
+            .host_str()
+            .is_some_and(|host| response_url_hosts.iter().any(|allowed| allowed == host));
+    if !allowed {
+        return Err(SlackActionError::DisallowedResponseUrl(
+            response_url.to_string(),
+        ));
+    }
 
-        *Report Skipped By:* {}
+    Ok(())
+}
 
-        *Requested By*: {}
-        {}
-        {}
-        "#,
-        slack_username, reporter_nip05_markdown, reason, target_message,
-    );
+fn check_slack_user(
+    event_value: &Value,
+    authorized_slack_users: &[String],
+) -> Result<(), SlackActionError> {
+    if authorized_slack_users.is_empty() {
+        return Ok(());
+    }
 
-    let trimmed_string = message
-        .lines()
-        .map(|line| line.trim())
-        .collect::<Vec<&str>>()
-        .join("\n");
+    let user_id = event_value["user"]["id"].as_str().unwrap_or_default();
+    let authorized = authorized_slack_users
+        .iter()
+        .any(|allowed| allowed == user_id);
+    if !authorized {
+        return Err(SlackActionError::UnauthorizedUser(user_id.to_string()));
+    }
 
-    trimmed_string
+    Ok(())
 }
 
-fn parse_slack_action(
-    block_actions_event: SlackInteractionBlockActionsEvent,
-) -> Result<(Url, String, ReportRequest, Option<Report>), AppError> {
-    let event_value = serde_json::to_value(block_actions_event)
-        .map_err(|e| anyhow!("Failed to convert block_actions_event to Value: {:?}", e))?;
+// Long content is cut in the reportedEvent block, so the event no longer
+// verifies. The whole event is fetched again from our relays instead
+async fn fetch_truncated_event(
+    message_dispatcher: &ActorRef<SupervisorMessage>,
+    event_value: &Value,
+) -> Result<Option<Event>, SlackActionError> {
+    let Some(embedded_event) = find_block_id(event_value, "reportedEvent")
+        .and_then(|reported_event_value| Event::from_json(reported_event_value).ok())
+        .filter(is_truncated_event)
+    else {
+        return Ok(None);
+    };
 
-    let response_url = event_value["response_url"]
-        .as_str()
-        .ok_or_else(|| anyhow!("Missing response_url"))?
-        .parse::<Url>()
-        .map_err(|_| anyhow!("Invalid response_url"))?;
+    match call_t!(
+        message_dispatcher,
+        SupervisorMessage::FindEvent,
+        FIND_EVENT_TIMEOUT_MS,
+        embedded_event.id
+    ) {
+        Ok(Ok(Some(full_event))) => Ok(Some(full_event)),
+        Ok(Ok(None)) => Err(SlackActionError::TruncatedEventUnavailable(
+            "not found on our relays".to_string(),
+        )),
+        Ok(Err(e)) => Err(SlackActionError::TruncatedEventUnavailable(e)),
+        Err(e) => Err(SlackActionError::TruncatedEventUnavailable(e.to_string())),
+    }
+}
+
+// The full event replaces the truncated one of the reportedEvent block
+fn parse_slack_action(
+    event_value: &Value,
+    full_event: Option<Event>,
+) -> Result<(Url, String, ReportRequest, ModeratorChoice), SlackActionError> {
+    let response_url = response_url(event_value).ok_or(SlackActionError::MissingResponseUrl)?;
 
     let slack_username = event_value["user"]["username"]
         .as_str()
-        .ok_or_else(|| anyhow!("Missing username"))?;
+        .ok_or(SlackActionError::MissingUsername)?;
 
     let action_value = event_value["actions"][0]["value"]
         .as_str()
@@ -274,47 +745,117 @@ fn parse_slack_action(
 
     let action_id = event_value["actions"][0]["action_id"]
         .as_str()
-        .ok_or_else(|| anyhow!("Missing action_id"))?;
-
-    let reported_event_value = find_block_id(&event_value, "reportedEvent")?;
-    let reported_pubkey = find_block_id(&event_value, "reportedPubkey")?;
-    let reporter_text = find_block_id(&event_value, "reporterText")?;
-
-    let target = match reported_event_value {
-        None => match reported_pubkey {
-            None => {
-                return Err(AppError::slack_parsing_error(
-                    "neither reportedEvent nor reportedPubkey present",
-                ));
-            }
-            Some(reported_pubkey_value) => {
-                let reported_pubkey = PublicKey::from_hex(reported_pubkey_value)
-                    .map_err(|_| AppError::slack_parsing_error("reported_pubkey"))?;
-                ReportTarget::Pubkey(reported_pubkey)
-            }
-        },
-        Some(reported_event_value) => {
-            let reported_event = Event::from_json(reported_event_value)
-                .map_err(|_| AppError::slack_parsing_error("reported_event"))?;
-            ReportTarget::Event(reported_event)
+        .ok_or(SlackActionError::MissingActionId)?;
+
+    let reported_event_value = find_block_id(event_value, "reportedEvent");
+    let reported_pubkey = find_block_id(event_value, "reportedPubkey");
+    let reporter_text = find_block_id(event_value, "reporterText");
+
+    let reported_event = match full_event {
+        Some(full_event) => Some(full_event),
+        None => reported_event_value
+            .map(|reported_event_value| {
+                Event::from_json(reported_event_value)
+                    .map_err(|e| SlackActionError::InvalidReportedEvent(e.to_string()))
+            })
+            .transpose()?,
+    };
+    let reported_pubkey = reported_pubkey
+        .map(|reported_pubkey_value| {
+            PublicKey::from_hex(&reported_pubkey_value)
+                .map_err(|_| SlackActionError::InvalidReportedPubkey(reported_pubkey_value))
+        })
+        .transpose()?;
+    // Messages of combined targets have both blocks
+    let target = match (reported_event, reported_pubkey) {
+        (Some(reported_event), None) => ReportTarget::Event(reported_event),
+        (None, Some(reported_pubkey)) => ReportTarget::Pubkey(reported_pubkey),
+        (Some(reported_event), Some(reported_pubkey)) => {
+            ReportTarget::EventAndPubkey(reported_event, reported_pubkey)
         }
+        (None, None) => return Err(SlackActionError::MissingTarget),
     };
 
     let reporter_pubkey = PublicKey::from_hex(action_value)
-        .map_err(|_| AppError::slack_parsing_error("reporter_pubkey"))?;
-
-    let report_request = ReportRequest::new(target, reporter_pubkey, reporter_text);
-    let maybe_category = Report::from_str(action_id).ok();
+        .map_err(|_| SlackActionError::InvalidReporterPubkey(action_value.to_string()))?;
+
+    let report_request = ReportRequest::builder()
+        .target(target)
+        .reporter_pubkey(reporter_pubkey)
+        .reporter_text(reporter_text)
+        .build()
+        .map_err(|e| SlackActionError::InvalidReportRequest(e.to_string()))?;
+    let choice = ModeratorChoice {
+        category: ModerationCategory::from_str(action_id).ok(),
+        report_type: selected_report_type(event_value),
+    };
 
     Ok((
         response_url,
         slack_username.to_string(),
         report_request,
-        maybe_category,
+        choice,
     ))
 }
 
-fn find_block_id(event_value: &Value, block_id_text: &str) -> Result<Option<String>, AppError> {
+// The report type select keeps its option in the message state, unset means
+// the one of the category
+fn selected_report_type(event_value: &Value) -> Option<Report> {
+    let value = event_value["state"]["values"][REPORT_TYPE_BLOCK_ID][REPORT_TYPE_ACTION_ID]
+        ["selected_option"]["value"]
+        .as_str()?;
+
+    Report::from_str(value).ok()
+}
+
+fn retract_block(report_id: EventId) -> Value {
+    json!({
+        "type": "actions",
+        "elements": [{
+            "type": "button",
+            "action_id": RETRACT_ACTION_ID,
+            "text": { "type": "plain_text", "text": "Retract" },
+            "style": "danger",
+            "value": report_id.to_hex(),
+            "confirm": {
+                "title": { "type": "plain_text", "text": "Retract this report?" },
+                "text": { "type": "plain_text", "text": "A deletion request will be published for it." },
+                "confirm": { "type": "plain_text", "text": "Retract" },
+                "deny": { "type": "plain_text", "text": "Cancel" }
+            }
+        }]
+    })
+}
+
+// None unless the retract button was clicked
+fn parse_retract_action(event_value: &Value) -> Option<Result<EventId, SlackActionError>> {
+    if event_value["actions"][0]["action_id"].as_str() != Some(RETRACT_ACTION_ID) {
+        return None;
+    }
+
+    let action_value = event_value["actions"][0]["value"]
+        .as_str()
+        .unwrap_or_default();
+
+    Some(
+        EventId::from_hex(action_value)
+            .map_err(|_| SlackActionError::InvalidReportId(action_value.to_string())),
+    )
+}
+
+// Other reporters of an aggregated message, none for single requests
+fn parse_co_reporters(event_value: &Value) -> Vec<PublicKey> {
+    let Some(co_reporters) = find_block_id(event_value, CO_REPORTERS_BLOCK_ID) else {
+        return Vec::new();
+    };
+
+    co_reporters
+        .split_whitespace()
+        .filter_map(|co_reporter| PublicKey::from_hex(co_reporter).ok())
+        .collect()
+}
+
+fn find_block_id(event_value: &Value, block_id_text: &str) -> Option<String> {
     let reported_event_value = event_value["message"]["blocks"]
         .as_array()
         .and_then(|blocks| {
@@ -339,20 +880,42 @@ fn find_block_id(event_value: &Value, block_id_text: &str) -> Result<Option<Stri
             })
         });
 
-    Ok(reported_event_value.map(|s| s.to_string()))
+    reported_event_value.map(|s| s.to_string())
 }
 
-async fn send_slack_response(response_url: &str, response_text: &str) -> Result<()> {
+// Published reports get a button to retract them, every resolved message
+// starts with who resolved it and how
+async fn send_slack_response(
+    http_client: &ReqwestClient,
+    response_url: &str,
+    response_text: &str,
+    maybe_report_id: Option<EventId>,
+    resolution: &Resolution,
+) -> Result<()> {
     debug!("Sending response to slack: {:?}", response_text);
-    let client = ReqwestClient::new();
 
-    let res = client
+    // The text is kept as the notification fallback, the blocks are what is
+    // rendered and each one must stay under the Slack size limit
+    let mut blocks = vec![json!({
+        "type": "context",
+        "elements": [{ "type": "mrkdwn", "text": format!("✅ {}", resolution) }]
+    })];
+    blocks.extend(markdown_sections(response_text).into_iter().map(|section| {
+        json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": section }
+        })
+    }));
+    blocks.extend(maybe_report_id.map(retract_block));
+
+    let res = http_client
         .post(response_url)
         .header("Content-Type", "application/json")
         .body(
             json!({
                 "replace_original": "true",
                 "text": response_text,
+                "blocks": blocks,
             })
             .to_string(),
         )
@@ -368,6 +931,43 @@ async fn send_slack_response(response_url: &str, response_text: &str) -> Result<
     Ok(())
 }
 
+// The original message is kept so they can try again
+async fn send_slack_error(
+    http_client: &ReqwestClient,
+    response_url: &str,
+    error: &SlackActionError,
+) {
+    send_slack_note(
+        http_client,
+        response_url,
+        &format!("⚠️ Couldn't process this action: {}.", error),
+    )
+    .await;
+}
+
+// Only shown to the moderator that clicked, the original message is kept
+async fn send_slack_note(http_client: &ReqwestClient, response_url: &str, text: &str) {
+    let res = http_client
+        .post(response_url)
+        .header("Content-Type", "application/json")
+        .body(
+            json!({
+                "response_type": "ephemeral",
+                "replace_original": false,
+                "text": text,
+            })
+            .to_string(),
+        )
+        .send()
+        .await;
+
+    match res {
+        Ok(res) if res.status().is_success() => {}
+        Ok(res) => error!("Failed to send slack note. Status: {}", res.status()),
+        Err(e) => error!("Failed to send slack note: {}", e),
+    }
+}
+
 fn slack_error_handler(
     err: Box<dyn std::error::Error + Send + Sync>,
     _client: Arc<SlackHyperClient>,
@@ -381,16 +981,26 @@ fn slack_error_handler(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::actors::utilities::resolved_reports::ResolvedReports;
     use crate::actors::TestActor;
+    use crate::adapters::decision_store::InMemoryDecisionStore;
+    use crate::adapters::slack_client_adapter::{CategoryButtons, SeverityTiersConfig};
+    use crate::domain_objects::truncated_event;
     use axum::{
         body::Body,
-        http::{Request, StatusCode},
+        http::{header::RETRY_AFTER, Request, StatusCode},
+        response::IntoResponse,
     };
     use handlebars::Handlebars;
     use http_body_util::BodyExt;
+    use ractor::{Actor, ActorProcessingErr};
     use serde_json::json;
+    use std::time::Duration;
+    use tokio::sync::Mutex;
     use tower::ServiceExt;
 
+    const TEST_SIGNING_SECRET: &str = "8f742231b10e8888abcd99b1e7d785a5";
+
     #[tokio::test]
     async fn test_fails_with_empty_request() {
         let (test_actor_ref, _receiver_actor_handle) =
@@ -400,10 +1010,16 @@ mod tests {
         let state = WebAppState {
             event_dispatcher: test_actor_ref,
             hb: Arc::new(Handlebars::new()),
+            decision_store: None,
+            slack_templates: Arc::new(SlackTemplates::default()),
+            http_client: ReqwestClient::new(),
         };
 
         let router = slack_interactions_route(&Config {
-            signing_secret: String::new().into(),
+            signing_secret: TEST_SIGNING_SECRET.to_string().into(),
+            workspaces: vec![],
+            response_url_hosts: default_response_url_hosts(),
+            authorized_slack_users: vec![],
         })
         .unwrap()
         .with_state(state);
@@ -423,6 +1039,78 @@ mod tests {
         assert!(body.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_mounts_a_route_per_workspace() {
+        let (test_actor_ref, _receiver_actor_handle) =
+            TestActor::<SupervisorMessage>::spawn_default()
+                .await
+                .unwrap();
+        let state = WebAppState {
+            event_dispatcher: test_actor_ref,
+            hb: Arc::new(Handlebars::new()),
+            decision_store: None,
+            slack_templates: Arc::new(SlackTemplates::default()),
+            http_client: ReqwestClient::new(),
+        };
+
+        let router = slack_interactions_route(&Config {
+            signing_secret: TEST_SIGNING_SECRET.to_string().into(),
+            workspaces: vec![WorkspaceConfig {
+                name: "partner".to_string(),
+                signing_secret: TEST_SIGNING_SECRET.to_string().into(),
+            }],
+            response_url_hosts: default_response_url_hosts(),
+            authorized_slack_users: vec![],
+        })
+        .unwrap()
+        .with_state(state);
+
+        for (uri, status) in [
+            ("/slack/partner/interactions", StatusCode::BAD_REQUEST),
+            ("/slack/unknown/interactions", StatusCode::NOT_FOUND),
+        ] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(uri)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), status);
+        }
+    }
+
+    #[test]
+    fn test_validates_signing_secrets() {
+        let config = |signing_secret: &str, workspace_signing_secret: &str| Config {
+            signing_secret: signing_secret.to_string().into(),
+            workspaces: vec![WorkspaceConfig {
+                name: "partner".to_string(),
+                signing_secret: workspace_signing_secret.to_string().into(),
+            }],
+            response_url_hosts: default_response_url_hosts(),
+            authorized_slack_users: vec![],
+        };
+
+        assert!(config(TEST_SIGNING_SECRET, TEST_SIGNING_SECRET)
+            .validate()
+            .is_ok());
+        assert!(config(config::NOT_SET, TEST_SIGNING_SECRET)
+            .validate()
+            .is_ok());
+        assert!(config(" ", TEST_SIGNING_SECRET).validate().is_err());
+
+        let error = config(TEST_SIGNING_SECRET, "xoxb-1234")
+            .validate()
+            .unwrap_err();
+        assert!(error.to_string().contains("partner workspace"), "{}", error);
+    }
+
     #[test]
     fn test_parse_slack_action_with_hateful() {
         let reporter_pubkey = Keys::generate().public_key();
@@ -442,15 +1130,16 @@ mod tests {
             &reported_event,
         );
 
-        let (response_url, username, parsed_report_request, maybe_moderated_report) =
-            parse_slack_action(slack_actions_event).unwrap();
+        let (response_url, username, parsed_report_request, choice) =
+            parse_slack_action(&serde_json::to_value(slack_actions_event).unwrap(), None).unwrap();
 
         assert_eq!(
             response_url,
             Url::parse("https://hooks.slack.com/foobar").unwrap()
         );
         assert_eq!(username, "daniel");
-        assert!(maybe_moderated_report.is_some());
+        assert_eq!(choice.category, Some(ModerationCategory::Nudity));
+        assert_eq!(choice.report_type, None);
         assert_eq!(parsed_report_request.target(), &reported_event.into());
         assert_eq!(parsed_report_request.reporter_pubkey(), &reporter_pubkey);
         assert_eq!(
@@ -478,15 +1167,15 @@ mod tests {
             &reported_event,
         );
 
-        let (response_url, username, parsed_report_request, maybe_moderated_report) =
-            parse_slack_action(slack_actions_event).unwrap();
+        let (response_url, username, parsed_report_request, choice) =
+            parse_slack_action(&serde_json::to_value(slack_actions_event).unwrap(), None).unwrap();
 
         assert_eq!(
             response_url,
             Url::parse("https://hooks.slack.com/foobar").unwrap()
         );
         assert_eq!(username, "daniel");
-        assert!(maybe_moderated_report.is_none());
+        assert_eq!(choice, ModeratorChoice::default());
         assert_eq!(parsed_report_request.target(), &reported_event.into());
         assert_eq!(parsed_report_request.reporter_pubkey(), &reporter_pubkey);
         assert_eq!(
@@ -495,6 +1184,543 @@ mod tests {
         );
     }
 
+    fn slack_action_value() -> Value {
+        let reported_event = EventBuilder::text_note("I'm so nude I'm freezing", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        serde_json::to_value(create_slack_actions_event(
+            "daniel",
+            "nudity",
+            &Keys::generate().public_key(),
+            &None,
+            &reported_event,
+        ))
+        .unwrap()
+    }
+
+    fn set_block_text(event_value: &mut Value, block_id: &str, text: &str) {
+        let blocks = event_value["message"]["blocks"].as_array_mut().unwrap();
+        let block = blocks
+            .iter_mut()
+            .find(|block| block["block_id"] == block_id)
+            .unwrap();
+        block["elements"][0]["elements"][0]["text"] = text.into();
+    }
+
+    #[test]
+    fn test_parse_slack_action_with_a_report_type() {
+        let mut event_value = slack_action_value();
+        event_value["state"] = json!({
+            "values": {
+                REPORT_TYPE_BLOCK_ID: {
+                    REPORT_TYPE_ACTION_ID: {
+                        "type": "static_select",
+                        "selected_option": {
+                            "text": { "type": "plain_text", "text": "illegal" },
+                            "value": "illegal"
+                        }
+                    }
+                }
+            }
+        });
+
+        let (_, _, _, choice) = parse_slack_action(&event_value, None).unwrap();
+
+        assert_eq!(
+            choice,
+            ModeratorChoice {
+                category: Some(ModerationCategory::Nudity),
+                report_type: Some(Report::Illegal),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_slack_action_from_severity_grouped_buttons() {
+        let reporter_pubkey = Keys::generate().public_key();
+        let category_buttons = CategoryButtons::new(
+            25,
+            &SeverityTiersConfig {
+                high: vec!["illegal".to_string()],
+                low: vec!["spam".to_string()],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let buttons: Vec<Value> = category_buttons
+            .blocks(&reporter_pubkey, None)
+            .iter()
+            .map(|block| serde_json::to_value(block).unwrap())
+            .filter(|block| block["type"] == "actions")
+            .flat_map(|block| block["elements"].as_array().unwrap().clone())
+            .collect();
+        assert_eq!(buttons.len(), ModerationCategory::ALL.len() + 1);
+
+        for button in buttons {
+            let mut event_value = slack_action_value();
+            event_value["actions"][0]["action_id"] = button["action_id"].clone();
+            event_value["actions"][0]["value"] = button["value"].clone();
+
+            let (_, _, report_request, choice) = parse_slack_action(&event_value, None).unwrap();
+
+            assert_eq!(report_request.reporter_pubkey(), &reporter_pubkey);
+            assert_eq!(
+                choice.category.map(|category| category.slug()),
+                button["action_id"]
+                    .as_str()
+                    .filter(|action_id| *action_id != "skip")
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_slack_action_missing_response_url() {
+        let mut event_value = slack_action_value();
+        event_value["response_url"] = Value::Null;
+
+        assert_eq!(
+            parse_slack_action(&event_value, None).unwrap_err(),
+            SlackActionError::MissingResponseUrl
+        );
+    }
+
+    #[test]
+    fn test_slack_response_url_is_allowed() {
+        let event_value = slack_action_value();
+
+        assert_eq!(
+            check_response_url(&event_value, &default_response_url_hosts()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_other_response_url_hosts_are_rejected() {
+        for response_url in [
+            "https://169.254.169.254/latest/meta-data",
+            "https://hooks.slack.com.evil.example/foobar",
+            "http://hooks.slack.com/foobar",
+        ] {
+            let mut event_value = slack_action_value();
+            event_value["response_url"] = response_url.into();
+
+            assert!(matches!(
+                check_response_url(&event_value, &default_response_url_hosts()),
+                Err(SlackActionError::DisallowedResponseUrl(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_everyone_is_authorized_without_an_allowlist() {
+        let event_value = slack_action_value();
+
+        assert_eq!(check_slack_user(&event_value, &[]), Ok(()));
+    }
+
+    #[test]
+    fn test_only_allowlisted_slack_users_are_authorized() {
+        let mut event_value = slack_action_value();
+        event_value["user"]["id"] = "U_MODERATOR".into();
+        let authorized_slack_users = vec!["U_MODERATOR".to_string()];
+
+        assert_eq!(
+            check_slack_user(&event_value, &authorized_slack_users),
+            Ok(())
+        );
+
+        event_value["user"]["id"] = "U_SOMEONE".into();
+        assert_eq!(
+            check_slack_user(&event_value, &authorized_slack_users),
+            Err(SlackActionError::UnauthorizedUser("U_SOMEONE".to_string()))
+        );
+
+        event_value["user"]["id"] = Value::Null;
+        assert!(check_slack_user(&event_value, &authorized_slack_users).is_err());
+    }
+
+    #[test]
+    fn test_parse_slack_action_missing_target() {
+        let mut event_value = slack_action_value();
+        event_value["message"]["blocks"]
+            .as_array_mut()
+            .unwrap()
+            .retain(|block| block["block_id"] != "reportedEvent");
+
+        let error = parse_slack_action(&event_value, None).unwrap_err();
+
+        assert_eq!(error, SlackActionError::MissingTarget);
+        assert!(error.to_string().contains("`reportedPubkey`"));
+    }
+
+    #[test]
+    fn test_parse_slack_action_with_event_and_pubkey() {
+        let mut event_value = slack_action_value();
+        let reported_event =
+            Event::from_json(find_block_id(&event_value, "reportedEvent").unwrap()).unwrap();
+        let reported_pubkey = reported_event.pubkey;
+        event_value["message"]["blocks"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({
+                "type": "context",
+                "block_id": "reportedPubkey",
+                "elements": [{ "type": "plain_text", "text": reported_pubkey.to_hex() }]
+            }));
+
+        let (_, _, report_request, _) = parse_slack_action(&event_value, None).unwrap();
+
+        let ReportTarget::EventAndPubkey(reported_event, pubkey) = report_request.target() else {
+            panic!(
+                "Expected a combined target, got {}",
+                report_request.target()
+            );
+        };
+        assert_eq!(reported_event.content, "I'm so nude I'm freezing");
+        assert_eq!(*pubkey, reported_pubkey);
+    }
+
+    #[test]
+    fn test_parse_slack_action_with_an_unrelated_pubkey() {
+        let mut event_value = slack_action_value();
+        event_value["message"]["blocks"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({
+                "type": "context",
+                "block_id": "reportedPubkey",
+                "elements": [{ "type": "plain_text", "text": Keys::generate().public_key().to_hex() }]
+            }));
+
+        let error = parse_slack_action(&event_value, None).unwrap_err();
+
+        assert!(matches!(error, SlackActionError::InvalidReportRequest(_)));
+    }
+
+    #[test]
+    fn test_parse_co_reporters() {
+        let mut event_value = slack_action_value();
+        assert!(parse_co_reporters(&event_value).is_empty());
+
+        let co_reporters = vec![Keys::generate().public_key(), Keys::generate().public_key()];
+        let co_reporters_text = co_reporters
+            .iter()
+            .map(|co_reporter| co_reporter.to_hex())
+            .collect::<Vec<_>>()
+            .join(" ");
+        event_value["message"]["blocks"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({
+                "type": "context",
+                "block_id": CO_REPORTERS_BLOCK_ID,
+                "elements": [{ "type": "plain_text", "text": co_reporters_text }]
+            }));
+
+        assert_eq!(parse_co_reporters(&event_value), co_reporters);
+    }
+
+    #[test]
+    fn test_parse_slack_action_invalid_reported_event() {
+        let mut event_value = slack_action_value();
+        set_block_text(&mut event_value, "reportedEvent", "not an event");
+
+        let error = parse_slack_action(&event_value, None).unwrap_err();
+
+        assert_eq!(error.reason(), "invalid_reported_event");
+    }
+
+    #[test]
+    fn test_parse_slack_action_with_a_truncated_event() {
+        let mut event_value = slack_action_value();
+        let reported_event =
+            Event::from_json(find_block_id(&event_value, "reportedEvent").unwrap()).unwrap();
+        let embedded_event = truncated_event(&reported_event, 8).unwrap();
+        set_block_text(&mut event_value, "reportedEvent", &embedded_event.as_json());
+
+        // Doesn't verify without the event fetched again
+        let error = parse_slack_action(&event_value, None).unwrap_err();
+        assert_eq!(error.reason(), "invalid_report_request");
+
+        let (_, _, report_request, _) =
+            parse_slack_action(&event_value, Some(reported_event.clone())).unwrap();
+        assert_eq!(report_request.target().event(), Some(&reported_event));
+    }
+
+    #[test]
+    fn test_parse_slack_action_invalid_reporter_pubkey() {
+        let mut event_value = slack_action_value();
+        event_value["actions"][0]["value"] = "skip".into();
+
+        let error = parse_slack_action(&event_value, None).unwrap_err();
+
+        assert_eq!(
+            error,
+            SlackActionError::InvalidReporterPubkey("skip".to_string())
+        );
+        assert!(error.to_string().contains("`skip`"));
+    }
+
+    #[test]
+    fn test_parse_retract_action() {
+        let mut event_value = slack_action_value();
+        assert!(parse_retract_action(&event_value).is_none());
+
+        let report_id = EventId::all_zeros();
+        event_value["actions"][0]["action_id"] = RETRACT_ACTION_ID.into();
+        event_value["actions"][0]["value"] = report_id.to_hex().into();
+        assert_eq!(parse_retract_action(&event_value), Some(Ok(report_id)));
+
+        event_value["actions"][0]["value"] = "nope".into();
+        assert_eq!(
+            parse_retract_action(&event_value),
+            Some(Err(SlackActionError::InvalidReportId("nope".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_parse_slack_action_missing_username() {
+        let mut event_value = slack_action_value();
+        event_value["user"]["username"] = Value::Null;
+
+        assert_eq!(
+            parse_slack_action(&event_value, None).unwrap_err(),
+            SlackActionError::MissingUsername
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stopped_supervisor_is_unavailable() {
+        let (test_actor_ref, receiver_actor_handle) =
+            TestActor::<SupervisorMessage>::spawn_default()
+                .await
+                .unwrap();
+        test_actor_ref.stop(None);
+        receiver_actor_handle.await.unwrap();
+
+        let decision_store = InMemoryDecisionStore::default();
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+
+        let error = slack_message(
+            test_actor_ref,
+            Some(Arc::new(decision_store.clone())),
+            &SlackTemplates::default(),
+            report_request,
+            ModeratorChoice::default(),
+            "daniel".to_string(),
+        )
+        .await
+        .unwrap_err();
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[RETRY_AFTER], "30");
+        assert!(decision_store.decisions.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_skipped_message_saves_decision() {
+        let (test_actor_ref, receiver_actor_handle) =
+            TestActor::<SupervisorMessage>::spawn_default()
+                .await
+                .unwrap();
+        let decision_store = InMemoryDecisionStore::default();
+        let reporter_pubkey = Keys::generate().public_key();
+        let reported_event = EventBuilder::text_note("This is not offensive", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let report_request =
+            ReportRequest::new(reported_event.clone().into(), reporter_pubkey, None);
+
+        slack_message(
+            test_actor_ref.clone(),
+            Some(Arc::new(decision_store.clone())),
+            &SlackTemplates::default(),
+            report_request,
+            ModeratorChoice::default(),
+            "daniel".to_string(),
+        )
+        .await
+        .unwrap();
+
+        test_actor_ref.stop(None);
+        receiver_actor_handle.await.unwrap();
+
+        let decisions = decision_store.decisions.lock().await;
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].report_id, None);
+        assert_eq!(decisions[0].category, None);
+        assert_eq!(decisions[0].moderator, "daniel");
+        assert_eq!(decisions[0].target_type(), "event");
+        assert_eq!(decisions[0].target_id(), reported_event.id.to_hex());
+        assert_eq!(decisions[0].reporter_pubkey, reporter_pubkey);
+    }
+
+    // Resolves like the supervisor does and keeps the published report ids.
+    // NIP-05 lookups are answered after the delay
+    struct ResolvingSupervisor;
+
+    #[ractor::async_trait]
+    impl Actor for ResolvingSupervisor {
+        type Msg = SupervisorMessage;
+        type State = (ResolvedReports, Arc<Mutex<Vec<EventId>>>, Duration);
+        type Arguments = (Arc<Mutex<Vec<EventId>>>, Duration);
+
+        async fn pre_start(
+            &self,
+            _: ActorRef<Self::Msg>,
+            (published, nip05_delay): Self::Arguments,
+        ) -> Result<Self::State, ActorProcessingErr> {
+            Ok((ResolvedReports::default(), published, nip05_delay))
+        }
+
+        async fn handle(
+            &self,
+            _: ActorRef<Self::Msg>,
+            message: Self::Msg,
+            (resolved_reports, published, nip05_delay): &mut Self::State,
+        ) -> Result<(), ActorProcessingErr> {
+            match message {
+                SupervisorMessage::Resolve(report_request, resolution, reply_port) => {
+                    let _ = reply_port.send(resolved_reports.resolve(
+                        &report_request,
+                        resolution,
+                        Timestamp::now(),
+                    ));
+                }
+                SupervisorMessage::Unresolve(report_request, resolution) => {
+                    resolved_reports.unresolve(&report_request, &resolution);
+                }
+                SupervisorMessage::GetNip05(_, reply_port) => {
+                    let nip05_delay = *nip05_delay;
+                    tokio::spawn(async move {
+                        tokio::time::sleep(nip05_delay).await;
+                        let _ = reply_port.send(Ok(None));
+                    });
+                }
+                SupervisorMessage::Publish(report) => published.lock().await.push(report.id()),
+                _ => {}
+            }
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_second_action_on_a_resolved_report_is_ignored() {
+        let published = Arc::new(Mutex::new(vec![]));
+        let (supervisor_ref, supervisor_handle) = Actor::spawn(
+            None,
+            ResolvingSupervisor,
+            (published.clone(), Duration::ZERO),
+        )
+        .await
+        .unwrap();
+        let decision_store = InMemoryDecisionStore::default();
+        let reported_event = EventBuilder::text_note("Buy my coin!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let report_request =
+            ReportRequest::new(reported_event.into(), Keys::generate().public_key(), None);
+        let slack_templates = SlackTemplates::default();
+        let act = |slack_username: &str, category| {
+            slack_message(
+                supervisor_ref.clone(),
+                Some(Arc::new(decision_store.clone())),
+                &slack_templates,
+                report_request.clone(),
+                ModeratorChoice {
+                    category,
+                    report_type: None,
+                },
+                slack_username.to_string(),
+            )
+        };
+
+        let first_outcome = act("daniel", Some(ModerationCategory::Spam)).await.unwrap();
+        // Clicked on the mirrored copy of the message
+        let second_outcome = act("martin", None).await.unwrap();
+
+        supervisor_ref.stop(None);
+        supervisor_handle.await.unwrap();
+
+        let daniel_resolution = Resolution {
+            moderator: "daniel".to_string(),
+            category: Some(ModerationCategory::Spam),
+        };
+        let SlackActionOutcome::Resolved {
+            report_id,
+            resolution,
+            ..
+        } = first_outcome
+        else {
+            panic!("First action wasn't resolved: {:?}", first_outcome);
+        };
+        assert_eq!(resolution, daniel_resolution);
+        assert_eq!(*published.lock().await, vec![report_id.unwrap()]);
+
+        let SlackActionOutcome::AlreadyResolved(earlier_resolution) = second_outcome else {
+            panic!("Second action wasn't ignored: {:?}", second_outcome);
+        };
+        assert_eq!(earlier_resolution, daniel_resolution);
+
+        let decisions = decision_store.decisions.lock().await;
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].moderator, "daniel");
+    }
+
+    #[tokio::test]
+    async fn test_timed_out_actions_can_be_retried() {
+        let published = Arc::new(Mutex::new(vec![]));
+        let nip05_delay = Duration::from_millis(100);
+        let (supervisor_ref, supervisor_handle) =
+            Actor::spawn(None, ResolvingSupervisor, (published.clone(), nip05_delay))
+                .await
+                .unwrap();
+        let reported_event = EventBuilder::text_note("Buy my coin!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let report_request =
+            ReportRequest::new(reported_event.into(), Keys::generate().public_key(), None);
+        let slack_templates = SlackTemplates::default();
+        let act = || {
+            slack_message(
+                supervisor_ref.clone(),
+                None,
+                &slack_templates,
+                report_request.clone(),
+                ModeratorChoice {
+                    category: Some(ModerationCategory::Spam),
+                    report_type: None,
+                },
+                "daniel".to_string(),
+            )
+        };
+
+        // Like the route timing out while the account links are looked up
+        assert!(tokio::time::timeout(Duration::from_millis(20), act())
+            .await
+            .is_err());
+        assert!(published.lock().await.is_empty());
+
+        let outcome = act().await.unwrap();
+
+        supervisor_ref.stop(None);
+        supervisor_handle.await.unwrap();
+
+        assert!(
+            matches!(outcome, SlackActionOutcome::Resolved { .. }),
+            "Retried action wasn't resolved: {:?}",
+            outcome
+        );
+        assert_eq!(published.lock().await.len(), 1);
+    }
+
     fn create_slack_actions_event(
         slack_username: &str,
         category_name: &str,
@@ -0,0 +1,274 @@
+use crate::actors::messages::SupervisorMessage;
+use crate::adapters::slack_markdown::code_blocks;
+use crate::adapters::slack_templates::{SlackTemplates, PROCESSED_TEMPLATE, SKIPPED_TEMPLATE};
+use crate::adapters::{njump_event_link, njump_npub_or_pubkey, njump_or_pubkey};
+use crate::config;
+use crate::domain_objects::{ModerationCategory, ReportRequest};
+use anyhow::Result;
+use futures::future::join_all;
+use metrics::counter;
+use nostr_sdk::prelude::*;
+use ractor::ActorRef;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::time::timeout;
+
+// The NIP-05 lookups of the accounts run at once, well within the timeout of
+// the Slack interactions route. Past it the accounts not looked up yet are
+// shown with their npub
+const ACCOUNT_LINKS_TIMEOUT_MS: u64 = 300;
+
+// Njump links, or plain pubkeys, of the accounts shown in the messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountLinks {
+    pub reporter: String,
+    pub reported: String,
+    // Only set when the event signer isn't the reported pubkey, like for
+    // reposts attributed to the reposted author
+    pub signer: Option<String>,
+}
+
+impl AccountLinks {
+    pub async fn resolve(
+        message_dispatcher: &ActorRef<SupervisorMessage>,
+        report_request: &ReportRequest,
+    ) -> Self {
+        let target = report_request.target();
+        let signer_pubkey =
+            (target.signer_pubkey() != target.pubkey()).then(|| target.signer_pubkey());
+        let pubkeys = [*report_request.reporter_pubkey(), target.pubkey()]
+            .into_iter()
+            .chain(signer_pubkey);
+
+        let deadline = Duration::from_millis(ACCOUNT_LINKS_TIMEOUT_MS);
+        let lookups = pubkeys.map(|pubkey| async move {
+            timeout(
+                deadline,
+                njump_or_pubkey(message_dispatcher.clone(), pubkey),
+            )
+            .await
+            .unwrap_or_else(|_| {
+                counter!("account_links_timeout").increment(1);
+                njump_npub_or_pubkey(pubkey)
+            })
+        });
+        let mut links = join_all(lookups).await.into_iter();
+
+        Self {
+            reporter: links.next().unwrap_or_default(),
+            reported: links.next().unwrap_or_default(),
+            signer: links.next(),
+        }
+    }
+}
+
+/// What the moderator did with the report request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlackDecision {
+    Processed {
+        category: ModerationCategory,
+        report_id: EventId,
+    },
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReportedEventSummary {
+    pub id: EventId,
+    pub link: String,
+    // Split in code blocks
+    pub content: String,
+}
+
+/// The message posted back to Slack once a moderator processed or skipped a
+/// report request, before it's rendered with the processed or skipped
+/// template. Free form text is already split in code blocks, the templates
+/// don't escape anything
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SlackReportMessage {
+    pub moderator: String,
+    pub reporter: String,
+    pub reported: String,
+    pub signer: Option<String>,
+    // The reporter text, split in code blocks
+    pub reason: Option<String>,
+    pub event: Option<ReportedEventSummary>,
+    #[serde(skip)]
+    pub decision: SlackDecision,
+}
+
+impl SlackReportMessage {
+    pub fn new(
+        slack_username: String,
+        report_request: &ReportRequest,
+        account_links: AccountLinks,
+        decision: SlackDecision,
+    ) -> Self {
+        let event = report_request
+            .target()
+            .event()
+            .map(|event| ReportedEventSummary {
+                id: event.id,
+                link: njump_event_link(event, config::reportinator::relays()),
+                content: code_blocks(&event.content).join("\n"),
+            });
+
+        Self {
+            moderator: slack_username,
+            reporter: account_links.reporter,
+            reported: account_links.reported,
+            signer: account_links.signer,
+            reason: report_request
+                .reporter_text()
+                .map(|text| code_blocks(text).join("\n")),
+            event,
+            decision,
+        }
+    }
+
+    /// What the templates can use, the category and report id are only there
+    /// for processed messages
+    pub fn context(&self) -> Result<Value> {
+        let mut context = serde_json::to_value(self)?;
+        if let SlackDecision::Processed {
+            category,
+            report_id,
+        } = &self.decision
+        {
+            context["category"] = json!(category.slug());
+            context["reportId"] = json!(report_id.to_hex());
+        }
+
+        Ok(context)
+    }
+
+    pub fn render(&self, slack_templates: &SlackTemplates) -> Result<String> {
+        let template = match self.decision {
+            SlackDecision::Processed { .. } => PROCESSED_TEMPLATE,
+            SlackDecision::Skipped => SKIPPED_TEMPLATE,
+        };
+
+        slack_templates.render(template, &self.context()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account_links(signer: Option<&str>) -> AccountLinks {
+        AccountLinks {
+            reporter: "https://njump.me/reporter@nos.social".to_string(),
+            reported: "https://njump.me/npub1reported".to_string(),
+            signer: signer.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_processed_event_report_message() {
+        let reported_event = EventBuilder::text_note("I hate you!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let report_request = ReportRequest::new(
+            reported_event.clone().into(),
+            Keys::generate().public_key(),
+            Some("This is hateful. Report it!".to_string()),
+        );
+        let report_id = EventId::all_zeros();
+
+        let message = SlackReportMessage::new(
+            "daniel".to_string(),
+            &report_request,
+            account_links(None),
+            SlackDecision::Processed {
+                category: ModerationCategory::Hate,
+                report_id,
+            },
+        );
+
+        assert_eq!(message.moderator, "daniel");
+        assert_eq!(message.reporter, "https://njump.me/reporter@nos.social");
+        assert_eq!(message.reported, "https://njump.me/npub1reported");
+        assert_eq!(message.signer, None);
+        assert_eq!(
+            message.reason.as_deref(),
+            Some("```This is hateful. Report it!```")
+        );
+        let event = message.event.as_ref().unwrap();
+        assert_eq!(event.id, reported_event.id);
+        assert_eq!(event.content, "```I hate you!!```");
+        assert!(event.link.starts_with("https://njump.me/nevent1"));
+        assert_eq!(
+            message.decision,
+            SlackDecision::Processed {
+                category: ModerationCategory::Hate,
+                report_id
+            }
+        );
+
+        let context = message.context().unwrap();
+        assert_eq!(context["category"], json!("hate"));
+        assert_eq!(context["reportId"], json!(report_id.to_hex()));
+        assert_eq!(context["event"]["id"], json!(reported_event.id.to_hex()));
+    }
+
+    #[test]
+    fn test_skipped_pubkey_report_message() {
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+
+        let message = SlackReportMessage::new(
+            "daniel".to_string(),
+            &report_request,
+            account_links(Some("https://njump.me/npub1signer")),
+            SlackDecision::Skipped,
+        );
+
+        assert_eq!(message.reason, None);
+        assert_eq!(message.event, None);
+        assert_eq!(
+            message.signer.as_deref(),
+            Some("https://njump.me/npub1signer")
+        );
+        assert_eq!(message.decision, SlackDecision::Skipped);
+
+        let context = message.context().unwrap();
+        assert!(context.get("category").is_none());
+        assert!(context.get("reportId").is_none());
+        assert_eq!(context["event"], Value::Null);
+    }
+
+    #[test]
+    fn test_renders_with_the_template_of_the_decision() {
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+        let message = |decision| {
+            SlackReportMessage::new(
+                "daniel".to_string(),
+                &report_request,
+                account_links(None),
+                decision,
+            )
+            .render(&SlackTemplates::default())
+            .unwrap()
+        };
+
+        let processed = message(SlackDecision::Processed {
+            category: ModerationCategory::Spam,
+            report_id: EventId::all_zeros(),
+        });
+        assert!(processed.contains("*Report Confirmed By:* daniel"));
+        assert!(processed.contains("*Categorized As:* `spam`"));
+
+        let skipped = message(SlackDecision::Skipped);
+        assert!(skipped.contains("*Report Skipped By:* daniel"));
+        assert!(!skipped.contains("Categorized As"));
+    }
+}
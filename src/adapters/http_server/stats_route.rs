@@ -0,0 +1,94 @@
+use axum::{routing::get, Json, Router};
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde_json::{Map, Value};
+
+// Counters exposed by /stats. Labeled counters are summed across labels.
+const STATS_COUNTERS: &[&str] = &[
+    "event_received",
+    "event_received_error",
+    "gift_wrap_error",
+    "report_rejected_low_pow",
+    "report_future_timestamp",
+    "report_unreportable_kind",
+    "report_stale_event",
+    "self_report",
+    "events_enqueued",
+    "events_enqueued_error",
+    "events_enqueued_invalid_signature",
+    "slack_write_message",
+    "slack_write_message_error",
+    "webhook_post",
+    "webhook_post_error",
+    "retry_exhausted",
+    "publish",
+    "publish_error",
+    "report_published",
+    "dead_letter_written",
+    "report_retracted",
+];
+
+pub fn stats_route(metrics_handle: PrometheusHandle) -> Router {
+    Router::new().route(
+        "/stats",
+        get(|| async move { Json(stats_snapshot(&metrics_handle.render())) }),
+    )
+}
+
+// The Prometheus handle only renders text, so the snapshot is taken from its
+// output. Counters that were never incremented are reported as zero.
+fn stats_snapshot(rendered_metrics: &str) -> Value {
+    let mut stats: Map<String, Value> = STATS_COUNTERS
+        .iter()
+        .map(|name| (name.to_string(), Value::from(0u64)))
+        .collect();
+
+    for line in rendered_metrics.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let Some((series, value)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        let name = series.split('{').next().unwrap_or_default();
+        let Some(total) = stats.get_mut(name) else {
+            continue;
+        };
+        let Ok(value) = value.parse::<f64>() else {
+            continue;
+        };
+
+        *total = Value::from(total.as_u64().unwrap_or_default() + value as u64);
+    }
+
+    Value::Object(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_stats_snapshot() {
+        let rendered_metrics = r#"
+# TYPE event_received counter
+event_received 12
+# TYPE gift_wrap_error counter
+gift_wrap_error{reason="wrong_kind"} 2
+gift_wrap_error{reason="decryption_failure"} 3
+# TYPE publish counter
+publish 4
+# TYPE actor_mailbox_len gauge
+actor_mailbox_len{actor="NotificationWriter"} 7
+"#;
+
+        let stats = stats_snapshot(rendered_metrics);
+
+        assert_eq!(stats["event_received"], json!(12));
+        assert_eq!(stats["gift_wrap_error"], json!(5));
+        assert_eq!(stats["publish"], json!(4));
+        assert_eq!(stats["publish_error"], json!(0));
+        assert!(stats.get("actor_mailbox_len").is_none());
+    }
+}
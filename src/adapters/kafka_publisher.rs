@@ -0,0 +1,212 @@
+use crate::actors::PubsubPort;
+use crate::adapters::google_publisher::CONTENT_TRUNCATED_ATTRIBUTE;
+use crate::config::{self, Configurable};
+use crate::domain_objects::{ReportRequest, ReportTarget};
+use anyhow::{anyhow, bail, Context, Result};
+use rdkafka::config::ClientConfig;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::time::Duration;
+use tracing::info;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Comma separated list of brokers, as expected by `bootstrap.servers`
+    pub brokers: String,
+    pub topic: String,
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "kafka"
+    }
+}
+
+// The part of the Kafka client we use, so it can be faked in tests
+#[ractor::async_trait]
+pub trait KafkaProducer: Send + Sync + 'static {
+    async fn produce(
+        &self,
+        topic: &str,
+        key: &str,
+        payload: Vec<u8>,
+        headers: &[(&str, &str)],
+    ) -> Result<()>;
+}
+
+pub struct RdKafkaProducer {
+    producer: FutureProducer,
+}
+
+impl RdKafkaProducer {
+    pub fn create(config: &Config) -> Result<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", &config.brokers)
+            .set("message.timeout.ms", "5000")
+            .create::<FutureProducer>()
+            .context("Failed to create Kafka producer")?;
+
+        Ok(Self { producer })
+    }
+}
+
+#[ractor::async_trait]
+impl KafkaProducer for RdKafkaProducer {
+    async fn produce(
+        &self,
+        topic: &str,
+        key: &str,
+        payload: Vec<u8>,
+        headers: &[(&str, &str)],
+    ) -> Result<()> {
+        let headers = headers
+            .iter()
+            .fold(OwnedHeaders::new(), |owned_headers, (key, value)| {
+                owned_headers.insert(Header {
+                    key,
+                    value: Some(*value),
+                })
+            });
+        let record = FutureRecord::to(topic)
+            .key(key)
+            .payload(&payload)
+            .headers(headers);
+
+        self.producer
+            .send(record, Timeout::After(Duration::from_secs(5)))
+            .await
+            .map_err(|(e, _)| anyhow!("Failed to produce Kafka message: {}", e))?;
+
+        Ok(())
+    }
+}
+
+pub struct KafkaPublisher<P: KafkaProducer> {
+    producer: P,
+    topic: String,
+}
+
+impl KafkaPublisher<RdKafkaProducer> {
+    pub fn create(config: &Config) -> Result<Self> {
+        Ok(Self::new(
+            RdKafkaProducer::create(config)?,
+            config.topic.clone(),
+        ))
+    }
+}
+
+impl<P: KafkaProducer> KafkaPublisher<P> {
+    pub fn new(producer: P, topic: String) -> Self {
+        Self { producer, topic }
+    }
+}
+
+#[ractor::async_trait]
+impl<P: KafkaProducer> PubsubPort for KafkaPublisher<P> {
+    async fn publish_event(&mut self, report_request: &ReportRequest) -> Result<()> {
+        if let ReportTarget::Pubkey(_) = report_request.target() {
+            bail!("Cannot publish event with Pubkey target to Kafka")
+        }
+
+        let truncated = report_request
+            .with_truncated_content(config::reportinator::max_reported_content_chars());
+        let payload =
+            serde_json::to_vec(truncated.as_ref()).context("Failed to serialize event to JSON")?;
+        // Same marker as the Pub/Sub attribute, a truncated event no longer
+        // matches its signature
+        let headers: &[(&str, &str)] = match truncated {
+            Cow::Owned(_) => &[(CONTENT_TRUNCATED_ATTRIBUTE, "true")],
+            Cow::Borrowed(_) => &[],
+        };
+
+        // Keying by reporter keeps the requests of each reporter in order
+        // within a partition
+        let key = report_request.reporter_pubkey().to_hex();
+
+        self.producer
+            .produce(&self.topic, &key, payload, headers)
+            .await
+            .context("Failed to publish event")?;
+
+        info!("Event published successfully to Kafka");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_sdk::prelude::*;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct MockProducer {
+        produced: Arc<Mutex<Vec<(String, String, Vec<u8>, usize)>>>,
+    }
+
+    #[ractor::async_trait]
+    impl KafkaProducer for MockProducer {
+        async fn produce(
+            &self,
+            topic: &str,
+            key: &str,
+            payload: Vec<u8>,
+            headers: &[(&str, &str)],
+        ) -> Result<()> {
+            self.produced.lock().await.push((
+                topic.to_string(),
+                key.to_string(),
+                payload,
+                headers.len(),
+            ));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publishes_keyed_by_reporter() {
+        let producer = MockProducer::default();
+        let mut publisher = KafkaPublisher::new(producer.clone(), "reports".to_string());
+
+        let reporter_pubkey = Keys::generate().public_key();
+        let reported_event = EventBuilder::text_note("I hate you!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let report_request = ReportRequest::new(
+            reported_event.into(),
+            reporter_pubkey,
+            Some("Report it!".to_string()),
+        );
+
+        publisher.publish_event(&report_request).await.unwrap();
+
+        let produced = producer.produced.lock().await;
+        assert_eq!(produced.len(), 1);
+        let (topic, key, payload, headers) = &produced[0];
+        assert_eq!(topic, "reports");
+        assert_eq!(key, &reporter_pubkey.to_hex());
+        assert_eq!(payload, &serde_json::to_vec(&report_request).unwrap());
+        // Not truncated, so no marker
+        assert_eq!(*headers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_pubkey_targets() {
+        let producer = MockProducer::default();
+        let mut publisher = KafkaPublisher::new(producer.clone(), "reports".to_string());
+
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+
+        assert!(publisher.publish_event(&report_request).await.is_err());
+        assert!(producer.produced.lock().await.is_empty());
+    }
+}
@@ -0,0 +1,19 @@
+use crate::actors::PubsubPort;
+use crate::domain_objects::ReportRequest;
+use anyhow::Result;
+use tracing::info;
+
+/// Publishes nowhere, for dry runs of the whole pipeline
+#[derive(Debug, Clone, Default)]
+pub struct NoopPublisher;
+
+#[ractor::async_trait]
+impl PubsubPort for NoopPublisher {
+    async fn publish_event(&mut self, report_request: &ReportRequest) -> Result<()> {
+        info!(
+            "Dry run, not publishing report request {}",
+            report_request.target()
+        );
+        Ok(())
+    }
+}
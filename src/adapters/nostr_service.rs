@@ -1,19 +1,237 @@
 use crate::actors::messages::RelayEventDispatcherMessage;
-use crate::actors::NostrPort;
-use anyhow::Result;
+use crate::actors::{NostrPort, PublishOutcome};
+use crate::config::reportinator::RelayPolicy;
+use crate::config::Configurable;
+use anyhow::{bail, Context, Result};
 use futures::future::join_all;
+use metrics::{counter, histogram};
+use nostr_sdk::client::Error as ClientError;
 use nostr_sdk::prelude::*;
 use ractor::{cast, concurrency::Duration, ActorRef};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+// NIP-17 list of the relays where a user wants to receive DMs
+const DM_RELAY_LIST_KIND: u16 = 10050;
+// Below the per request limit of most relays
+const BACKFILL_PAGE_SIZE: usize = 500;
+// Added to publish_timeout_secs before giving up on the relays' answers
+const PUBLISH_TIMEOUT_MARGIN: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub filters: Vec<FilterConfig>,
+    // How long publishing waits for relays to confirm the event, independent
+    // of the connection timeout
+    #[serde(default = "default_publish_timeout_secs")]
+    pub publish_timeout_secs: u64,
+    // Relays for profile metadata and NIP-05 lookups. The subscription
+    // relays are used when empty
+    #[serde(default)]
+    pub metadata_relays: Vec<String>,
+    // Extra relays from the NIP-65 lists of the accounts tagged in what we
+    // publish, like the reported account or the reporter we acknowledge.
+    // Zero only publishes to our relays
+    #[serde(default)]
+    pub outbox_max_relays: usize,
+    #[serde(default = "default_outbox_cache_secs")]
+    pub outbox_cache_secs: u64,
+}
+
+fn default_publish_timeout_secs() -> u64 {
+    10
+}
+
+fn default_outbox_cache_secs() -> u64 {
+    3600
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "nostr"
+    }
+}
+
+/// One relay subscription filter. Authors accept hex or npub public keys.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilterConfig {
+    pub kinds: Vec<u16>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    // Unix timestamp
+    #[serde(default)]
+    pub since: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    // Only match events with a p tag for the reportinator public key, like
+    // the gift wraps sent to us
+    #[serde(default)]
+    pub tagging_reportinator: bool,
+}
+
+impl Config {
+    pub fn filters(&self, reportinator_public_key: PublicKey) -> Result<Vec<Filter>> {
+        self.filters_for(&[reportinator_public_key])
+    }
+
+    // While a rotated key is in its grace period, filters tagging the
+    // reportinator match both the old and the new public key
+    pub fn filters_for(&self, reportinator_public_keys: &[PublicKey]) -> Result<Vec<Filter>> {
+        if self.filters.is_empty() {
+            bail!("At least one nostr filter must be configured");
+        }
+
+        self.filters
+            .iter()
+            .map(|filter_config| filter_config.filter(reportinator_public_keys))
+            .collect()
+    }
+}
+
+impl FilterConfig {
+    fn filter(&self, reportinator_public_keys: &[PublicKey]) -> Result<Filter> {
+        // A filter without kinds would subscribe to everything
+        if self.kinds.is_empty() {
+            bail!("Nostr filters need at least one kind");
+        }
+
+        let authors = self
+            .authors
+            .iter()
+            .map(|author| {
+                PublicKey::from_str(author)
+                    .with_context(|| format!("Invalid filter author {}", author))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut filter = Filter::new().kinds(self.kinds.iter().copied().map(Kind::from));
+
+        if !authors.is_empty() {
+            filter = filter.authors(authors);
+        }
+        if let Some(since) = self.since {
+            filter = filter.since(Timestamp::from(since));
+        }
+        if let Some(limit) = self.limit {
+            filter = filter.limit(limit);
+        }
+        if self.tagging_reportinator {
+            filter = filter.pubkeys(reportinator_public_keys.iter().copied());
+        }
+
+        Ok(filter)
+    }
+}
+
+/// The NIP-65 relays of the accounts our events tag, up to max_relays in
+/// total and only the ones the relay policy allows. Lists are cached since
+/// the same reporters and targets keep coming back.
+struct OutboxRelays {
+    max_relays: usize,
+    cache_ttl: Duration,
+    cache: Mutex<HashMap<PublicKey, (Vec<Url>, Instant)>>,
+    relay_policy: RelayPolicy,
+}
+
+impl OutboxRelays {
+    fn new(config: &Config, relay_policy: RelayPolicy) -> Self {
+        Self {
+            max_relays: config.outbox_max_relays,
+            cache_ttl: Duration::from_secs(config.outbox_cache_secs),
+            cache: Mutex::new(HashMap::new()),
+            relay_policy,
+        }
+    }
+
+    fn cached(&self, public_key: &PublicKey, now: Instant) -> Option<Vec<Url>> {
+        let cache = self.cache.lock().expect("Outbox cache lock poisoned");
+        let (relays, fetched_at) = cache.get(public_key)?;
+        (now.duration_since(*fetched_at) < self.cache_ttl).then(|| relays.clone())
+    }
+
+    fn cache(&self, public_key: PublicKey, relays: Vec<Url>, now: Instant) {
+        let mut cache = self.cache.lock().expect("Outbox cache lock poisoned");
+        cache.retain(|_, (_, fetched_at)| now.duration_since(*fetched_at) < self.cache_ttl);
+        cache.insert(public_key, (relays, now));
+    }
+
+    // Relays we are already connected to are skipped, the event reaches
+    // them anyway
+    async fn relays_for<F, Fut>(
+        &self,
+        public_keys: &[PublicKey],
+        own_relays: &[Url],
+        lookup: F,
+    ) -> Vec<Url>
+    where
+        F: Fn(PublicKey) -> Fut,
+        Fut: Future<Output = Vec<Url>>,
+    {
+        let mut outbox_relays = Vec::new();
+        for public_key in public_keys {
+            if outbox_relays.len() >= self.max_relays {
+                break;
+            }
+
+            let relays = match self.cached(public_key, Instant::now()) {
+                Some(relays) => relays,
+                None => {
+                    let relays = lookup(*public_key).await;
+                    self.cache(*public_key, relays.clone(), Instant::now());
+                    relays
+                }
+            };
+
+            for relay in relays {
+                if outbox_relays.len() >= self.max_relays {
+                    break;
+                }
+                if !own_relays.contains(&relay)
+                    && !outbox_relays.contains(&relay)
+                    && self.relay_policy.check(relay.as_str()).is_ok()
+                {
+                    outbox_relays.push(relay);
+                }
+            }
+        }
+
+        outbox_relays
+    }
+}
 
 #[derive(Clone)]
 pub struct NostrService {
-    filters: Vec<Filter>,
+    config: Config,
+    // Shared with the subscription tasks, replaced when the key is rotated
+    filters: Arc<RwLock<Vec<Filter>>>,
     client: Client,
+    // Only set when metadata relays are configured
+    metadata_client: Option<Client>,
+    publish_timeout: Duration,
+    outbox: Arc<OutboxRelays>,
+    // Also checked for the relays added at runtime
+    relay_policy: RelayPolicy,
 }
 impl NostrService {
-    pub async fn create(relays: Vec<String>, filters: Vec<Filter>) -> Result<Self> {
+    /// Relays the policy doesn't allow are skipped, it's an error if none of
+    /// the relays or none of the metadata relays remain
+    pub async fn create(
+        relays: Vec<String>,
+        config: Config,
+        reportinator_public_key: PublicKey,
+        relay_policy: RelayPolicy,
+    ) -> Result<Self> {
+        let filters = config.filters(reportinator_public_key)?;
+        let relays = relay_policy.filter(relays)?;
+        let publish_timeout = Duration::from_secs(config.publish_timeout_secs);
+
         let opts = Options::new()
             .skip_disconnected_relays(true)
             .wait_for_send(false)
@@ -21,56 +239,450 @@ impl NostrService {
             .send_timeout(Some(Duration::from_secs(5)))
             .wait_for_subscription(true);
 
-        let client = ClientBuilder::new().opts(opts).build();
+        let client = ClientBuilder::new().opts(opts.clone()).build();
         for relay in relays.iter().cloned() {
             client.add_relay(relay).await?;
         }
 
-        Ok(Self { client, filters })
+        let metadata_client = if config.metadata_relays.is_empty() {
+            None
+        } else {
+            let metadata_client = ClientBuilder::new().opts(opts).build();
+            for relay in relay_policy.filter(config.metadata_relays.clone())? {
+                metadata_client.add_relay(relay).await?;
+            }
+            Some(metadata_client)
+        };
+
+        Ok(Self {
+            outbox: Arc::new(OutboxRelays::new(&config, relay_policy.clone())),
+            config,
+            filters: Arc::new(RwLock::new(filters)),
+            client,
+            metadata_client,
+            publish_timeout,
+            relay_policy,
+        })
+    }
+
+    fn filters(&self) -> Vec<Filter> {
+        self.filters.read().expect("Filters lock poisoned").clone()
+    }
+
+    fn metadata_client(&self) -> &Client {
+        self.metadata_client.as_ref().unwrap_or(&self.client)
+    }
+
+    fn send_options(&self) -> RelaySendOptions {
+        RelaySendOptions::new()
+            .skip_disconnected(true)
+            .skip_send_confirmation(false)
+            .timeout(Some(self.publish_timeout))
+    }
+
+    // Relays of other users are held to the relay policy like ours, the ones
+    // it doesn't allow are skipped
+    fn allowed_relays(&self, relays: impl IntoIterator<Item = String>) -> Vec<String> {
+        relays
+            .into_iter()
+            .filter(|relay| match self.relay_policy.check(relay) {
+                Ok(()) => true,
+                Err(e) => {
+                    counter!("relay_policy_skipped").increment(1);
+                    debug!("Skipping relay {}: {}", relay, e);
+                    false
+                }
+            })
+            .collect()
+    }
+
+    // Relays from the latest kind 10050 list of the user the relay policy
+    // allows, if any
+    async fn dm_relays(&self, public_key: PublicKey) -> Vec<String> {
+        let filter = Filter::new()
+            .kind(Kind::from(DM_RELAY_LIST_KIND))
+            .author(public_key)
+            .limit(1);
+
+        let events = match self
+            .client
+            .get_events_of(vec![filter], Some(Duration::from_secs(5)))
+            .await
+        {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Failed to get DM relays of {}: {}", public_key, e);
+                return vec![];
+            }
+        };
+
+        let dm_relays = events
+            .into_iter()
+            .max_by_key(|event| event.created_at)
+            .map(|event| {
+                event
+                    .tags
+                    .iter()
+                    .filter_map(|tag| match tag.as_vec() {
+                        [name, url, ..] if name == "relay" => Some(url.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        self.allowed_relays(dm_relays)
+    }
+
+    // Write relays from the latest kind 10002 list of the user the relay
+    // policy allows, no more than the outbox uses
+    async fn nip65_relays(&self, public_key: PublicKey) -> Vec<Url> {
+        let filter = Filter::new()
+            .kind(Kind::RelayList)
+            .author(public_key)
+            .limit(1);
+
+        let events = match self
+            .metadata_client()
+            .get_events_of(vec![filter], Some(Duration::from_secs(5)))
+            .await
+        {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Failed to get relay list of {}: {}", public_key, e);
+                return vec![];
+            }
+        };
+
+        let Some(event) = events.into_iter().max_by_key(|event| event.created_at) else {
+            return vec![];
+        };
+
+        write_relays(&event)
+            .filter(|url| self.relay_policy.check(url.as_str()).is_ok())
+            .take(self.outbox.max_relays)
+            .collect()
+    }
+
+    // A short lived client, so relays of other users never join the pool our
+    // subscriptions are sent to. Only to the relays the policy allows
+    async fn send_to(&self, event: Event, relays: Vec<String>) -> Result<()> {
+        let relays = self.allowed_relays(relays);
+        if relays.is_empty() {
+            bail!("None of the relays is allowed by reportinator.relay_policy");
+        }
+
+        let client = Client::default();
+        for relay in relays {
+            client.add_relay(relay).await?;
+        }
+        client.connect_with_timeout(Duration::from_secs(5)).await;
+
+        let result = client.pool().send_event(event, self.send_options()).await;
+
+        if let Err(e) = client.shutdown().await {
+            error!("Failed to shutdown client: {}", e);
+        }
+
+        result?;
+        Ok(())
+    }
+
+    // Best effort and in the background, our own relays already got it
+    fn publish_to_outbox(&self, event: Event) {
+        if self.outbox.max_relays == 0 {
+            return;
+        }
+
+        let nostr_service = self.clone();
+        tokio::spawn(async move {
+            let public_keys: Vec<PublicKey> = event.public_keys().copied().collect();
+            let own_relays: Vec<Url> = nostr_service.client.relays().await.into_keys().collect();
+            let outbox_relays = nostr_service
+                .outbox
+                .relays_for(&public_keys, &own_relays, |public_key| {
+                    nostr_service.nip65_relays(public_key)
+                })
+                .await;
+            if outbox_relays.is_empty() {
+                return;
+            }
+
+            let relay_count = outbox_relays.len();
+            let outbox_relays = outbox_relays.iter().map(Url::to_string).collect();
+            match nostr_service.send_to(event.clone(), outbox_relays).await {
+                Ok(()) => {
+                    counter!("outbox_published").increment(1);
+                    debug!("Event {} sent to {} outbox relays", event.id(), relay_count);
+                }
+                Err(e) => {
+                    counter!("outbox_publish_error").increment(1);
+                    warn!(
+                        "Failed to send event {} to outbox relays: {}",
+                        event.id(),
+                        e
+                    );
+                }
+            }
+        });
     }
 }
 
+// NIP-65 relays we can publish to for the author, the ones marked write or
+// not marked at all
+fn write_relays(relay_list: &Event) -> impl Iterator<Item = Url> + '_ {
+    relay_list
+        .tags
+        .iter()
+        .filter_map(|tag| match tag.as_vec() {
+            [name, url] if name == "r" => Url::parse(url).ok(),
+            [name, url, marker, ..] if name == "r" && marker == "write" => Url::parse(url).ok(),
+            _ => None,
+        })
+        .filter(|url| matches!(url.scheme(), "ws" | "wss"))
+}
+
 #[async_trait]
 impl NostrPort for NostrService {
     async fn connect(&self) -> Result<()> {
         self.client.connect().await;
+        if let Some(metadata_client) = &self.metadata_client {
+            metadata_client.connect().await;
+        }
         Ok(())
     }
 
     async fn reconnect(&self) -> Result<()> {
         self.client.disconnect().await?;
         self.client.connect().await;
+        if let Some(metadata_client) = &self.metadata_client {
+            metadata_client.disconnect().await?;
+            metadata_client.connect().await;
+        }
         Ok(())
     }
 
-    async fn publish(&self, event: Event) -> Result<()> {
-        self.client.send_event(event).await?;
+    async fn is_connected(&self) -> bool {
+        !all_disconnected(&self.client).await
+    }
+
+    async fn connected_relays(&self) -> (usize, usize) {
+        connected_relays(&self.client).await
+    }
+
+    async fn relay_connections(&self) -> Vec<(Url, bool)> {
+        let relays = self.client.pool().relays().await;
+
+        let futures: Vec<_> = relays
+            .iter()
+            .map(|(url, relay)| async move { (url.clone(), relay.is_connected().await) })
+            .collect();
+
+        join_all(futures).await
+    }
+
+    // The relays resubscribe by themselves once connected, so unlike
+    // reconnect the subscription doesn't need to be started again
+    async fn reconnect_relays(&self, relays: Vec<Url>) -> Result<()> {
+        let futures: Vec<_> = relays
+            .iter()
+            .map(|url| async move {
+                let relay = self.client.relay(url).await?;
+                relay.connect(Some(Duration::from_secs(5))).await;
+                Ok::<_, anyhow::Error>(())
+            })
+            .collect();
+
+        join_all(futures).await.into_iter().collect()
+    }
+
+    // Unlike the rest of the client sends, reports wait for the relays' OK so
+    // failures are known. Relays still silent after publish_timeout_secs, plus
+    // a margin for the relays' own timeouts to be reported first, are
+    // considered timed out
+    async fn publish(&self, event: Event) -> Result<PublishOutcome> {
+        let relays: Vec<Url> = self.client.relays().await.into_keys().collect();
+        let send = self
+            .client
+            .pool()
+            .send_event(event.clone(), self.send_options());
+
+        let deadline = self.publish_timeout + PUBLISH_TIMEOUT_MARGIN;
+        let outcome = match tokio::time::timeout(deadline, send).await {
+            Ok(output) => {
+                let output = output?;
+                publish_outcome(output.success, output.failed)
+            }
+            Err(_) => PublishOutcome {
+                timed_out: relays,
+                ..Default::default()
+            },
+        };
+
+        if outcome.is_published() {
+            self.publish_to_outbox(event);
+        }
+        Ok(outcome)
+    }
+
+    async fn publish_to(&self, event: Event, relays: Vec<String>) -> Result<()> {
+        self.send_to(event, relays).await
+    }
+
+    async fn publish_direct_message(&self, gift_wrap: Event, receiver: PublicKey) -> Result<()> {
+        let dm_relays = self.dm_relays(receiver).await;
+        if dm_relays.is_empty() {
+            counter!("direct_message_relays_unknown").increment(1);
+            info!(
+                "DM relays of {} unknown or not allowed, using ours",
+                receiver
+            );
+            self.publish(gift_wrap).await?.ensure_published()?;
+            return Ok(());
+        }
+
+        self.send_to(gift_wrap, dm_relays).await
+    }
+
+    async fn set_reportinator_public_keys(&self, public_keys: Vec<PublicKey>) -> Result<()> {
+        let filters = self.config.filters_for(&public_keys)?;
+        *self.filters.write().expect("Filters lock poisoned") = filters;
         Ok(())
     }
 
-    async fn get_nip05(&self, public_key: PublicKey) -> Option<String> {
-        let Some(metadata) = self.client.metadata(public_key).await.ok() else {
-            error!("Failed to get metadata for public key: {}", public_key);
-            return None;
+    // Accounts without metadata have no NIP-05, that's not a failure
+    async fn get_nip05(&self, public_key: PublicKey) -> Result<Option<String>> {
+        let metadata = match self.metadata_client().metadata(public_key).await {
+            Ok(metadata) => metadata,
+            Err(ClientError::MetadataNotFound) => {
+                info!("No metadata found for public key: {}", public_key);
+                return Ok(None);
+            }
+            Err(e) => {
+                error!("Failed to get metadata for public key: {}", public_key);
+                bail!("Failed to get metadata for {}: {}", public_key, e);
+            }
         };
 
         if let Some(nip05_value) = metadata.nip05 {
-            let Ok(verified) = nip05::verify(&public_key, &nip05_value, None).await else {
-                error!("Failed to verify Nip05 for public key: {}", public_key);
-                return None;
+            let verified = match nip05::verify(&public_key, &nip05_value, None).await {
+                Ok(verified) => verified,
+                Err(e) => {
+                    error!("Failed to verify Nip05 for public key: {}", public_key);
+                    bail!("Failed to verify the NIP-05 of {}: {}", public_key, e);
+                }
             };
 
             if !verified {
                 error!("Nip05 for public key: {} is not verified", public_key);
-                return None;
+                return Ok(None);
             }
 
             info!("Nip05 for public key: {} is: {}", public_key, nip05_value);
-            return Some(nip05_value);
+            return Ok(Some(nip05_value));
         }
 
         info!("No Nip05 found for public key: {}", public_key);
-        None
+        Ok(None)
+    }
+
+    async fn get_events(&self, filters: Vec<Filter>) -> Result<Vec<Event>> {
+        let events = self
+            .client
+            .get_events_of(filters, Some(Duration::from_secs(5)))
+            .await?;
+        Ok(events)
+    }
+
+    // Relays cap how many events a single request returns, so the range is
+    // walked back from until in pages. A page is asked again from the oldest
+    // timestamp it had, ids already seen are dropped.
+    async fn fetch_range(
+        &self,
+        since: Timestamp,
+        until: Timestamp,
+        limit: usize,
+    ) -> Result<Vec<Event>> {
+        let mut events = Vec::new();
+        let mut seen_ids = HashSet::new();
+        let mut page_until = until;
+
+        while events.len() < limit {
+            let page_size = (limit - events.len()).min(BACKFILL_PAGE_SIZE);
+            let filters = self
+                .filters()
+                .into_iter()
+                .map(|filter| filter.since(since).until(page_until).limit(page_size))
+                .collect();
+
+            let page = self
+                .client
+                .get_events_of(filters, Some(Duration::from_secs(10)))
+                .await?;
+            let Some(oldest) = page.iter().map(|event| event.created_at).min() else {
+                break;
+            };
+
+            let seen_before = seen_ids.len();
+            for event in page {
+                if seen_ids.insert(event.id) {
+                    events.push(event);
+                }
+            }
+
+            // Nothing new means the range is exhausted
+            if seen_ids.len() == seen_before {
+                break;
+            }
+            page_until = oldest;
+        }
+
+        events.sort_by_key(|event| std::cmp::Reverse(event.created_at));
+        events.truncate(limit);
+        Ok(events)
+    }
+
+    // Connecting can take up to the connection timeout, longer than the admin
+    // routes wait, so the relay is connected and subscribed in the background
+    async fn add_relay(&self, url: Url) -> Result<bool> {
+        self.relay_policy
+            .check(url.as_str())
+            .with_context(|| format!("{} is not allowed", url))?;
+        if !self.client.add_relay(url.clone()).await? {
+            return Ok(false);
+        }
+
+        let client = self.client.clone();
+        let filters = self.filters();
+        tokio::spawn(async move {
+            if let Err(e) = client.connect_relay(url.clone()).await {
+                error!("Failed to connect to relay {}: {}", url, e);
+                return;
+            }
+
+            // Only this relay, the subscriptions on the others are untouched.
+            // It's already in the pool, so reconnects subscribe to it too
+            if let Err(e) = client.subscribe_to([url.clone()], filters, None).await {
+                error!("Failed to subscribe on relay {}: {}", url, e);
+            }
+        });
+
+        Ok(true)
+    }
+
+    async fn remove_relay(&self, url: Url) -> Result<bool> {
+        let relays = self.client.relays().await;
+        if !relays.contains_key(&url) {
+            return Ok(false);
+        }
+
+        // We would never reconnect to anything
+        if relays.len() == 1 {
+            bail!("{} is the only relay left", url);
+        }
+
+        self.client.remove_relay(url).await?;
+        Ok(true)
     }
 
     async fn subscribe(
@@ -110,11 +722,14 @@ impl NostrPort for NostrService {
             return Ok(());
         }
 
-        info!("Subscribing to {:?}", &self.filters);
+        let filters = self.filters();
+        info!("Subscribing to {:?}", &filters);
         // If we ever have different type of subscriptions, we should separate
         // creation from handling. We can have a single handler for all subs.
         // See: https://github.com/rust-nostr/nostr/issues/345#issuecomment-1985925161
-        self.client.subscribe(self.filters.clone(), None).await?;
+        self.client.subscribe(filters, None).await?;
+        let subscribed_at = Instant::now();
+        let first_event_received = AtomicBool::new(false);
         self.client
             .handle_notifications(|notification| async {
                 if cancellation_token.is_cancelled() {
@@ -122,6 +737,11 @@ impl NostrPort for NostrService {
                 }
 
                 if let RelayPoolNotification::Event { event, .. } = notification {
+                    if !first_event_received.swap(true, Ordering::Relaxed) {
+                        histogram!("subscription_first_event_seconds")
+                            .record(subscribed_at.elapsed().as_secs_f64());
+                    }
+
                     cast!(
                         dispatcher_actor,
                         RelayEventDispatcherMessage::EventReceived(*event)
@@ -139,12 +759,419 @@ impl NostrPort for NostrService {
     }
 }
 
+// Relays only tell why they failed through the error message
+fn publish_outcome(success: HashSet<Url>, failed: HashMap<Url, Option<String>>) -> PublishOutcome {
+    let mut outcome = PublishOutcome {
+        accepted: success.into_iter().collect(),
+        ..Default::default()
+    };
+
+    for (url, reason) in failed {
+        let reason = reason.unwrap_or_else(|| "no reason given".to_string());
+        if is_timeout(&reason) {
+            outcome.timed_out.push(url);
+        } else {
+            outcome.rejected.push((url, reason));
+        }
+    }
+
+    outcome.accepted.sort();
+    outcome.rejected.sort();
+    outcome.timed_out.sort();
+    outcome
+}
+
+fn is_timeout(reason: &str) -> bool {
+    let reason = reason.to_lowercase();
+    reason.contains("timeout") || reason.contains("timed out")
+}
+
 async fn all_disconnected(client: &Client) -> bool {
+    let (connected, _) = connected_relays(client).await;
+    connected == 0
+}
+
+// Connected relays and all of them
+async fn connected_relays(client: &Client) -> (usize, usize) {
     let relays = client.pool().relays().await;
 
     let futures: Vec<_> = relays.values().map(|relay| relay.is_connected()).collect();
 
     let results = join_all(futures).await;
 
-    results.iter().all(|&is_connected| !is_connected)
+    let connected = results.iter().filter(|&&is_connected| is_connected).count();
+    (connected, relays.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config as ConfigTree;
+
+    #[test]
+    fn test_default_filter_is_gift_wraps_for_reportinator() {
+        let config = ConfigTree::new("config").unwrap().get::<Config>().unwrap();
+        let reportinator_public_key = Keys::generate().public_key();
+
+        let filters = config.filters(reportinator_public_key).unwrap();
+
+        assert_eq!(
+            filters,
+            vec![Filter::new()
+                .pubkey(reportinator_public_key)
+                .limit(0)
+                .kind(Kind::GiftWrap)]
+        );
+    }
+
+    #[test]
+    fn test_builds_multiple_filters() {
+        let author = Keys::generate().public_key();
+        let config = Config {
+            filters: vec![
+                FilterConfig {
+                    kinds: vec![1059],
+                    authors: vec![],
+                    since: None,
+                    limit: Some(0),
+                    tagging_reportinator: true,
+                },
+                FilterConfig {
+                    kinds: vec![1984],
+                    authors: vec![author.to_hex()],
+                    since: Some(1_700_000_000),
+                    limit: None,
+                    tagging_reportinator: false,
+                },
+            ],
+            publish_timeout_secs: 10,
+            metadata_relays: vec![],
+            outbox_max_relays: 0,
+            outbox_cache_secs: 3600,
+        };
+
+        let filters = config.filters(Keys::generate().public_key()).unwrap();
+
+        assert_eq!(filters.len(), 2);
+        assert_eq!(
+            filters[1],
+            Filter::new()
+                .kind(Kind::Reporting)
+                .author(author)
+                .since(Timestamp::from(1_700_000_000))
+        );
+    }
+
+    #[test]
+    fn test_filters_match_every_reportinator_key() {
+        let config = ConfigTree::new("config").unwrap().get::<Config>().unwrap();
+        let old_public_key = Keys::generate().public_key();
+        let new_public_key = Keys::generate().public_key();
+
+        let filters = config
+            .filters_for(&[new_public_key, old_public_key])
+            .unwrap();
+
+        assert_eq!(
+            filters,
+            vec![Filter::new()
+                .pubkeys([new_public_key, old_public_key])
+                .limit(0)
+                .kind(Kind::GiftWrap)]
+        );
+    }
+
+    #[test]
+    fn test_fails_without_filters() {
+        let config = Config {
+            filters: vec![],
+            publish_timeout_secs: 10,
+            metadata_relays: vec![],
+            outbox_max_relays: 0,
+            outbox_cache_secs: 3600,
+        };
+
+        assert!(config.filters(Keys::generate().public_key()).is_err());
+    }
+
+    #[test]
+    fn test_fails_with_filter_without_kinds() {
+        let config = Config {
+            filters: vec![FilterConfig {
+                kinds: vec![],
+                authors: vec![],
+                since: None,
+                limit: None,
+                tagging_reportinator: false,
+            }],
+            publish_timeout_secs: 10,
+            metadata_relays: vec![],
+            outbox_max_relays: 0,
+            outbox_cache_secs: 3600,
+        };
+
+        assert!(config.filters(Keys::generate().public_key()).is_err());
+    }
+
+    async fn relay_urls(client: &Client) -> Vec<Url> {
+        let mut urls: Vec<Url> = client.pool().relays().await.into_keys().collect();
+        urls.sort();
+        urls
+    }
+
+    #[tokio::test]
+    async fn test_metadata_client_uses_metadata_relays() {
+        let mut config = ConfigTree::new("config").unwrap().get::<Config>().unwrap();
+        config.metadata_relays = vec![
+            "wss://purplepag.es".to_string(),
+            "wss://relay.nos.social".to_string(),
+        ];
+
+        let nostr_service = NostrService::create(
+            vec!["ws://localhost".to_string()],
+            config,
+            Keys::generate().public_key(),
+            RelayPolicy::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            relay_urls(nostr_service.metadata_client()).await,
+            vec![
+                Url::parse("wss://purplepag.es").unwrap(),
+                Url::parse("wss://relay.nos.social").unwrap()
+            ]
+        );
+        assert_eq!(
+            relay_urls(&nostr_service.client).await,
+            vec![Url::parse("ws://localhost").unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metadata_client_defaults_to_subscription_relays() {
+        let config = ConfigTree::new("config").unwrap().get::<Config>().unwrap();
+
+        let nostr_service = NostrService::create(
+            vec!["ws://localhost".to_string()],
+            config,
+            Keys::generate().public_key(),
+            RelayPolicy::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            relay_urls(nostr_service.metadata_client()).await,
+            vec![Url::parse("ws://localhost").unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skips_relays_the_policy_does_not_allow() {
+        let mut config = ConfigTree::new("config").unwrap().get::<Config>().unwrap();
+        config.metadata_relays = vec![
+            "ws://purplepag.es".to_string(),
+            "wss://purplepag.es".to_string(),
+        ];
+        let relay_policy = RelayPolicy {
+            allowed_schemes: vec!["wss".to_string()],
+            allowed_hosts: vec!["relay.nos.social".to_string(), "purplepag.es".to_string()],
+            denied_hosts: vec![],
+        };
+
+        let nostr_service = NostrService::create(
+            vec![
+                "ws://relay.nos.social".to_string(),
+                "wss://relay.nos.social".to_string(),
+                "wss://untrusted.example.com".to_string(),
+            ],
+            config.clone(),
+            Keys::generate().public_key(),
+            relay_policy.clone(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            relay_urls(&nostr_service.client).await,
+            urls(&["wss://relay.nos.social"])
+        );
+        assert_eq!(
+            relay_urls(nostr_service.metadata_client()).await,
+            urls(&["wss://purplepag.es"])
+        );
+        assert!(nostr_service
+            .add_relay(Url::parse("wss://untrusted.example.com").unwrap())
+            .await
+            .is_err());
+
+        assert!(NostrService::create(
+            vec!["ws://localhost".to_string()],
+            config,
+            Keys::generate().public_key(),
+            relay_policy,
+        )
+        .await
+        .is_err());
+    }
+
+    fn outbox(max_relays: usize, relay_policy: RelayPolicy) -> OutboxRelays {
+        OutboxRelays::new(
+            &Config {
+                filters: vec![],
+                publish_timeout_secs: 10,
+                metadata_relays: vec![],
+                outbox_max_relays: max_relays,
+                outbox_cache_secs: 3600,
+            },
+            relay_policy,
+        )
+    }
+
+    fn urls(urls: &[&str]) -> Vec<Url> {
+        urls.iter().map(|url| Url::parse(url).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_outbox_relays_are_bounded_and_cached() {
+        let outbox = outbox(3, RelayPolicy::default());
+        let reported = Keys::generate().public_key();
+        let reporter = Keys::generate().public_key();
+        let relay_lists = HashMap::from([
+            (
+                reported,
+                urls(&["wss://ours.example.com", "wss://a.example.com"]),
+            ),
+            (
+                reporter,
+                urls(&[
+                    "wss://a.example.com",
+                    "wss://b.example.com",
+                    "wss://c.example.com",
+                    "wss://d.example.com",
+                ]),
+            ),
+        ]);
+        let lookups = Arc::new(Mutex::new(Vec::new()));
+        let lookup = |public_key: PublicKey| {
+            lookups.lock().unwrap().push(public_key);
+            let relays = relay_lists.get(&public_key).cloned().unwrap_or_default();
+            async move { relays }
+        };
+        let own_relays = urls(&["wss://ours.example.com"]);
+
+        let relays = outbox
+            .relays_for(&[reported, reporter], &own_relays, lookup)
+            .await;
+        assert_eq!(
+            relays,
+            urls(&[
+                "wss://a.example.com",
+                "wss://b.example.com",
+                "wss://c.example.com"
+            ])
+        );
+
+        // Served from the cache the second time
+        let relays_again = outbox
+            .relays_for(&[reported, reporter], &own_relays, lookup)
+            .await;
+        assert_eq!(relays_again, relays);
+        assert_eq!(*lookups.lock().unwrap(), vec![reported, reporter]);
+    }
+
+    #[tokio::test]
+    async fn test_outbox_is_empty_when_disabled() {
+        let lookup = |_| async { urls(&["wss://a.example.com"]) };
+
+        let relays = outbox(0, RelayPolicy::default())
+            .relays_for(&[Keys::generate().public_key()], &[], lookup)
+            .await;
+
+        assert!(relays.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_outbox_skips_relays_the_policy_does_not_allow() {
+        let relay_policy = RelayPolicy {
+            denied_hosts: vec!["a.example.com".to_string()],
+            ..RelayPolicy::default()
+        };
+        let lookup = |_| async {
+            urls(&[
+                "wss://a.example.com",
+                "wss://b.example.com",
+                "wss://c.example.com",
+            ])
+        };
+
+        let relays = outbox(2, relay_policy)
+            .relays_for(&[Keys::generate().public_key()], &[], lookup)
+            .await;
+
+        assert_eq!(
+            relays,
+            urls(&["wss://b.example.com", "wss://c.example.com"])
+        );
+    }
+
+    #[test]
+    fn test_only_write_relays_are_outbox_relays() {
+        let relay_list = EventBuilder::new(
+            Kind::RelayList,
+            "",
+            [
+                Tag::parse(&["r", "wss://both.example.com"]).unwrap(),
+                Tag::parse(&["r", "wss://write.example.com", "write"]).unwrap(),
+                Tag::parse(&["r", "wss://read.example.com", "read"]).unwrap(),
+                Tag::parse(&["r", "https://not.a.relay.com"]).unwrap(),
+            ],
+        )
+        .to_event(&Keys::generate())
+        .unwrap();
+
+        assert_eq!(
+            write_relays(&relay_list).collect::<Vec<_>>(),
+            urls(&["wss://both.example.com", "wss://write.example.com"])
+        );
+    }
+
+    #[test]
+    fn test_publish_outcome_with_mixed_relays() {
+        let url = |url: &str| Url::parse(url).unwrap();
+        let success = HashSet::from([url("wss://accepted.example.com")]);
+        let failed = HashMap::from([
+            (
+                url("wss://rejected.example.com"),
+                Some("blocked: pubkey not allowed".to_string()),
+            ),
+            (
+                url("wss://slow.example.com"),
+                Some("recv message response timeout".to_string()),
+            ),
+            (url("wss://quiet.example.com"), None),
+        ]);
+
+        let outcome = publish_outcome(success, failed);
+
+        assert!(outcome.is_published());
+        assert!(outcome.is_partial());
+        assert_eq!(outcome.accepted, vec![url("wss://accepted.example.com")]);
+        assert_eq!(
+            outcome.rejected,
+            vec![
+                (
+                    url("wss://quiet.example.com"),
+                    "no reason given".to_string()
+                ),
+                (
+                    url("wss://rejected.example.com"),
+                    "blocked: pubkey not allowed".to_string()
+                ),
+            ]
+        );
+        assert_eq!(outcome.timed_out, vec![url("wss://slow.example.com")]);
+    }
 }
@@ -0,0 +1,87 @@
+use crate::domain_objects::ReportRequest;
+use anyhow::{anyhow, bail, Context, Result};
+use nostr_sdk::nostr::nips::nip44::v2::{decrypt_to_bytes, encrypt_to_bytes, ConversationKey};
+use std::collections::HashMap;
+
+/// Attribute set on encrypted Pub/Sub messages, with the scheme as its value.
+/// Messages without it are plaintext JSON
+pub const ENCRYPTION_ATTRIBUTE: &str = "encryption";
+pub const ENCRYPTION_SCHEME: &str = "nip44-v2";
+/// The most NIP-44 v2 encrypts, longer payloads fail
+pub const MAX_PAYLOAD_BYTES: usize = 65535;
+
+/// Symmetric key shared with the consumers of the topic. Payloads are
+/// encrypted with NIP-44 v2, which limits them to 64KB
+#[derive(Clone)]
+pub struct PayloadKey(ConversationKey);
+
+impl PayloadKey {
+    // 64 hex characters, like the output of `openssl rand -hex 32`
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.is_ascii() {
+            bail!("The payload encryption key must be 64 hex characters");
+        }
+
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .context("The payload encryption key must be 64 hex characters")?;
+        }
+
+        Ok(Self(ConversationKey::new(key)))
+    }
+
+    pub fn encrypt(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        encrypt_to_bytes(&self.0, payload).map_err(|e| anyhow!("Failed to encrypt payload: {}", e))
+    }
+
+    pub fn decrypt(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        decrypt_to_bytes(&self.0, payload).map_err(|e| anyhow!("Failed to decrypt payload: {}", e))
+    }
+}
+
+/// For consumers of the topic: the report request in the data of a Pub/Sub
+/// message, decrypted first when its attributes say so
+pub fn decode_report_request(
+    data: &[u8],
+    attributes: &HashMap<String, String>,
+    key: Option<&PayloadKey>,
+) -> Result<ReportRequest> {
+    let json = match attributes.get(ENCRYPTION_ATTRIBUTE).map(String::as_str) {
+        None => data.to_vec(),
+        Some(ENCRYPTION_SCHEME) => key
+            .context("The payload is encrypted but no key was given")?
+            .decrypt(data)?,
+        Some(scheme) => bail!("Unknown payload encryption scheme {}", scheme),
+    };
+
+    serde_json::from_slice(&json).context("Failed to parse the report request")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "4f3c2b1a0e9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a3f2e1d0c9b8a7f6e5d4c3b";
+
+    #[test]
+    fn test_parses_hex_keys() {
+        assert!(PayloadKey::from_hex(KEY).is_ok());
+        assert!(PayloadKey::from_hex(&format!(" {}\n", KEY)).is_ok());
+        assert!(PayloadKey::from_hex(&KEY[..62]).is_err());
+        assert!(PayloadKey::from_hex(&KEY.replace('4', "g")).is_err());
+    }
+
+    #[test]
+    fn test_decrypts_with_the_same_key_only() {
+        let key = PayloadKey::from_hex(KEY).unwrap();
+        let other_key = PayloadKey::from_hex(&KEY.replace('4', "5")).unwrap();
+
+        let encrypted = key.encrypt(b"{\"reportedEvent\":{}}").unwrap();
+
+        assert_ne!(encrypted, b"{\"reportedEvent\":{}}");
+        assert_eq!(key.decrypt(&encrypted).unwrap(), b"{\"reportedEvent\":{}}");
+        assert!(other_key.decrypt(&encrypted).is_err());
+    }
+}
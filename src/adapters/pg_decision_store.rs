@@ -0,0 +1,55 @@
+use crate::adapters::decision_store::{DecisionStore, ModeratorDecision};
+use anyhow::{Context, Result};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+
+pub struct PgDecisionStore {
+    pool: PgPool,
+}
+
+impl PgDecisionStore {
+    // Connects and runs the pending migrations from the `migrations` dir
+    pub async fn create(database_url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(database_url)
+            .await
+            .context("Failed to connect to the decisions database")?;
+
+        sqlx::migrate!("./migrations")
+            .run(&pool)
+            .await
+            .context("Failed to run the decisions database migrations")?;
+
+        Ok(Self { pool })
+    }
+}
+
+#[ractor::async_trait]
+impl DecisionStore for PgDecisionStore {
+    async fn save(&self, decision: &ModeratorDecision) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO moderator_decisions
+                (report_id, category, moderator, target_type, target, reporter_pubkey, decided_at)
+            VALUES ($1, $2, $3, $4, $5, $6, to_timestamp($7))
+            "#,
+        )
+        .bind(decision.report_id.map(|id| id.to_hex()))
+        .bind(
+            decision
+                .category
+                .as_ref()
+                .map(|category| category.to_string()),
+        )
+        .bind(&decision.moderator)
+        .bind(decision.target_type())
+        .bind(decision.target_id())
+        .bind(decision.reporter_pubkey.to_hex())
+        .bind(decision.decided_at.as_u64() as f64)
+        .execute(&self.pool)
+        .await
+        .context("Failed to save moderator decision")?;
+
+        Ok(())
+    }
+}
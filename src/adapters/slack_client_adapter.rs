@@ -1,21 +1,198 @@
 use crate::actors::messages::SupervisorMessage;
-use crate::actors::{SlackClientPort, SlackClientPortBuilder};
+use crate::actors::utilities::category_suggestions::CategorySuggestions;
+use crate::actors::utilities::downstream_limit::limited;
+use crate::actors::utilities::language_detection::language_name;
+use crate::actors::utilities::mailbox;
+use crate::actors::{NotificationPort, SlackNotifierBuilder};
 use crate::adapters::njump_or_pubkey;
-use crate::config::Configurable;
-use crate::domain_objects::ReportRequest;
-use anyhow::Result;
-use hyper_rustls::HttpsConnector;
-use hyper_util::client::legacy::connect::HttpConnector;
-use nostr_sdk::nips::nip56::Report;
-use ractor::ActorRef;
+use crate::adapters::slack_expiry::{Expiring, SlackExpiry, SlackExpiryMessage};
+use crate::adapters::slack_markdown::{code_blocks, markdown_sections};
+use crate::adapters::slack_templates::{
+    SlackTemplates, AGGREGATED_REPORT_TEMPLATE, ESCALATED_REPORT_TEMPLATE, REPORT_TEMPLATE,
+};
+use crate::config::{self, Configurable};
+use crate::domain_objects::{
+    truncate_content, truncated_event, ModerationCategory, ReportRequest, ReportTarget,
+};
+use anyhow::{bail, Context, Result};
+use metrics::counter;
+use nostr_sdk::prelude::{Event, JsonUtil, PublicKey, Report};
+use ractor::{Actor, ActorRef};
 use serde::Deserialize;
+use serde_json::json;
 use slack_morphism::prelude::*;
-use tracing::info;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+pub const DEFAULT_WORKSPACE: &str = "default";
+pub const MIRROR_WORKSPACE: &str = "mirror";
+// Slack rejects actions blocks with more elements than this
+pub const MAX_ACTIONS_PER_BLOCK: usize = 25;
+// Where the interaction route finds the NIP-56 report type picked, if any
+pub const REPORT_TYPE_BLOCK_ID: &str = "reportType";
+pub const REPORT_TYPE_ACTION_ID: &str = "report_type";
+// Who resolves the report requests skipped once their message expires
+pub const EXPIRY_MODERATOR: &str = "expiry";
+// Where the interaction route finds the other reporters of an aggregated
+// message, the buttons only carry the first one
+pub const CO_REPORTERS_BLOCK_ID: &str = "coReporters";
+// Hex pubkeys and their separators fitting the 3000 characters of a block
+const MAX_CO_REPORTERS: usize = 45;
+// Content of the embedded event JSON, leaving room in the 3000 characters
+// of a block for the tags and the rest of the event
+const MAX_EMBEDDED_CONTENT_CHARS: usize = 1500;
+// NIP-56 report types moderators can pick instead of the one of the category
+const REPORT_TYPES: [Report; 7] = [
+    Report::Nudity,
+    Report::Malware,
+    Report::Profanity,
+    Report::Illegal,
+    Report::Spam,
+    Report::Impersonation,
+    Report::Other,
+];
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub token: String,
     pub channel_id: SlackChannelId,
+    // Reporter pubkeys whose requests go to this workspace. Empty means all
+    #[serde(default)]
+    pub reporters: Vec<String>,
+    // More workspaces that also get the report requests they accept
+    #[serde(default)]
+    pub workspaces: Vec<WorkspaceConfig>,
+    // Channel of the top level workspace getting a copy of the report
+    // requests for a sample of the targets, for QA
+    #[serde(default)]
+    pub mirror_channel_id: Option<SlackChannelId>,
+    // Fraction of the targets mirrored, from 0 to 1
+    #[serde(default)]
+    pub mirror_sample_rate: f64,
+    // Buttons in each actions block of a message, more go to extra blocks.
+    // Capped at what Slack accepts
+    #[serde(default = "default_buttons_per_block")]
+    pub buttons_per_block: usize,
+    // Category buttons grouped by severity, highest first. Empty keeps them
+    // in a single group
+    #[serde(default)]
+    pub severity_tiers: SeverityTiersConfig,
+    // Messages no moderator acted on for this long expire. Unset keeps them
+    // waiting forever
+    #[serde(default)]
+    pub expire_after_secs: Option<u64>,
+    #[serde(default)]
+    pub on_expiry: OnExpiry,
+    // Messages waiting to expire, the ones posted after that never do
+    #[serde(default = "default_max_pending_expiries")]
+    pub max_pending_expiries: usize,
+}
+
+/// What happens to a Slack message once it expires
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnExpiry {
+    // Marked as expired and its buttons removed
+    #[default]
+    Mark,
+    // Same, but also resolved as skipped so clicks on other copies of the
+    // message are ignored
+    Skip,
+    // The buttons are kept and the channel is pinged in a thread reply
+    Escalate,
+}
+
+impl OnExpiry {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OnExpiry::Mark => "mark",
+            OnExpiry::Skip => "skip",
+            OnExpiry::Escalate => "escalate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfirmationExpiry {
+    pub after: Duration,
+    pub on_expiry: OnExpiry,
+    pub max_pending: usize,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SeverityTiersConfig {
+    // Category slugs, like spam or sexual/minors
+    #[serde(default)]
+    pub high: Vec<String>,
+    #[serde(default)]
+    pub medium: Vec<String>,
+    #[serde(default)]
+    pub low: Vec<String>,
+}
+
+fn default_buttons_per_block() -> usize {
+    MAX_ACTIONS_PER_BLOCK
+}
+
+fn default_max_pending_expiries() -> usize {
+    10_000
+}
+
+impl Config {
+    /// None if messages never expire
+    pub fn confirmation_expiry(&self) -> Option<ConfirmationExpiry> {
+        self.expire_after_secs
+            .map(|expire_after_secs| ConfirmationExpiry {
+                after: Duration::from_secs(expire_after_secs),
+                on_expiry: self.on_expiry,
+                max_pending: self.max_pending_expiries,
+            })
+    }
+
+    /// The top level workspace followed by the additional ones and the mirror
+    pub fn workspaces(&self) -> Vec<WorkspaceConfig> {
+        let default_workspace = WorkspaceConfig {
+            name: DEFAULT_WORKSPACE.to_string(),
+            token: self.token.clone(),
+            channel_id: self.channel_id.clone(),
+            reporters: self.reporters.clone(),
+            sample_rate: None,
+        };
+
+        std::iter::once(default_workspace)
+            .chain(self.workspaces.iter().cloned())
+            .chain(self.mirror_workspace())
+            .collect()
+    }
+
+    fn mirror_workspace(&self) -> Option<WorkspaceConfig> {
+        let mirror_channel_id = self.mirror_channel_id.clone()?;
+        if self.mirror_sample_rate <= 0.0 {
+            return None;
+        }
+
+        Some(WorkspaceConfig {
+            name: MIRROR_WORKSPACE.to_string(),
+            token: self.token.clone(),
+            channel_id: mirror_channel_id,
+            reporters: Vec::new(),
+            sample_rate: Some(self.mirror_sample_rate),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkspaceConfig {
+    pub name: String,
+    pub token: String,
+    pub channel_id: SlackChannelId,
+    #[serde(default)]
+    pub reporters: Vec<String>,
+    // Only set for the mirror, which gets copies for this fraction of targets
+    #[serde(skip)]
+    pub sample_rate: Option<f64>,
 }
 
 impl Configurable for Config {
@@ -26,51 +203,268 @@ impl Configurable for Config {
 
 #[derive(Clone)]
 pub struct SlackClientAdapter {
-    config: Config,
-    client: SlackClient<SlackClientHyperConnector<HttpsConnector<HttpConnector>>>,
+    config: WorkspaceConfig,
+    client: Arc<SlackHyperClient>,
     nostr_actor: ActorRef<SupervisorMessage>,
+    templates: Arc<SlackTemplates>,
+    category_buttons: CategoryButtons,
+    category_suggestions: CategorySuggestions,
+    // Unset if messages never expire
+    slack_expiry: Option<ActorRef<SlackExpiryMessage<PostedMessage>>>,
+}
+
+/// Every workspace adapter built shares the same client and its connection
+/// pool, only the token differs
+pub struct SlackClientAdapterBuilder {
+    client: Arc<SlackHyperClient>,
+    templates: Arc<SlackTemplates>,
+    category_buttons: CategoryButtons,
+    category_suggestions: CategorySuggestions,
+    // Unset if messages never expire
+    slack_expiry: Option<ActorRef<SlackExpiryMessage<PostedMessage>>>,
 }
 
-#[derive(Default)]
-pub struct SlackClientAdapterBuilder {}
+impl SlackClientAdapterBuilder {
+    /// Also starts the single actor expiring the messages of every workspace
+    pub async fn create(
+        templates: SlackTemplates,
+        category_buttons: CategoryButtons,
+        category_suggestions: CategorySuggestions,
+        confirmation_expiry: Option<ConfirmationExpiry>,
+    ) -> Result<Self> {
+        let slack_expiry = match confirmation_expiry {
+            Some(confirmation_expiry) => {
+                let (slack_expiry, _slack_expiry_handle) = Actor::spawn(
+                    Some("slack_expiry".to_string()),
+                    SlackExpiry::default(),
+                    confirmation_expiry,
+                )
+                .await?;
+                Some(slack_expiry)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            client: Arc::new(SlackClient::new(SlackClientHyperConnector::new()?)),
+            templates: Arc::new(templates),
+            category_buttons,
+            category_suggestions,
+            slack_expiry,
+        })
+    }
+}
 
-impl SlackClientPortBuilder for SlackClientAdapterBuilder {
+impl SlackNotifierBuilder for SlackClientAdapterBuilder {
     fn build(
         &self,
-        config: Config,
+        config: WorkspaceConfig,
         nostr_actor: ActorRef<SupervisorMessage>,
-    ) -> Result<impl SlackClientPort> {
-        let client = SlackClient::new(SlackClientHyperConnector::new()?);
+    ) -> Result<impl NotificationPort> {
         Ok(SlackClientAdapter {
             config,
-            client,
+            client: self.client.clone(),
             nostr_actor,
+            templates: self.templates.clone(),
+            category_buttons: self.category_buttons.clone(),
+            category_suggestions: self.category_suggestions.clone(),
+            slack_expiry: self.slack_expiry.clone(),
         })
     }
 }
 
 impl SlackClientAdapter {
-    async fn post_message(&self, message: SlackApiChatPostMessageRequest) {
+    async fn post_message(
+        &self,
+        message: SlackApiChatPostMessageRequest,
+        report_requests: &[ReportRequest],
+    ) {
         let token = SlackApiToken::new(self.config.token.clone().into());
         let session = self.client.open_session(&token);
 
         let post_chat_resp = session.chat_post_message(&message).await;
         info!("post chat resp: {:#?}", &post_chat_resp);
+
+        let (Ok(posted), Some(slack_expiry)) = (post_chat_resp, &self.slack_expiry) else {
+            return;
+        };
+
+        let posted = PostedMessage {
+            adapter: self.clone(),
+            report_requests: report_requests.to_vec(),
+            content: message.content,
+            posted,
+        };
+        if let Err(e) = mailbox::cast(slack_expiry, SlackExpiryMessage::Schedule(posted)) {
+            error!("Failed to schedule the expiry of the Slack message: {}", e);
+        }
+    }
+
+    async fn update_expired(
+        &self,
+        content: SlackMessageContent,
+        posted: SlackApiChatPostMessageResponse,
+        on_expiry: OnExpiry,
+    ) -> Result<()> {
+        let token = SlackApiToken::new(self.config.token.clone().into());
+        let session = self.client.open_session(&token);
+
+        match on_expiry {
+            OnExpiry::Mark | OnExpiry::Skip => {
+                let update = SlackApiChatUpdateRequest::new(
+                    posted.channel,
+                    expired_content(content, on_expiry),
+                    posted.ts,
+                );
+                limited(session.chat_update(&update)).await?;
+            }
+            OnExpiry::Escalate => {
+                let reply = SlackApiChatPostMessageRequest::new(
+                    posted.channel,
+                    SlackMessageContent::new()
+                        .with_text("<!here> ⏰ No moderator acted on this report yet".to_string()),
+                )
+                .with_thread_ts(posted.ts);
+                limited(session.chat_post_message(&reply)).await?;
+            }
+        }
+
+        Ok(())
     }
 }
 
+/// A message posted to a workspace, kept until it expires
+pub struct PostedMessage {
+    adapter: SlackClientAdapter,
+    report_requests: Vec<ReportRequest>,
+    content: SlackMessageContent,
+    posted: SlackApiChatPostMessageResponse,
+}
+
 #[ractor::async_trait]
-impl SlackClientPort for SlackClientAdapter {
-    async fn write_message(&self, report_request: &ReportRequest) -> Result<()> {
+impl Expiring for PostedMessage {
+    fn report_requests(&self) -> &[ReportRequest] {
+        &self.report_requests
+    }
+
+    fn supervisor(&self) -> &ActorRef<SupervisorMessage> {
+        &self.adapter.nostr_actor
+    }
+
+    async fn expire(self, on_expiry: OnExpiry) -> Result<()> {
+        self.adapter
+            .update_expired(self.content, self.posted, on_expiry)
+            .await
+    }
+}
+
+// The message up to its buttons, which start after the first divider, and a
+// note saying it expired
+fn expired_content(content: SlackMessageContent, on_expiry: OnExpiry) -> SlackMessageContent {
+    let note = match on_expiry {
+        OnExpiry::Skip => "⏰ Expired with no moderator action, skipped",
+        OnExpiry::Mark | OnExpiry::Escalate => "⏰ Expired with no moderator action",
+    };
+    let mut blocks: Vec<SlackBlock> = content
+        .blocks
+        .unwrap_or_default()
+        .into_iter()
+        .take_while(|block| !matches!(block, SlackBlock::Divider(_)))
+        .collect();
+    blocks.push(SlackContextBlock::new(slack_blocks![some_into(md!(note.to_string()))]).into());
+
+    SlackMessageContent::new()
+        .with_text(content.text.unwrap_or_default())
+        .with_blocks(blocks)
+}
+
+fn count_suggestion(suggested_category: Option<ModerationCategory>) {
+    if let Some(category) = suggested_category {
+        counter!("category_suggested", "category" => category.slug()).increment(1);
+    }
+}
+
+#[ractor::async_trait]
+impl NotificationPort for SlackClientAdapter {
+    async fn notify(&self, report_request: &ReportRequest, suppressed: usize) -> Result<()> {
         let reported_pubkey_or_nip05_link =
             njump_or_pubkey(self.nostr_actor.clone(), report_request.target().pubkey()).await;
         let reporter_pubkey_or_nip05_link =
             njump_or_pubkey(self.nostr_actor.clone(), *report_request.reporter_pubkey()).await;
 
+        let summary = self.templates.render(
+            REPORT_TEMPLATE,
+            &json!({
+                "reporter": reporter_pubkey_or_nip05_link,
+                "reported": reported_pubkey_or_nip05_link,
+            }),
+        )?;
+        let suggested_category = self.category_suggestions.suggest(report_request);
+        count_suggestion(suggested_category);
         let message = PubkeyReportRequestMessage::new(
             report_request,
-            reported_pubkey_or_nip05_link,
-            reporter_pubkey_or_nip05_link,
+            summary,
+            &self.category_buttons,
+            suggested_category,
+            suppressed,
+        );
+
+        let message_req = SlackApiChatPostMessageRequest::new(
+            self.config.channel_id.clone(),
+            message.render_template(),
+        );
+
+        self.post_message(message_req, std::slice::from_ref(report_request))
+            .await;
+
+        Ok(())
+    }
+
+    async fn notify_aggregated(
+        &self,
+        report_requests: &[ReportRequest],
+        suppressed: usize,
+    ) -> Result<()> {
+        let Some(first_report_request) = report_requests.first() else {
+            return Ok(());
+        };
+
+        let reported_pubkey_or_nip05_link = njump_or_pubkey(
+            self.nostr_actor.clone(),
+            first_report_request.target().pubkey(),
+        )
+        .await;
+
+        let mut reporters = Vec::new();
+        for report_request in report_requests {
+            let reporter_pubkey_or_nip05_link =
+                njump_or_pubkey(self.nostr_actor.clone(), *report_request.reporter_pubkey()).await;
+            reporters.push((report_request, reporter_pubkey_or_nip05_link));
+        }
+
+        // Only the content tracker aggregates event targets, for reporters of
+        // the same content. Combined ones are aggregated by account
+        let template = match first_report_request.target() {
+            ReportTarget::Event(_) => ESCALATED_REPORT_TEMPLATE,
+            ReportTarget::Pubkey(_) | ReportTarget::EventAndPubkey(..) => {
+                AGGREGATED_REPORT_TEMPLATE
+            }
+        };
+        let summary = self.templates.render(
+            template,
+            &json!({
+                "count": reporters.len(),
+                "reported": reported_pubkey_or_nip05_link,
+            }),
+        )?;
+        let suggested_category = self.category_suggestions.suggest_for_all(report_requests);
+        count_suggestion(suggested_category);
+        let message = AggregatedPubkeyReportRequestMessage::new(
+            reporters,
+            summary,
+            &self.category_buttons,
+            suggested_category,
+            suppressed,
         );
 
         let message_req = SlackApiChatPostMessageRequest::new(
@@ -78,48 +472,49 @@ impl SlackClientPort for SlackClientAdapter {
             message.render_template(),
         );
 
-        self.post_message(message_req).await;
+        self.post_message(message_req, report_requests).await;
 
         Ok(())
     }
+
+    async fn check_connectivity(&self) -> Result<String> {
+        let token = SlackApiToken::new(self.config.token.clone().into());
+        let session = self.client.open_session(&token);
+
+        let response = session.auth_test().await?;
+        Ok(format!(
+            "Authenticated to team {}",
+            response.team_id.value()
+        ))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PubkeyReportRequestMessage<'a> {
     report_request: &'a ReportRequest,
-    reported_pubkey_or_nip05_link: String,
-    reporter_pubkey_or_nip05_link: String,
+    // Rendered from the slack_report template
+    summary: String,
+    category_buttons: &'a CategoryButtons,
+    suggested_category: Option<ModerationCategory>,
+    // Report requests on the same target held back by the cooldown since the
+    // previous message
+    suppressed: usize,
 }
 impl<'a> PubkeyReportRequestMessage<'a> {
     pub fn new(
         report_request: &'a ReportRequest,
-        reported_pubkey_or_nip05_link: String,
-        reporter_pubkey_or_nip05_link: String,
+        summary: String,
+        category_buttons: &'a CategoryButtons,
+        suggested_category: Option<ModerationCategory>,
+        suppressed: usize,
     ) -> Self {
         Self {
             report_request,
-            reported_pubkey_or_nip05_link,
-            reporter_pubkey_or_nip05_link,
-        }
-    }
-
-    fn category_buttons(&self) -> Vec<SlackActionBlockElement> {
-        let pubkey = self.report_request.reporter_pubkey().to_string();
-
-        slack_blocks![
-            some_into(
-                SlackBlockButtonElement::new("skip".into(), pt!("Skip"))
-                    .with_style("danger".to_string())
-                    .with_value(pubkey.clone())
-            ),
-            some_into(report_to_button(Report::Nudity).with_value(pubkey.clone())),
-            some_into(report_to_button(Report::Malware).with_value(pubkey.clone())),
-            some_into(report_to_button(Report::Profanity).with_value(pubkey.clone())),
-            some_into(report_to_button(Report::Illegal).with_value(pubkey.clone())),
-            some_into(report_to_button(Report::Spam).with_value(pubkey.clone())),
-            some_into(report_to_button(Report::Impersonation).with_value(pubkey.clone())),
-            some_into(report_to_button(Report::Other).with_value(pubkey.clone()))
-        ]
+            summary,
+            category_buttons,
+            suggested_category,
+            suppressed,
+        }
     }
 }
 
@@ -131,32 +526,690 @@ impl<'a> SlackMessageTemplate for PubkeyReportRequestMessage<'a> {
             .map(|t| t.to_string())
             .unwrap_or_default();
 
+        let mut blocks: Vec<SlackBlock> = slack_blocks![some_into(
+            SlackSectionBlock::new().with_text(md!(self.summary.clone()))
+        )];
+        blocks.extend(self_report_block([self.report_request]));
+        blocks.extend(suppressed_block(self.suppressed));
+        blocks.extend(reporter_text_blocks(&text));
+        blocks.extend(language_block(self.report_request));
+        blocks.extend(suggested_category_block(self.suggested_category));
+        blocks.extend(gift_wrap_id_block(self.report_request));
+        blocks.extend(target_blocks(self.report_request.target()));
+        blocks.extend(slack_blocks![some_into(SlackDividerBlock::new())]);
+        blocks.extend(self.category_buttons.blocks(
+            self.report_request.reporter_pubkey(),
+            self.suggested_category,
+        ));
+        blocks.push(report_type_block());
+
         SlackMessageContent::new()
-            .with_text(format!(
-                "New moderation request sent by {} to report account {}",
-                self.reporter_pubkey_or_nip05_link, self.reported_pubkey_or_nip05_link
-            ))
-            .with_blocks(slack_blocks![
-                some_into(SlackSectionBlock::new().with_text(md!(
-                    "New moderation request sent by {} to report account {}",
-                    self.reporter_pubkey_or_nip05_link,
-                    self.reported_pubkey_or_nip05_link
-                ))),
-                some_into(SlackSectionBlock::new().with_text(md!(text))),
-                some_into(
-                    SlackContextBlock::new(slack_blocks![some(pt!(self
-                        .report_request
-                        .target()
-                        .pubkey()
-                        .to_string()))])
-                    .with_block_id("reportedPubkey".to_string().into())
-                ),
-                some_into(SlackDividerBlock::new()),
-                some_into(SlackActionsBlock::new(self.category_buttons()))
-            ])
-    }
-}
-
-fn report_to_button(report: Report) -> SlackBlockButtonElement {
-    SlackBlockButtonElement::new(report.to_string().into(), pt!(report.to_string()))
+            .with_text(self.summary.clone())
+            .with_blocks(blocks)
+    }
+}
+
+// The reporter text is free form so it may need more than one section to fit
+// the Slack block size limit
+fn reporter_text_blocks(text: &str) -> Vec<SlackBlock> {
+    markdown_sections(text)
+        .into_iter()
+        .map(|section| SlackSectionBlock::new().with_text(md!(section)).into())
+        .collect()
+}
+
+// The interaction route takes what to report from the reportedPubkey or
+// reportedEvent block, or both for combined targets. Events carry their
+// JSON after their content, long content cut so the route fetches the
+// event again
+fn target_blocks(target: &ReportTarget) -> Vec<SlackBlock> {
+    match target {
+        ReportTarget::Event(event) => event_blocks(event),
+        ReportTarget::Pubkey(pubkey) => vec![pubkey_block(pubkey)],
+        ReportTarget::EventAndPubkey(event, pubkey) => {
+            let mut blocks = event_blocks(event);
+            blocks.push(pubkey_block(pubkey));
+            blocks
+        }
+    }
+}
+
+fn event_blocks(event: &Event) -> Vec<SlackBlock> {
+    code_blocks(&displayed_content(event))
+        .into_iter()
+        .map(|code_block| SlackSectionBlock::new().with_text(md!(code_block)).into())
+        .chain(std::iter::once(
+            SlackContextBlock::new(slack_blocks![some(pt!(embedded_event_json(event)))])
+                .with_block_id("reportedEvent".to_string().into())
+                .into(),
+        ))
+        .collect()
+}
+
+fn pubkey_block(pubkey: &PublicKey) -> SlackBlock {
+    SlackContextBlock::new(slack_blocks![some(pt!(pubkey.to_string()))])
+        .with_block_id("reportedPubkey".to_string().into())
+        .into()
+}
+
+fn embedded_event_json(event: &Event) -> String {
+    truncated_event(event, MAX_EMBEDDED_CONTENT_CHARS)
+        .unwrap_or_else(|| event.clone())
+        .as_json()
+}
+
+// Cut to max_reported_content_chars, the embedded event JSON has its own
+// limit
+fn displayed_content(event: &Event) -> String {
+    config::reportinator::max_reported_content_chars()
+        .and_then(|max_chars| truncate_content(&event.content, max_chars))
+        .unwrap_or_else(|| event.content.clone())
+}
+
+// Self reports only get here when reportinator.self_reports is flag
+fn self_report_block<'a>(
+    report_requests: impl IntoIterator<Item = &'a ReportRequest>,
+) -> Option<SlackBlock> {
+    let report_requests: Vec<&ReportRequest> = report_requests.into_iter().collect();
+    let self_reports = report_requests
+        .iter()
+        .filter(|report_request| report_request.is_self_report())
+        .count();
+    if self_reports == 0 {
+        return None;
+    }
+
+    Some(
+        SlackContextBlock::new(slack_blocks![some_into(md!(
+            ":warning: *Self report*: {} of {} reporters reported their own account or event",
+            self_reports,
+            report_requests.len()
+        ))])
+        .into(),
+    )
+}
+
+// The report requests that came in while the target was on cooldown are only
+// counted, so moderators know how many they missed
+fn suppressed_block(suppressed: usize) -> Option<SlackBlock> {
+    if suppressed == 0 {
+        return None;
+    }
+
+    Some(
+        SlackContextBlock::new(slack_blocks![some_into(md!(
+            ":mute: {} more report requests on this target were held back by the cooldown since the previous message",
+            suppressed
+        ))])
+        .into(),
+    )
+}
+
+// Only a hint from category_suggestions, nothing is done until a moderator
+// picks a category
+fn suggested_category_block(suggested_category: Option<ModerationCategory>) -> Option<SlackBlock> {
+    let category = suggested_category?;
+
+    Some(
+        SlackContextBlock::new(slack_blocks![some_into(md!(
+            ":bulb: Suggested category from the reporter text: *{}*",
+            category.slug()
+        ))])
+        .into(),
+    )
+}
+
+// Set when language_detection is enabled, so moderators can route the
+// message by language
+fn language_block(report_request: &ReportRequest) -> Option<SlackBlock> {
+    let lang = report_request.lang()?;
+
+    Some(
+        SlackContextBlock::new(slack_blocks![some_into(md!(
+            ":globe_with_meridians: Language: *{}* ({})",
+            language_name(lang),
+            lang
+        ))])
+        .into(),
+    )
+}
+
+// Lets us trace the message back to the gift wrap it came in
+fn gift_wrap_id_block(report_request: &ReportRequest) -> Option<SlackBlock> {
+    let gift_wrap_id = report_request.gift_wrap_id()?;
+
+    Some(SlackContextBlock::new(slack_blocks![some(pt!("Gift wrap {}", gift_wrap_id))]).into())
+}
+
+// A single message for a storm of report requests on the same pubkey. The
+// buttons carry the first reporter pubkey so the interaction route can act on
+// the whole aggregate as it does for a single request, the other reporters
+// come along in the coReporters block to be told about the decision.
+#[derive(Debug, Clone)]
+pub struct AggregatedPubkeyReportRequestMessage<'a> {
+    reporters: Vec<(&'a ReportRequest, String)>,
+    // Rendered from the slack_aggregated_report template
+    summary: String,
+    category_buttons: &'a CategoryButtons,
+    suggested_category: Option<ModerationCategory>,
+    suppressed: usize,
+}
+impl<'a> AggregatedPubkeyReportRequestMessage<'a> {
+    pub fn new(
+        reporters: Vec<(&'a ReportRequest, String)>,
+        summary: String,
+        category_buttons: &'a CategoryButtons,
+        suggested_category: Option<ModerationCategory>,
+        suppressed: usize,
+    ) -> Self {
+        Self {
+            reporters,
+            summary,
+            category_buttons,
+            suggested_category,
+            suppressed,
+        }
+    }
+
+    fn reporter_blocks(&self) -> Vec<SlackBlock> {
+        self.reporters
+            .iter()
+            .map(|(report_request, reporter_pubkey_or_nip05_link)| {
+                let lang = report_request
+                    .lang()
+                    .map(|lang| format!(" ({})", language_name(lang)))
+                    .unwrap_or_default();
+                let text = report_request
+                    .reporter_text()
+                    .map(|t| format!(": {}", t))
+                    .unwrap_or_default();
+
+                SlackContextBlock::new(slack_blocks![some_into(md!(
+                    "{}{}{}",
+                    reporter_pubkey_or_nip05_link,
+                    lang,
+                    text
+                ))])
+                .into()
+            })
+            .collect()
+    }
+
+    fn co_reporters_block(&self) -> Option<SlackBlock> {
+        let first_reporter = self.reporters.first()?.0.reporter_pubkey();
+        let mut co_reporters: Vec<String> = Vec::new();
+        for (report_request, _) in &self.reporters {
+            let reporter = report_request.reporter_pubkey();
+            let hex = reporter.to_hex();
+            if reporter != first_reporter && !co_reporters.contains(&hex) {
+                co_reporters.push(hex);
+            }
+        }
+        if co_reporters.is_empty() {
+            return None;
+        }
+        co_reporters.truncate(MAX_CO_REPORTERS);
+
+        Some(
+            SlackContextBlock::new(slack_blocks![some(pt!(co_reporters.join(" ")))])
+                .with_block_id(CO_REPORTERS_BLOCK_ID.to_string().into())
+                .into(),
+        )
+    }
+}
+
+impl<'a> SlackMessageTemplate for AggregatedPubkeyReportRequestMessage<'a> {
+    fn render_template(&self) -> SlackMessageContent {
+        let Some((first_report_request, _)) = self.reporters.first() else {
+            return SlackMessageContent::new();
+        };
+
+        let mut blocks: Vec<SlackBlock> = slack_blocks![some_into(
+            SlackSectionBlock::new().with_text(md!(self.summary.clone()))
+        )];
+        blocks.extend(self_report_block(
+            self.reporters
+                .iter()
+                .map(|(report_request, _)| *report_request),
+        ));
+        blocks.extend(suppressed_block(self.suppressed));
+        blocks.extend(self.reporter_blocks());
+        blocks.extend(suggested_category_block(self.suggested_category));
+        blocks.extend(target_blocks(first_report_request.target()));
+        blocks.extend(self.co_reporters_block());
+        blocks.extend(slack_blocks![some_into(SlackDividerBlock::new())]);
+        blocks.extend(self.category_buttons.blocks(
+            first_report_request.reporter_pubkey(),
+            self.suggested_category,
+        ));
+        blocks.push(report_type_block());
+
+        SlackMessageContent::new()
+            .with_text(self.summary.clone())
+            .with_blocks(blocks)
+    }
+}
+
+// Buttons past buttons_per_block continue in the next actions block, in order
+fn actions_blocks(
+    buttons: Vec<SlackActionBlockElement>,
+    buttons_per_block: usize,
+) -> Vec<SlackBlock> {
+    buttons
+        .chunks(buttons_per_block.clamp(1, MAX_ACTIONS_PER_BLOCK))
+        .map(|chunk| SlackActionsBlock::new(chunk.to_vec()).into())
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    High,
+    Medium,
+    Low,
+    // Categories missing from the configured tiers
+    Unrated,
+}
+
+impl Severity {
+    fn label(&self) -> &'static str {
+        match self {
+            Severity::High => "High severity",
+            Severity::Medium => "Medium severity",
+            Severity::Low => "Low severity",
+            Severity::Unrated => "Other",
+        }
+    }
+}
+
+/// The moderation buttons of a message, skip first and then the categories,
+/// in a labeled group per severity when tiers are configured
+#[derive(Debug, Clone)]
+pub struct CategoryButtons {
+    buttons_per_block: usize,
+    // Empty when there are no tiers, every category goes in a single group
+    tiers: Vec<(Severity, Vec<ModerationCategory>)>,
+}
+
+impl CategoryButtons {
+    pub fn new(buttons_per_block: usize, severity_tiers: &SeverityTiersConfig) -> Result<Self> {
+        let configured_tiers = [
+            (Severity::High, &severity_tiers.high),
+            (Severity::Medium, &severity_tiers.medium),
+            (Severity::Low, &severity_tiers.low),
+        ];
+
+        let mut tiers = Vec::new();
+        let mut rated = Vec::new();
+        for (severity, slugs) in configured_tiers {
+            let mut categories = Vec::new();
+            for slug in slugs {
+                let category = ModerationCategory::from_str(slug).with_context(|| {
+                    format!("Invalid category {} for {}", slug, severity.label())
+                })?;
+                if rated.contains(&category) {
+                    bail!("Category {} is in more than one severity tier", slug);
+                }
+
+                rated.push(category);
+                categories.push(category);
+            }
+
+            if !categories.is_empty() {
+                tiers.push((severity, categories));
+            }
+        }
+
+        let unrated: Vec<ModerationCategory> = ModerationCategory::ALL
+            .into_iter()
+            .filter(|category| !rated.contains(category))
+            .collect();
+        if !tiers.is_empty() && !unrated.is_empty() {
+            tiers.push((Severity::Unrated, unrated));
+        }
+
+        Ok(Self {
+            buttons_per_block,
+            tiers,
+        })
+    }
+
+    pub fn blocks(
+        &self,
+        reporter_pubkey: &PublicKey,
+        suggested_category: Option<ModerationCategory>,
+    ) -> Vec<SlackBlock> {
+        if self.tiers.is_empty() {
+            return actions_blocks(
+                category_buttons(reporter_pubkey, suggested_category),
+                self.buttons_per_block,
+            );
+        }
+
+        let mut blocks = actions_blocks(
+            vec![skip_button(reporter_pubkey).into()],
+            self.buttons_per_block,
+        );
+        for (severity, categories) in &self.tiers {
+            blocks.push(SlackDividerBlock::new().into());
+            blocks.push(
+                SlackContextBlock::new(slack_blocks![some_into(md!("*{}*", severity.label()))])
+                    .into(),
+            );
+            blocks.extend(actions_blocks(
+                categories
+                    .iter()
+                    .map(|category| {
+                        category_button(*category, reporter_pubkey, suggested_category).into()
+                    })
+                    .collect(),
+                self.buttons_per_block,
+            ));
+        }
+
+        blocks
+    }
+}
+
+// Every button carries the reporter pubkey, the interaction route gets it
+// back from the clicked one
+fn category_buttons(
+    reporter_pubkey: &PublicKey,
+    suggested_category: Option<ModerationCategory>,
+) -> Vec<SlackActionBlockElement> {
+    std::iter::once(skip_button(reporter_pubkey))
+        .chain(
+            ModerationCategory::ALL
+                .into_iter()
+                .map(|category| category_button(category, reporter_pubkey, suggested_category)),
+        )
+        .map(Into::into)
+        .collect()
+}
+
+fn skip_button(reporter_pubkey: &PublicKey) -> SlackBlockButtonElement {
+    SlackBlockButtonElement::new("skip".into(), pt!("Skip"))
+        .with_style("danger".to_string())
+        .with_value(reporter_pubkey.to_string())
+}
+
+// The suggested category, if any, gets the primary style
+fn category_button(
+    category: ModerationCategory,
+    reporter_pubkey: &PublicKey,
+    suggested_category: Option<ModerationCategory>,
+) -> SlackBlockButtonElement {
+    let button = category_to_button(category).with_value(reporter_pubkey.to_string());
+    match suggested_category == Some(category) {
+        true => button.with_style("primary".to_string()),
+        false => button,
+    }
+}
+
+// Optional, picked before clicking a category. The category still sets the
+// label and description of the report, only the NIP-56 type changes
+fn report_type_block() -> SlackBlock {
+    let options = REPORT_TYPES
+        .iter()
+        .map(|report_type| {
+            SlackBlockChoiceItem::new(pt!(report_type.to_string()), report_type.to_string())
+        })
+        .collect();
+
+    SlackActionsBlock::new(vec![SlackBlockStaticSelectElement::new(
+        REPORT_TYPE_ACTION_ID.into(),
+    )
+    .with_placeholder(pt!("NIP-56 type: from the category"))
+    .with_options(options)
+    .into()])
+    .with_block_id(REPORT_TYPE_BLOCK_ID.to_string().into())
+    .into()
+}
+
+fn category_to_button(category: ModerationCategory) -> SlackBlockButtonElement {
+    SlackBlockButtonElement::new(category.slug().into(), pt!(category.slug()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain_objects::is_truncated_event;
+    use nostr_sdk::prelude::{EventBuilder, Keys};
+
+    fn buttons(count: usize) -> Vec<SlackActionBlockElement> {
+        (0..count)
+            .map(|i| {
+                SlackBlockButtonElement::new(format!("category_{}", i).into(), pt!("{}", i)).into()
+            })
+            .collect()
+    }
+
+    fn block_sizes(blocks: &[SlackBlock]) -> Vec<usize> {
+        blocks
+            .iter()
+            .map(|block| match block {
+                SlackBlock::Actions(actions) => actions.elements.len(),
+                other => panic!("Expected an actions block, got {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_many_buttons_are_split_into_several_actions_blocks() {
+        assert_eq!(block_sizes(&actions_blocks(buttons(9), 25)), vec![9]);
+        assert_eq!(
+            block_sizes(&actions_blocks(buttons(60), 25)),
+            vec![25, 25, 10]
+        );
+        assert_eq!(block_sizes(&actions_blocks(buttons(9), 4)), vec![4, 4, 1]);
+        // More than Slack accepts, or nothing at all, is capped
+        assert_eq!(block_sizes(&actions_blocks(buttons(30), 100)), vec![25, 5]);
+        assert_eq!(block_sizes(&actions_blocks(buttons(2), 0)), vec![1, 1]);
+    }
+
+    fn slugs(slugs: &[&str]) -> Vec<String> {
+        slugs.iter().map(|slug| slug.to_string()).collect()
+    }
+
+    fn severity_tiers(high: &[&str], medium: &[&str], low: &[&str]) -> SeverityTiersConfig {
+        SeverityTiersConfig {
+            high: slugs(high),
+            medium: slugs(medium),
+            low: slugs(low),
+        }
+    }
+
+    #[test]
+    fn test_groups_category_buttons_by_severity() {
+        let reporter_pubkey = nostr_sdk::prelude::Keys::generate().public_key();
+        let high = ["sexual/minors", "illegal"];
+        let low = ["spam", "profanity"];
+        let category_buttons = CategoryButtons::new(25, &severity_tiers(&high, &[], &low)).unwrap();
+
+        let blocks = category_buttons.blocks(&reporter_pubkey, None);
+
+        // Each group as its label and the action ids of its buttons
+        let mut groups: Vec<(Option<String>, Vec<String>)> = vec![(None, vec![])];
+        for block in blocks {
+            match block {
+                SlackBlock::Divider(_) => {}
+                SlackBlock::Context(context) => {
+                    let label = serde_json::to_value(&context.elements[0]).unwrap()["text"]
+                        .as_str()
+                        .unwrap()
+                        .to_string();
+                    groups.push((Some(label), vec![]));
+                }
+                SlackBlock::Actions(actions) => {
+                    for element in actions.elements {
+                        let button = match element {
+                            SlackActionBlockElement::Button(button) => button,
+                            other => panic!("Expected a button, got {:?}", other),
+                        };
+                        // What the interaction route reads the reporter from
+                        assert_eq!(button.value, Some(reporter_pubkey.to_string()));
+                        groups.last_mut().unwrap().1.push(button.action_id.0);
+                    }
+                }
+                other => panic!("Unexpected block {:?}", other),
+            }
+        }
+
+        let unrated = ModerationCategory::ALL
+            .iter()
+            .map(|category| category.slug().to_string())
+            .filter(|slug| !high.contains(&slug.as_str()) && !low.contains(&slug.as_str()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                (None, slugs(&["skip"])),
+                (Some("*High severity*".to_string()), slugs(&high)),
+                (Some("*Low severity*".to_string()), slugs(&low)),
+                (Some("*Other*".to_string()), unrated),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_without_severity_tiers_buttons_are_not_grouped() {
+        let reporter_pubkey = nostr_sdk::prelude::Keys::generate().public_key();
+        let category_buttons = CategoryButtons::new(10, &SeverityTiersConfig::default()).unwrap();
+
+        assert_eq!(
+            block_sizes(&category_buttons.blocks(&reporter_pubkey, None)),
+            vec![10, 9]
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_severity_tiers() {
+        assert!(CategoryButtons::new(25, &severity_tiers(&["spamm"], &[], &[])).is_err());
+        assert!(CategoryButtons::new(25, &severity_tiers(&["spam"], &[], &["spam"])).is_err());
+    }
+
+    #[test]
+    fn test_highlights_the_suggested_category() {
+        let reporter_pubkey = nostr_sdk::prelude::Keys::generate().public_key();
+        let styles = |suggested_category| -> Vec<(String, Option<String>)> {
+            category_buttons(&reporter_pubkey, suggested_category)
+                .into_iter()
+                .map(|button| match button {
+                    SlackActionBlockElement::Button(button) => (button.action_id.0, button.style),
+                    other => panic!("Expected a button, got {:?}", other),
+                })
+                .filter(|(_, style)| style.is_some())
+                .collect()
+        };
+
+        assert_eq!(
+            styles(Some(ModerationCategory::Spam)),
+            vec![
+                ("skip".to_string(), Some("danger".to_string())),
+                ("spam".to_string(), Some("primary".to_string())),
+            ]
+        );
+        assert_eq!(
+            styles(None),
+            vec![("skip".to_string(), Some("danger".to_string()))]
+        );
+        assert!(suggested_category_block(None).is_none());
+    }
+
+    #[test]
+    fn test_aggregated_messages_carry_the_other_reporters() {
+        let reported_pubkey = Keys::generate().public_key();
+        let first_reporter = Keys::generate().public_key();
+        let other_reporter = Keys::generate().public_key();
+        let report_requests: Vec<ReportRequest> = [
+            first_reporter,
+            other_reporter,
+            first_reporter,
+            other_reporter,
+        ]
+        .into_iter()
+        .map(|reporter| ReportRequest::new(reported_pubkey.into(), reporter, None))
+        .collect();
+        let category_buttons =
+            CategoryButtons::new(MAX_ACTIONS_PER_BLOCK, &SeverityTiersConfig::default()).unwrap();
+        let message = AggregatedPubkeyReportRequestMessage::new(
+            report_requests
+                .iter()
+                .map(|report_request| (report_request, "reporter".to_string()))
+                .collect(),
+            "4 reports".to_string(),
+            &category_buttons,
+            None,
+            0,
+        );
+
+        let blocks = serde_json::to_value(message.render_template().blocks).unwrap();
+        let co_reporters_block = blocks
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|block| block["block_id"] == CO_REPORTERS_BLOCK_ID)
+            .unwrap();
+
+        assert_eq!(
+            co_reporters_block["elements"][0]["text"],
+            json!(other_reporter.to_hex())
+        );
+    }
+
+    #[test]
+    fn test_long_reported_events_are_embedded_truncated() {
+        let reported_event = EventBuilder::text_note("a".repeat(5000), [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        let blocks = serde_json::to_value(event_blocks(&reported_event)).unwrap();
+        let reported_event_block = blocks
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|block| block["block_id"] == "reportedEvent")
+            .unwrap();
+        let embedded_json = reported_event_block["elements"][0]["text"]
+            .as_str()
+            .unwrap();
+        let embedded_event = Event::from_json(embedded_json).unwrap();
+
+        assert!(embedded_json.chars().count() <= 3000);
+        assert_eq!(embedded_event.id, reported_event.id);
+        assert!(is_truncated_event(&embedded_event));
+    }
+
+    #[test]
+    fn test_expired_message_loses_its_buttons() {
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            Some("Spammer".to_string()),
+        );
+        let category_buttons =
+            CategoryButtons::new(MAX_ACTIONS_PER_BLOCK, &SeverityTiersConfig::default()).unwrap();
+        let content = PubkeyReportRequestMessage::new(
+            &report_request,
+            "New report".to_string(),
+            &category_buttons,
+            None,
+            0,
+        )
+        .render_template();
+
+        let expired = expired_content(content, OnExpiry::Skip);
+
+        let blocks = expired.blocks.unwrap();
+        assert!(blocks
+            .iter()
+            .all(|block| !matches!(block, SlackBlock::Actions(_))));
+        // The target is still there for anyone looking back at it
+        assert!(serde_json::to_string(&blocks)
+            .unwrap()
+            .contains("reportedPubkey"));
+        assert!(serde_json::to_string(blocks.last().unwrap())
+            .unwrap()
+            .contains("Expired with no moderator action, skipped"));
+        assert_eq!(expired.text, Some("New report".to_string()));
+    }
 }
@@ -0,0 +1,374 @@
+//! Expires the Slack messages no moderator acted on. A single actor keeps the
+//! posted messages in the order they expire and looks for the due ones on a
+//! tick, instead of a task sleeping per message. Copies of a message, posted
+//! to other workspaces or to the mirror, expire together with it.
+use crate::actors::messages::SupervisorMessage;
+use crate::actors::utilities::mailbox::{self, Counted};
+use crate::actors::utilities::resolved_reports::Resolution;
+use crate::adapters::slack_client_adapter::{ConfirmationExpiry, OnExpiry, EXPIRY_MODERATOR};
+use crate::domain_objects::ReportRequest;
+use crate::service_manager::actor_name;
+use anyhow::Result;
+use metrics::counter;
+use ractor::{call_t, Actor, ActorProcessingErr, ActorRef};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+// How often due messages are looked for, so how late they may expire
+const TICK: Duration = Duration::from_secs(1);
+
+/// A posted message that can be expired
+#[ractor::async_trait]
+pub trait Expiring: Send + Sync + 'static {
+    fn report_requests(&self) -> &[ReportRequest];
+
+    // Tells whether a moderator acted on the report requests
+    fn supervisor(&self) -> &ActorRef<SupervisorMessage>;
+
+    async fn expire(self, on_expiry: OnExpiry) -> Result<()>;
+}
+
+pub enum SlackExpiryMessage<T> {
+    Schedule(T),
+    Tick,
+}
+
+impl<T: 'static> Counted for SlackExpiryMessage<T> {
+    fn is_counted(&self) -> bool {
+        matches!(self, SlackExpiryMessage::Schedule(_))
+    }
+}
+
+pub struct SlackExpiry<T> {
+    _phantom: std::marker::PhantomData<T>,
+}
+
+impl<T> Default for SlackExpiry<T> {
+    fn default() -> Self {
+        Self {
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+pub struct State<T> {
+    confirmation_expiry: ConfirmationExpiry,
+    // Every message expires after the same time, so the ones posted first
+    // are always the first due
+    pending: VecDeque<(Instant, T)>,
+}
+
+#[ractor::async_trait]
+impl<T: Expiring> Actor for SlackExpiry<T> {
+    type Msg = SlackExpiryMessage<T>;
+    type State = State<T>;
+    type Arguments = ConfirmationExpiry;
+
+    async fn pre_start(
+        &self,
+        myself: ActorRef<Self::Msg>,
+        confirmation_expiry: ConfirmationExpiry,
+    ) -> Result<Self::State, ActorProcessingErr> {
+        mailbox::register::<Self::Msg>(actor_name::<Self>());
+        myself.send_interval(TICK, || SlackExpiryMessage::Tick);
+
+        Ok(State {
+            confirmation_expiry,
+            pending: VecDeque::new(),
+        })
+    }
+
+    async fn handle(
+        &self,
+        _: ActorRef<Self::Msg>,
+        message: Self::Msg,
+        state: &mut Self::State,
+    ) -> Result<(), ActorProcessingErr> {
+        let _handling = mailbox::handling(&message);
+        match message {
+            SlackExpiryMessage::Schedule(posted) => {
+                if state.pending.len() >= state.confirmation_expiry.max_pending {
+                    counter!("slack_expiry_overflow").increment(1);
+                    warn!(
+                        "{} Slack messages already waiting to expire, this one won't",
+                        state.pending.len()
+                    );
+                    return Ok(());
+                }
+
+                let expires_at = Instant::now() + state.confirmation_expiry.after;
+                state.pending.push_back((expires_at, posted));
+            }
+            SlackExpiryMessage::Tick => {
+                let now = Instant::now();
+                while state
+                    .pending
+                    .front()
+                    .is_some_and(|(expires_at, _)| *expires_at <= now)
+                {
+                    let Some((_, posted)) = state.pending.pop_front() else {
+                        break;
+                    };
+                    let copies = take_copies(&mut state.pending, posted);
+                    let on_expiry = state.confirmation_expiry.on_expiry;
+                    // Slack is called in the background so scheduling isn't
+                    // held up
+                    tokio::spawn(expire_copies(copies, on_expiry));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The due message and the copies of it still pending, the ones for any of
+// its report requests
+fn take_copies<T: Expiring>(pending: &mut VecDeque<(Instant, T)>, posted: T) -> Vec<T> {
+    let (copies, rest): (VecDeque<_>, VecDeque<_>) =
+        std::mem::take(pending).into_iter().partition(|(_, other)| {
+            other
+                .report_requests()
+                .iter()
+                .any(|report_request| posted.report_requests().contains(report_request))
+        });
+    *pending = rest;
+
+    std::iter::once(posted)
+        .chain(copies.into_iter().map(|(_, copy)| copy))
+        .collect()
+}
+
+// Asked once for all the copies, skipping on expiry resolves the report
+// requests so a second ask would tell a moderator acted
+async fn expire_copies<T: Expiring>(copies: Vec<T>, on_expiry: OnExpiry) {
+    let Some(supervisor) = copies.first().map(|posted| posted.supervisor().clone()) else {
+        return;
+    };
+
+    let mut report_requests: Vec<ReportRequest> = Vec::new();
+    for report_request in copies.iter().flat_map(|posted| posted.report_requests()) {
+        if !report_requests.contains(report_request) {
+            report_requests.push(report_request.clone());
+        }
+    }
+
+    if !is_unactioned(&supervisor, report_requests, on_expiry).await {
+        return;
+    }
+
+    for posted in copies {
+        counter!("report_expired_unactioned", "on_expiry" => on_expiry.name()).increment(1);
+        if let Err(e) = posted.expire(on_expiry).await {
+            error!("Failed to update the expired Slack message: {}", e);
+        }
+    }
+}
+
+/// Tells whether no moderator acted on the report requests of an expired
+/// message. Skipping on expiry resolves them then
+async fn is_unactioned(
+    supervisor: &ActorRef<SupervisorMessage>,
+    report_requests: Vec<ReportRequest>,
+    on_expiry: OnExpiry,
+) -> bool {
+    let resolution = (on_expiry == OnExpiry::Skip).then(|| Resolution {
+        moderator: EXPIRY_MODERATOR.to_string(),
+        category: None,
+    });
+    match call_t!(
+        supervisor,
+        SupervisorMessage::ExpireUnactioned,
+        1000,
+        report_requests,
+        resolution
+    ) {
+        Ok(expired) => expired,
+        Err(e) => {
+            error!("Failed to check if a moderator acted on the message: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::utilities::resolved_reports::ResolvedReports;
+    use nostr_sdk::prelude::{Keys, Timestamp};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    // Answers like the supervisor, remembering the resolutions
+    struct ResolvingSupervisor;
+
+    #[ractor::async_trait]
+    impl Actor for ResolvingSupervisor {
+        type Msg = SupervisorMessage;
+        type State = ResolvedReports;
+        type Arguments = ();
+
+        async fn pre_start(
+            &self,
+            _: ActorRef<Self::Msg>,
+            _: Self::Arguments,
+        ) -> Result<Self::State, ActorProcessingErr> {
+            Ok(ResolvedReports::default())
+        }
+
+        async fn handle(
+            &self,
+            _: ActorRef<Self::Msg>,
+            message: Self::Msg,
+            resolved_reports: &mut Self::State,
+        ) -> Result<(), ActorProcessingErr> {
+            match message {
+                SupervisorMessage::Resolve(report_request, resolution, reply_port) => {
+                    let _ = reply_port.send(resolved_reports.resolve(
+                        &report_request,
+                        resolution,
+                        Timestamp::now(),
+                    ));
+                }
+                SupervisorMessage::ExpireUnactioned(report_requests, resolution, reply_port) => {
+                    let _ = reply_port.send(resolved_reports.expire(
+                        &report_requests,
+                        resolution,
+                        Timestamp::now(),
+                    ));
+                }
+                _ => {}
+            }
+
+            Ok(())
+        }
+    }
+
+    // Records the workspaces of the expired messages instead of calling Slack
+    struct RecordedMessage {
+        workspace: &'static str,
+        report_requests: Vec<ReportRequest>,
+        supervisor: ActorRef<SupervisorMessage>,
+        expired: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    #[ractor::async_trait]
+    impl Expiring for RecordedMessage {
+        fn report_requests(&self) -> &[ReportRequest] {
+            &self.report_requests
+        }
+
+        fn supervisor(&self) -> &ActorRef<SupervisorMessage> {
+            &self.supervisor
+        }
+
+        async fn expire(self, _on_expiry: OnExpiry) -> Result<()> {
+            self.expired.lock().await.push(self.workspace);
+            Ok(())
+        }
+    }
+
+    fn report_request() -> ReportRequest {
+        ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_expires_messages_no_moderator_acted_on() {
+        let (supervisor, supervisor_handle) =
+            Actor::spawn(None, ResolvingSupervisor, ()).await.unwrap();
+
+        // Nobody clicked anything before the timeout
+        let unactioned_request = report_request();
+        assert!(
+            is_unactioned(
+                &supervisor,
+                vec![unactioned_request.clone()],
+                OnExpiry::Skip
+            )
+            .await
+        );
+
+        // Skipped by the expiry, a late click is ignored
+        let late_resolution = Resolution {
+            moderator: "daniel".to_string(),
+            category: None,
+        };
+        let earlier_resolution = call_t!(
+            supervisor,
+            SupervisorMessage::Resolve,
+            100,
+            unactioned_request,
+            late_resolution.clone()
+        )
+        .unwrap();
+        assert_eq!(
+            earlier_resolution.map(|resolution| resolution.moderator),
+            Some(EXPIRY_MODERATOR.to_string())
+        );
+
+        // A moderator acted on it in time
+        let actioned_request = report_request();
+        call_t!(
+            supervisor,
+            SupervisorMessage::Resolve,
+            100,
+            actioned_request.clone(),
+            late_resolution
+        )
+        .unwrap();
+        assert!(!is_unactioned(&supervisor, vec![actioned_request], OnExpiry::Skip).await);
+
+        supervisor.stop(None);
+        supervisor_handle.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_skipping_expires_every_copy() {
+        let (supervisor, supervisor_handle) =
+            Actor::spawn(None, ResolvingSupervisor, ()).await.unwrap();
+        let (slack_expiry, slack_expiry_handle) = Actor::spawn(
+            None,
+            SlackExpiry::default(),
+            ConfirmationExpiry {
+                after: Duration::from_millis(10),
+                on_expiry: OnExpiry::Skip,
+                max_pending: 10,
+            },
+        )
+        .await
+        .unwrap();
+
+        let expired = Arc::new(Mutex::new(Vec::new()));
+        let copied_request = report_request();
+        for (workspace, report_requests) in [
+            ("default", vec![copied_request.clone()]),
+            ("mirror", vec![copied_request.clone()]),
+            ("other", vec![report_request()]),
+        ] {
+            slack_expiry
+                .cast(SlackExpiryMessage::Schedule(RecordedMessage {
+                    workspace,
+                    report_requests,
+                    supervisor: supervisor.clone(),
+                    expired: expired.clone(),
+                }))
+                .unwrap();
+        }
+
+        tokio::time::sleep(TICK + Duration::from_millis(500)).await;
+
+        let mut expired = expired.lock().await.clone();
+        expired.sort();
+        assert_eq!(expired, ["default", "mirror", "other"]);
+
+        slack_expiry.stop(None);
+        supervisor.stop(None);
+        slack_expiry_handle.await.unwrap();
+        supervisor_handle.await.unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+//! Helpers to safely embed arbitrary content, like the reported event or the
+//! reporter text, in Slack mrkdwn messages.
+
+/// Slack rejects section blocks with more than 3000 characters of text
+pub const SLACK_BLOCK_TEXT_LIMIT: usize = 3000;
+
+const CODE_FENCE: &str = "```";
+const ZERO_WIDTH_SPACE: char = '\u{200B}';
+// Both fences and the newlines around the content
+const CODE_BLOCK_OVERHEAD: usize = 2 * CODE_FENCE.len() + 3;
+
+/// Makes sure the content can't open or close a code block by putting a zero
+/// width space between consecutive backticks. The text looks the same once
+/// rendered.
+pub fn escape_code_fences(content: &str) -> String {
+    let mut escaped = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        escaped.push(c);
+        if c == '`' && chars.peek() == Some(&'`') {
+            escaped.push(ZERO_WIDTH_SPACE);
+        }
+    }
+
+    escaped
+}
+
+/// Escapes the content and fences it in as many code blocks as needed so each
+/// one fits in a single Slack block
+pub fn code_blocks(content: &str) -> Vec<String> {
+    split_chars(
+        &escape_code_fences(content),
+        SLACK_BLOCK_TEXT_LIMIT - CODE_BLOCK_OVERHEAD,
+    )
+    .into_iter()
+    .map(|chunk| format!("{CODE_FENCE}\n{chunk}\n{CODE_FENCE}"))
+    .collect()
+}
+
+/// Splits a mrkdwn message in sections that fit in a Slack block. Code blocks
+/// are never split, so they should come from `code_blocks`.
+pub fn markdown_sections(text: &str) -> Vec<String> {
+    let mut sections = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+
+    for unit in units(text)
+        .iter()
+        .flat_map(|unit| split_chars(unit, SLACK_BLOCK_TEXT_LIMIT))
+    {
+        let unit_len = unit.chars().count();
+        let separator_len = usize::from(!current.is_empty());
+
+        if !current.is_empty() && current_len + separator_len + unit_len > SLACK_BLOCK_TEXT_LIMIT {
+            sections.push(std::mem::take(&mut current));
+            current_len = 0;
+        }
+
+        if !current.is_empty() {
+            current.push('\n');
+            current_len += 1;
+        }
+
+        current.push_str(&unit);
+        current_len += unit_len;
+    }
+
+    if !current.is_empty() {
+        sections.push(current);
+    }
+
+    sections
+}
+
+// Lines of the text, except for code blocks which are kept as a single unit
+fn units(text: &str) -> Vec<String> {
+    let mut units = Vec::new();
+    let mut code_block: Option<String> = None;
+
+    for line in text.lines() {
+        let is_fence = line.trim_start().starts_with(CODE_FENCE);
+
+        match code_block.as_mut() {
+            Some(block) => {
+                block.push('\n');
+                block.push_str(line);
+            }
+            None if is_fence => {
+                code_block = Some(line.to_string());
+                continue;
+            }
+            None => {
+                units.push(line.to_string());
+                continue;
+            }
+        }
+
+        if is_fence {
+            units.extend(code_block.take());
+        }
+    }
+
+    // An unterminated code block is still sent, we just keep it together
+    units.extend(code_block);
+    units
+}
+
+fn split_chars(text: &str, max_chars: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+
+    chars
+        .chunks(max_chars)
+        .map(|chunk| chunk.iter().collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_escape_code_fences() {
+        let content = "Look ``` at ````` this `code`";
+
+        let escaped = escape_code_fences(content);
+
+        assert!(!escaped.contains("``"));
+        assert_eq!(escaped.replace(ZERO_WIDTH_SPACE, ""), content);
+    }
+
+    #[test]
+    fn test_code_blocks_with_backticks() {
+        let blocks = code_blocks("```rm -rf /```");
+
+        assert_eq!(blocks.len(), 1);
+        // Only our own fences remain
+        assert_eq!(blocks[0].matches(CODE_FENCE).count(), 2);
+        assert!(blocks[0].starts_with("```\n"));
+        assert!(blocks[0].ends_with("\n```"));
+    }
+
+    #[test]
+    fn test_code_blocks_with_long_content() {
+        let content = "a".repeat(7000);
+
+        let blocks = code_blocks(&content);
+
+        assert_eq!(blocks.len(), 3);
+        for block in &blocks {
+            assert!(block.chars().count() <= SLACK_BLOCK_TEXT_LIMIT);
+            assert!(block.starts_with("```\n"));
+            assert!(block.ends_with("\n```"));
+        }
+
+        let joined: String = blocks
+            .iter()
+            .map(|b| b.trim_start_matches("```\n").trim_end_matches("\n```"))
+            .collect();
+        assert_eq!(joined, content);
+    }
+
+    #[test]
+    fn test_markdown_sections_keep_code_blocks_together() {
+        let content = "b".repeat(4000);
+        let text = format!(
+            "*Header*\n*Reported Event content:*\n{}\n*Footer*",
+            code_blocks(&content).join("\n")
+        );
+
+        let sections = markdown_sections(&text);
+
+        assert!(sections.len() > 1);
+        for section in &sections {
+            assert!(section.chars().count() <= SLACK_BLOCK_TEXT_LIMIT);
+            assert_eq!(section.matches(CODE_FENCE).count() % 2, 0);
+        }
+        assert!(sections[0].starts_with("*Header*"));
+        assert!(sections.last().unwrap().ends_with("*Footer*"));
+    }
+
+    #[test]
+    fn test_markdown_sections_short_text() {
+        assert_eq!(markdown_sections("Hello\nWorld"), vec!["Hello\nWorld"]);
+    }
+}
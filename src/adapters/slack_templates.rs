@@ -0,0 +1,247 @@
+use crate::config::{self, Configurable};
+use anyhow::{anyhow, Result};
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::warn;
+
+pub const REPORT_TEMPLATE: &str = "slack_report";
+pub const AGGREGATED_REPORT_TEMPLATE: &str = "slack_aggregated_report";
+pub const PROCESSED_TEMPLATE: &str = "slack_processed";
+pub const SKIPPED_TEMPLATE: &str = "slack_skipped";
+pub const ESCALATED_REPORT_TEMPLATE: &str = "slack_escalated_report";
+
+// Used for every template missing from templates_dir
+const EMBEDDED_TEMPLATES: [(&str, &str); 5] = [
+    (
+        REPORT_TEMPLATE,
+        include_str!("../../templates/slack_report.hbs"),
+    ),
+    (
+        AGGREGATED_REPORT_TEMPLATE,
+        include_str!("../../templates/slack_aggregated_report.hbs"),
+    ),
+    (
+        PROCESSED_TEMPLATE,
+        include_str!("../../templates/slack_processed.hbs"),
+    ),
+    (
+        SKIPPED_TEMPLATE,
+        include_str!("../../templates/slack_skipped.hbs"),
+    ),
+    (
+        ESCALATED_REPORT_TEMPLATE,
+        include_str!("../../templates/slack_escalated_report.hbs"),
+    ),
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub templates_dir: String,
+    // Put before every rendered message, like "[STAGING]", so moderators can
+    // tell environments apart
+    #[serde(default)]
+    pub message_banner: Option<String>,
+    // Without a message_banner, use the environment in brackets, except in
+    // production
+    #[serde(default)]
+    pub banner_from_environment: bool,
+}
+
+impl Config {
+    fn banner(&self, environment: &str) -> Option<String> {
+        if let Some(message_banner) = &self.message_banner {
+            let message_banner = message_banner.trim();
+            return (!message_banner.is_empty()).then(|| message_banner.to_string());
+        }
+
+        (self.banner_from_environment && environment != "production")
+            .then(|| format!("[{}]", environment.to_uppercase()))
+    }
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "http"
+    }
+}
+
+/// Handlebars templates for the text of Slack messages, so the wording can be
+/// changed without a release. Slack mrkdwn is not HTML, nothing is escaped.
+#[derive(Clone)]
+pub struct SlackTemplates {
+    hb: Handlebars<'static>,
+    banner: Option<String>,
+}
+
+impl SlackTemplates {
+    pub fn load(config: &Config) -> Result<Self> {
+        let mut hb = Handlebars::new();
+        hb.register_escape_fn(handlebars::no_escape);
+
+        for (name, embedded_template) in EMBEDDED_TEMPLATES {
+            let template_path = Path::new(&config.templates_dir).join(format!("{}.hbs", name));
+            if !template_path.exists() {
+                warn!(
+                    "Template not found at {}, using the embedded one",
+                    template_path.display()
+                );
+                hb.register_template_string(name, embedded_template)
+                    .map_err(|e| anyhow!("Failed to load the embedded template {}: {}", name, e))?;
+                continue;
+            }
+
+            hb.register_template_file(name, &template_path)
+                .map_err(|e| {
+                    anyhow!(
+                        "Failed to load template at {}: {}",
+                        template_path.display(),
+                        e
+                    )
+                })?;
+        }
+
+        Ok(Self {
+            hb,
+            banner: config.banner(&config::environment()),
+        })
+    }
+
+    /// Renders one of the templates above, without the trailing newline files
+    /// usually end with, after the banner if there's one
+    pub fn render<T: Serialize>(&self, name: &str, context: &T) -> Result<String> {
+        let rendered = self
+            .hb
+            .render(name, context)
+            .map_err(|e| anyhow!("Failed to render template {}: {}", name, e))?;
+
+        match &self.banner {
+            Some(banner) => Ok(format!("{} {}", banner, rendered.trim_end())),
+            None => Ok(rendered.trim_end().to_string()),
+        }
+    }
+}
+
+impl Default for SlackTemplates {
+    // Only the embedded templates, which are checked by the tests
+    fn default() -> Self {
+        let mut hb = Handlebars::new();
+        hb.register_escape_fn(handlebars::no_escape);
+        for (name, embedded_template) in EMBEDDED_TEMPLATES {
+            hb.register_template_string(name, embedded_template)
+                .expect("Embedded Slack templates are valid");
+        }
+
+        Self { hb, banner: None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(templates_dir: &Path) -> Config {
+        Config {
+            templates_dir: templates_dir.to_string_lossy().to_string(),
+            message_banner: None,
+            banner_from_environment: false,
+        }
+    }
+
+    #[test]
+    fn test_missing_templates_use_the_embedded_ones() {
+        let templates =
+            SlackTemplates::load(&config(Path::new("/nonexistent/reportinator"))).unwrap();
+
+        let rendered = templates
+            .render(
+                REPORT_TEMPLATE,
+                &json!({ "reporter": "<https://njump.me/a|a>", "reported": "b" }),
+            )
+            .unwrap();
+        assert_eq!(
+            rendered,
+            "New moderation request sent by <https://njump.me/a|a> to report account b"
+        );
+
+        let rendered = templates
+            .render(
+                SKIPPED_TEMPLATE,
+                &json!({ "moderator": "daniel", "reporter": "a", "reported": "b" }),
+            )
+            .unwrap();
+        assert!(rendered.contains("*Report Skipped By:* daniel"));
+        assert!(!rendered.contains("Reporter Reason"));
+        assert!(!rendered.contains("Reported Event Id"));
+    }
+
+    #[test]
+    fn test_templates_dir_overrides_the_embedded_ones() {
+        let templates_dir = std::env::temp_dir().join("test_slack_templates_dir_overrides");
+        std::fs::create_dir_all(&templates_dir).unwrap();
+        std::fs::write(
+            templates_dir.join("slack_report.hbs"),
+            "{{reporter}} flagged {{reported}}\n",
+        )
+        .unwrap();
+
+        let templates = SlackTemplates::load(&config(&templates_dir)).unwrap();
+        let context = json!({ "reporter": "a", "reported": "b", "count": 2 });
+
+        assert_eq!(
+            templates.render(REPORT_TEMPLATE, &context).unwrap(),
+            "a flagged b"
+        );
+        assert_eq!(
+            templates
+                .render(AGGREGATED_REPORT_TEMPLATE, &context)
+                .unwrap(),
+            "2 moderation requests received to report account b"
+        );
+        std::fs::remove_dir_all(templates_dir).unwrap();
+    }
+
+    #[test]
+    fn test_banner_goes_before_every_message() {
+        let templates = SlackTemplates::load(&Config {
+            message_banner: Some("[STAGING]".to_string()),
+            ..config(Path::new("/nonexistent/reportinator"))
+        })
+        .unwrap();
+
+        let context = json!({ "moderator": "daniel", "reporter": "a", "reported": "b" });
+        assert_eq!(
+            templates.render(REPORT_TEMPLATE, &context).unwrap(),
+            "[STAGING] New moderation request sent by a to report account b"
+        );
+        assert!(templates
+            .render(SKIPPED_TEMPLATE, &context)
+            .unwrap()
+            .starts_with("[STAGING] "));
+    }
+
+    #[test]
+    fn test_banner_from_the_environment() {
+        let from_environment = Config {
+            banner_from_environment: true,
+            ..config(Path::new("templates"))
+        };
+
+        assert_eq!(
+            from_environment.banner("staging"),
+            Some("[STAGING]".to_string())
+        );
+        assert_eq!(from_environment.banner("production"), None);
+        assert_eq!(config(Path::new("templates")).banner("staging"), None);
+        // An explicit banner wins, even in production
+        let explicit = Config {
+            message_banner: Some(" :test_tube: QA ".to_string()),
+            ..from_environment
+        };
+        assert_eq!(
+            explicit.banner("production"),
+            Some(":test_tube: QA".to_string())
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use crate::actors::NotificationPort;
+use crate::config::{self, Configurable};
+use crate::domain_objects::ReportRequest;
+use anyhow::{bail, ensure, Context, Result};
+use nostr_sdk::hashes::hmac::{Hmac, HmacEngine};
+use nostr_sdk::hashes::{sha256, Hash, HashEngine};
+use nostr_sdk::prelude::Timestamp;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::time::Duration;
+
+pub const TIMESTAMP_HEADER: &str = "X-Reportinator-Request-Timestamp";
+pub const SIGNATURE_HEADER: &str = "X-Reportinator-Signature";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Gets a POST with the report requests as JSON
+    pub url: String,
+    // Shared with the webhook, which checks the signature of every request
+    pub signing_secret: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "webhook"
+    }
+
+    // Placeholders left as <NOT_SET> are listed by --check-config with the
+    // other missing values instead
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            !self.signing_secret.trim().is_empty(),
+            "webhook.signing_secret is empty, set APP__WEBHOOK__SIGNING_SECRET"
+        );
+        if self.url == config::NOT_SET {
+            return Ok(());
+        }
+
+        let url =
+            Url::parse(&self.url).with_context(|| format!("Invalid webhook url {}", self.url))?;
+        ensure!(
+            matches!(url.scheme(), "http" | "https"),
+            "The webhook url {} is not http or https",
+            self.url
+        );
+        Ok(())
+    }
+}
+
+/// Posts report requests to a configured URL instead of Slack, for
+/// moderation tools of our own. A single request is sent as
+/// `{"reportRequest": ..., "suppressed": 0}`, several on the same target as
+/// `{"reportRequests": [...], "suppressed": 0}`. Any status other than 2xx
+/// is an error, so the request is retried. Requests are signed like Slack
+/// signs its own, see `signature`
+pub struct WebhookNotifier {
+    url: Url,
+    signing_secret: String,
+    client: Client,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &Config) -> Result<Self> {
+        config.validate()?;
+        ensure!(
+            config.url != config::NOT_SET && config.signing_secret != config::NOT_SET,
+            "The webhook backend needs APP__WEBHOOK__URL and APP__WEBHOOK__SIGNING_SECRET"
+        );
+
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .context("Failed to build the webhook HTTP client")?;
+
+        Ok(Self {
+            url: Url::parse(&config.url)?,
+            signing_secret: config.signing_secret.clone(),
+            client,
+        })
+    }
+
+    async fn post(&self, payload: Value) -> Result<()> {
+        let body = payload.to_string();
+        let timestamp = Timestamp::now().as_u64();
+        let response = self
+            .client
+            .post(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .header(TIMESTAMP_HEADER, timestamp)
+            .header(
+                SIGNATURE_HEADER,
+                signature(&self.signing_secret, timestamp, &body),
+            )
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("Failed to post to the webhook {}", self.url))?;
+
+        if !response.status().is_success() {
+            bail!(
+                "The webhook {} answered with {}",
+                self.url,
+                response.status()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// `v0=` and the hex HMAC-SHA256 of `v0:<timestamp>:<body>` keyed with the
+/// signing secret. The webhook computes it again to check the request came
+/// from us, and rejects old timestamps so requests can't be replayed
+pub fn signature(signing_secret: &str, timestamp: u64, body: &str) -> String {
+    let mut engine = HmacEngine::<sha256::Hash>::new(signing_secret.as_bytes());
+    engine.input(format!("v0:{}:{}", timestamp, body).as_bytes());
+    format!("v0={:x}", Hmac::<sha256::Hash>::from_engine(engine))
+}
+
+// Same truncation as published report requests
+fn truncated(report_request: &ReportRequest) -> Cow<'_, ReportRequest> {
+    report_request.with_truncated_content(config::reportinator::max_reported_content_chars())
+}
+
+#[ractor::async_trait]
+impl NotificationPort for WebhookNotifier {
+    async fn notify(&self, report_request: &ReportRequest, suppressed: usize) -> Result<()> {
+        self.post(json!({
+            "reportRequest": truncated(report_request).as_ref(),
+            "suppressed": suppressed,
+        }))
+        .await
+    }
+
+    async fn notify_aggregated(
+        &self,
+        report_requests: &[ReportRequest],
+        suppressed: usize,
+    ) -> Result<()> {
+        let report_requests = report_requests
+            .iter()
+            .map(|report_request| serde_json::to_value(truncated(report_request).as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.post(json!({
+            "reportRequests": report_requests,
+            "suppressed": suppressed,
+        }))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        extract::State,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Router,
+    };
+    use nostr_sdk::prelude::Keys;
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    type Received = Arc<Mutex<Vec<Value>>>;
+
+    const SIGNING_SECRET: &str = "webhook_signing_secret";
+
+    // Checks the request is signed like the webhook would
+    fn is_signed(headers: &HeaderMap, body: &str) -> bool {
+        let Some(timestamp) = headers
+            .get(TIMESTAMP_HEADER)
+            .and_then(|timestamp| timestamp.to_str().ok())
+            .and_then(|timestamp| timestamp.parse::<u64>().ok())
+        else {
+            return false;
+        };
+
+        headers
+            .get(SIGNATURE_HEADER)
+            .is_some_and(|signature_header| {
+                signature_header.as_bytes() == signature(SIGNING_SECRET, timestamp, body).as_bytes()
+            })
+    }
+
+    // Answers signed requests with the given status, keeping the payloads it
+    // got, and the rest with 401
+    async fn webhook(status: StatusCode) -> (Config, Received) {
+        let received = Received::default();
+        let router =
+            Router::new()
+                .route(
+                    "/reports",
+                    post(
+                        move |State(received): State<Received>,
+                              headers: HeaderMap,
+                              body: String| async move {
+                            if !is_signed(&headers, &body) {
+                                return StatusCode::UNAUTHORIZED;
+                            }
+
+                            received
+                                .lock()
+                                .await
+                                .push(serde_json::from_str(&body).unwrap());
+                            status
+                        },
+                    ),
+                )
+                .with_state(received.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let config = Config {
+            url: format!("http://{}/reports", addr),
+            signing_secret: SIGNING_SECRET.to_string(),
+            timeout_secs: 5,
+        };
+        (config, received)
+    }
+
+    fn report_request() -> ReportRequest {
+        ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            Some("Spammer".to_string()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_posts_report_requests_as_json() {
+        let (config, received) = webhook(StatusCode::OK).await;
+        let notifier = WebhookNotifier::new(&config).unwrap();
+        let first_request = report_request();
+        let second_request = report_request();
+
+        notifier.notify(&first_request, 2).await.unwrap();
+        notifier
+            .notify_aggregated(&[first_request.clone(), second_request.clone()], 0)
+            .await
+            .unwrap();
+
+        let received = received.lock().await;
+        assert_eq!(
+            received.as_slice(),
+            [
+                json!({
+                    "reportRequest": first_request,
+                    "suppressed": 2,
+                }),
+                json!({
+                    "reportRequests": [first_request, second_request],
+                    "suppressed": 0,
+                }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fails_when_the_webhook_does_not_accept_it() {
+        let (config, _received) = webhook(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let notifier = WebhookNotifier::new(&config).unwrap();
+
+        let error = notifier.notify(&report_request(), 0).await.unwrap_err();
+
+        assert!(error.to_string().contains("500"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_signed_with_the_signing_secret() {
+        let (config, received) = webhook(StatusCode::OK).await;
+        let notifier = WebhookNotifier::new(&Config {
+            signing_secret: "another_secret".to_string(),
+            ..config
+        })
+        .unwrap();
+
+        let error = notifier.notify(&report_request(), 0).await.unwrap_err();
+
+        assert!(error.to_string().contains("401"), "{}", error);
+        assert!(received.lock().await.is_empty());
+        assert_eq!(
+            signature(
+                "8f742231b10e8888abcd99yyyzzz85a5",
+                1531420618,
+                r#"{"suppressed":0}"#
+            ),
+            "v0=fb8776877f9b42fb26f48a2da9e406ca731172930ad95b949f9b8882a0a2ed04"
+        );
+    }
+
+    #[test]
+    fn test_webhook_url_must_be_http() {
+        let config = |url: &str| Config {
+            url: url.to_string(),
+            signing_secret: SIGNING_SECRET.to_string(),
+            timeout_secs: 10,
+        };
+
+        assert!(config("https://moderation.example.com/reports")
+            .validate()
+            .is_ok());
+        assert!(config("wss://moderation.example.com").validate().is_err());
+        assert!(config("not a url").validate().is_err());
+        assert!(config(config::NOT_SET).validate().is_ok());
+        assert!(WebhookNotifier::new(&config(config::NOT_SET)).is_err());
+    }
+}
@@ -1,7 +1,9 @@
 use anyhow::Result;
-use clap::{Arg, Command};
+use clap::{value_parser, Arg, ArgAction, Command};
 use nostr_sdk::prelude::*;
-use reportinator_server::{AsGiftWrap, ReportRequest, ReportTarget};
+use reportinator_server::{
+    AsGiftWrap, ProofOfWork, ReportRequest, ReportTarget, DEFAULT_MAX_POW_ITERATIONS,
+};
 use std::io::{self, BufRead};
 use std::str::FromStr;
 
@@ -13,39 +15,88 @@ async fn main() -> Result<()> {
         .about("Handles sending secret messages using Nostr")
         .arg(Arg::new("receiver_pubkey").required(true))
         .arg(Arg::new("reported_pubkey").required(false))
+        .arg(
+            Arg::new("with_event")
+                .long("with-event")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("reported_pubkey")
+                .help("Report a note read from stdin along its author"),
+        )
+        .arg(
+            Arg::new("pow")
+                .long("pow")
+                .value_name("DIFFICULTY")
+                .value_parser(value_parser!(u8))
+                .help("NIP-13 difficulty mined into the kind 1059 gift wrap"),
+        )
+        .arg(
+            Arg::new("pow_seal")
+                .long("pow-seal")
+                .action(ArgAction::SetTrue)
+                .requires("pow")
+                .help("Also mine the kind 13 seal to the same difficulty"),
+        )
+        .arg(
+            Arg::new("pow_max_iterations")
+                .long("pow-max-iterations")
+                .value_name("ITERATIONS")
+                .value_parser(value_parser!(u64))
+                .help(format!(
+                    "Nonces tried before giving up on the difficulty [default: {}]",
+                    DEFAULT_MAX_POW_ITERATIONS
+                )),
+        )
         .get_matches();
 
     let receiver_pubkey_str = matches.get_one::<String>("receiver_pubkey").unwrap();
     let receiver_pubkey =
         PublicKey::from_str(receiver_pubkey_str).expect("Failed to parse the public key");
     let maybe_reported_pubkey_str = matches.get_one::<String>("reported_pubkey");
+    let pow = ProofOfWork {
+        difficulty: matches.get_one::<u8>("pow").copied().unwrap_or_default(),
+        seal: matches.get_flag("pow_seal"),
+        max_iterations: matches
+            .get_one::<u64>("pow_max_iterations")
+            .copied()
+            .unwrap_or(DEFAULT_MAX_POW_ITERATIONS),
+    };
     let test_secret = "7786a6328328930d6da0d494524dc3a8597abd8f41616621fabb7ad60c9ef143";
     let sender_keys = Keys::parse(test_secret).expect("Failed to parse the secret");
 
+    let read_reported_event = || -> Result<Event> {
+        let stdin = io::stdin();
+        let mut iterator = stdin.lock().lines();
+        let message = iterator
+            .next()
+            .expect("Failed to read message from stdin")
+            .expect("Failed to read line");
+
+        Ok(EventBuilder::text_note(message, []).to_event(&sender_keys)?)
+    };
+
+    // The note is signed here, its author is the only pubkey it can be
+    // reported along
     let target = match maybe_reported_pubkey_str {
-        Some(reported_pubkey_str) => {
-            let reported_pubkey =
-                PublicKey::from_str(reported_pubkey_str).expect("Failed to parse the public key");
-            ReportTarget::Pubkey(reported_pubkey)
-        }
-        None => {
-            let stdin = io::stdin();
-            let mut iterator = stdin.lock().lines();
-            let message = iterator
-                .next()
-                .expect("Failed to read message from stdin")
-                .expect("Failed to read line");
-
-            let reported_event = EventBuilder::text_note(message, []).to_event(&sender_keys)?;
-            ReportTarget::Event(reported_event)
+        Some(reported_pubkey_str) => ReportTarget::Pubkey(
+            PublicKey::from_str(reported_pubkey_str).expect("Failed to parse the public key"),
+        ),
+        None if matches.get_flag("with_event") => {
+            let reported_event = read_reported_event()?;
+            let reported_pubkey = reported_event.pubkey;
+            ReportTarget::EventAndPubkey(reported_event, reported_pubkey)
         }
+        None => ReportTarget::Event(read_reported_event()?),
     };
 
     let reporter_pubkey = sender_keys.public_key();
     let reporter_text = Some("This is wrong, report it!".to_string());
-    let report_request = ReportRequest::new(target, reporter_pubkey, reporter_text);
+    let report_request = ReportRequest::builder()
+        .target(target)
+        .reporter_pubkey(reporter_pubkey)
+        .reporter_text(reporter_text)
+        .build()?;
     let event_result = report_request
-        .as_gift_wrap(&sender_keys, &receiver_pubkey)
+        .as_gift_wrap_with_pow(&sender_keys, &receiver_pubkey, pow)
         .await;
 
     match event_result {
@@ -54,6 +105,8 @@ async fn main() -> Result<()> {
         }
         Err(e) => {
             eprintln!("Error creating private DM message: {}", e);
+            // Mining may have given up, scripts must not get an empty fixture
+            std::process::exit(1);
         }
     }
 
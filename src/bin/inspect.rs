@@ -0,0 +1,49 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{Arg, Command};
+use nostr_sdk::prelude::*;
+use reportinator_server::GiftWrappedReportRequest;
+use std::io::{self, Read};
+use std::process::ExitCode;
+
+// Same variable the server reads its keys from
+const SECRET_KEY_VARIABLE: &str = "APP__REPORTINATOR__KEYS";
+
+// The opposite of giftwrapper, decrypts a gift wrapped report request to
+// debug what the server got
+fn main() -> Result<ExitCode> {
+    let matches = Command::new("reportinator-inspect")
+        .version("1.0")
+        .about("Decrypts a kind 1059 gift wrap read from stdin and prints its report request")
+        .arg(Arg::new("secret_key").long("secret-key").help(format!(
+            "Reportinator nsec or hex secret key, defaults to {}",
+            SECRET_KEY_VARIABLE
+        )))
+        .get_matches();
+
+    let secret_key = match matches.get_one::<String>("secret_key") {
+        Some(secret_key) => secret_key.clone(),
+        None => std::env::var(SECRET_KEY_VARIABLE)
+            .map_err(|_| anyhow!("Pass --secret-key or set {}", SECRET_KEY_VARIABLE))?,
+    };
+    let keys = Keys::parse(secret_key.trim()).context("Failed to parse the secret key")?;
+
+    let mut event_json = String::new();
+    io::stdin()
+        .read_to_string(&mut event_json)
+        .context("Failed to read the event from stdin")?;
+    let event = Event::from_json(event_json.trim()).context("Failed to parse the event")?;
+
+    let report_request = GiftWrappedReportRequest::try_from(event)
+        .and_then(|gift_wrap| gift_wrap.extract_report_request(&keys));
+
+    match report_request {
+        Ok(report_request) => {
+            println!("{}", serde_json::to_string_pretty(&report_request)?);
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(e) => {
+            eprintln!("{} ({})", e, e.reason());
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
@@ -1,8 +1,14 @@
+pub mod notifications;
+pub use notifications::{Config as NotificationsConfig, NotificationBackend};
+pub mod pubsub;
+pub use pubsub::{Config as PubsubConfig, PubsubBackend};
 pub mod reportinator;
 pub use reportinator::Config as ReportinatorConfig;
+mod secret_files;
+use secret_files::SecretFiles;
 
 use anyhow::{Context, Result};
-use config_rs::{Config as ConfigTree, Environment, File};
+use config_rs::{Config as ConfigTree, Environment, File, Map, Value};
 use serde::de::DeserializeOwned;
 use std::{any::type_name, env};
 
@@ -12,6 +18,9 @@ use std::{any::type_name, env};
 
 pub const ENVIRONMENT_PREFIX: &str = "APP";
 pub const CONFIG_SEPARATOR: &str = "__";
+// Placeholder used in the settings files for values that must be provided
+// through the environment
+pub const NOT_SET: &str = "<NOT_SET>";
 
 #[must_use]
 pub fn environment() -> String {
@@ -25,6 +34,12 @@ pub fn environment() -> String {
 
 pub trait Configurable {
     fn key() -> &'static str;
+
+    /// Checks what deserializing can't, run by `Config::get` so errors show up
+    /// on startup and in --check-config
+    fn validate(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -45,6 +60,11 @@ impl Config {
             .add_source(File::with_name(&env_config_path).required(false))
             .add_source(File::with_name(&local_config_path).required(false))
             .add_source(Environment::with_prefix(ENVIRONMENT_PREFIX).separator(CONFIG_SEPARATOR))
+            // `*_FILE` variables win over plain ones
+            .add_source(SecretFiles::with_prefix(
+                ENVIRONMENT_PREFIX,
+                CONFIG_SEPARATOR,
+            ))
             .build()
             .map(|c| Config { config: c })
             .map_err(Into::into)
@@ -55,11 +75,18 @@ impl Config {
         T: Configurable,
         T: DeserializeOwned,
     {
-        self.config.get::<T>(T::key()).context(format!(
+        let value = self.config.get::<T>(T::key()).context(format!(
             "Error loading configuration for `{}` at `{}`",
             type_name::<T>(),
             T::key(),
-        ))
+        ))?;
+
+        value.validate().context(format!(
+            "Invalid configuration for `{}` at `{}`",
+            type_name::<T>(),
+            T::key(),
+        ))?;
+        Ok(value)
     }
 
     pub fn get_by_key<T>(&self, key: &str) -> Result<T>
@@ -71,4 +98,56 @@ impl Config {
             type_name::<T>(),
         ))
     }
+
+    /// Returns the environment variables that still need to be set because
+    /// their value was left as the `<NOT_SET>` placeholder. Sections that
+    /// aren't read, like the ones of other backends, may keep them
+    pub fn missing_values(&self, unused_sections: &[&str]) -> Result<Vec<String>> {
+        let mut table = self.config.collect()?;
+        table.retain(|section, _| !unused_sections.contains(&section.as_str()));
+
+        let mut missing = Vec::new();
+        collect_missing_values(&[], table, &mut missing);
+        missing.sort();
+
+        Ok(missing)
+    }
+}
+
+fn collect_missing_values(path: &[String], table: Map<String, Value>, missing: &mut Vec<String>) {
+    for (key, value) in table {
+        let mut key_path = path.to_vec();
+        key_path.push(key.to_uppercase());
+
+        if let Ok(nested_table) = value.clone().into_table() {
+            collect_missing_values(&key_path, nested_table, missing);
+            continue;
+        }
+
+        if value.into_string().is_ok_and(|s| s == NOT_SET) {
+            missing.push(format!(
+                "{ENVIRONMENT_PREFIX}{CONFIG_SEPARATOR}{}",
+                key_path.join(CONFIG_SEPARATOR)
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_values_lists_not_set_placeholders() {
+        let config = Config::new("config").unwrap();
+
+        let missing = config.missing_values(&[]).unwrap();
+
+        assert!(missing.contains(&"APP__SLACK__TOKEN".to_string()));
+        assert!(!missing.iter().any(|m| m.starts_with("APP__REPORTINATOR")));
+
+        let missing = config.missing_values(&["slack"]).unwrap();
+
+        assert!(!missing.iter().any(|m| m.starts_with("APP__SLACK")));
+    }
 }
@@ -0,0 +1,79 @@
+use crate::config::Configurable;
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+
+// Selects how moderators are told about report requests. Batching and the
+// cooldown, the same for every backend, are read from this section by the
+// notification writer
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub backend: NotificationBackend,
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "notifications"
+    }
+}
+
+/// An unknown backend fails loading the config, with the valid ones listed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationBackend {
+    // A message with moderation buttons in each configured workspace
+    #[default]
+    Slack,
+    // POSTs the report requests as JSON, see the webhook section
+    Webhook,
+}
+
+impl NotificationBackend {
+    /// The config sections only the other backends read
+    pub fn unused_sections(&self) -> &'static [&'static str] {
+        match self {
+            NotificationBackend::Slack => &["webhook"],
+            NotificationBackend::Webhook => &["slack"],
+        }
+    }
+}
+
+impl Display for NotificationBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            NotificationBackend::Slack => write!(f, "slack"),
+            NotificationBackend::Webhook => write!(f, "webhook"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_rs::Config as ConfigTree;
+
+    fn backend(value: Option<&str>) -> Result<Config, config_rs::ConfigError> {
+        let mut builder = ConfigTree::builder()
+            .set_default("notifications.unrelated", "value")
+            .unwrap();
+        if let Some(value) = value {
+            builder = builder
+                .set_override("notifications.backend", value)
+                .unwrap();
+        }
+
+        builder.build().unwrap().get::<Config>(Config::key())
+    }
+
+    #[test]
+    fn test_parses_notification_backends() {
+        assert_eq!(backend(None).unwrap().backend, NotificationBackend::Slack);
+        assert_eq!(
+            backend(Some("webhook")).unwrap().backend,
+            NotificationBackend::Webhook
+        );
+
+        let error = backend(Some("email")).unwrap_err().to_string();
+        assert!(error.contains("`slack`, `webhook`"), "{}", error);
+    }
+}
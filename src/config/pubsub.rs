@@ -0,0 +1,77 @@
+use crate::config::Configurable;
+use serde::Deserialize;
+use std::fmt::{self, Display, Formatter};
+
+// Selects where report requests for events are published for analysis
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub backend: PubsubBackend,
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "pubsub"
+    }
+}
+
+/// An unknown backend fails loading the config, with the valid ones listed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PubsubBackend {
+    #[default]
+    Google,
+    // Only available when building with the `kafka` feature
+    Kafka,
+    // Logs the report requests instead of publishing them, for dry runs
+    Noop,
+    // Appends them to a local rotating JSONL file, see the file_sink section
+    File,
+}
+
+impl Display for PubsubBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PubsubBackend::Google => write!(f, "google"),
+            PubsubBackend::Kafka => write!(f, "kafka"),
+            PubsubBackend::Noop => write!(f, "noop"),
+            PubsubBackend::File => write!(f, "file"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_rs::Config as ConfigTree;
+
+    fn backend(value: Option<&str>) -> Result<Config, config_rs::ConfigError> {
+        let mut builder = ConfigTree::builder()
+            .set_default("pubsub.unrelated", "value")
+            .unwrap();
+        if let Some(value) = value {
+            builder = builder.set_override("pubsub.backend", value).unwrap();
+        }
+
+        builder.build().unwrap().get::<Config>(Config::key())
+    }
+
+    #[test]
+    fn test_parses_pubsub_backends() {
+        assert_eq!(backend(None).unwrap().backend, PubsubBackend::Google);
+        assert_eq!(
+            backend(Some("kafka")).unwrap().backend,
+            PubsubBackend::Kafka
+        );
+        assert_eq!(backend(Some("noop")).unwrap().backend, PubsubBackend::Noop);
+        assert_eq!(backend(Some("file")).unwrap().backend, PubsubBackend::File);
+    }
+
+    #[test]
+    fn test_unknown_pubsub_backend_lists_the_valid_ones() {
+        let error = backend(Some("gogle")).unwrap_err().to_string();
+
+        assert!(error.contains("gogle"), "{}", error);
+        assert!(error.contains("`google`, `kafka`, `noop`"), "{}", error);
+    }
+}
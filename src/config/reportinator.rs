@@ -1,20 +1,256 @@
 use crate::config::Configurable;
-use nostr_sdk::Keys;
+use crate::domain_objects::ModerationCategory;
+use anyhow::{bail, Result};
+use nostr_sdk::{Keys, Kind, PublicKey, Url};
 use serde::{de, Deserialize, Deserializer};
-use std::sync::OnceLock;
+use std::env;
+use std::sync::{OnceLock, RwLock};
+use tracing::warn;
+
+/// Comma separated relays, taking precedence over `reportinator.relays`
+pub const RELAYS_ENV_VAR: &str = "RELAY_ADDRESSES_CSV";
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     #[serde(deserialize_with = "parse_keys")]
     pub keys: Keys,
-    #[serde(deserialize_with = "parse_relays")]
+    // Comma separated, RELAY_ADDRESSES_CSV wins when set. See `get_relays`
+    #[serde(default, deserialize_with = "parse_relays")]
     pub relays: Vec<String>,
+    // Kinds used for the published moderation reports. One event is created
+    // per kind, all of them sharing the same NIP-56 tag structure.
+    #[serde(
+        default = "default_report_kinds",
+        deserialize_with = "parse_report_kinds"
+    )]
+    pub report_kinds: Vec<Kind>,
+    // When set, published reports get a NIP-40 expiration tag this many
+    // seconds in the future
+    #[serde(default)]
+    pub report_expiration_secs: Option<u64>,
+    // Whether a NIP-32 label event is published along the reports
+    #[serde(default)]
+    pub label_events: LabelEvents,
+    // Reported event content longer than this many characters is cut in Slack
+    // messages and Pub/Sub payloads. No limit when unset
+    #[serde(default)]
+    pub max_reported_content_chars: Option<usize>,
+    // Alternate format reports published next to the regular ones, to
+    // compare them before switching
+    #[serde(default)]
+    pub shadow_reports: ShadowReports,
+    // Reports in these categories get a NIP-36 content-warning tag, so
+    // clients can hide the reported content. None by default
+    #[serde(default)]
+    pub content_warning_categories: Vec<ModerationCategory>,
+    // Pubkeys, hex or npub, tagged with plain p tags on the published reports
+    // so they get notified, like moderators or authorities. Not on label
+    // events, their p tags are the labeled targets
+    #[serde(default, deserialize_with = "parse_mentions")]
+    pub mentions: Vec<PublicKey>,
+    // NIP-05 lookups for the njump links in Slack messages
+    #[serde(default)]
+    pub nip05_lookup: Nip05Lookup,
+    // Schemes and hosts of the relays we may connect to
+    #[serde(default)]
+    pub relay_policy: RelayPolicy,
+}
+
+/// Which relay URLs we connect to. Disallowed relays are skipped with a
+/// warning, it's an error if none remain
+#[derive(Debug, Clone, Deserialize)]
+pub struct RelayPolicy {
+    #[serde(default = "default_allowed_schemes")]
+    pub allowed_schemes: Vec<String>,
+    // Empty allows any host
+    #[serde(default)]
+    pub allowed_hosts: Vec<String>,
+    // Checked after allowed_hosts
+    #[serde(default)]
+    pub denied_hosts: Vec<String>,
+}
+
+impl Default for RelayPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_schemes: default_allowed_schemes(),
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+        }
+    }
+}
+
+fn default_allowed_schemes() -> Vec<String> {
+    vec!["wss".to_string(), "ws".to_string()]
+}
+
+impl RelayPolicy {
+    /// Hosts are compared whole and case insensitively, subdomains of an
+    /// allowed host aren't allowed
+    pub fn check(&self, relay: &str) -> Result<()> {
+        let url = Url::parse(relay)?;
+        let scheme = url.scheme();
+        if !self
+            .allowed_schemes
+            .iter()
+            .any(|allowed_scheme| allowed_scheme.eq_ignore_ascii_case(scheme))
+        {
+            bail!("the {} scheme is not allowed", scheme);
+        }
+
+        let Some(host) = url.host_str() else {
+            bail!("it has no host");
+        };
+        let listed = |hosts: &[String]| hosts.iter().any(|h| h.eq_ignore_ascii_case(host));
+        if !self.allowed_hosts.is_empty() && !listed(&self.allowed_hosts) {
+            bail!("{} is not an allowed host", host);
+        }
+        if listed(&self.denied_hosts) {
+            bail!("{} is a denied host", host);
+        }
+
+        Ok(())
+    }
+
+    /// The allowed relays, in order
+    pub fn filter(&self, relays: Vec<String>) -> Result<Vec<String>> {
+        let allowed_relays: Vec<String> = relays
+            .iter()
+            .filter(|relay| match self.check(relay) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("Skipping relay {}: {}", relay, e);
+                    false
+                }
+            })
+            .cloned()
+            .collect();
+
+        if allowed_relays.is_empty() {
+            bail!(
+                "None of the relays {:?} is allowed by reportinator.relay_policy",
+                relays
+            );
+        }
+
+        Ok(allowed_relays)
+    }
+}
+
+/// Lookups that time out are tried again, up to attempts times in total, but
+/// accounts without a NIP-05 fall back to their npub right away
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Nip05Lookup {
+    #[serde(default = "default_nip05_timeout_ms")]
+    pub timeout_ms: u64,
+    #[serde(default = "default_nip05_attempts")]
+    pub attempts: u32,
+}
+
+impl Default for Nip05Lookup {
+    fn default() -> Self {
+        Self {
+            timeout_ms: default_nip05_timeout_ms(),
+            attempts: default_nip05_attempts(),
+        }
+    }
+}
+
+fn default_nip05_timeout_ms() -> u64 {
+    250
+}
+
+fn default_nip05_attempts() -> u32 {
+    2
+}
+
+/// A NIP-32 label event with the NIP-69 label of the category, published for
+/// a sample of the targets to shadow_reports.relays, or only logged
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShadowReports {
+    #[serde(default)]
+    pub enabled: bool,
+    // Fraction of the targets, from 0 to 1, sampled by reported pubkey
+    #[serde(default = "default_shadow_sample_rate")]
+    pub sample_rate: f64,
+    // Kept apart from our relays. Shadow reports are only logged when empty
+    #[serde(default)]
+    pub relays: Vec<String>,
+}
+
+impl Default for ShadowReports {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_rate: default_shadow_sample_rate(),
+            relays: Vec::new(),
+        }
+    }
+}
+
+fn default_shadow_sample_rate() -> f64 {
+    1.0
+}
+
+/// Publishing of kind 1985 NIP-32 label events carrying the NIP-69 label
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelEvents {
+    #[default]
+    Off,
+    // Along the reports of every report kind
+    Also,
+    // Instead of the reports, report_kinds is then ignored
+    Only,
+}
+
+impl Config {
+    /// Relays from `RELAY_ADDRESSES_CSV`, falling back to `relays` when the
+    /// variable is unset or empty, without the ones `relay_policy` doesn't
+    /// allow. It's an error if none remain.
+    pub fn get_relays(&self) -> Result<Vec<String>> {
+        let relays = resolve_relays(env::var(RELAYS_ENV_VAR).ok(), &self.relays)?;
+        self.relay_policy.filter(relays)
+    }
+
+    /// Kinds of the events published for every moderated report
+    pub fn published_kinds(&self) -> Vec<Kind> {
+        let mut kinds = match self.label_events {
+            LabelEvents::Only => vec![],
+            LabelEvents::Off | LabelEvents::Also => self.report_kinds.clone(),
+        };
+
+        if self.label_events != LabelEvents::Off {
+            kinds.push(Kind::Label);
+        }
+
+        kinds
+    }
 }
 
 impl Configurable for Config {
     fn key() -> &'static str {
         "reportinator"
     }
+
+    fn validate(&self) -> Result<()> {
+        if self.nip05_lookup.attempts == 0 {
+            bail!("nip05_lookup.attempts must be at least 1");
+        }
+
+        if self.relay_policy.allowed_schemes.is_empty() {
+            bail!("relay_policy.allowed_schemes can't be empty");
+        }
+
+        if !(0.0..=1.0).contains(&self.shadow_reports.sample_rate) {
+            bail!(
+                "shadow_reports.sample_rate must be between 0 and 1, got {}",
+                self.shadow_reports.sample_rate
+            );
+        }
+
+        Ok(())
+    }
 }
 
 fn parse_keys<'de, D>(deserializer: D) -> Result<Keys, D::Error>
@@ -25,18 +261,67 @@ where
     Keys::parse(s).map_err(de::Error::custom)
 }
 
+// Emptiness is checked by `get_relays`, once the env var is considered too
 fn parse_relays<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
 where
     D: Deserializer<'de>,
 {
     let s = String::deserialize(deserializer)?;
+    Ok(split_relays(&s))
+}
+
+fn split_relays(csv: &str) -> Vec<String> {
+    csv.split(',')
+        .map(|relay| relay.trim().to_string())
+        .filter(|relay| !relay.is_empty())
+        .collect()
+}
 
-    if s.trim().is_empty() {
-        return Err(anyhow::anyhow!("RELAY_ADDRESSES_CSV env variable is empty"))
-            .map_err(de::Error::custom);
+// The env var, unless unset or blank, then the configured relays
+fn resolve_relays(relays_csv: Option<String>, configured_relays: &[String]) -> Result<Vec<String>> {
+    let relays = match relays_csv.map(|csv| split_relays(&csv)) {
+        Some(relays) if !relays.is_empty() => relays,
+        _ => configured_relays.to_vec(),
+    };
+
+    if relays.is_empty() {
+        bail!(
+            "No relays configured, set {} or reportinator.relays",
+            RELAYS_ENV_VAR
+        );
+    }
+
+    Ok(relays)
+}
+
+fn default_report_kinds() -> Vec<Kind> {
+    vec![Kind::Reporting]
+}
+
+fn parse_report_kinds<'de, D>(deserializer: D) -> Result<Vec<Kind>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let kinds = Vec::<u16>::deserialize(deserializer)?;
+
+    if kinds.is_empty() {
+        return Err(de::Error::custom("report_kinds can't be empty"));
     }
 
-    Ok(s.split(',').map(|s| s.trim().to_string()).collect())
+    Ok(kinds.into_iter().map(Kind::from).collect())
+}
+
+fn parse_mentions<'de, D>(deserializer: D) -> Result<Vec<PublicKey>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|mention| {
+            PublicKey::parse(mention)
+                .map_err(|e| de::Error::custom(format!("Invalid mention {}: {}", mention, e)))
+        })
+        .collect()
 }
 
 /*
@@ -52,6 +337,147 @@ pub fn config<'a>() -> &'a Config {
     CONFIG.get().unwrap()
 }
 
+/// The configured relays, empty if config was not set
+pub fn relays<'a>() -> &'a [String] {
+    CONFIG
+        .get()
+        .map(|config| config.relays.as_slice())
+        .unwrap_or_default()
+}
+
+/// Where shadow reports are published, empty if config was not set
+pub fn shadow_relays<'a>() -> &'a [String] {
+    CONFIG
+        .get()
+        .map(|config| config.shadow_reports.relays.as_slice())
+        .unwrap_or_default()
+}
+
+/// No limit if config was not set
+pub fn max_reported_content_chars() -> Option<usize> {
+    CONFIG
+        .get()
+        .and_then(|config| config.max_reported_content_chars)
+}
+
+/// The defaults if config was not set
+pub fn nip05_lookup() -> Nip05Lookup {
+    CONFIG
+        .get()
+        .map(|config| config.nip05_lookup)
+        .unwrap_or_default()
+}
+
 pub fn set_config(config: Config) -> Result<(), Config> {
     CONFIG.set(config)
 }
+
+// Keys rotated at runtime take precedence over the configured ones
+static SIGNING_KEYS: RwLock<Option<Keys>> = RwLock::new(None);
+
+/// Keys used to sign the published reports. This will panic if config was
+/// not set and the keys were never rotated.
+pub fn signing_keys() -> Keys {
+    let rotated_keys = SIGNING_KEYS.read().expect("Signing keys lock poisoned");
+
+    match rotated_keys.as_ref() {
+        Some(keys) => keys.clone(),
+        None => config().keys.clone(),
+    }
+}
+
+pub fn set_signing_keys(keys: Keys) {
+    *SIGNING_KEYS.write().expect("Signing keys lock poisoned") = Some(keys);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configured_relays() -> Vec<String> {
+        vec!["wss://configured.example.com".to_string()]
+    }
+
+    #[test]
+    fn test_relays_env_var_takes_precedence() {
+        let relays = resolve_relays(
+            Some("wss://a.example.com, wss://b.example.com".to_string()),
+            &configured_relays(),
+        )
+        .unwrap();
+
+        assert_eq!(relays, vec!["wss://a.example.com", "wss://b.example.com"]);
+    }
+
+    #[test]
+    fn test_relays_fall_back_to_the_config() {
+        assert_eq!(
+            resolve_relays(None, &configured_relays()).unwrap(),
+            configured_relays()
+        );
+        assert_eq!(
+            resolve_relays(Some(" , ".to_string()), &configured_relays()).unwrap(),
+            configured_relays()
+        );
+    }
+
+    #[test]
+    fn test_relays_fail_when_both_are_empty() {
+        assert!(resolve_relays(None, &[]).is_err());
+        assert!(resolve_relays(Some(String::new()), &[]).is_err());
+    }
+
+    fn relay_policy() -> RelayPolicy {
+        RelayPolicy {
+            allowed_schemes: vec!["wss".to_string()],
+            allowed_hosts: vec!["relay.nos.social".to_string(), "nos.lol".to_string()],
+            denied_hosts: vec!["nos.lol".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_relay_policy_skips_disallowed_relays() {
+        let relays = vec![
+            "wss://relay.nos.social".to_string(),
+            "ws://relay.nos.social".to_string(),
+            "wss://untrusted.example.com".to_string(),
+            "wss://nos.lol".to_string(),
+            "wss://sub.relay.nos.social".to_string(),
+            "not a url".to_string(),
+            "wss://RELAY.NOS.SOCIAL/path".to_string(),
+        ];
+
+        assert_eq!(
+            relay_policy().filter(relays).unwrap(),
+            vec!["wss://relay.nos.social", "wss://RELAY.NOS.SOCIAL/path"]
+        );
+    }
+
+    #[test]
+    fn test_relay_policy_fails_when_no_relay_is_allowed() {
+        let error = relay_policy()
+            .filter(vec![
+                "ws://relay.nos.social".to_string(),
+                "wss://untrusted.example.com".to_string(),
+            ])
+            .unwrap_err();
+
+        assert!(error.to_string().contains("relay_policy"), "{}", error);
+    }
+
+    #[test]
+    fn test_default_relay_policy_allows_any_websocket_relay() {
+        let relays = vec![
+            "ws://localhost".to_string(),
+            "wss://relay.example.com".to_string(),
+        ];
+
+        assert_eq!(
+            RelayPolicy::default().filter(relays.clone()).unwrap(),
+            relays
+        );
+        assert!(RelayPolicy::default()
+            .check("https://relay.example.com")
+            .is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use config_rs::{ConfigError, Map, Source, Value, ValueKind};
+use std::collections::HashMap;
+use std::{env, fs};
+
+const FILE_SUFFIX: &str = "_FILE";
+
+/// Config source for secrets mounted as files, like Docker or Kubernetes
+/// secrets. An `APP__SLACK__TOKEN_FILE` variable pointing to a file sets
+/// `slack.token` to the file contents, trailing whitespace removed.
+#[derive(Debug, Clone)]
+pub struct SecretFiles {
+    prefix: String,
+    separator: String,
+    // Used instead of the process environment when set
+    source: Option<HashMap<String, String>>,
+}
+
+impl SecretFiles {
+    pub fn with_prefix(prefix: &str, separator: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            separator: separator.to_string(),
+            source: None,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn source(mut self, source: Option<HashMap<String, String>>) -> Self {
+        self.source = source;
+        self
+    }
+
+    // The config key for a `*_FILE` variable with our prefix
+    fn key(&self, variable: &str) -> Option<String> {
+        let path = variable
+            .strip_prefix(&self.prefix)?
+            .strip_prefix(&self.separator)?
+            .strip_suffix(FILE_SUFFIX)?;
+
+        if path.is_empty() {
+            return None;
+        }
+
+        Some(path.to_lowercase().replace(&self.separator, "."))
+    }
+}
+
+impl Source for SecretFiles {
+    fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+        let variables: Vec<(String, String)> = match &self.source {
+            Some(source) => source.clone().into_iter().collect(),
+            None => env::vars().collect(),
+        };
+
+        let mut secrets = Map::new();
+        for (variable, secret_path) in variables {
+            let Some(key) = self.key(&variable) else {
+                continue;
+            };
+
+            let contents = fs::read_to_string(&secret_path).map_err(|e| {
+                ConfigError::Message(format!(
+                    "Failed to read secret file {} set in {}: {}",
+                    secret_path, variable, e
+                ))
+            })?;
+
+            secrets.insert(
+                key,
+                Value::new(
+                    Some(&secret_path),
+                    ValueKind::String(contents.trim_end().to_string()),
+                ),
+            );
+        }
+
+        Ok(secrets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config_rs::Config as ConfigTree;
+
+    fn secret_files(variables: &[(&str, &str)]) -> SecretFiles {
+        let source = variables
+            .iter()
+            .map(|(variable, value)| (variable.to_string(), value.to_string()))
+            .collect();
+
+        SecretFiles::with_prefix("APP", "__").source(Some(source))
+    }
+
+    #[test]
+    fn test_reads_secret_from_file() {
+        let secret_path = env::temp_dir().join("test_reads_secret_from_file_token");
+        fs::write(&secret_path, "xoxb-secret\n").unwrap();
+
+        let config = ConfigTree::builder()
+            .set_default("slack.token", "<NOT_SET>")
+            .unwrap()
+            .add_source(secret_files(&[
+                ("APP__SLACK__TOKEN_FILE", secret_path.to_str().unwrap()),
+                ("APP__SLACK__CHANNEL_ID", "C123"),
+                ("OTHER__TOKEN_FILE", "/nonexistent"),
+            ]))
+            .build()
+            .unwrap();
+
+        assert_eq!(config.get_string("slack.token").unwrap(), "xoxb-secret");
+        assert!(config.get_string("slack.channel_id").is_err());
+
+        fs::remove_file(secret_path).unwrap();
+    }
+
+    #[test]
+    fn test_fails_on_missing_secret_file() {
+        let result = ConfigTree::builder()
+            .add_source(secret_files(&[(
+                "APP__SLACK__TOKEN_FILE",
+                "/nonexistent/reportinator/token",
+            )]))
+            .build();
+
+        assert!(result.is_err());
+    }
+}
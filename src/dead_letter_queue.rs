@@ -0,0 +1,339 @@
+use crate::actors::NostrPort;
+use crate::config::Configurable;
+use crate::domain_objects::ModeratedReport;
+use anyhow::{Context, Result};
+use metrics::counter;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{error, info};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // JSONL file where reports that failed to be published are kept
+    #[serde(default = "default_path")]
+    pub path: String,
+    // JSONL file where report requests the retry actor gave up on are kept
+    #[serde(default = "default_report_requests_path")]
+    pub report_requests_path: String,
+}
+
+fn default_path() -> String {
+    "dead_letter.jsonl".to_string()
+}
+
+fn default_report_requests_path() -> String {
+    "dead_letter_report_requests.jsonl".to_string()
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "dead_letter"
+    }
+}
+
+// Each line of the file is an entry, like a moderated report, that failed to
+// make it downstream
+#[derive(Debug, Clone)]
+pub struct DeadLetterQueue {
+    path: PathBuf,
+}
+
+impl DeadLetterQueue {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            path: PathBuf::from(&config.path),
+        }
+    }
+
+    /// The queue for report requests instead of moderated reports
+    pub fn for_report_requests(config: &Config) -> Self {
+        Self {
+            path: PathBuf::from(&config.report_requests_path),
+        }
+    }
+
+    pub fn append<T: Serialize>(&self, entry: &T) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    pub fn read<T: DeserializeOwned>(&self) -> Result<Vec<T>> {
+        read_entries(&self.path)
+    }
+
+    // Where the entries being replayed are moved, so what the actors append
+    // meanwhile starts a new file
+    fn replaying_path(&self) -> PathBuf {
+        self.path.with_extension("jsonl.replaying")
+    }
+}
+
+fn read_entries<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    BufReader::new(file)
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| {
+            let line = line?;
+            serde_json::from_str(&line)
+                .with_context(|| format!("Invalid dead letter entry: {}", line))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayBackoff {
+    pub attempts: u32,
+    pub initial_delay: Duration,
+}
+
+impl Default for ReplayBackoff {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            initial_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Waits with backoff for the relays the dispatcher connects to. Returns
+/// false if they are still disconnected after the last attempt
+pub async fn wait_until_connected<T: NostrPort>(nostr_client: &T, backoff: ReplayBackoff) -> bool {
+    let mut delay = backoff.initial_delay;
+    for _ in 0..backoff.attempts {
+        if nostr_client.is_connected().await {
+            return true;
+        }
+
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+
+    nostr_client.is_connected().await
+}
+
+/// Moves the file aside and hands every entry to `send`, which passes it on
+/// to the actor of the normal path. The move is an atomic rename, so the
+/// entries the actors append meanwhile, like the ones failing again, go to a
+/// new file. The ones `send` can't pass on are appended there too. Entries
+/// of a replay that stopped halfway are replayed first. Returns the number
+/// of entries passed on
+pub fn replay<T, F>(dead_letter_queue: &DeadLetterQueue, mut send: F) -> Result<usize>
+where
+    T: Clone + Serialize + DeserializeOwned,
+    F: FnMut(T) -> Result<()>,
+{
+    let replaying_path = dead_letter_queue.replaying_path();
+    let mut replayed = 0;
+    if replaying_path.exists() {
+        replayed += replay_file(dead_letter_queue, &replaying_path, &mut send)?;
+    }
+
+    if dead_letter_queue.path.exists() {
+        fs::rename(&dead_letter_queue.path, &replaying_path).with_context(|| {
+            format!(
+                "Failed to move {} aside for the replay",
+                dead_letter_queue.path.display()
+            )
+        })?;
+        replayed += replay_file(dead_letter_queue, &replaying_path, &mut send)?;
+    }
+
+    if replayed == 0 {
+        info!(
+            "No dead letters to replay in {}",
+            dead_letter_queue.path.display()
+        );
+        return Ok(0);
+    }
+
+    info!(
+        "Replayed {} dead letters from {}",
+        replayed,
+        dead_letter_queue.path.display()
+    );
+    Ok(replayed)
+}
+
+// Removed once every entry was passed on or appended back, a crash before
+// that replays them again
+fn replay_file<T, F>(
+    dead_letter_queue: &DeadLetterQueue,
+    replaying_path: &Path,
+    send: &mut F,
+) -> Result<usize>
+where
+    T: Clone + Serialize + DeserializeOwned,
+    F: FnMut(T) -> Result<()>,
+{
+    let entries: Vec<T> = read_entries(replaying_path)?;
+
+    let mut replayed = 0;
+    for entry in entries {
+        match send(entry.clone()) {
+            Ok(()) => {
+                replayed += 1;
+                counter!("dead_letter_replayed").increment(1);
+            }
+            Err(e) => {
+                counter!("dead_letter_replay_error").increment(1);
+                error!("Failed to replay dead letter: {}", e);
+                dead_letter_queue.append(&entry)?;
+            }
+        }
+    }
+
+    fs::remove_file(replaying_path)
+        .with_context(|| format!("Failed to remove {}", replaying_path.display()))?;
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::bail;
+    use nostr_sdk::prelude::*;
+    use serde_json::json;
+
+    fn moderated_report(content: &str) -> ModeratedReport {
+        let event = EventBuilder::new(Kind::Reporting, content, [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        serde_json::from_value(json!({ "events": [event] })).unwrap()
+    }
+
+    fn dead_letter_queue(name: &str) -> DeadLetterQueue {
+        let path =
+            std::env::temp_dir().join(format!("{}_{}.jsonl", name, Timestamp::now().as_u64()));
+        let _ = fs::remove_file(&path);
+
+        DeadLetterQueue::new(&Config {
+            path: path.to_string_lossy().to_string(),
+            report_requests_path: default_report_requests_path(),
+        })
+    }
+
+    #[test]
+    fn test_replayed_entry_is_removed() {
+        let dead_letter_queue = dead_letter_queue("test_replayed_entry_is_removed");
+        let replayable = moderated_report("replayable");
+        let unsent = moderated_report("unsent");
+        dead_letter_queue.append(&replayable).unwrap();
+        dead_letter_queue.append(&unsent).unwrap();
+
+        let mut sent = vec![];
+        let replayed = replay(&dead_letter_queue, |moderated_report: ModeratedReport| {
+            if moderated_report.id() == unsent.id() {
+                bail!("The dispatcher is gone");
+            }
+            sent.push(moderated_report);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(replayed, 1);
+        assert_eq!(sent, vec![replayable]);
+        assert_eq!(
+            dead_letter_queue.read::<ModeratedReport>().unwrap(),
+            vec![unsent]
+        );
+
+        fs::remove_file(&dead_letter_queue.path).unwrap();
+    }
+
+    #[test]
+    fn test_entries_failing_again_are_kept() {
+        let dead_letter_queue = dead_letter_queue("test_entries_failing_again_are_kept");
+        let failing = moderated_report("failing");
+        dead_letter_queue.append(&failing).unwrap();
+
+        // Like the dispatcher, which appends the reports it fails to publish
+        let replayed = replay(&dead_letter_queue, |moderated_report: ModeratedReport| {
+            dead_letter_queue.append(&moderated_report)
+        })
+        .unwrap();
+
+        assert_eq!(replayed, 1);
+        assert_eq!(
+            dead_letter_queue.read::<ModeratedReport>().unwrap(),
+            vec![failing]
+        );
+
+        fs::remove_file(&dead_letter_queue.path).unwrap();
+    }
+
+    #[test]
+    fn test_entries_appended_during_the_replay_are_kept() {
+        let dead_letter_queue =
+            dead_letter_queue("test_entries_appended_during_the_replay_are_kept");
+        let replayable = moderated_report("replayable");
+        let appended = moderated_report("appended");
+        dead_letter_queue.append(&replayable).unwrap();
+
+        // Another actor failing to publish while the replay runs
+        let other_dead_letter_queue = dead_letter_queue.clone();
+        let replayed = replay(&dead_letter_queue, |_: ModeratedReport| {
+            other_dead_letter_queue.append(&appended)
+        })
+        .unwrap();
+
+        assert_eq!(replayed, 1);
+        assert_eq!(
+            dead_letter_queue.read::<ModeratedReport>().unwrap(),
+            vec![appended]
+        );
+        assert!(!dead_letter_queue.replaying_path().exists());
+
+        fs::remove_file(&dead_letter_queue.path).unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_replays_are_resumed() {
+        let dead_letter_queue = dead_letter_queue("test_interrupted_replays_are_resumed");
+        let interrupted = moderated_report("interrupted");
+        let queued = moderated_report("queued");
+        fs::write(
+            dead_letter_queue.replaying_path(),
+            format!("{}\n", serde_json::to_string(&interrupted).unwrap()),
+        )
+        .unwrap();
+        dead_letter_queue.append(&queued).unwrap();
+
+        let mut sent = vec![];
+        let replayed = replay(&dead_letter_queue, |moderated_report: ModeratedReport| {
+            sent.push(moderated_report);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(replayed, 2);
+        assert_eq!(sent, vec![interrupted, queued]);
+        assert!(!dead_letter_queue.path.exists());
+        assert!(!dead_letter_queue.replaying_path().exists());
+    }
+
+    #[test]
+    fn test_missing_file_has_nothing_to_replay() {
+        let dead_letter_queue = dead_letter_queue("test_missing_file_has_nothing_to_replay");
+
+        let replayed = replay(&dead_letter_queue, |_: ModeratedReport| Ok(())).unwrap();
+
+        assert_eq!(replayed, 0);
+        assert!(!dead_letter_queue.path.exists());
+    }
+}
@@ -1,11 +1,16 @@
 pub mod gift_wrap;
-pub use gift_wrap::GiftWrappedReportRequest;
+pub use gift_wrap::{GiftWrapError, GiftWrappedReportRequest};
 
 pub mod report_request;
+pub use report_request::truncate_content;
 pub use report_request::ReportRequest;
 pub use report_request::ReportTarget;
+pub use report_request::{is_truncated_event, truncated_event};
 
 pub mod as_gift_wrap;
 
+pub mod moderation_category;
+pub use moderation_category::ModerationCategory;
+
 pub mod moderated_report;
 pub use moderated_report::ModeratedReport;
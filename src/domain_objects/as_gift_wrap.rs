@@ -1,8 +1,47 @@
+use super::gift_wrap::leading_zero_bits;
 use super::ReportRequest;
 use crate::domain_objects::GiftWrappedReportRequest;
-use anyhow::Result;
+use anyhow::{bail, Result};
 use nostr_sdk::prelude::*;
 
+// NIP-59 recommends randomizing the seal and gift wrap timestamps up to two
+// days in the past so relays and observers can't correlate them with the
+// moment the message was actually written.
+const TWO_DAYS: u64 = 2 * 24 * 60 * 60;
+
+/// Enough for a difficulty in the mid twenties, it takes about 2^difficulty
+/// attempts on average
+pub const DEFAULT_MAX_POW_ITERATIONS: u64 = 1 << 28;
+
+/// NIP-13 proof of work mined into our gift wraps, and optionally their seals,
+/// so they get through relays requiring it. No work for a zero difficulty
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofOfWork {
+    pub difficulty: u8,
+    pub seal: bool,
+    // Mining errors out once this many nonces were tried
+    pub max_iterations: u64,
+}
+
+impl Default for ProofOfWork {
+    fn default() -> Self {
+        Self {
+            difficulty: 0,
+            seal: false,
+            max_iterations: DEFAULT_MAX_POW_ITERATIONS,
+        }
+    }
+}
+
+impl ProofOfWork {
+    fn for_seal(&self) -> Self {
+        match self.seal {
+            true => *self,
+            false => Self::default(),
+        }
+    }
+}
+
 #[async_trait]
 pub trait AsGiftWrap {
     #[allow(unused)]
@@ -10,26 +49,72 @@ pub trait AsGiftWrap {
         &self,
         reporter_keys: &Keys,
         receiver_pubkey: &PublicKey,
+    ) -> Result<GiftWrappedReportRequest> {
+        self.as_gift_wrap_with_pow(reporter_keys, receiver_pubkey, ProofOfWork::default())
+            .await
+    }
+
+    /// Like `as_gift_wrap`, mining the proof of work into the gift wrap
+    async fn as_gift_wrap_with_pow(
+        &self,
+        reporter_keys: &Keys,
+        receiver_pubkey: &PublicKey,
+        pow: ProofOfWork,
     ) -> Result<GiftWrappedReportRequest>;
 
     fn random_time_in_last_two_days(&self) -> Timestamp {
-        let now = Timestamp::now();
-        let two_days = 2 * 24 * 60 * 60;
-        now - (rand::random::<u64>() % two_days)
+        random_time_in_last_two_days()
     }
 }
 
+fn random_time_in_last_two_days() -> Timestamp {
+    let now = Timestamp::now();
+    now - (rand::random::<u64>() % TWO_DAYS)
+}
+
+/// Gift wraps a plain text private message, like the acknowledgments the
+/// reportinator sends back to reporters. Same timestamps as `as_gift_wrap`
+pub async fn gift_wrapped_message(
+    sender_keys: &Keys,
+    receiver_pubkey: &PublicKey,
+    content: String,
+) -> Result<Event> {
+    let kind_14_rumor = rumor(sender_keys, receiver_pubkey, content);
+    let kind_13_seal = seal(
+        sender_keys,
+        receiver_pubkey,
+        &kind_14_rumor,
+        random_time_in_last_two_days(),
+        &ProofOfWork::default(),
+    )
+    .await?;
+
+    wrap(
+        receiver_pubkey,
+        &kind_13_seal,
+        random_time_in_last_two_days(),
+        &ProofOfWork::default(),
+    )
+    .await
+}
+
 #[async_trait]
 impl AsGiftWrap for ReportRequest {
-    // NOTE: This roughly creates a message as described by nip 17 but it's still
-    // not ready, just for testing purposes. There are more details to consider to
-    // properly implement the nip like created_at treatment. The nip itself is not
-    // finished at this time so hopefully in the future this can be done through the
-    // nostr crate.
-    async fn as_gift_wrap(
+    // Creates a message as described by NIP-17, using the timestamps
+    // recommended by NIP-59:
+    //
+    // - The kind 14 rumor keeps the real created_at, it's only visible to the
+    //   receiver once decrypted and it's the actual time of the request.
+    // - The kind 13 seal, signed by the reporter, gets a random created_at
+    //   within the last two days.
+    // - The kind 1059 gift wrap, signed by a one time key, gets its own
+    //   random created_at within the last two days, independent from the
+    //   seal one.
+    async fn as_gift_wrap_with_pow(
         &self,
         reporter_keys: &Keys,
         receiver_pubkey: &PublicKey,
+        pow: ProofOfWork,
     ) -> Result<GiftWrappedReportRequest> {
         if self.reporter_pubkey() != &reporter_keys.public_key() {
             return Err(anyhow::anyhow!(
@@ -39,33 +124,125 @@ impl AsGiftWrap for ReportRequest {
 
         let report_request_json =
             serde_json::to_string(self).expect("Failed to serialize ReportRequest to JSON");
-        // Compose rumor
-        let kind_14_rumor =
-            EventBuilder::private_msg_rumor(*receiver_pubkey, report_request_json, None)
-                .to_unsigned_event(reporter_keys.public_key());
-
-        // Compose seal
-        let content: String = NostrSigner::Keys(reporter_keys.clone())
-            .nip44_encrypt(*receiver_pubkey, kind_14_rumor.as_json())
-            .await?;
-        let kind_13_seal = EventBuilder::new(Kind::Seal, content, [])
-            .custom_created_at(self.random_time_in_last_two_days())
-            .to_event(reporter_keys)?;
-
-        // Compose gift wrap
-        let expiration = None; // TODO
-        let kind_1059_gift_wrap: Event =
-            EventBuilder::gift_wrap_from_seal(receiver_pubkey, &kind_13_seal, expiration)?;
+
+        let kind_14_rumor = rumor(reporter_keys, receiver_pubkey, report_request_json);
+        let kind_13_seal = seal(
+            reporter_keys,
+            receiver_pubkey,
+            &kind_14_rumor,
+            self.random_time_in_last_two_days(),
+            &pow.for_seal(),
+        )
+        .await?;
+        let kind_1059_gift_wrap = wrap(
+            receiver_pubkey,
+            &kind_13_seal,
+            self.random_time_in_last_two_days(),
+            &pow,
+        )
+        .await?;
 
         let gift_wrap = GiftWrappedReportRequest::try_from(kind_1059_gift_wrap)?;
         Ok(gift_wrap)
     }
 }
 
+// The unsigned kind 14 rumor, created_at is the real current time
+pub(super) fn rumor(
+    reporter_keys: &Keys,
+    receiver_pubkey: &PublicKey,
+    content: String,
+) -> UnsignedEvent {
+    EventBuilder::private_msg_rumor(*receiver_pubkey, content, None)
+        .to_unsigned_event(reporter_keys.public_key())
+}
+
+// The kind 13 seal, the rumor encrypted and signed by the reporter
+pub(super) async fn seal(
+    reporter_keys: &Keys,
+    receiver_pubkey: &PublicKey,
+    rumor: &UnsignedEvent,
+    created_at: Timestamp,
+    pow: &ProofOfWork,
+) -> Result<Event> {
+    let content: String = NostrSigner::Keys(reporter_keys.clone())
+        .nip44_encrypt(*receiver_pubkey, rumor.as_json())
+        .await?;
+
+    mined_event(Kind::Seal, content, vec![], created_at, reporter_keys, pow)
+}
+
+// The kind 1059 gift wrap, the seal encrypted and signed by a one time key
+pub(super) async fn wrap(
+    receiver_pubkey: &PublicKey,
+    seal: &Event,
+    created_at: Timestamp,
+    pow: &ProofOfWork,
+) -> Result<Event> {
+    let ephemeral_keys = Keys::generate();
+    let content: String = NostrSigner::Keys(ephemeral_keys.clone())
+        .nip44_encrypt(*receiver_pubkey, seal.as_json())
+        .await?;
+
+    mined_event(
+        Kind::GiftWrap,
+        content,
+        vec![Tag::public_key(*receiver_pubkey)],
+        created_at,
+        &ephemeral_keys,
+        pow,
+    )
+}
+
+// Tries nonces until the id has the difficulty, keeping the created_at we
+// picked instead of bumping it like `EventBuilder::pow` does. Only the
+// winning nonce gets signed
+fn mined_event(
+    kind: Kind,
+    content: String,
+    tags: Vec<Tag>,
+    created_at: Timestamp,
+    keys: &Keys,
+    pow: &ProofOfWork,
+) -> Result<Event> {
+    if pow.difficulty == 0 {
+        let event = EventBuilder::new(kind, content, tags)
+            .custom_created_at(created_at)
+            .to_event(keys)?;
+        return Ok(event);
+    }
+
+    let public_key = keys.public_key();
+    for nonce in 0..pow.max_iterations {
+        let mut nonce_tags = tags.clone();
+        nonce_tags.push(Tag::pow(nonce as u128, pow.difficulty));
+
+        let id = EventId::new(&public_key, &created_at, &kind, &nonce_tags, &content);
+        if leading_zero_bits(id.as_bytes()) >= pow.difficulty {
+            let event = EventBuilder::new(kind, content, nonce_tags)
+                .custom_created_at(created_at)
+                .to_event(keys)?;
+            return Ok(event);
+        }
+    }
+
+    bail!(
+        "Difficulty {} not reached after {} iterations",
+        pow.difficulty,
+        pow.max_iterations
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn assert_within_last_two_days(timestamp: Timestamp) {
+        let now = Timestamp::now();
+        assert!(timestamp <= now);
+        assert!(timestamp >= now - TWO_DAYS);
+    }
+
     #[tokio::test]
     async fn test_as_gift_wrap() {
         let reporter_keys = Keys::generate();
@@ -85,6 +262,160 @@ mod tests {
             .extract_report_request(&receiver_keys)
             .expect("Failed to extract report request");
 
-        assert_eq!(unwrapped_report_request, report_request);
+        assert_eq!(
+            unwrapped_report_request,
+            report_request.with_gift_wrap_id(gift_wrap.id())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_gift_wrapped_message() {
+        let sender_keys = Keys::generate();
+        let receiver_keys = Keys::generate();
+
+        let gift_wrap = gift_wrapped_message(
+            &sender_keys,
+            &receiver_keys.public_key(),
+            "Thanks for your report".to_string(),
+        )
+        .await
+        .unwrap();
+
+        let unwrapped_gift = extract_rumor(&receiver_keys, &gift_wrap).unwrap();
+        assert_eq!(unwrapped_gift.sender, sender_keys.public_key());
+        assert_eq!(unwrapped_gift.rumor.content, "Thanks for your report");
+        assert_within_last_two_days(gift_wrap.created_at);
+    }
+
+    #[test]
+    fn test_rumor_keeps_the_real_created_at() {
+        let reporter_keys = Keys::generate();
+        let receiver_pubkey = Keys::generate().public_key();
+        let before = Timestamp::now();
+
+        let rumor = rumor(&reporter_keys, &receiver_pubkey, "content".to_string());
+
+        assert_eq!(rumor.kind, Kind::PrivateDirectMessage);
+        assert_eq!(rumor.pubkey, reporter_keys.public_key());
+        assert!(rumor.created_at >= before);
+        assert!(rumor.created_at <= Timestamp::now());
+    }
+
+    #[tokio::test]
+    async fn test_seal_and_wrap_are_randomized_in_the_past() {
+        let reporter_keys = Keys::generate();
+        let receiver_pubkey = Keys::generate().public_key();
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            reporter_keys.public_key(),
+            None,
+        );
+
+        let rumor = rumor(&reporter_keys, &receiver_pubkey, "content".to_string());
+        let seal = seal(
+            &reporter_keys,
+            &receiver_pubkey,
+            &rumor,
+            report_request.random_time_in_last_two_days(),
+            &ProofOfWork::default(),
+        )
+        .await
+        .unwrap();
+        let gift_wrap = wrap(
+            &receiver_pubkey,
+            &seal,
+            report_request.random_time_in_last_two_days(),
+            &ProofOfWork::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(seal.kind, Kind::Seal);
+        assert_eq!(seal.pubkey, reporter_keys.public_key());
+        assert_within_last_two_days(seal.created_at);
+
+        assert_eq!(gift_wrap.kind, Kind::GiftWrap);
+        assert_ne!(gift_wrap.pubkey, reporter_keys.public_key());
+        assert_within_last_two_days(gift_wrap.created_at);
+    }
+
+    #[tokio::test]
+    async fn test_as_gift_wrap_with_pow() {
+        let reporter_keys = Keys::generate();
+        let receiver_keys = Keys::generate();
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            reporter_keys.public_key(),
+            None,
+        );
+        let pow = ProofOfWork {
+            difficulty: 8,
+            ..Default::default()
+        };
+
+        let gift_wrap = report_request
+            .as_gift_wrap_with_pow(&reporter_keys, &receiver_keys.public_key(), pow)
+            .await
+            .unwrap();
+
+        assert!(gift_wrap.pow_difficulty() >= 8);
+        assert_eq!(
+            gift_wrap.extract_report_request(&receiver_keys).unwrap(),
+            report_request.with_gift_wrap_id(gift_wrap.id())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_seal_pow_is_optional() {
+        let reporter_keys = Keys::generate();
+        let receiver_pubkey = Keys::generate().public_key();
+        let rumor = rumor(&reporter_keys, &receiver_pubkey, "content".to_string());
+        let pow = |seal| ProofOfWork {
+            difficulty: 8,
+            seal,
+            ..Default::default()
+        };
+
+        let mined_seal = seal(
+            &reporter_keys,
+            &receiver_pubkey,
+            &rumor,
+            Timestamp::now(),
+            &pow(true).for_seal(),
+        )
+        .await
+        .unwrap();
+        assert!(leading_zero_bits(mined_seal.id.as_bytes()) >= 8);
+        assert!(mined_seal.verify().is_ok());
+
+        assert_eq!(pow(false).for_seal(), ProofOfWork::default());
+    }
+
+    #[tokio::test]
+    async fn test_pow_gives_up_after_max_iterations() {
+        let reporter_keys = Keys::generate();
+        let receiver_pubkey = Keys::generate().public_key();
+        let rumor = rumor(&reporter_keys, &receiver_pubkey, "content".to_string());
+        let pow = ProofOfWork {
+            difficulty: 255,
+            seal: true,
+            max_iterations: 10,
+        };
+
+        let error = seal(
+            &reporter_keys,
+            &receiver_pubkey,
+            &rumor,
+            Timestamp::now(),
+            &pow,
+        )
+        .await
+        .unwrap_err();
+
+        assert!(
+            error.to_string().contains("after 10 iterations"),
+            "{}",
+            error
+        );
     }
 }
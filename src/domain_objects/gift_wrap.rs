@@ -1,9 +1,8 @@
-use super::report_request::ReportRequestRumorContent;
+use super::report_request::{ReportRequestRumorContent, SignatureVerification};
 use crate::domain_objects::ReportRequest;
-use anyhow::{bail, Context, Result};
 use nostr_sdk::prelude::*;
 use std::convert::TryFrom;
-use std::fmt::Debug;
+use std::fmt::{self, Debug, Display, Formatter};
 
 //Newtype
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -18,39 +17,277 @@ impl GiftWrappedReportRequest {
         self.0.as_json()
     }
 
-    pub fn extract_report_request(&self, keys: &Keys) -> Result<ReportRequest> {
-        let unwrapped_gift = extract_rumor(keys, &self.0).context("Couldn't extract rumor")?;
+    pub fn id(&self) -> EventId {
+        self.0.id
+    }
 
-        let report_request_rumor_content =
-            ReportRequestRumorContent::parse(&unwrapped_gift.rumor.content).context(format!(
-                "Failed to parse report request rumor content: {}",
-                unwrapped_gift.rumor.content
-            ))?;
+    pub fn created_at(&self) -> Timestamp {
+        self.0.created_at
+    }
 
-        let report_request =
-            report_request_rumor_content.into_report_request(unwrapped_gift.rumor.pubkey);
+    /// NIP-13 difficulty of the outer gift wrap, the number of leading zero
+    /// bits of its id
+    pub fn pow_difficulty(&self) -> u8 {
+        leading_zero_bits(self.0.id.as_bytes())
+    }
+
+    pub fn extract_report_request(&self, keys: &Keys) -> Result<ReportRequest, GiftWrapError> {
+        self.extract_report_request_with(keys, SignatureVerification::Full)
+    }
+
+    /// Like `extract_report_request`, with an id only check of the reported
+    /// event when asked to
+    pub fn extract_report_request_with(
+        &self,
+        keys: &Keys,
+        signature_verification: SignatureVerification,
+    ) -> Result<ReportRequest, GiftWrapError> {
+        let unwrapped_gift = extract_rumor(keys, &self.0)
+            .map_err(|e| GiftWrapError::Decryption(self.0.id, e.to_string()))?;
+
+        let rumor = unwrapped_gift.rumor;
+        let report_request_rumor_content = ReportRequestRumorContent::parse(&rumor.content)
+            .map_err(|e| {
+                if is_json_object(&rumor.content) {
+                    GiftWrapError::RumorContentParse(self.0.id, format!("{}: {}", e, rumor.content))
+                } else {
+                    GiftWrapError::NonReportDm(self.0.id, rumor.kind)
+                }
+            })?;
+
+        report_request_rumor_content
+            .into_report_request(rumor.pubkey, Some(self.0.id), signature_verification)
+            .map_err(|_| GiftWrapError::InvalidReportRequest(self.0.id))
+    }
+}
+
+// Report requests are JSON objects, anything else is someone just sending
+// us a DM rather than a malformed report request
+fn is_json_object(content: &str) -> bool {
+    serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(content).is_ok()
+}
+
+// Saturates at 255 for the all zeroes id
+pub(super) fn leading_zero_bits(bytes: &[u8]) -> u8 {
+    let mut bits: u32 = 0;
+    for byte in bytes {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
 
-        if !report_request.valid() {
-            bail!("{} is not a valid gift wrapped report request", self.0.id());
+    bits.min(u8::MAX as u32) as u8
+}
+
+/// The reasons why we couldn't get a report request out of a gift wrap. They
+/// help telling apart spam, bugs on the reporter side or key mismatches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GiftWrapError {
+    WrongKind(EventId, Kind),
+    Decryption(EventId, String),
+    // Decrypted fine, with the kind of the rumor, but it isn't a report
+    // request at all
+    NonReportDm(EventId, Kind),
+    RumorContentParse(EventId, String),
+    InvalidReportRequest(EventId),
+}
+
+impl GiftWrapError {
+    /// Short label used for metrics and log fields
+    pub fn reason(&self) -> &'static str {
+        match self {
+            GiftWrapError::WrongKind(..) => "wrong_kind",
+            GiftWrapError::Decryption(..) => "decryption_failure",
+            GiftWrapError::NonReportDm(..) => "non_report_dm",
+            GiftWrapError::RumorContentParse(..) => "rumor_content_parse_failure",
+            GiftWrapError::InvalidReportRequest(..) => "invalid_report_request",
         }
+    }
+}
 
-        Ok(report_request)
+impl Display for GiftWrapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GiftWrapError::WrongKind(id, kind) => {
+                write!(f, "Event kind is not 1059. id:{} kind:{}", id, kind)
+            }
+            GiftWrapError::Decryption(id, e) => {
+                write!(f, "Couldn't extract rumor from {}: {}", id, e)
+            }
+            GiftWrapError::NonReportDm(id, kind) => {
+                write!(f, "{} is a kind {} DM, not a report request", id, kind)
+            }
+            GiftWrapError::RumorContentParse(id, e) => write!(
+                f,
+                "Failed to parse report request rumor content from {}: {}",
+                id, e
+            ),
+            GiftWrapError::InvalidReportRequest(id) => {
+                write!(f, "{} is not a valid gift wrapped report request", id)
+            }
+        }
     }
 }
 
+impl std::error::Error for GiftWrapError {}
+
 impl TryFrom<Event> for GiftWrappedReportRequest {
-    // TODO: We should have better custom errors at some point
-    type Error = anyhow::Error;
+    type Error = GiftWrapError;
 
-    fn try_from(event: Event) -> Result<Self> {
+    fn try_from(event: Event) -> Result<Self, Self::Error> {
         if event.kind == Kind::GiftWrap {
             Ok(GiftWrappedReportRequest::new(event))
         } else {
-            bail!(
-                "Event kind is not 1059. id:{} kind:{}",
-                event.id,
-                event.kind
-            )
+            Err(GiftWrapError::WrongKind(event.id, event.kind))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain_objects::as_gift_wrap::{rumor, seal, wrap, AsGiftWrap, ProofOfWork};
+
+    async fn gift_wrap_with_rumor(
+        sender_keys: &Keys,
+        receiver_pubkey: &PublicKey,
+        rumor: UnsignedEvent,
+    ) -> GiftWrappedReportRequest {
+        let pow = ProofOfWork::default();
+        let seal = seal(sender_keys, receiver_pubkey, &rumor, Timestamp::now(), &pow)
+            .await
+            .unwrap();
+        let gift_wrap = wrap(receiver_pubkey, &seal, Timestamp::now(), &pow)
+            .await
+            .unwrap();
+
+        GiftWrappedReportRequest::try_from(gift_wrap).unwrap()
+    }
+
+    async fn gift_wrap_with_content(
+        sender_keys: &Keys,
+        receiver_pubkey: &PublicKey,
+        content: &str,
+    ) -> GiftWrappedReportRequest {
+        let rumor = rumor(sender_keys, receiver_pubkey, content.to_string());
+        gift_wrap_with_rumor(sender_keys, receiver_pubkey, rumor).await
+    }
+
+    #[test]
+    fn test_leading_zero_bits_of_high_difficulty_id() {
+        let id =
+            EventId::from_hex("00000fffffffffffffffffffffffffffffffffffffffffffffffffffffffffff")
+                .unwrap();
+
+        assert_eq!(leading_zero_bits(id.as_bytes()), 20);
+    }
+
+    #[test]
+    fn test_leading_zero_bits_of_low_difficulty_id() {
+        let id =
+            EventId::from_hex("4fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff")
+                .unwrap();
+
+        assert_eq!(leading_zero_bits(id.as_bytes()), 1);
+    }
+
+    #[test]
+    fn test_wrong_kind() {
+        let event = EventBuilder::text_note("Not a gift wrap", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        let error = GiftWrappedReportRequest::try_from(event).unwrap_err();
+
+        assert_eq!(error.reason(), "wrong_kind");
+    }
+
+    #[tokio::test]
+    async fn test_decryption_failure() {
+        let sender_keys = Keys::generate();
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            sender_keys.public_key(),
+            None,
+        );
+
+        // Wrapped for someone else
+        let gift_wrap = report_request
+            .as_gift_wrap(&sender_keys, &Keys::generate().public_key())
+            .await
+            .unwrap();
+
+        let error = gift_wrap
+            .extract_report_request(&Keys::generate())
+            .unwrap_err();
+
+        assert_eq!(error.reason(), "decryption_failure");
+    }
+
+    #[tokio::test]
+    async fn test_rumor_content_parse_failure() {
+        let sender_keys = Keys::generate();
+        let receiver_keys = Keys::generate();
+
+        let gift_wrap = gift_wrap_with_content(
+            &sender_keys,
+            &receiver_keys.public_key(),
+            r#"{"reporterText": "No target"}"#,
+        )
+        .await;
+
+        let error = gift_wrap
+            .extract_report_request(&receiver_keys)
+            .unwrap_err();
+
+        assert_eq!(error.reason(), "rumor_content_parse_failure");
+    }
+
+    #[tokio::test]
+    async fn test_non_report_dm() {
+        let sender_keys = Keys::generate();
+        let receiver_keys = Keys::generate();
+
+        let text_note_rumor =
+            EventBuilder::text_note("Hi there!", []).to_unsigned_event(sender_keys.public_key());
+        let gift_wrap =
+            gift_wrap_with_rumor(&sender_keys, &receiver_keys.public_key(), text_note_rumor).await;
+
+        let error = gift_wrap
+            .extract_report_request(&receiver_keys)
+            .unwrap_err();
+
+        assert_eq!(error.reason(), "non_report_dm");
+        assert_eq!(
+            error,
+            GiftWrapError::NonReportDm(gift_wrap.id(), Kind::TextNote)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_report_request() {
+        let sender_keys = Keys::generate();
+        let receiver_keys = Keys::generate();
+
+        let reported_event = EventBuilder::text_note("Original content", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let mut reported_event_value = serde_json::to_value(reported_event).unwrap();
+        reported_event_value["content"] = "Tampered content".into();
+        let tampered_event: Event = serde_json::from_value(reported_event_value).unwrap();
+
+        let report_request =
+            ReportRequest::new(tampered_event.into(), sender_keys.public_key(), None);
+        let gift_wrap = report_request
+            .as_gift_wrap(&sender_keys, &receiver_keys.public_key())
+            .await
+            .unwrap();
+
+        let error = gift_wrap
+            .extract_report_request(&receiver_keys)
+            .unwrap_err();
+
+        assert_eq!(error.reason(), "invalid_report_request");
+    }
+}
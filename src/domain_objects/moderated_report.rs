@@ -1,42 +1,216 @@
-use crate::config;
-use crate::domain_objects::{ReportRequest, ReportTarget};
-use anyhow::Result;
+use crate::config::{self, reportinator::LabelEvents, ReportinatorConfig};
+use crate::domain_objects::report_request::is_sampled;
+use crate::domain_objects::{ModerationCategory, ReportRequest};
+use anyhow::{bail, Result};
 use nostr_sdk::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::fmt::{self, Display, Formatter};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "StoredModeratedReport", into = "StoredModeratedReport")]
 pub struct ModeratedReport {
+    // The primary report, the one we refer to when we talk about the report
+    // id. There's always one
     event: Event,
+    // The rest of the configured report kinds, then the NIP-32 label event
+    // if enabled
+    other_events: Vec<Event>,
+    category: Option<ModerationCategory>,
+    // Alternate format of the same report for reportinator.shadow_reports,
+    // published apart from the events above
+    shadow_events: Vec<Event>,
+}
+
+// How reports are serialized, like in the dead letter queue. A report
+// without events is rejected
+#[derive(Serialize, Deserialize)]
+struct StoredModeratedReport {
+    events: Vec<Event>,
+    // Only missing from reports dead lettered before it was kept
+    #[serde(default)]
+    category: Option<ModerationCategory>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    shadow_events: Vec<Event>,
+}
+
+impl TryFrom<StoredModeratedReport> for ModeratedReport {
+    type Error = String;
+
+    fn try_from(stored: StoredModeratedReport) -> Result<Self, Self::Error> {
+        let mut events = stored.events.into_iter();
+        let event = events
+            .next()
+            .ok_or_else(|| "A moderated report needs at least one event".to_string())?;
+
+        Ok(Self {
+            event,
+            other_events: events.collect(),
+            category: stored.category,
+            shadow_events: stored.shadow_events,
+        })
+    }
+}
+
+impl From<ModeratedReport> for StoredModeratedReport {
+    fn from(moderated_report: ModeratedReport) -> Self {
+        let mut events = vec![moderated_report.event];
+        events.extend(moderated_report.other_events);
+
+        Self {
+            events,
+            category: moderated_report.category,
+            shadow_events: moderated_report.shadow_events,
+        }
+    }
 }
 
 impl ModeratedReport {
-    pub(super) fn create(reported_request: &ReportRequest, category: Report) -> Result<Self> {
-        let reportinator_keys = &config::reportinator::config().keys;
+    /// The NIP-56 report type is the one of the category unless a moderator
+    /// picked one
+    pub(super) fn create(
+        reported_request: &ReportRequest,
+        category: ModerationCategory,
+        report_type: Option<Report>,
+    ) -> Result<Self> {
+        Self::create_with_config(
+            reported_request,
+            category,
+            report_type,
+            config::reportinator::config(),
+            &config::reportinator::signing_keys(),
+        )
+    }
+
+    fn create_with_config(
+        reported_request: &ReportRequest,
+        category: ModerationCategory,
+        report_type: Option<Report>,
+        reportinator_config: &ReportinatorConfig,
+        reportinator_keys: &Keys,
+    ) -> Result<Self> {
+        // Combined targets get both tags like event ones, with their own pubkey
+        let reported_pubkey = reported_request.target().pubkey();
+        let reported_event_id = reported_request.target().event().map(|event| event.id);
+
+        let report_type = report_type.unwrap_or_else(|| category.nip56_report_type());
+        let expiration = reportinator_config
+            .report_expiration_secs
+            .map(|secs| Timestamp::now() + secs);
+
+        let content_warning = reportinator_config
+            .content_warning_categories
+            .contains(&category)
+            .then(|| category.content_warning());
 
-        let (reported_pubkey, reported_event_id) = match reported_request.target() {
-            ReportTarget::Event(event) => (event.pubkey, Some(event.id)),
-            ReportTarget::Pubkey(pubkey) => (*pubkey, None),
+        let report_kinds = match reportinator_config.label_events {
+            LabelEvents::Only => &[][..],
+            LabelEvents::Off | LabelEvents::Also => &reportinator_config.report_kinds[..],
         };
-        let tags = Self::set_tags(reported_pubkey, reported_event_id, category.clone());
-        let report_event = EventBuilder::new(Kind::Reporting, report_description(category), tags)
-            .to_event(&reportinator_keys)?;
+
+        let mut events = report_kinds
+            .iter()
+            .map(|kind| {
+                let tags = Self::set_tags(
+                    reported_pubkey,
+                    reported_event_id,
+                    category,
+                    report_type.clone(),
+                    expiration,
+                    content_warning,
+                    &reportinator_config.mentions,
+                );
+                EventBuilder::new(*kind, category.description(), tags).to_event(reportinator_keys)
+            })
+            .collect::<Result<Vec<Event>, _>>()?;
+
+        if reportinator_config.label_events != LabelEvents::Off {
+            let tags = Self::set_label_tags(
+                reported_pubkey,
+                reported_event_id,
+                category,
+                expiration,
+                content_warning,
+            );
+            events.push(
+                EventBuilder::new(Kind::Label, category.description(), tags)
+                    .to_event(reportinator_keys)?,
+            );
+        }
+
+        let mut events = events.into_iter();
+        let Some(event) = events.next() else {
+            bail!("No report kinds configured");
+        };
+
+        let shadow_reports = &reportinator_config.shadow_reports;
+        let mut shadow_events = Vec::new();
+        if shadow_reports.enabled && is_sampled(&reported_pubkey, shadow_reports.sample_rate) {
+            let tags = Self::set_label_tags(
+                reported_pubkey,
+                reported_event_id,
+                category,
+                expiration,
+                content_warning,
+            );
+            shadow_events.push(
+                EventBuilder::new(Kind::Label, category.description(), tags)
+                    .to_event(reportinator_keys)?,
+            );
+        }
 
         Ok(Self {
-            event: report_event,
+            event,
+            other_events: events.collect(),
+            category: Some(category),
+            shadow_events,
         })
     }
 
     fn set_tags(
         reported_pubkey: PublicKey,
         reported_event_id: Option<EventId>,
-        category: Report,
+        category: ModerationCategory,
+        report_type: Report,
+        expiration: Option<Timestamp>,
+        content_warning: Option<&str>,
+        mentions: &[PublicKey],
     ) -> impl IntoIterator<Item = Tag> {
-        let pubkey_tag = Tag::public_key_report(reported_pubkey, category.clone());
+        let pubkey_tag = Tag::public_key_report(reported_pubkey, report_type.clone());
         let mut tags = vec![pubkey_tag];
 
-        reported_event_id.inspect(|id| tags.push(Tag::event_report(*id, category)));
+        reported_event_id.inspect(|id| tags.push(Tag::event_report(*id, report_type)));
+        // The label is always there so categories sharing a report type can
+        // still be told apart
+        tags.extend(category.label_tags());
+        expiration.inspect(|timestamp| tags.push(Tag::expiration(*timestamp)));
+        content_warning.inspect(|reason| tags.push(content_warning_tag(reason)));
+        // Without a report type, and never the reported pubkey again so its
+        // report tag is the only p tag about it
+        tags.extend(
+            mentions
+                .iter()
+                .filter(|mention| **mention != reported_pubkey)
+                .map(|mention| Tag::public_key(*mention)),
+        );
+
+        tags
+    }
+
+    // NIP-32 labels the targets with plain p and e tags, no report type
+    fn set_label_tags(
+        reported_pubkey: PublicKey,
+        reported_event_id: Option<EventId>,
+        category: ModerationCategory,
+        expiration: Option<Timestamp>,
+        content_warning: Option<&str>,
+    ) -> impl IntoIterator<Item = Tag> {
+        let mut tags = category.label_tags().to_vec();
+        tags.push(Tag::public_key(reported_pubkey));
+
+        reported_event_id.inspect(|id| tags.push(Tag::event(*id)));
+        expiration.inspect(|timestamp| tags.push(Tag::expiration(*timestamp)));
+        content_warning.inspect(|reason| tags.push(content_warning_tag(reason)));
 
         tags
     }
@@ -45,25 +219,564 @@ impl ModeratedReport {
         self.event.clone()
     }
 
+    /// The primary report first
+    pub fn events(&self) -> impl Iterator<Item = &Event> {
+        std::iter::once(&self.event).chain(&self.other_events)
+    }
+
     pub fn id(&self) -> EventId {
         self.event.id
     }
-}
 
-fn report_description(report: Report) -> &'static str {
-    match report {
-        Report::Nudity => "Depictions of nudity, porn, or sexually explicit content.",
-        Report::Malware => "Virus, trojan horse, worm, robot, spyware, adware, back door, ransomware, rootkit, kidnapper, etc.",
-        Report::Profanity => "Profanity, hateful speech, or other offensive content.",
-        Report::Illegal => "Content that may be illegal in some jurisdictions.",
-        Report::Spam => "Spam.",
-        Report::Impersonation => "Someone pretending to be someone else.",
-        Report::Other => "For reports that don't fit in the above categories.",
+    pub fn shadow_events(&self) -> &[Event] {
+        &self.shadow_events
     }
+
+    pub fn category(&self) -> Option<ModerationCategory> {
+        self.category
+    }
+
+    /// The category slug for metric labels
+    pub fn category_label(&self) -> &'static str {
+        self.category
+            .map(|category| category.slug())
+            .unwrap_or("unknown")
+    }
+
+    /// NIP-09 deletion request for the events of a report we published. It
+    /// must be signed by the same keys as the report.
+    pub fn retraction(report_events: &[Event], reportinator_keys: &Keys) -> Result<Event> {
+        if report_events.is_empty() {
+            bail!("No report events to retract");
+        }
+
+        if let Some(event) = report_events
+            .iter()
+            .find(|event| event.author() != reportinator_keys.public_key())
+        {
+            bail!("Report event {} was not signed by our keys", event.id);
+        }
+
+        let retraction = EventBuilder::delete_with_reason(
+            report_events.iter().map(|event| event.id),
+            "Report published in error",
+        )
+        .to_event(reportinator_keys)?;
+
+        Ok(retraction)
+    }
+
+    /// Same as `ReportTarget::label`, event reports are the ones with an e tag.
+    /// Combined event and pubkey reports have the same tags, they count as
+    /// event ones
+    pub fn target_label(&self) -> &'static str {
+        if self.event.event_ids().next().is_some() {
+            "event"
+        } else {
+            "pubkey"
+        }
+    }
+}
+
+fn content_warning_tag(reason: &str) -> Tag {
+    Tag::from_standardized_without_cell(TagStandard::ContentWarning {
+        reason: Some(reason.to_string()),
+    })
 }
 
 impl Display for ModeratedReport {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", serde_json::to_string_pretty(&self.event).unwrap())
+        write!(
+            f,
+            "{}",
+            serde_json::to_string_pretty(&self.event()).unwrap()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::domain_objects::ReportTarget;
+
+    fn reportinator_config() -> ReportinatorConfig {
+        Config::new("config")
+            .unwrap()
+            .get::<ReportinatorConfig>()
+            .unwrap()
+    }
+
+    fn report_request() -> ReportRequest {
+        let reported_event = EventBuilder::text_note("I'm a hateful text", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+
+        ReportRequest::new(reported_event.into(), Keys::generate().public_key(), None)
+    }
+
+    #[test]
+    fn test_defaults_to_a_single_1984_report() {
+        let config = reportinator_config();
+        let report_request = report_request();
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        assert_eq!(moderated_report.events().count(), 1);
+        assert_eq!(moderated_report.event().kind, Kind::Reporting);
+    }
+
+    #[test]
+    fn test_creates_one_report_per_configured_kind() {
+        let mut config = reportinator_config();
+        config.report_kinds = vec![Kind::Reporting, Kind::from(1985)];
+        let report_request = report_request();
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        let kinds: Vec<Kind> = moderated_report.events().map(|e| e.kind).collect();
+        assert_eq!(kinds, vec![Kind::Reporting, Kind::from(1985)]);
+        assert_eq!(
+            moderated_report.id(),
+            moderated_report.events().next().unwrap().id
+        );
+
+        // All events share the same tag structure
+        let tags: Vec<_> = moderated_report.events().map(|e| e.tags.clone()).collect();
+        assert_eq!(tags[0], tags[1]);
+    }
+
+    #[test]
+    fn test_a_chosen_report_type_overrides_the_category_one() {
+        let config = reportinator_config();
+        let report_request = report_request();
+        let ReportTarget::Event(reported_event) = report_request.target() else {
+            panic!("Expected an event report request");
+        };
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Hate,
+            Some(Report::Illegal),
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        let report_value = serde_json::to_value(moderated_report.event()).unwrap();
+        assert_eq!(
+            report_value["tags"],
+            serde_json::json!([
+                ["p", reported_event.pubkey.to_hex(), "illegal"],
+                ["e", reported_event.id.to_hex(), "illegal"],
+                ["L", "MOD"],
+                ["l", "IH", "MOD"],
+            ])
+        );
+        assert_eq!(moderated_report.category(), Some(ModerationCategory::Hate));
+    }
+
+    #[test]
+    fn test_mentions_the_configured_pubkeys() {
+        let mut config = reportinator_config();
+        config.label_events = LabelEvents::Also;
+        let report_request = report_request();
+        let ReportTarget::Event(reported_event) = report_request.target() else {
+            panic!("Expected an event report request");
+        };
+        let moderator = Keys::generate().public_key();
+        config.mentions = vec![moderator, reported_event.pubkey];
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        let report_value = serde_json::to_value(moderated_report.event()).unwrap();
+        assert_eq!(
+            report_value["tags"],
+            serde_json::json!([
+                ["p", reported_event.pubkey.to_hex(), "spam"],
+                ["e", reported_event.id.to_hex(), "spam"],
+                ["L", "MOD"],
+                ["l", "SP-spm", "MOD"],
+                ["p", moderator.to_hex()],
+            ])
+        );
+
+        // The label event only labels the target
+        let label_event = moderated_report.events().nth(1).unwrap();
+        assert_eq!(label_event.kind, Kind::Label);
+        assert_eq!(
+            label_event.public_keys().collect::<Vec<_>>(),
+            vec![&reported_event.pubkey]
+        );
+    }
+
+    #[test]
+    fn test_adds_a_label_event_to_the_reports() {
+        let mut config = reportinator_config();
+        config.label_events = LabelEvents::Also;
+        let report_request = report_request();
+        let ReportTarget::Event(reported_event) = report_request.target() else {
+            panic!("Expected an event report request");
+        };
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Harassment,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        let kinds: Vec<Kind> = moderated_report.events().map(|e| e.kind).collect();
+        assert_eq!(kinds, vec![Kind::Reporting, Kind::Label]);
+
+        let label_event_value =
+            serde_json::to_value(moderated_report.events().nth(1).unwrap()).unwrap();
+        assert_eq!(
+            label_event_value["tags"],
+            serde_json::json!([
+                ["L", "MOD"],
+                ["l", "IL-har", "MOD"],
+                ["p", reported_event.pubkey.to_hex()],
+                ["e", reported_event.id.to_hex()],
+            ])
+        );
+        assert_eq!(
+            label_event_value["content"],
+            ModerationCategory::Harassment.description()
+        );
+    }
+
+    #[test]
+    fn test_publishes_only_the_label_event() {
+        let mut config = reportinator_config();
+        config.label_events = LabelEvents::Only;
+        let pubkey_report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &pubkey_report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        assert_eq!(moderated_report.events().count(), 1);
+        assert_eq!(moderated_report.event().kind, Kind::Label);
+        assert_eq!(moderated_report.target_label(), "pubkey");
+        assert_eq!(config.published_kinds(), vec![Kind::Label]);
+
+        let label_event_value = serde_json::to_value(moderated_report.event()).unwrap();
+        assert_eq!(
+            label_event_value["tags"][1],
+            serde_json::json!(["l", "SP-spm", "MOD"])
+        );
+    }
+
+    #[test]
+    fn test_retraction_deletes_every_report_event() {
+        let mut config = reportinator_config();
+        config.report_kinds = vec![Kind::Reporting, Kind::from(1985)];
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request(),
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        let report_events: Vec<Event> = moderated_report.events().cloned().collect();
+        let retraction = ModeratedReport::retraction(&report_events, &config.keys).unwrap();
+
+        assert_eq!(retraction.kind, Kind::EventDeletion);
+        assert_eq!(retraction.author(), config.keys.public_key());
+        let deleted_ids: Vec<EventId> = retraction.event_ids().copied().collect();
+        assert_eq!(
+            deleted_ids,
+            moderated_report
+                .events()
+                .map(|event| event.id)
+                .collect::<Vec<_>>()
+        );
+
+        assert!(ModeratedReport::retraction(&report_events, &Keys::generate()).is_err());
+    }
+
+    #[test]
+    fn test_no_expiration_by_default() {
+        let config = reportinator_config();
+        let report_request = report_request();
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        let report_event_value = serde_json::to_value(moderated_report.event()).unwrap();
+        let tags = report_event_value["tags"].as_array().unwrap();
+        assert!(!tags.iter().any(|tag| tag[0] == "expiration"));
+    }
+
+    #[test]
+    fn test_adds_expiration_tag() {
+        let mut config = reportinator_config();
+        config.report_expiration_secs = Some(3600);
+        let report_request = report_request();
+        let expected_expiration = Timestamp::now().as_u64() + 3600;
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        let report_event_value = serde_json::to_value(moderated_report.event()).unwrap();
+        let expiration_tag = report_event_value["tags"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|tag| tag[0] == "expiration")
+            .expect("Missing expiration tag")
+            .clone();
+
+        let expiration: u64 = expiration_tag[1].as_str().unwrap().parse().unwrap();
+        // Allow for the clock moving while the test runs
+        assert!(expiration >= expected_expiration);
+        assert!(expiration <= expected_expiration + 5);
+    }
+
+    #[test]
+    fn test_adds_content_warnings_for_the_configured_categories() {
+        let mut config = reportinator_config();
+        config.label_events = LabelEvents::Also;
+        config.content_warning_categories = vec![ModerationCategory::ViolenceGraphic];
+        let report_request = report_request();
+
+        let content_warnings = |category| -> Vec<Vec<String>> {
+            ModeratedReport::create_with_config(
+                &report_request,
+                category,
+                None,
+                &config,
+                &config.keys,
+            )
+            .unwrap()
+            .events()
+            .flat_map(|event| event.tags.iter())
+            .map(|tag| tag.as_vec().to_vec())
+            .filter(|tag| tag[0] == "content-warning")
+            .collect()
+        };
+
+        let warning = vec![
+            "content-warning".to_string(),
+            "Graphic violence".to_string(),
+        ];
+        // On the report and on the label event
+        assert_eq!(
+            content_warnings(ModerationCategory::ViolenceGraphic),
+            vec![warning.clone(), warning]
+        );
+        assert!(content_warnings(ModerationCategory::Spam).is_empty());
+    }
+
+    #[test]
+    fn test_fails_without_report_kinds() {
+        let mut config = reportinator_config();
+        config.report_kinds = vec![];
+        let report_request = report_request();
+
+        let result = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_a_stored_report_needs_an_event() {
+        let error = serde_json::from_value::<ModeratedReport>(serde_json::json!({
+            "events": [],
+            "category": "spam",
+        }))
+        .unwrap_err();
+
+        assert!(
+            error.to_string().contains("at least one event"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_target_label() {
+        let config = reportinator_config();
+        let pubkey_report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+
+        let event_report = ModeratedReport::create_with_config(
+            &report_request(),
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+        let pubkey_report = ModeratedReport::create_with_config(
+            &pubkey_report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        assert_eq!(event_report.target_label(), "event");
+        assert_eq!(pubkey_report.target_label(), "pubkey");
+    }
+
+    #[test]
+    fn test_creates_shadow_reports_when_enabled() {
+        let mut config = reportinator_config();
+        let report_request = report_request();
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+        assert!(moderated_report.shadow_events().is_empty());
+
+        config.shadow_reports.enabled = true;
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        let kinds: Vec<Kind> = moderated_report.events().map(|e| e.kind).collect();
+        assert_eq!(kinds, vec![Kind::Reporting]);
+        let [shadow_event] = moderated_report.shadow_events() else {
+            panic!("Expected a single shadow event");
+        };
+        assert_eq!(shadow_event.kind, Kind::Label);
+        assert_eq!(shadow_event.pubkey, config.keys.public_key());
+        let shadow_event_value = serde_json::to_value(shadow_event).unwrap();
+        assert_eq!(
+            shadow_event_value["tags"][1],
+            serde_json::json!(["l", "SP-spm", "MOD"])
+        );
+
+        // Targets out of the sample only get the regular reports
+        config.shadow_reports.sample_rate = 0.0;
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+        assert!(moderated_report.shadow_events().is_empty());
+    }
+
+    #[test]
+    fn test_keeps_the_category() {
+        let config = reportinator_config();
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request(),
+            ModerationCategory::Impersonation,
+            None,
+            &config,
+            &config.keys,
+        )
+        .unwrap();
+
+        assert_eq!(
+            moderated_report.category(),
+            Some(ModerationCategory::Impersonation)
+        );
+        assert_eq!(moderated_report.category_label(), "impersonation");
+
+        let json = serde_json::to_value(&moderated_report).unwrap();
+        assert_eq!(json["category"], "impersonation");
+        assert_eq!(
+            serde_json::from_value::<ModeratedReport>(json.clone()).unwrap(),
+            moderated_report
+        );
+
+        // As dead lettered before the category was kept
+        let legacy_report: ModeratedReport =
+            serde_json::from_value(serde_json::json!({ "events": json["events"] })).unwrap();
+        assert_eq!(legacy_report.category(), None);
+        assert_eq!(legacy_report.category_label(), "unknown");
+    }
+
+    #[test]
+    fn test_signs_with_the_given_keys() {
+        let config = reportinator_config();
+        let rotated_keys = Keys::generate();
+        let report_request = report_request();
+
+        let moderated_report = ModeratedReport::create_with_config(
+            &report_request,
+            ModerationCategory::Spam,
+            None,
+            &config,
+            &rotated_keys,
+        )
+        .unwrap();
+
+        assert_eq!(moderated_report.event().pubkey, rotated_keys.public_key());
     }
 }
@@ -0,0 +1,298 @@
+use anyhow::{anyhow, Error, Result};
+use nostr_sdk::prelude::*;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// NIP-32 namespace of the NIP-69 moderation vocabulary
+pub const MODERATION_LABEL_NAMESPACE: &str = "MOD";
+
+/// Categories moderators can pick for a report. NIP-56 report types are too
+/// coarse for some of them, so every category also has a NIP-69 label that is
+/// always published along the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModerationCategory {
+    Nudity,
+    Sexual,
+    SexualMinors,
+    Hate,
+    HateThreatening,
+    Harassment,
+    HarassmentThreatening,
+    SelfHarm,
+    SelfHarmIntent,
+    SelfHarmInstructions,
+    Violence,
+    ViolenceGraphic,
+    Profanity,
+    Malware,
+    Illegal,
+    Spam,
+    Impersonation,
+    Other,
+}
+
+impl ModerationCategory {
+    pub const ALL: [ModerationCategory; 18] = [
+        ModerationCategory::Nudity,
+        ModerationCategory::Sexual,
+        ModerationCategory::SexualMinors,
+        ModerationCategory::Hate,
+        ModerationCategory::HateThreatening,
+        ModerationCategory::Harassment,
+        ModerationCategory::HarassmentThreatening,
+        ModerationCategory::SelfHarm,
+        ModerationCategory::SelfHarmIntent,
+        ModerationCategory::SelfHarmInstructions,
+        ModerationCategory::Violence,
+        ModerationCategory::ViolenceGraphic,
+        ModerationCategory::Profanity,
+        ModerationCategory::Malware,
+        ModerationCategory::Illegal,
+        ModerationCategory::Spam,
+        ModerationCategory::Impersonation,
+        ModerationCategory::Other,
+    ];
+
+    /// Identifier used in Slack actions and storage
+    pub fn slug(&self) -> &'static str {
+        match self {
+            ModerationCategory::Nudity => "nudity",
+            ModerationCategory::Sexual => "sexual",
+            ModerationCategory::SexualMinors => "sexual/minors",
+            ModerationCategory::Hate => "hate",
+            ModerationCategory::HateThreatening => "hate/threatening",
+            ModerationCategory::Harassment => "harassment",
+            ModerationCategory::HarassmentThreatening => "harassment/threatening",
+            ModerationCategory::SelfHarm => "self-harm",
+            ModerationCategory::SelfHarmIntent => "self-harm/intent",
+            ModerationCategory::SelfHarmInstructions => "self-harm/instructions",
+            ModerationCategory::Violence => "violence",
+            ModerationCategory::ViolenceGraphic => "violence/graphic",
+            ModerationCategory::Profanity => "profanity",
+            ModerationCategory::Malware => "malware",
+            ModerationCategory::Illegal => "illegal",
+            ModerationCategory::Spam => "spam",
+            ModerationCategory::Impersonation => "impersonation",
+            ModerationCategory::Other => "other",
+        }
+    }
+
+    /// The closest NIP-56 report type
+    pub fn nip56_report_type(&self) -> Report {
+        match self {
+            ModerationCategory::Nudity | ModerationCategory::Sexual => Report::Nudity,
+            // Sexual content involving minors is illegal everywhere
+            ModerationCategory::SexualMinors | ModerationCategory::Illegal => Report::Illegal,
+            // NIP-56 profanity covers hateful speech
+            ModerationCategory::Hate
+            | ModerationCategory::HateThreatening
+            | ModerationCategory::Profanity => Report::Profanity,
+            ModerationCategory::Malware => Report::Malware,
+            ModerationCategory::Spam => Report::Spam,
+            ModerationCategory::Impersonation => Report::Impersonation,
+            // No NIP-56 type for these, the label keeps the distinction
+            ModerationCategory::Harassment
+            | ModerationCategory::HarassmentThreatening
+            | ModerationCategory::SelfHarm
+            | ModerationCategory::SelfHarmIntent
+            | ModerationCategory::SelfHarmInstructions
+            | ModerationCategory::Violence
+            | ModerationCategory::ViolenceGraphic
+            | ModerationCategory::Other => Report::Other,
+        }
+    }
+
+    /// Label in the NIP-69 moderation vocabulary, under the `MOD` namespace
+    pub fn nip69_label(&self) -> &'static str {
+        match self {
+            ModerationCategory::Nudity => "NS-nud",
+            ModerationCategory::Sexual => "NS-sex",
+            ModerationCategory::SexualMinors => "IL-csa",
+            ModerationCategory::Hate => "IH",
+            ModerationCategory::HateThreatening => "IH-thr",
+            ModerationCategory::Harassment => "IL-har",
+            ModerationCategory::HarassmentThreatening => "IL-thr",
+            ModerationCategory::SelfHarm => "VI-sh",
+            ModerationCategory::SelfHarmIntent => "VI-shi",
+            ModerationCategory::SelfHarmInstructions => "VI-shn",
+            ModerationCategory::Violence => "VI-hum",
+            ModerationCategory::ViolenceGraphic => "VI-gfx",
+            ModerationCategory::Profanity => "PR-pro",
+            ModerationCategory::Malware => "IL-mal",
+            ModerationCategory::Illegal => "IL",
+            ModerationCategory::Spam => "SP-spm",
+            ModerationCategory::Impersonation => "IL-idt",
+            ModerationCategory::Other => "NA",
+        }
+    }
+
+    /// NIP-32 `L` and `l` tags for the label
+    pub fn label_tags(&self) -> [Tag; 2] {
+        [
+            Tag::custom(
+                TagKind::SingleLetter(SingleLetterTag::uppercase(Alphabet::L)),
+                [MODERATION_LABEL_NAMESPACE],
+            ),
+            Tag::custom(
+                TagKind::SingleLetter(SingleLetterTag::lowercase(Alphabet::L)),
+                [self.nip69_label(), MODERATION_LABEL_NAMESPACE],
+            ),
+        ]
+    }
+
+    /// Reason of the NIP-36 content-warning tag, shown by clients before
+    /// the content
+    pub fn content_warning(&self) -> &'static str {
+        match self {
+            ModerationCategory::Nudity => "Nudity",
+            ModerationCategory::Sexual => "Sexual content",
+            ModerationCategory::SexualMinors => "Sexual content involving minors",
+            ModerationCategory::Hate => "Hateful content",
+            ModerationCategory::HateThreatening => "Hateful and threatening content",
+            ModerationCategory::Harassment => "Harassment",
+            ModerationCategory::HarassmentThreatening => "Threatening harassment",
+            ModerationCategory::SelfHarm
+            | ModerationCategory::SelfHarmIntent
+            | ModerationCategory::SelfHarmInstructions => "Self-harm",
+            ModerationCategory::Violence => "Violence",
+            ModerationCategory::ViolenceGraphic => "Graphic violence",
+            ModerationCategory::Profanity => "Profanity",
+            ModerationCategory::Malware => "Malware",
+            ModerationCategory::Illegal => "Possibly illegal content",
+            ModerationCategory::Spam => "Spam",
+            ModerationCategory::Impersonation => "Impersonation",
+            ModerationCategory::Other => "Reported content",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ModerationCategory::Nudity => "Depictions of nudity, porn, or sexually explicit content.",
+            ModerationCategory::Sexual => "Content meant to arouse sexual excitement.",
+            ModerationCategory::SexualMinors => "Sexual content that includes an individual who is under 18 years old.",
+            ModerationCategory::Hate => "Content that expresses, incites, or promotes hate based on a protected characteristic.",
+            ModerationCategory::HateThreatening => "Hateful content that also includes violence or serious harm towards the targeted group.",
+            ModerationCategory::Harassment => "Content that expresses, incites, or promotes harassing language towards any target.",
+            ModerationCategory::HarassmentThreatening => "Harassment content that also includes violence or serious harm towards any target.",
+            ModerationCategory::SelfHarm => "Content that promotes, encourages, or depicts acts of self-harm.",
+            ModerationCategory::SelfHarmIntent => "Content where the speaker expresses that they are engaging or intend to engage in acts of self-harm.",
+            ModerationCategory::SelfHarmInstructions => "Content that encourages performing acts of self-harm, or that gives instructions or advice on how to commit such acts.",
+            ModerationCategory::Violence => "Content that depicts death, violence, or physical injury.",
+            ModerationCategory::ViolenceGraphic => "Content that depicts death, violence, or physical injury in graphic detail.",
+            ModerationCategory::Profanity => "Profanity, hateful speech, or other offensive content.",
+            ModerationCategory::Malware => "Virus, trojan horse, worm, robot, spyware, adware, back door, ransomware, rootkit, kidnapper, etc.",
+            ModerationCategory::Illegal => "Content that may be illegal in some jurisdictions.",
+            ModerationCategory::Spam => "Spam.",
+            ModerationCategory::Impersonation => "Someone pretending to be someone else.",
+            ModerationCategory::Other => "For reports that don't fit in the above categories.",
+        }
+    }
+}
+
+impl FromStr for ModerationCategory {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ModerationCategory::ALL
+            .into_iter()
+            .find(|category| category.slug() == s)
+            .ok_or_else(|| anyhow!("Unknown moderation category: {}", s))
+    }
+}
+
+impl Display for ModerationCategory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.slug())
+    }
+}
+
+// Stored as the slug, like in Slack actions
+impl Serialize for ModerationCategory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.slug())
+    }
+}
+
+impl<'de> Deserialize<'de> for ModerationCategory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let slug = String::deserialize(deserializer)?;
+        ModerationCategory::from_str(&slug).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slug_roundtrip() {
+        for category in ModerationCategory::ALL {
+            assert_eq!(
+                ModerationCategory::from_str(category.slug()).unwrap(),
+                category
+            );
+        }
+
+        assert!(ModerationCategory::from_str("skip").is_err());
+    }
+
+    #[test]
+    fn test_nip56_slugs_keep_their_report_type() {
+        for report in [
+            Report::Nudity,
+            Report::Malware,
+            Report::Profanity,
+            Report::Illegal,
+            Report::Spam,
+            Report::Impersonation,
+            Report::Other,
+        ] {
+            let category = ModerationCategory::from_str(&report.to_string()).unwrap();
+            assert_eq!(category.nip56_report_type(), report);
+        }
+    }
+
+    #[test]
+    fn test_every_other_mapping_has_a_specific_label() {
+        let other_mappings: Vec<ModerationCategory> = ModerationCategory::ALL
+            .into_iter()
+            .filter(|category| category.nip56_report_type() == Report::Other)
+            .collect();
+
+        assert_eq!(
+            other_mappings,
+            vec![
+                ModerationCategory::Harassment,
+                ModerationCategory::HarassmentThreatening,
+                ModerationCategory::SelfHarm,
+                ModerationCategory::SelfHarmIntent,
+                ModerationCategory::SelfHarmInstructions,
+                ModerationCategory::Violence,
+                ModerationCategory::ViolenceGraphic,
+                ModerationCategory::Other,
+            ]
+        );
+
+        for category in other_mappings {
+            let tags = serde_json::to_value(category.label_tags()).unwrap();
+            assert_eq!(tags[0], serde_json::json!(["L", "MOD"]));
+            assert_eq!(
+                tags[1],
+                serde_json::json!(["l", category.nip69_label(), "MOD"])
+            );
+        }
+    }
+
+    #[test]
+    fn test_labels_are_unique() {
+        let mut labels: Vec<&str> = ModerationCategory::ALL
+            .iter()
+            .map(|category| category.nip69_label())
+            .collect();
+        labels.sort();
+        labels.dedup();
+
+        assert_eq!(labels.len(), ModerationCategory::ALL.len());
+    }
+}
@@ -1,26 +1,178 @@
-use super::ModeratedReport;
+use super::{ModeratedReport, ModerationCategory};
 use anyhow::Result;
 use nostr_sdk::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::fmt::{self, Display, Formatter};
 
+/// In JSON a `reportedEvent`, a `reportedPubkey` or both of them for
+/// `EventAndPubkey`
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(try_from = "ReportTargetFields", into = "ReportTargetFields")]
 pub enum ReportTarget {
-    #[serde(rename = "reportedEvent")]
     Event(Event),
-    #[serde(rename = "reportedPubkey")]
     Pubkey(PublicKey),
+    // The event and the account, reported together for the same category
+    EventAndPubkey(Event, PublicKey),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReportTargetFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reported_event: Option<Event>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reported_pubkey: Option<PublicKey>,
+}
+
+impl TryFrom<ReportTargetFields> for ReportTarget {
+    type Error = String;
+
+    fn try_from(fields: ReportTargetFields) -> Result<Self, Self::Error> {
+        match (fields.reported_event, fields.reported_pubkey) {
+            (Some(event), None) => Ok(ReportTarget::Event(event)),
+            (None, Some(pubkey)) => Ok(ReportTarget::Pubkey(pubkey)),
+            (Some(event), Some(pubkey)) if ReportTarget::is_accountable(&event, &pubkey) => {
+                Ok(ReportTarget::EventAndPubkey(event, pubkey))
+            }
+            (Some(_), Some(pubkey)) => Err(ReportRequestError::UnrelatedPubkey(pubkey).to_string()),
+            (None, None) => Err("missing reportedEvent or reportedPubkey".to_string()),
+        }
+    }
+}
+
+impl From<ReportTarget> for ReportTargetFields {
+    fn from(target: ReportTarget) -> Self {
+        let (reported_event, reported_pubkey) = match target {
+            ReportTarget::Event(event) => (Some(event), None),
+            ReportTarget::Pubkey(pubkey) => (None, Some(pubkey)),
+            ReportTarget::EventAndPubkey(event, pubkey) => (Some(event), Some(pubkey)),
+        };
+
+        Self {
+            reported_event,
+            reported_pubkey,
+        }
+    }
 }
 
 impl ReportTarget {
+    /// The account responsible for the reported content. That's the signer
+    /// of the event, except for NIP-18 reposts which are attributed to the
+    /// author of the reposted note, the first `p` tag. A repost without one
+    /// falls back to the signer. Combined targets name the account
+    /// themselves.
     pub fn pubkey(&self) -> PublicKey {
         match self {
-            ReportTarget::Event(event) => event.author(),
+            ReportTarget::Event(event) => attributed_pubkey(event),
+            ReportTarget::Pubkey(pubkey) | ReportTarget::EventAndPubkey(_, pubkey) => *pubkey,
+        }
+    }
+
+    /// Whether the pubkey can be reported along the event, it must be its
+    /// signer or, for reposts, the author of the reposted note
+    pub fn is_accountable(event: &Event, pubkey: &PublicKey) -> bool {
+        *pubkey == event.pubkey || *pubkey == attributed_pubkey(event)
+    }
+
+    /// The raw `pubkey` of the reported event, or the reported pubkey
+    pub fn signer_pubkey(&self) -> PublicKey {
+        match self {
+            ReportTarget::Event(event) | ReportTarget::EventAndPubkey(event, _) => event.pubkey,
             ReportTarget::Pubkey(pubkey) => *pubkey,
         }
     }
+
+    /// The reported event, also for combined targets
+    pub fn event(&self) -> Option<&Event> {
+        match self {
+            ReportTarget::Event(event) | ReportTarget::EventAndPubkey(event, _) => Some(event),
+            ReportTarget::Pubkey(_) => None,
+        }
+    }
+
+    /// The event id, or the pubkey when no event is reported
+    pub fn id(&self) -> String {
+        match self.event() {
+            Some(event) => event.id.to_hex(),
+            None => self.pubkey().to_hex(),
+        }
+    }
+
+    /// `event`, `pubkey` or `event_and_pubkey`, for metric labels and storage
+    pub fn label(&self) -> &'static str {
+        match self {
+            ReportTarget::Event(_) => "event",
+            ReportTarget::Pubkey(_) => "pubkey",
+            ReportTarget::EventAndPubkey(..) => "event_and_pubkey",
+        }
+    }
+
+    // The same target with another version of the reported event
+    fn with_event(&self, event: Event) -> Self {
+        match self {
+            ReportTarget::EventAndPubkey(_, pubkey) => ReportTarget::EventAndPubkey(event, *pubkey),
+            _ => ReportTarget::Event(event),
+        }
+    }
+}
+
+/// Appended to reported content cut to reportinator.max_reported_content_chars
+pub const TRUNCATED_MARKER: &str = "… (truncated)";
+
+/// The first max_chars characters of the content and the truncated marker,
+/// None when the content already fits
+pub fn truncate_content(content: &str, max_chars: usize) -> Option<String> {
+    let (cut_at, _) = content.char_indices().nth(max_chars)?;
+
+    Some(format!("{}{}", &content[..cut_at], TRUNCATED_MARKER))
+}
+
+/// The event with its content cut to max_chars characters, None when the
+/// content already fits. The id stays the same, the signature no longer
+/// verifies
+pub fn truncated_event(event: &Event, max_chars: usize) -> Option<Event> {
+    let content = truncate_content(&event.content, max_chars)?;
+
+    let mut event_value = serde_json::to_value(event).expect("Events serialize to JSON");
+    event_value["content"] = content.into();
+    serde_json::from_value::<Event>(event_value).ok()
+}
+
+/// Whether the content of the event was cut by truncated_event
+pub fn is_truncated_event(event: &Event) -> bool {
+    event.content.ends_with(TRUNCATED_MARKER) && event.verify_id().is_err()
+}
+
+// Keys are already uniformly distributed so their first bytes are a stable
+// hash, the same target is always in or out of the sample
+pub fn is_sampled(target_pubkey: &PublicKey, sample_rate: f64) -> bool {
+    sample_bucket(target_pubkey) < sample_rate
+}
+
+// Position of the target in [0, 1)
+fn sample_bucket(target_pubkey: &PublicKey) -> f64 {
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&target_pubkey.to_bytes()[..8]);
+
+    (u64::from_be_bytes(prefix) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn attributed_pubkey(event: &Event) -> PublicKey {
+    if !is_repost(event) {
+        return event.author();
+    }
+
+    event
+        .public_keys()
+        .next()
+        .copied()
+        .unwrap_or(event.author())
+}
+
+fn is_repost(event: &Event) -> bool {
+    event.kind == Kind::Repost || event.kind == Kind::GenericRepost
 }
 
 impl From<Event> for ReportTarget {
@@ -35,11 +187,20 @@ impl From<PublicKey> for ReportTarget {
     }
 }
 
+impl From<(Event, PublicKey)> for ReportTarget {
+    fn from((event, pubkey): (Event, PublicKey)) -> Self {
+        ReportTarget::EventAndPubkey(event, pubkey)
+    }
+}
+
 impl Display for ReportTarget {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             ReportTarget::Event(event) => write!(f, "Event {}", event.id),
             ReportTarget::Pubkey(pubkey) => write!(f, "Pubkey {}", pubkey),
+            ReportTarget::EventAndPubkey(event, pubkey) => {
+                write!(f, "Event {} and pubkey {}", event.id, pubkey)
+            }
         }
     }
 }
@@ -61,8 +222,19 @@ impl ReportRequestRumorContent {
 }
 
 impl ReportRequestRumorContent {
-    pub fn into_report_request(self, pubkey: PublicKey) -> ReportRequest {
-        ReportRequest::new(self.target, pubkey, self.reporter_text)
+    pub fn into_report_request(
+        self,
+        pubkey: PublicKey,
+        gift_wrap_id: Option<EventId>,
+        signature_verification: SignatureVerification,
+    ) -> Result<ReportRequest, ReportRequestError> {
+        ReportRequest::builder()
+            .target(self.target)
+            .reporter_pubkey(pubkey)
+            .reporter_text(self.reporter_text)
+            .gift_wrap_id(gift_wrap_id)
+            .signature_verification(signature_verification)
+            .build()
     }
 }
 
@@ -73,20 +245,32 @@ pub struct ReportRequest {
     target: ReportTarget,
     reporter_pubkey: PublicKey,
     reporter_text: Option<String>,
+    // Id of the kind 1059 event the request came in, to trace it back
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gift_wrap_id: Option<EventId>,
+    // ISO 639-3 code of the language detected by language_detection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lang: Option<String>,
 }
 
 impl ReportRequest {
+    /// Builds the request without validating the target, use
+    /// `ReportRequest::builder` for input coming from the outside
     #[allow(unused)]
     pub fn new(
         target: ReportTarget,
         reporter_pubkey: PublicKey,
         reporter_text: Option<String>,
     ) -> Self {
-        ReportRequest {
-            target,
-            reporter_pubkey,
-            reporter_text,
-        }
+        ReportRequestBuilder::default()
+            .target(target)
+            .reporter_pubkey(reporter_pubkey)
+            .reporter_text(reporter_text)
+            .build_unvalidated()
+    }
+
+    pub fn builder() -> ReportRequestBuilder {
+        ReportRequestBuilder::default()
     }
 
     pub fn target(&self) -> &ReportTarget {
@@ -102,22 +286,68 @@ impl ReportRequest {
         self.reporter_text.as_ref()
     }
 
-    pub fn valid(&self) -> bool {
-        match &self.target {
-            ReportTarget::Event(event) => event.verify().is_ok(),
-            ReportTarget::Pubkey(_) => true,
-        }
+    pub fn gift_wrap_id(&self) -> Option<&EventId> {
+        self.gift_wrap_id.as_ref()
+    }
+
+    pub fn lang(&self) -> Option<&str> {
+        self.lang.as_deref()
+    }
+
+    pub fn with_lang(mut self, lang: Option<String>) -> Self {
+        self.lang = lang;
+        self
+    }
+
+    /// The reporter reported their own pubkey or one of their own events
+    pub fn is_self_report(&self) -> bool {
+        self.target.pubkey() == self.reporter_pubkey
+    }
+
+    #[cfg(test)]
+    pub fn with_gift_wrap_id(mut self, gift_wrap_id: EventId) -> Self {
+        self.gift_wrap_id = Some(gift_wrap_id);
+        self
+    }
+
+    /// For payloads leaving the server, with the content of the reported
+    /// event cut to max_chars characters. The event id, which is what the
+    /// report tags point to, stays the same but the signature no longer
+    /// verifies once the content is cut.
+    pub fn with_truncated_content(&self, max_chars: Option<usize>) -> Cow<'_, ReportRequest> {
+        let Some(event) = self.target.event() else {
+            return Cow::Borrowed(self);
+        };
+        let Some(truncated_event) =
+            max_chars.and_then(|max_chars| truncated_event(event, max_chars))
+        else {
+            return Cow::Borrowed(self);
+        };
+
+        Cow::Owned(ReportRequest {
+            target: self.target.with_event(truncated_event),
+            ..self.clone()
+        })
     }
 
     pub fn report(
         &self,
-        maybe_moderation_category: Option<Report>,
+        maybe_moderation_category: Option<ModerationCategory>,
+        report_type: Option<Report>,
     ) -> Result<Option<ModeratedReport>> {
         let Some(moderation_category) = maybe_moderation_category else {
             return Ok(None);
         };
 
-        let moderated_report = ModeratedReport::create(self, moderation_category)?;
+        // Requests may have been built with an id only check, nothing is
+        // published for an event whose signature doesn't verify
+        if let Some(event) = self.target.event() {
+            if event.verify().is_err() {
+                return Err(ReportRequestError::InvalidEventSignature(event.id).into());
+            }
+        }
+
+        let moderated_report = ModeratedReport::create(self, moderation_category, report_type)?;
         Ok(Some(moderated_report))
     }
 }
@@ -128,6 +358,153 @@ impl Display for ReportRequest {
     }
 }
 
+/// How much of a reported event is checked when its report request is built.
+/// The signature is always checked before a report is published
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureVerification {
+    // The id and the signature
+    #[default]
+    Full,
+    // Only the id, a hash that's much cheaper to check than the signature.
+    // Enough to drop requests for events tampered with, see
+    // benches/event_verification.rs
+    IdOnly,
+}
+
+/// The single construction path for report requests coming from gift wraps,
+/// Slack or the CLI. Surrounding whitespace is trimmed from the reporter text,
+/// text that's left empty is rejected.
+#[derive(Debug, Clone, Default)]
+pub struct ReportRequestBuilder {
+    target: Option<ReportTarget>,
+    reporter_pubkey: Option<PublicKey>,
+    reporter_text: Option<String>,
+    gift_wrap_id: Option<EventId>,
+    signature_verification: SignatureVerification,
+}
+
+impl ReportRequestBuilder {
+    pub fn target(mut self, target: impl Into<ReportTarget>) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+
+    pub fn reporter_pubkey(mut self, reporter_pubkey: PublicKey) -> Self {
+        self.reporter_pubkey = Some(reporter_pubkey);
+        self
+    }
+
+    pub fn reporter_text(mut self, reporter_text: Option<String>) -> Self {
+        self.reporter_text = reporter_text;
+        self
+    }
+
+    pub fn gift_wrap_id(mut self, gift_wrap_id: Option<EventId>) -> Self {
+        self.gift_wrap_id = gift_wrap_id;
+        self
+    }
+
+    pub fn signature_verification(mut self, signature_verification: SignatureVerification) -> Self {
+        self.signature_verification = signature_verification;
+        self
+    }
+
+    pub fn build(self) -> Result<ReportRequest, ReportRequestError> {
+        let Some(target) = &self.target else {
+            return Err(ReportRequestError::MissingTarget);
+        };
+
+        if self.reporter_pubkey.is_none() {
+            return Err(ReportRequestError::MissingReporterPubkey);
+        }
+
+        if let Some(reporter_text) = &self.reporter_text {
+            if reporter_text.trim().is_empty() {
+                return Err(ReportRequestError::EmptyReporterText);
+            }
+        }
+
+        if let ReportTarget::EventAndPubkey(event, pubkey) = target {
+            if !ReportTarget::is_accountable(event, pubkey) {
+                return Err(ReportRequestError::UnrelatedPubkey(*pubkey));
+            }
+        }
+
+        if let Some(event) = target.event() {
+            match self.signature_verification {
+                SignatureVerification::Full if event.verify().is_err() => {
+                    return Err(ReportRequestError::InvalidEventSignature(event.id));
+                }
+                SignatureVerification::IdOnly if event.verify_id().is_err() => {
+                    return Err(ReportRequestError::InvalidEventId(event.id));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(self.build_unvalidated())
+    }
+
+    // Only reachable after the fields were set
+    fn build_unvalidated(self) -> ReportRequest {
+        ReportRequest {
+            target: self.target.expect("Report request target not set"),
+            reporter_pubkey: self
+                .reporter_pubkey
+                .expect("Report request reporter pubkey not set"),
+            reporter_text: normalize_reporter_text(self.reporter_text),
+            gift_wrap_id: self.gift_wrap_id,
+            lang: None,
+        }
+    }
+}
+
+fn normalize_reporter_text(reporter_text: Option<String>) -> Option<String> {
+    reporter_text
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReportRequestError {
+    MissingTarget,
+    MissingReporterPubkey,
+    EmptyReporterText,
+    UnrelatedPubkey(PublicKey),
+    InvalidEventSignature(EventId),
+    InvalidEventId(EventId),
+}
+
+impl Display for ReportRequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportRequestError::MissingTarget => write!(f, "Report request has no target"),
+            ReportRequestError::MissingReporterPubkey => {
+                write!(f, "Report request has no reporter pubkey")
+            }
+            ReportRequestError::EmptyReporterText => {
+                write!(f, "Report request has an empty reporter text")
+            }
+            ReportRequestError::UnrelatedPubkey(pubkey) => {
+                write!(
+                    f,
+                    "Reported pubkey {} didn't sign the reported event",
+                    pubkey
+                )
+            }
+            ReportRequestError::InvalidEventSignature(id) => {
+                write!(f, "Reported event {} has an invalid signature", id)
+            }
+            ReportRequestError::InvalidEventId(id) => {
+                write!(f, "Reported event {} doesn't match its content", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReportRequestError {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,7 +512,6 @@ mod tests {
         reportinator::{self, Config as ReportinatorConfig},
         Config,
     };
-    use nostr_sdk::nips::nip56::Report;
     use serde_json::json;
     use std::str::FromStr;
 
@@ -187,8 +563,236 @@ mod tests {
         assert_eq!(report_request.target(), &reported_target);
         assert_eq!(report_request.reporter_pubkey(), &reporter_pubkey);
         assert_eq!(report_request.reporter_text(), reporter_text.as_ref());
-        assert_eq!(report_request.valid(), true);
-        assert_eq!(report_request.report(None).unwrap(), None);
+        assert_eq!(report_request.report(None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_repost_pubkey_is_the_reposted_author() {
+        let reposter_keys = Keys::generate();
+        let original_author = Keys::generate().public_key();
+        let repost = EventBuilder::new(
+            Kind::Repost,
+            "",
+            [
+                Tag::event(EventId::all_zeros()),
+                Tag::public_key(original_author),
+            ],
+        )
+        .to_event(&reposter_keys)
+        .unwrap();
+        assert!(ReportTarget::is_accountable(&repost, &original_author));
+        assert!(ReportTarget::is_accountable(
+            &repost,
+            &reposter_keys.public_key()
+        ));
+        let target = ReportTarget::Event(repost);
+
+        assert_eq!(target.pubkey(), original_author);
+        assert_eq!(target.signer_pubkey(), reposter_keys.public_key());
+    }
+
+    #[test]
+    fn test_note_and_bare_repost_pubkey_is_the_signer() {
+        let keys = Keys::generate();
+        let note =
+            EventBuilder::text_note("Hello", [Tag::public_key(Keys::generate().public_key())])
+                .to_event(&keys)
+                .unwrap();
+        let bare_repost = EventBuilder::new(Kind::Repost, "", [])
+            .to_event(&keys)
+            .unwrap();
+
+        for target in [ReportTarget::Event(note), ReportTarget::Event(bare_repost)] {
+            assert_eq!(target.pubkey(), keys.public_key());
+            assert_eq!(target.signer_pubkey(), keys.public_key());
+        }
+    }
+
+    #[test]
+    fn test_self_reports() {
+        let reporter_keys = Keys::generate();
+        let own_event = EventBuilder::text_note("Mine", [])
+            .to_event(&reporter_keys)
+            .unwrap();
+        let report_request =
+            |target: ReportTarget| ReportRequest::new(target, reporter_keys.public_key(), None);
+
+        assert!(report_request(reporter_keys.public_key().into()).is_self_report());
+        assert!(report_request(own_event.into()).is_self_report());
+        assert!(!report_request(Keys::generate().public_key().into()).is_self_report());
+    }
+
+    #[test]
+    fn test_builder_trims_reporter_text() {
+        let report_request = ReportRequest::builder()
+            .target(Keys::generate().public_key())
+            .reporter_pubkey(Keys::generate().public_key())
+            .reporter_text(Some("  Report it!\n".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            report_request.reporter_text(),
+            Some(&"Report it!".to_string())
+        );
+
+        let report_request = ReportRequest::builder()
+            .target(Keys::generate().public_key())
+            .reporter_pubkey(Keys::generate().public_key())
+            .reporter_text(Some("   ".to_string()))
+            .build();
+        assert_eq!(report_request, Err(ReportRequestError::EmptyReporterText));
+    }
+
+    #[test]
+    fn test_gift_wrap_id_is_optional_in_json() {
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            None,
+        );
+        let json = serde_json::to_value(&report_request).unwrap();
+        assert!(json.get("giftWrapId").is_none());
+        assert_eq!(
+            serde_json::from_value::<ReportRequest>(json).unwrap(),
+            report_request
+        );
+
+        let gift_wrap_id = EventId::all_zeros();
+        let report_request = report_request.with_gift_wrap_id(gift_wrap_id);
+        let json = serde_json::to_value(&report_request).unwrap();
+        assert_eq!(json["giftWrapId"], json!(gift_wrap_id.to_hex()));
+        assert_eq!(
+            serde_json::from_value::<ReportRequest>(json)
+                .unwrap()
+                .gift_wrap_id(),
+            Some(&gift_wrap_id)
+        );
+    }
+
+    #[test]
+    fn test_lang_is_optional_in_json() {
+        let report_request = ReportRequest::new(
+            Keys::generate().public_key().into(),
+            Keys::generate().public_key(),
+            Some("Esta cuenta es falsa".to_string()),
+        );
+        let json = serde_json::to_value(&report_request).unwrap();
+        assert!(json.get("lang").is_none());
+
+        let report_request = report_request.with_lang(Some("spa".to_string()));
+        let json = serde_json::to_value(&report_request).unwrap();
+        assert_eq!(json["lang"], json!("spa"));
+        assert_eq!(
+            serde_json::from_value::<ReportRequest>(json).unwrap(),
+            report_request
+        );
+    }
+
+    #[test]
+    fn test_builder_requires_target_and_reporter() {
+        let missing_target = ReportRequest::builder()
+            .reporter_pubkey(Keys::generate().public_key())
+            .build();
+        assert_eq!(missing_target, Err(ReportRequestError::MissingTarget));
+
+        let missing_reporter = ReportRequest::builder()
+            .target(Keys::generate().public_key())
+            .build();
+        assert_eq!(
+            missing_reporter,
+            Err(ReportRequestError::MissingReporterPubkey)
+        );
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_signature() {
+        let event = EventBuilder::text_note("Original", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let mut event_value = serde_json::to_value(&event).unwrap();
+        event_value["content"] = json!("Tampered");
+        let tampered_event: Event = serde_json::from_value(event_value).unwrap();
+
+        let result = ReportRequest::builder()
+            .target(tampered_event.clone())
+            .reporter_pubkey(Keys::generate().public_key())
+            .build();
+
+        assert_eq!(
+            result,
+            Err(ReportRequestError::InvalidEventSignature(tampered_event.id))
+        );
+    }
+
+    // Same id and content, signed by someone else
+    fn event_with_tampered_signature() -> Event {
+        let keys = Keys::generate();
+        let event = EventBuilder::text_note("Original", [])
+            .to_event(&keys)
+            .unwrap();
+        let other_event = EventBuilder::text_note("Other", [])
+            .to_event(&keys)
+            .unwrap();
+        let mut event_value = serde_json::to_value(&event).unwrap();
+        event_value["sig"] = json!(other_event.sig.to_string());
+
+        serde_json::from_value(event_value).unwrap()
+    }
+
+    #[test]
+    fn test_id_only_verification_rejects_tampered_id() {
+        let event = EventBuilder::text_note("Original", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let mut event_value = serde_json::to_value(&event).unwrap();
+        event_value["content"] = json!("Tampered");
+        let tampered_event: Event = serde_json::from_value(event_value).unwrap();
+
+        let result = ReportRequest::builder()
+            .target(tampered_event.clone())
+            .reporter_pubkey(Keys::generate().public_key())
+            .signature_verification(SignatureVerification::IdOnly)
+            .build();
+
+        assert_eq!(
+            result,
+            Err(ReportRequestError::InvalidEventId(tampered_event.id))
+        );
+    }
+
+    #[test]
+    fn test_tampered_signature_is_only_rejected_by_full_verification() {
+        let tampered_event = event_with_tampered_signature();
+        let builder = || {
+            ReportRequest::builder()
+                .target(tampered_event.clone())
+                .reporter_pubkey(Keys::generate().public_key())
+        };
+
+        assert_eq!(
+            builder().build(),
+            Err(ReportRequestError::InvalidEventSignature(tampered_event.id))
+        );
+        assert!(builder()
+            .signature_verification(SignatureVerification::IdOnly)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_tampered_signature_is_never_reported() {
+        let report_request = ReportRequest::builder()
+            .target(event_with_tampered_signature())
+            .reporter_pubkey(Keys::generate().public_key())
+            .signature_verification(SignatureVerification::IdOnly)
+            .build()
+            .unwrap();
+
+        let error = report_request
+            .report(Some(ModerationCategory::Spam), None)
+            .unwrap_err();
+        assert!(error.to_string().contains("invalid signature"), "{}", error);
+        assert!(report_request.report(None, None).unwrap().is_none());
     }
 
     #[test]
@@ -196,8 +800,8 @@ mod tests {
         let (report_request, reported_target, _reporter_pubkey, _reporter_text) =
             setup_test_environment(true);
 
-        let category = Report::from_str("malware").unwrap();
-        let maybe_report_event = report_request.report(Some(category)).unwrap();
+        let category = ModerationCategory::from_str("malware").unwrap();
+        let maybe_report_event = report_request.report(Some(category), None).unwrap();
         let report_event = maybe_report_event.unwrap().event();
         let report_event_value = serde_json::to_value(report_event).unwrap();
 
@@ -227,8 +831,8 @@ mod tests {
         let (report_request, reported_target, _reporter_pubkey, _reporter_text) =
             setup_test_environment(false);
 
-        let category = Report::from_str("other").unwrap();
-        let maybe_report_event = report_request.report(Some(category)).unwrap();
+        let category = ModerationCategory::from_str("other").unwrap();
+        let maybe_report_event = report_request.report(Some(category), None).unwrap();
         let report_event = maybe_report_event.unwrap().event();
         let report_event_value = serde_json::to_value(report_event).unwrap();
 
@@ -254,4 +858,162 @@ mod tests {
             assert_eq!(&report_event_value["tags"][i], expected_tag);
         }
     }
+
+    #[test]
+    fn test_report_event_and_pubkey() {
+        let (event_report_request, reported_target, reporter_pubkey, _reporter_text) =
+            setup_test_environment(true);
+        let ReportTarget::Event(reported_event) = reported_target else {
+            panic!("Expected an event target");
+        };
+        let reported_pubkey = reported_event.pubkey;
+        let report_request = ReportRequest::builder()
+            .target((reported_event.clone(), reported_pubkey))
+            .reporter_pubkey(reporter_pubkey)
+            .build()
+            .unwrap();
+
+        let unrelated_pubkey = Keys::generate().public_key();
+        assert_eq!(
+            ReportRequest::builder()
+                .target((reported_event.clone(), unrelated_pubkey))
+                .reporter_pubkey(reporter_pubkey)
+                .build(),
+            Err(ReportRequestError::UnrelatedPubkey(unrelated_pubkey))
+        );
+        assert!(serde_json::from_value::<ReportRequest>(json!({
+            "reportedEvent": reported_event,
+            "reportedPubkey": unrelated_pubkey.to_hex(),
+            "reporterPubkey": reporter_pubkey.to_hex(),
+            "reporterText": null
+        }))
+        .is_err());
+
+        assert_eq!(report_request.target().pubkey(), reported_pubkey);
+        assert_eq!(
+            report_request.target().signer_pubkey(),
+            reported_event.pubkey
+        );
+        assert_eq!(report_request.target().label(), "event_and_pubkey");
+        assert_eq!(
+            report_request.target().id(),
+            event_report_request.target().id()
+        );
+
+        let category = ModerationCategory::from_str("spam").unwrap();
+        let report_event = report_request
+            .report(Some(category), None)
+            .unwrap()
+            .unwrap()
+            .event();
+        let report_event_value = serde_json::to_value(report_event).unwrap();
+
+        assert_eq!(
+            report_event_value["tags"][0],
+            json!(["p", reported_pubkey, "spam"])
+        );
+        assert_eq!(
+            report_event_value["tags"][1],
+            json!(["e", reported_event.id, "spam"])
+        );
+    }
+
+    #[test]
+    fn test_target_json_shapes() {
+        let reported_event = EventBuilder::text_note("Buy my coin!!", [])
+            .to_event(&Keys::generate())
+            .unwrap();
+        let reported_pubkey = reported_event.pubkey;
+        let reporter_pubkey = Keys::generate().public_key();
+
+        for target in [
+            ReportTarget::Event(reported_event.clone()),
+            ReportTarget::Pubkey(reported_pubkey),
+            ReportTarget::EventAndPubkey(reported_event.clone(), reported_pubkey),
+        ] {
+            let report_request = ReportRequest::new(target.clone(), reporter_pubkey, None);
+            let json = serde_json::to_value(&report_request).unwrap();
+
+            assert_eq!(json["reportedEvent"].is_object(), target.event().is_some());
+            assert_eq!(
+                json.get("reportedPubkey").is_some(),
+                !matches!(target, ReportTarget::Event(_))
+            );
+            assert_eq!(
+                serde_json::from_value::<ReportRequest>(json).unwrap(),
+                report_request
+            );
+        }
+
+        let rumor_content = ReportRequestRumorContent::parse(
+            &json!({
+                "reportedEvent": reported_event,
+                "reportedPubkey": reported_pubkey.to_hex(),
+                "reporterText": "Spam from a spam account"
+            })
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            rumor_content.target,
+            ReportTarget::EventAndPubkey(reported_event, reported_pubkey)
+        );
+
+        assert!(ReportRequestRumorContent::parse(r#"{"reporterText": "No target"}"#).is_err());
+    }
+
+    #[test]
+    fn test_sampling_is_stable_at_the_boundary() {
+        let target_pubkey = Keys::generate().public_key();
+        let bucket = sample_bucket(&target_pubkey);
+
+        assert!((0.0..1.0).contains(&bucket));
+        assert_eq!(bucket, sample_bucket(&target_pubkey));
+        assert!(!is_sampled(&target_pubkey, bucket));
+        assert!(is_sampled(&target_pubkey, bucket + f64::EPSILON));
+        assert!(!is_sampled(&target_pubkey, 0.0));
+        assert!(is_sampled(&target_pubkey, 1.0));
+    }
+
+    #[test]
+    fn test_truncates_content_over_the_limit() {
+        assert_eq!(truncate_content("Short enough", 12), None);
+        assert_eq!(
+            truncate_content("Way too long", 7),
+            Some("Way too… (truncated)".to_string())
+        );
+        // Multibyte characters count as one
+        assert_eq!(
+            truncate_content("¡Olé, olé!", 4),
+            Some("¡Olé… (truncated)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_truncated_report_requests_keep_the_reported_event_id() {
+        let (report_request, reported_target, _, _) = setup_test_environment(true);
+        let ReportTarget::Event(reported_event) = reported_target else {
+            panic!("Expected an event target");
+        };
+
+        assert!(matches!(
+            report_request.with_truncated_content(None),
+            Cow::Borrowed(_)
+        ));
+        assert!(matches!(
+            report_request.with_truncated_content(Some(100)),
+            Cow::Borrowed(_)
+        ));
+
+        let truncated = report_request.with_truncated_content(Some(8));
+        let ReportTarget::Event(truncated_event) = truncated.target() else {
+            panic!("Expected an event target");
+        };
+        assert_eq!(truncated_event.content, "I'm a ha… (truncated)");
+        assert_eq!(truncated_event.id, reported_event.id);
+        assert_eq!(truncated_event.pubkey, reported_event.pubkey);
+        assert_eq!(truncated.reporter_text(), report_request.reporter_text());
+        assert!(is_truncated_event(truncated_event));
+        assert!(!is_truncated_event(&reported_event));
+    }
 }
@@ -1,4 +1,7 @@
 pub mod config;
 mod domain_objects;
-pub use crate::domain_objects::as_gift_wrap::AsGiftWrap;
+pub use crate::domain_objects::as_gift_wrap::{
+    AsGiftWrap, ProofOfWork, DEFAULT_MAX_POW_ITERATIONS,
+};
+pub use crate::domain_objects::gift_wrap::{GiftWrapError, GiftWrappedReportRequest};
 pub use crate::domain_objects::report_request::{ReportRequest, ReportTarget};
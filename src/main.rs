@@ -1,19 +1,41 @@
 mod actors;
 mod adapters;
+mod dead_letter_queue;
 mod domain_objects;
 mod service_manager;
 
 use crate::{
-    actors::Supervisor,
-    adapters::{GooglePublisher, HttpServer, NostrService, SlackClientAdapterBuilder},
-    service_manager::ServiceManager,
+    actors::{
+        messages::{EventEnqueuerMessage, RelayEventDispatcherMessage, SupervisorMessage},
+        utilities::{category_suggestions::CategorySuggestions, downstream_limit, mailbox},
+        Supervisor,
+    },
+    adapters::{
+        file_report_sink::Config as FileSinkConfig,
+        google_publisher::Config as GoogleConfig,
+        http_server,
+        nostr_service::Config as NostrConfig,
+        slack_client_adapter::{CategoryButtons, Config as SlackConfig},
+        slack_templates::SlackTemplates,
+        webhook_notifier::Config as WebhookConfig,
+        FileReportSink, GooglePublisher, HttpServer, NoopPublisher, NostrService,
+        SlackClientAdapterBuilder,
+    },
+    dead_letter_queue::ReplayBackoff,
+    service_manager::{self, ServiceManager},
 };
-use actors::{NostrPort, PubsubPort, SlackClientPortBuilder};
-use anyhow::{Context, Result};
+use actors::{NostrPort, PubsubPort, SlackNotifierBuilder};
+#[cfg(feature = "kafka")]
+use adapters::{kafka_publisher::Config as KafkaConfig, KafkaPublisher};
+use anyhow::{bail, Context, Result};
+use clap::{Arg, ArgAction, Command};
 use nostr_sdk::prelude::*;
-use reportinator_server::config::ReportinatorConfig;
 use reportinator_server::config::{self, Config};
-use tracing::info;
+use reportinator_server::config::{
+    NotificationBackend, NotificationsConfig, PubsubBackend, PubsubConfig, ReportinatorConfig,
+};
+use std::time::Duration;
+use tracing::{error, info, warn};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
 #[tokio::main]
@@ -23,11 +45,34 @@ async fn main() -> Result<()> {
         .with(EnvFilter::from_default_env())
         .init();
 
+    let matches = Command::new("reportinator_server")
+        .about("Moderates Nostr content reported through gift wrapped DMs")
+        .arg(
+            Arg::new("check-config")
+                .long("check-config")
+                .help("Validates the configuration and exits without starting the server")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("replay-dlq")
+                .long("replay-dlq")
+                .help("Republishes the reports in the dead letter file before starting")
+                .action(ArgAction::SetTrue),
+        )
+        .get_matches();
+
     let config = Config::new("config")?;
-    let app_config = config.get::<ReportinatorConfig>()?;
+
+    if matches.get_flag("check-config") {
+        return check_config(&config);
+    }
+
+    let mut app_config = config.get::<ReportinatorConfig>()?;
+    app_config.relays = app_config.get_relays()?;
     // There are places that are non-trivial to pass app_config to,
     //   so we will set a global here for the interim.
     config::reportinator::set_config(app_config.clone()).expect("Failed to set config");
+    downstream_limit::set_limit(&config.get()?);
 
     let reportinator_public_key = app_config.keys.public_key();
     info!(
@@ -35,28 +80,139 @@ async fn main() -> Result<()> {
         reportinator_public_key.to_string()
     );
 
-    //TODO: We should probably also filter through `since`
-    let gift_wrap_filter = vec![Filter::new()
-        .pubkey(reportinator_public_key)
-        .limit(0)
-        .kind(Kind::GiftWrap)];
-
     info!("Using relays: {:?}", app_config.relays);
 
-    let nostr_subscriber = NostrService::create(app_config.relays, gift_wrap_filter).await?;
-    let google_publisher = GooglePublisher::create().await?;
-    let slack_writer_builder = SlackClientAdapterBuilder::default();
+    let nostr_subscriber = NostrService::create(
+        app_config.relays,
+        config.get::<NostrConfig>()?,
+        reportinator_public_key,
+        app_config.relay_policy.clone(),
+    )
+    .await?;
+
+    let pubsub_publisher = create_pubsub_publisher(&config).await?;
+    let slack_notifier_builder = create_slack_notifier_builder(&config).await?;
 
     start_server(
         config,
         nostr_subscriber,
-        google_publisher,
-        slack_writer_builder,
+        pubsub_publisher,
+        slack_notifier_builder,
         app_config.keys,
+        matches.get_flag("replay-dlq"),
     )
     .await
 }
 
+async fn create_pubsub_publisher(config: &Config) -> Result<Box<dyn PubsubPort>> {
+    let pubsub_config = config.get::<PubsubConfig>()?;
+
+    info!("Using the {} pubsub backend", pubsub_config.backend);
+
+    match pubsub_config.backend {
+        PubsubBackend::Google => Ok(Box::new(
+            GooglePublisher::create(&config.get::<GoogleConfig>()?).await?,
+        )),
+        #[cfg(feature = "kafka")]
+        PubsubBackend::Kafka => Ok(Box::new(KafkaPublisher::create(
+            &config.get::<KafkaConfig>()?,
+        )?)),
+        #[cfg(not(feature = "kafka"))]
+        PubsubBackend::Kafka => bail!("The kafka pubsub backend requires the `kafka` feature"),
+        PubsubBackend::Noop => Ok(Box::new(NoopPublisher)),
+        PubsubBackend::File => Ok(Box::new(FileReportSink::new(
+            &config.get::<FileSinkConfig>()?,
+        )?)),
+    }
+}
+
+// Only the Slack backend reads the slack section
+async fn create_slack_notifier_builder(
+    config: &Config,
+) -> Result<Option<SlackClientAdapterBuilder>> {
+    if config.get::<NotificationsConfig>()?.backend != NotificationBackend::Slack {
+        return Ok(None);
+    }
+
+    let slack_config = config.get::<SlackConfig>()?;
+    let slack_notifier_builder = SlackClientAdapterBuilder::create(
+        SlackTemplates::load(&config.get()?)?,
+        CategoryButtons::new(slack_config.buttons_per_block, &slack_config.severity_tiers)?,
+        CategorySuggestions::new(&config.get()?)?,
+        slack_config.confirmation_expiry(),
+    )
+    .await?;
+
+    Ok(Some(slack_notifier_builder))
+}
+
+fn check_pubsub_config(config: &Config) -> Result<()> {
+    let pubsub_config = config.get::<PubsubConfig>()?;
+
+    match pubsub_config.backend {
+        PubsubBackend::Google => config.get::<GoogleConfig>()?.encryption_key().map(|_| ()),
+        #[cfg(feature = "kafka")]
+        PubsubBackend::Kafka => config.get::<KafkaConfig>().map(|_| ()),
+        #[cfg(not(feature = "kafka"))]
+        PubsubBackend::Kafka => bail!("The kafka pubsub backend requires the `kafka` feature"),
+        PubsubBackend::Noop => Ok(()),
+        PubsubBackend::File => config.get::<FileSinkConfig>().map(|_| ()),
+    }
+}
+
+fn check_notifications_config(config: &Config) -> Result<()> {
+    match config.get::<NotificationsConfig>()?.backend {
+        NotificationBackend::Slack => {
+            let slack_config = config.get::<SlackConfig>()?;
+            CategoryButtons::new(slack_config.buttons_per_block, &slack_config.severity_tiers)
+                .map(|_| ())
+        }
+        NotificationBackend::Webhook => config.get::<WebhookConfig>().map(|_| ()),
+    }
+}
+
+/// Loads every config section and validates it without binding ports or
+/// connecting to relays. Any error makes the process exit with a non zero code
+fn check_config(config: &Config) -> Result<()> {
+    let mut app_config = config.get::<ReportinatorConfig>()?;
+    app_config.relays = app_config.get_relays()?;
+    config
+        .get::<NostrConfig>()?
+        .filters(app_config.keys.public_key())?;
+    config.get::<dead_letter_queue::Config>()?;
+    config.get::<actors::retry_actor::Config>()?;
+    config.get::<actors::content_tracker::Config>()?;
+    config.get::<actors::relay_health_actor::Config>()?;
+    config.get::<actors::utilities::dedup::Config>()?;
+    config.get::<downstream_limit::Config>()?;
+    config.get::<actors::utilities::reporter_queue::Config>()?;
+    actors::utilities::auto_skip::AutoSkipRules::new(&config.get()?)?;
+    CategorySuggestions::new(&config.get()?)?;
+    actors::utilities::language_detection::LanguageDetector::new(&config.get()?)?;
+    actors::utilities::quiet_hours::QuietHours::new(&config.get()?)?;
+    config.get::<service_manager::Config>()?;
+    check_pubsub_config(config)?;
+    check_notifications_config(config)?;
+    HttpServer::check_config(config)?;
+
+    let notification_backend = config.get::<NotificationsConfig>()?.backend;
+    let missing_values = config.missing_values(notification_backend.unused_sections())?;
+    if !missing_values.is_empty() {
+        bail!(
+            "Missing required environment variables: {}",
+            missing_values.join(", ")
+        );
+    }
+
+    info!(
+        "Configuration is valid. Reportinator public key: {}, relays: {:?}",
+        app_config.keys.public_key(),
+        app_config.relays
+    );
+
+    Ok(())
+}
+
 /// Starts the server by spawning actors and wiring them together
 /// ┌────────────────────────────┐                       ┌───────────────────────┐                  ┌──────────────────────┐
 /// │ ┌───────────────────────┐  │        OpenAI         │       Cleanstr        │                  │  Manual Moderation   │
@@ -94,31 +250,74 @@ async fn main() -> Result<()> {
 ///                                                     │                                                                       │
 ///                                                     │                          Reportinator Server                          │
 ///                                                     └───────────────────────────────────────────────────────────────────────┘
+const MAILBOX_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+const MAILBOX_WARNING_THRESHOLD: usize = 100;
+
 async fn start_server(
     config: Config,
     nostr_subscriber: impl NostrPort,
     google_publisher: impl PubsubPort,
-    slack_writer_builder: impl SlackClientPortBuilder,
+    slack_notifier_builder: Option<impl SlackNotifierBuilder>,
     reportinator_keys: Keys,
+    replay_dead_letters: bool,
 ) -> Result<()> {
     let mut manager = ServiceManager::new();
+    let shutdown_config = config.get::<service_manager::Config>()?;
+
+    // Installed before the actors start so none of their metrics are lost
+    let metrics_handle = http_server::install_metrics_recorder()?;
 
     // Spawn actors and wire them together
+    let relays = nostr_subscriber.clone();
     let supervisor = manager
         .spawn_actor(
             Supervisor::new(config.clone()),
             (
                 nostr_subscriber,
                 google_publisher,
-                slack_writer_builder,
+                slack_notifier_builder,
                 reportinator_keys,
             ),
         )
         .await?;
 
-    manager.spawn_service(|cancellation_token| {
-        HttpServer::run(config, supervisor, cancellation_token)
-    });
+    // Replayed through the dispatcher and the enqueuer like any other report,
+    // once the dispatcher connected to the relays
+    if replay_dead_letters {
+        let supervisor = supervisor.clone();
+        tokio::spawn(async move {
+            if !dead_letter_queue::wait_until_connected(&relays, ReplayBackoff::default()).await {
+                warn!("Relays are disconnected, keeping the dead letters for the next replay");
+                return;
+            }
+
+            if let Err(e) = supervisor.cast(SupervisorMessage::ReplayDeadLetters) {
+                error!("Failed to replay the dead letters: {}", e);
+            }
+        });
+    }
+
+    // Shutdown drains in this order: requests in flight, then queued
+    // reports, then publishes to relays, and only then actors are stopped
+    manager.spawn_drained_service(
+        "HTTP drain",
+        Duration::from_secs(shutdown_config.http_drain_secs),
+        |cancellation_token| {
+            HttpServer::run(config, supervisor, metrics_handle, cancellation_token)
+        },
+    );
+    manager.add_mailbox_drain_phase(
+        "Enqueuer flush",
+        mailbox::pending::<EventEnqueuerMessage>,
+        Duration::from_secs(shutdown_config.enqueuer_flush_secs),
+    );
+    manager.add_mailbox_drain_phase(
+        "Relay publish flush",
+        mailbox::pending::<RelayEventDispatcherMessage>,
+        Duration::from_secs(shutdown_config.relay_publish_flush_secs),
+    );
+
+    manager.spawn_mailbox_monitor(MAILBOX_SAMPLE_INTERVAL, MAILBOX_WARNING_THRESHOLD);
 
     manager
         .listen_stop_signals()
@@ -1,18 +1,105 @@
+use crate::actors::utilities::mailbox;
+use crate::config::Configurable;
 use anyhow::{Context, Error, Result};
+use metrics::gauge;
 use ractor::{Actor, ActorCell, ActorRef};
-use regex::Regex;
+use serde::Deserialize;
+use std::sync::Mutex;
 use tokio::macros::support::Future;
 use tokio::signal;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep, timeout, Duration, Instant};
 use tokio_util::sync::CancellationToken;
 use tokio_util::task::TaskTracker;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    // Deadlines for each shutdown phase, in the order they run
+    #[serde(default = "default_http_drain_secs")]
+    pub http_drain_secs: u64,
+    #[serde(default = "default_flush_secs")]
+    pub enqueuer_flush_secs: u64,
+    #[serde(default = "default_flush_secs")]
+    pub relay_publish_flush_secs: u64,
+}
+
+fn default_http_drain_secs() -> u64 {
+    5
+}
+
+fn default_flush_secs() -> u64 {
+    10
+}
+
+impl Configurable for Config {
+    fn key() -> &'static str {
+        "shutdown"
+    }
+}
+
+// Work that must finish, or be given up on, before actors are stopped
+enum DrainPhase {
+    Service {
+        name: String,
+        handle: JoinHandle<()>,
+        timeout: Duration,
+    },
+    Mailbox {
+        name: String,
+        pending: fn() -> usize,
+        timeout: Duration,
+    },
+}
+
+impl DrainPhase {
+    async fn drain(self) {
+        match self {
+            DrainPhase::Service {
+                name,
+                handle,
+                timeout: deadline,
+            } => {
+                let abort_handle = handle.abort_handle();
+                if timeout(deadline, handle).await.is_err() {
+                    warn!("{} didn't finish in {:?}, forcing stop", name, deadline);
+                    abort_handle.abort();
+                    return;
+                }
+                info!("{} finished", name);
+            }
+            DrainPhase::Mailbox {
+                name,
+                pending,
+                timeout: deadline,
+            } => {
+                let started = Instant::now();
+                while pending() > 0 {
+                    if started.elapsed() >= deadline {
+                        warn!(
+                            "{} didn't finish in {:?}, {} messages left unprocessed",
+                            name,
+                            deadline,
+                            pending()
+                        );
+                        return;
+                    }
+                    sleep(Duration::from_millis(100)).await;
+                }
+                info!("{} finished", name);
+            }
+        }
+    }
+}
 
 pub struct ServiceManager {
     actors: Vec<ActorCell>,
     tracker: TaskTracker,
     token: CancellationToken,
+    // Cancelled after the drain phases, stops the actors
+    actors_token: CancellationToken,
+    drain_phases: Mutex<Vec<DrainPhase>>,
     actors_sender: mpsc::Sender<ActorCell>,
 }
 
@@ -24,6 +111,8 @@ impl ServiceManager {
             actors: Vec::new(),
             tracker: TaskTracker::new(),
             token: CancellationToken::new(),
+            actors_token: CancellationToken::new(),
+            drain_phases: Mutex::default(),
             actors_sender,
         };
 
@@ -39,7 +128,7 @@ impl ServiceManager {
     where
         A: Actor,
     {
-        let name = Some(simplify_type_name(std::any::type_name::<A>()));
+        let name = Some(actor_name::<A>());
         let (actor_ref, actor_handle) = Actor::spawn(name, actor, args).await?;
         self.tracker.reopen();
         let token = self.token.clone();
@@ -69,7 +158,7 @@ impl ServiceManager {
     where
         A: Actor,
     {
-        let name = Some(simplify_type_name(std::any::type_name::<A>()));
+        let name = Some(actor_name::<A>());
         let (actor_ref, actor_handle) = Actor::spawn(name, actor, args).await?;
         self.tracker.reopen();
         self.tracker.spawn_blocking(move || {
@@ -113,6 +202,38 @@ impl ServiceManager {
         join_handle
     }
 
+    // Like spawn_service, but on shutdown the actors are not stopped until
+    // the service finishes or the timeout expires, whatever comes first
+    pub fn spawn_drained_service<F, Fut>(&self, name: &str, timeout: Duration, task: F)
+    where
+        F: FnOnce(CancellationToken) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<()>> + Send,
+    {
+        let handle = self.spawn_service(task);
+        self.add_drain_phase(DrainPhase::Service {
+            name: name.to_string(),
+            handle,
+            timeout,
+        });
+    }
+
+    // On shutdown, waits up to the timeout for the pending messages of an
+    // actor mailbox, see `mailbox::pending`, before stopping the actors
+    pub fn add_mailbox_drain_phase(&self, name: &str, pending: fn() -> usize, timeout: Duration) {
+        self.add_drain_phase(DrainPhase::Mailbox {
+            name: name.to_string(),
+            pending,
+            timeout,
+        });
+    }
+
+    fn add_drain_phase(&self, drain_phase: DrainPhase) {
+        self.drain_phases
+            .lock()
+            .expect("Drain phases lock poisoned")
+            .push(drain_phase);
+    }
+
     // Spawn through a function that receives a cancellation token. This function will be called in a new thread
     pub fn spawn_blocking_service<F, Fut>(&self, task: F) -> JoinHandle<()>
     where
@@ -136,6 +257,38 @@ impl ServiceManager {
         join_handle
     }
 
+    // Periodically export the pending messages of each actor mailbox and warn
+    // when one of them is falling behind
+    pub fn spawn_mailbox_monitor(
+        &self,
+        sample_interval: Duration,
+        warning_threshold: usize,
+    ) -> JoinHandle<()> {
+        self.spawn_service(move |cancellation_token| async move {
+            let mut ticker = interval(sample_interval);
+            loop {
+                tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+                    _ = ticker.tick() => {
+                        for (actor_name, len) in mailbox::mailbox_lens() {
+                            gauge!("actor_mailbox_len", "actor" => actor_name.clone())
+                                .set(len as f64);
+
+                            if len > warning_threshold {
+                                warn!(
+                                    "Actor {} has {} pending messages, it may be falling behind",
+                                    actor_name, len
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
     // Wait until all actors and services are done
     pub async fn listen_stop_signals(&self) -> Result<()> {
         #[cfg(unix)]
@@ -167,13 +320,25 @@ impl ServiceManager {
     // Stop all actors and services
     pub async fn stop(&self) {
         self.token.cancel();
+
+        let drain_phases = std::mem::take(
+            &mut *self
+                .drain_phases
+                .lock()
+                .expect("Drain phases lock poisoned"),
+        );
+        for drain_phase in drain_phases {
+            drain_phase.drain().await;
+        }
+
+        self.actors_token.cancel();
         info!("Wait for all tasks to complete after the cancel");
         self.tracker.wait().await;
         info!("All tasks completed bye bye");
     }
 
     fn spawn_cleaning_task(&self, mut actors_receiver: mpsc::Receiver<ActorCell>) {
-        let token_clone = self.token.clone();
+        let token_clone = self.actors_token.clone();
 
         tokio::spawn(async move {
             let mut actors: Vec<ActorCell> = Vec::new();
@@ -201,27 +366,68 @@ impl ServiceManager {
 
 impl Drop for ServiceManager {
     fn drop(&mut self) {
-        if !self.token.is_cancelled() {
+        if !self.token.is_cancelled() || !self.actors_token.is_cancelled() {
             debug!("ServiceManager is being dropped");
             self.token.cancel();
+            self.actors_token.cancel();
         }
     }
 }
 
-fn simplify_type_name(input: &str) -> String {
-    let mut result = input.to_string();
-    // Match segments starting with lowercase followed by any of the specified delimiters
-    let regex = Regex::new(r"\b[a-z]\w*(::|<)").unwrap();
-
-    // As long as there's a match, keep replacing
-    while let Some(mat) = regex.find(&result) {
-        // Calculate the replacement range to keep the delimiter
-        let range = mat.start()..mat.end() - 2;
-        // Replace the matched segment with an empty string, effectively removing it
-        result.replace_range(range, "");
+/// Name of an actor for logs and metric labels, like `RelayEventDispatcher`
+/// whatever its generic arguments
+pub fn actor_name<A: ?Sized>() -> String {
+    simplify_type_name(std::any::type_name::<A>())
+}
+
+/// Drops the module paths and generic arguments of a type name, so
+/// `reportinator_server::actors::RelayEventDispatcher<NostrService>` becomes
+/// `RelayEventDispatcher`. Tuples keep their elements, each simplified.
+pub fn simplify_type_name(input: &str) -> String {
+    let input = input.trim();
+    if let Some(elements) = input
+        .strip_prefix('(')
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let elements: Vec<String> = split_top_level(elements)
+            .into_iter()
+            .map(simplify_type_name)
+            .collect();
+        return format!("({})", elements.join(", "));
     }
 
-    result.replace(':', "")
+    let without_generics = input.split('<').next().unwrap_or(input);
+    let name = without_generics
+        .rsplit("::")
+        .next()
+        .unwrap_or(without_generics);
+
+    name.trim_start_matches(['&', ' ']).to_string()
+}
+
+// Splits on the commas that are not inside generic arguments or nested tuples
+fn split_top_level(input: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in input.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                parts.push(&input[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&input[start..]);
+
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .collect()
 }
 
 #[cfg(test)]
@@ -234,6 +440,45 @@ mod service_manager_tests {
         time::{sleep, Duration},
     };
 
+    #[test]
+    fn simplify_type_name_drops_paths_and_generics() {
+        assert_eq!(
+            simplify_type_name("reportinator_server::actors::gift_unwrapper::GiftUnwrapper"),
+            "GiftUnwrapper"
+        );
+        assert_eq!(
+            simplify_type_name(
+                "reportinator_server::actors::relay_event_dispatcher::RelayEventDispatcher<reportinator_server::adapters::nostr_service::NostrService>"
+            ),
+            "RelayEventDispatcher"
+        );
+        assert_eq!(
+            simplify_type_name(
+                "a::Supervisor<b::Service<c::Inner>, alloc::boxed::Box<dyn d::Port>>"
+            ),
+            "Supervisor"
+        );
+        assert_eq!(simplify_type_name("&str"), "str");
+    }
+
+    #[test]
+    fn simplify_type_name_keeps_tuple_elements() {
+        assert_eq!(
+            simplify_type_name("(a::Keys, core::option::Option<b::Config>)"),
+            "(Keys, Option)"
+        );
+        assert_eq!(
+            simplify_type_name("(a::B<(c::D, e::F)>, (g::H, i::J))"),
+            "(B, (H, J))"
+        );
+        assert_eq!(simplify_type_name("()"), "()");
+    }
+
+    #[test]
+    fn actor_names_are_generic_free() {
+        assert_eq!(actor_name::<TestActor<String>>(), "TestActor");
+    }
+
     #[tokio::test]
     async fn service_manager_spawns_and_manages_test_actor() {
         let mut service_manager = ServiceManager::new();
@@ -267,4 +512,24 @@ mod service_manager_tests {
 
         service_manager.stop().await;
     }
+
+    #[tokio::test]
+    async fn stop_forces_services_past_their_drain_timeout() {
+        let service_manager = ServiceManager::new();
+
+        // Ignores the cancellation
+        service_manager.spawn_drained_service(
+            "Stuck service",
+            Duration::from_millis(50),
+            |_cancellation_token| async {
+                sleep(Duration::from_secs(3600)).await;
+                Ok(())
+            },
+        );
+        service_manager.add_mailbox_drain_phase("Stuck mailbox", || 1, Duration::from_millis(50));
+
+        tokio::time::timeout(Duration::from_secs(5), service_manager.stop())
+            .await
+            .expect("Stop should give up on stuck phases");
+    }
 }